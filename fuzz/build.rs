@@ -0,0 +1,4 @@
+fn main() {
+    // libyara and the interceptors of libFuzzer both define `memmem`.
+    println!("cargo:rustc-link-arg-bins=-Wl,--allow-multiple-definition");
+}
@@ -0,0 +1,116 @@
+//! Write the seed corpus of the fuzz targets to `seeds/`, from the fixtures of the tests.
+//!
+//! ```sh
+//! cargo run --manifest-path fuzz/Cargo.toml --example seeds
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rs_yara::Compiler;
+
+/// Larger compiled rules are left out, libFuzzer is slow on large inputs.
+const MAX_LOAD_SEED: usize = 64 * 1024;
+
+fn write(dir: &Path, name: &str, data: &[u8]) {
+    fs::write(dir.join(name), data).expect("Should have written the seed");
+}
+
+/// The entries of a corpus of `tests/syntax`.
+fn entries(path: &Path) -> Vec<String> {
+    fs::read_to_string(path)
+        .expect("Should have read the corpus")
+        .split("\n-----\n")
+        .map(str::to_owned)
+        .collect()
+}
+
+fn main() {
+    let fuzz = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let tests = fuzz.join("../tests");
+    let seeds = fuzz.join("seeds");
+    for target in &["compile", "load", "scan"] {
+        let dir = seeds.join(target);
+        fs::remove_dir_all(&dir).ok();
+        fs::create_dir_all(&dir).expect("Should have created the seeds directory");
+    }
+
+    let mut sources = vec![
+        (
+            "rules".to_owned(),
+            fs::read_to_string(tests.join("rules.txt")).unwrap(),
+        ),
+        (
+            "scan".to_owned(),
+            fs::read_to_string(fuzz.join("rules/scan.yar")).unwrap(),
+        ),
+    ];
+    for (i, source) in entries(&tests.join("syntax/valid.txt"))
+        .into_iter()
+        .enumerate()
+    {
+        sources.push((format!("valid-{:02}", i), source));
+    }
+    for (i, source) in entries(&tests.join("syntax/invalid.txt"))
+        .into_iter()
+        .enumerate()
+    {
+        sources.push((format!("invalid-{:02}", i), source));
+    }
+
+    for (name, source) in &sources {
+        write(
+            &seeds.join("compile"),
+            &format!("{}.yar", name),
+            source.as_bytes(),
+        );
+
+        let mut compiler = Compiler::new().unwrap();
+        if compiler.add_rules_str(source).is_err() {
+            continue;
+        }
+        if let Ok(mut rules) = compiler.compile_rules() {
+            let mut saved = Vec::new();
+            rules.save_to_stream(&mut saved).unwrap();
+            if saved.len() > MAX_LOAD_SEED {
+                continue;
+            }
+            write(&seeds.join("load"), &format!("{}.yarc", name), &saved);
+        }
+    }
+
+    let scan = seeds.join("scan");
+    write(
+        &scan,
+        "scanfile.txt",
+        &fs::read(tests.join("scanfile.txt")).unwrap(),
+    );
+    write(
+        &scan,
+        "text.txt",
+        b"Rust and YARA, a word, http://example.com/ me@example.com abc abc abc xyz",
+    );
+    write(
+        &scan,
+        "wide.bin",
+        &"wide Rust"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<_>>(),
+    );
+    write(
+        &scan,
+        "xor.bin",
+        &b"xored".iter().map(|b| b ^ 0x5a).collect::<Vec<_>>(),
+    );
+    let mut pe = b"MZ".to_vec();
+    pe.resize(0x40, 0);
+    pe[0x3c] = 0x40;
+    pe.extend_from_slice(b"PE\0\0\x4c\x01\x01\0");
+    pe.resize(0x200, 0);
+    write(&scan, "pe.bin", &pe);
+    let mut elf = b"\x7fELF\x02\x01\x01".to_vec();
+    elf.resize(0x40, 0);
+    elf[0x10] = 2;
+    write(&scan, "elf.bin", &elf);
+}
@@ -0,0 +1,28 @@
+//! Compile arbitrary rule sources, then scan the source with the compiled rules.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rs_yara::Compiler;
+
+fuzz_target!(|data: &[u8]| {
+    let source = match std::str::from_utf8(data) {
+        Ok(source) => source,
+        Err(_) => return,
+    };
+    // Sources are passed to libyara as C strings.
+    if source.contains('\0') {
+        return;
+    }
+
+    let mut compiler = Compiler::new().unwrap();
+    if compiler.add_rules_str(source).is_err() {
+        return;
+    }
+    let _ = compiler.warnings();
+    let rules = match compiler.compile_rules() {
+        Ok(rules) => rules,
+        Err(_) => return,
+    };
+    let _ = rules.get_rules();
+    let _ = rules.scan_mem(data, 1);
+});
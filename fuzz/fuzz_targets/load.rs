@@ -0,0 +1,15 @@
+//! Load arbitrary bytes as compiled rules, then use the rules.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rs_yara::Rules;
+
+fuzz_target!(|data: &[u8]| {
+    let rules = match Rules::load_from_stream(data) {
+        Ok(rules) => rules,
+        Err(_) => return,
+    };
+    let _ = rules.stats();
+    let _ = rules.get_rules();
+    let _ = rules.scan_mem(b"I love Rust!", 1);
+});
@@ -0,0 +1,34 @@
+//! Scan arbitrary bytes with the rules of `rules/scan.yar`.
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use rs_yara::{Compiler, Rules};
+
+static RULES: OnceLock<Rules> = OnceLock::new();
+
+fn rules() -> &'static Rules {
+    RULES.get_or_init(|| {
+        let mut compiler = Compiler::new().unwrap();
+        compiler
+            .add_rules_str(include_str!("../rules/scan.yar"))
+            .expect("Should have compiled the scan rules");
+        compiler.compile_rules().unwrap()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let matches = match rules().scan_mem(data, 1) {
+        Ok(matches) => matches,
+        Err(_) => return,
+    };
+    for rule in matches {
+        for string in rule.strings {
+            for m in string.matches {
+                assert!(m.data.len() <= m.length);
+                assert!(m.offset + m.length <= data.len());
+            }
+        }
+    }
+});
@@ -0,0 +1,426 @@
+//! Scans of the members of archives and compressed files, recursively.
+//!
+//! An [`ArchiveScanner`] scans a file, then the members of the file when an [`Extractor`]
+//! recognizes it, then the members of the members. Members are reported with a virtual path:
+//! the path of their container, `!`, then their name in the container, as in
+//! `outer.zip!inner/tool.exe` or `logs.tar.gz!logs.tar!app.log`.
+//!
+//! zip, gzip and tar are extracted by default, bzip2 and xz with the `bzip2` and `xz` features.
+//! [`Limits`] on the depth, the number and the total size of the members defend against
+//! archive bombs.
+//!
+//! ```
+//! use rs_yara::archive::ArchiveScanner;
+//! use rs_yara::Compiler;
+//!
+//! let mut compiler = Compiler::new()?;
+//! compiler.add_rules_str(r#"rule rust { strings: $a = "Rust" condition: $a }"#)?;
+//! let rules = compiler.compile_rules()?;
+//!
+//! let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+//! std::io::Write::write_all(&mut gzip, b"I love Rust").unwrap();
+//! let data = gzip.finish().unwrap();
+//!
+//! let mut matched = Vec::new();
+//! ArchiveScanner::new().scan_mem(&rules, "love.txt.gz", &data, 10, |path, result| {
+//!     if !result.unwrap().is_empty() {
+//!         matched.push(path.to_owned());
+//!     }
+//! });
+//! assert_eq!(vec!["love.txt.gz!love.txt"], matched);
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::fs;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use crate::errors::*;
+use crate::{Rule, Rules};
+
+/// A member of an archive.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Member {
+    /// Name of the member in the archive.
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Extracts the members of a format of archive.
+pub trait Extractor: Send + Sync {
+    /// Whether `data` is in the format of the extractor, usually from its magic bytes.
+    fn detect(&self, data: &[u8]) -> bool;
+
+    /// Add the members of `data` to `members`, read through `budget`. `name` is the file name of
+    /// the archive.
+    ///
+    /// The members added before an error are still scanned.
+    fn extract(
+        &self,
+        name: &str,
+        data: &[u8],
+        budget: &mut Budget,
+        members: &mut Vec<Member>,
+    ) -> Result<(), ArchiveError>;
+}
+
+/// Limits of the extraction of a scanned file, for all the nested archives together.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Archives nested deeper are not extracted. The scanned file has depth 0.
+    pub max_depth: usize,
+    /// Total size of the extracted members.
+    pub max_total_size: u64,
+    /// Total number of extracted members.
+    pub max_members: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: 8,
+            max_total_size: 256 * 1024 * 1024,
+            max_members: 10_000,
+        }
+    }
+}
+
+/// What is left of the [`Limits`] of a scanned file, given to extractors.
+#[derive(Debug)]
+pub struct Budget {
+    limits: Limits,
+    size: u64,
+    members: usize,
+}
+
+impl Budget {
+    pub fn new(limits: Limits) -> Self {
+        Budget {
+            limits,
+            size: 0,
+            members: 0,
+        }
+    }
+
+    /// Read a member, failing when it exceeds the limits. `format` names the archive in errors.
+    pub fn read<R: Read>(
+        &mut self,
+        format: &'static str,
+        reader: R,
+    ) -> Result<Vec<u8>, ArchiveError> {
+        if self.members >= self.limits.max_members {
+            return Err(ArchiveError::TooManyMembers(self.limits.max_members));
+        }
+        self.members += 1;
+
+        let remaining = self.limits.max_total_size - self.size;
+        let mut data = Vec::new();
+        reader
+            .take(remaining + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| invalid(format, e))?;
+        if data.len() as u64 > remaining {
+            self.size = self.limits.max_total_size;
+            return Err(ArchiveError::TooLarge(self.limits.max_total_size));
+        }
+        self.size += data.len() as u64;
+        Ok(data)
+    }
+}
+
+fn invalid<E: ToString>(format: &'static str, error: E) -> ArchiveError {
+    ArchiveError::Invalid {
+        format,
+        message: error.to_string(),
+    }
+}
+
+/// Scans files and their members with the registered extractors.
+pub struct ArchiveScanner {
+    extractors: Vec<Box<dyn Extractor>>,
+    limits: Limits,
+}
+
+impl Default for ArchiveScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ArchiveScanner {
+    /// A scanner with the extractors of the enabled formats, and the default limits.
+    pub fn new() -> Self {
+        let mut scanner = Self::empty();
+        scanner.add_extractor(Zip);
+        scanner.add_extractor(Gzip);
+        scanner.add_extractor(Tar);
+        #[cfg(feature = "bzip2")]
+        scanner.add_extractor(Bzip2);
+        #[cfg(feature = "xz")]
+        scanner.add_extractor(Xz);
+        scanner
+    }
+
+    /// A scanner without extractors.
+    pub fn empty() -> Self {
+        ArchiveScanner {
+            extractors: Vec::new(),
+            limits: Limits::default(),
+        }
+    }
+
+    /// Add an extractor, tried after the ones already added.
+    pub fn add_extractor<E: Extractor + 'static>(&mut self, extractor: E) {
+        self.extractors.push(Box::new(extractor));
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Scan `data` and its members, calling `callback` with the virtual path and the result of
+    /// each scan.
+    ///
+    /// An archive which cannot be fully extracted is reported a second time, with the error.
+    /// Members extracted before the error are still scanned.
+    pub fn scan_mem<'r, F>(
+        &self,
+        rules: &'r Rules,
+        path: &str,
+        data: &[u8],
+        timeout: u16,
+        mut callback: F,
+    ) where
+        F: FnMut(&str, Result<Vec<Rule<'r>>, Error>),
+    {
+        let mut budget = Budget::new(self.limits);
+        self.visit(rules, path, data, 0, timeout, &mut budget, &mut callback);
+    }
+
+    /// Scan a file and its members, see [`scan_mem`](#method.scan_mem).
+    pub fn scan_file<'r, P, F>(&self, rules: &'r Rules, path: P, timeout: u16, mut callback: F)
+    where
+        P: AsRef<Path>,
+        F: FnMut(&str, Result<Vec<Rule<'r>>, Error>),
+    {
+        let path = path.as_ref();
+        let name = path.to_string_lossy();
+        match fs::read(path) {
+            Ok(data) => self.scan_mem(rules, &name, &data, timeout, callback),
+            Err(e) => callback(
+                &name,
+                Err(IoError::new(e, IoErrorKind::ReadingScanFile).into()),
+            ),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn visit<'r, F>(
+        &self,
+        rules: &'r Rules,
+        path: &str,
+        data: &[u8],
+        depth: usize,
+        timeout: u16,
+        budget: &mut Budget,
+        callback: &mut F,
+    ) where
+        F: FnMut(&str, Result<Vec<Rule<'r>>, Error>),
+    {
+        callback(path, rules.scan_mem(data, timeout).map_err(Into::into));
+
+        let extractor = match self.extractors.iter().find(|e| e.detect(data)) {
+            Some(extractor) => extractor,
+            None => return,
+        };
+        if depth >= self.limits.max_depth {
+            callback(
+                path,
+                Err(ArchiveError::TooDeep(self.limits.max_depth).into()),
+            );
+            return;
+        }
+
+        let name = path.rsplit(['!', '/']).next().unwrap_or(path);
+        let mut members = Vec::new();
+        let result = extractor.extract(name, data, budget, &mut members);
+        for member in members {
+            let member_path = format!("{}!{}", path, member.name);
+            self.visit(
+                rules,
+                &member_path,
+                &member.data,
+                depth + 1,
+                timeout,
+                budget,
+                callback,
+            );
+        }
+        if let Err(error) = result {
+            callback(path, Err(error.into()));
+        }
+    }
+}
+
+/// zip archives. Directories are skipped.
+pub struct Zip;
+
+impl Extractor for Zip {
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06")
+    }
+
+    fn extract(
+        &self,
+        _name: &str,
+        data: &[u8],
+        budget: &mut Budget,
+        members: &mut Vec<Member>,
+    ) -> Result<(), ArchiveError> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| invalid("zip", e))?;
+        for i in 0..archive.len() {
+            let file = archive.by_index(i).map_err(|e| invalid("zip", e))?;
+            if file.is_dir() {
+                continue;
+            }
+            let name = file.name().to_owned();
+            members.push(Member {
+                name,
+                data: budget.read("zip", file)?,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// The name of the single member of a compressed file: its name with the extension replaced,
+/// as in `(".tgz", ".tar")`, or `data`.
+fn decompressed_name(name: &str, extensions: &[(&str, &str)]) -> String {
+    extensions
+        .iter()
+        .find_map(|(ext, replacement)| {
+            name.strip_suffix(ext)
+                .filter(|stem| !stem.is_empty())
+                .map(|stem| format!("{}{}", stem, replacement))
+        })
+        .unwrap_or_else(|| "data".to_owned())
+}
+
+/// gzip files, with one member named by the gzip header, or else by the file name.
+pub struct Gzip;
+
+impl Extractor for Gzip {
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(b"\x1f\x8b")
+    }
+
+    fn extract(
+        &self,
+        name: &str,
+        data: &[u8],
+        budget: &mut Budget,
+        members: &mut Vec<Member>,
+    ) -> Result<(), ArchiveError> {
+        let decoder = flate2::read::MultiGzDecoder::new(data);
+        let header_name = decoder
+            .header()
+            .and_then(|h| h.filename())
+            .map(|f| String::from_utf8_lossy(f).into_owned());
+        let data = budget.read("gzip", decoder)?;
+        members.push(Member {
+            name: header_name
+                .unwrap_or_else(|| decompressed_name(name, &[(".gz", ""), (".tgz", ".tar")])),
+            data,
+        });
+        Ok(())
+    }
+}
+
+/// tar archives. Only regular files are members.
+pub struct Tar;
+
+impl Extractor for Tar {
+    fn detect(&self, data: &[u8]) -> bool {
+        data.get(257..262) == Some(b"ustar")
+    }
+
+    fn extract(
+        &self,
+        _name: &str,
+        data: &[u8],
+        budget: &mut Budget,
+        members: &mut Vec<Member>,
+    ) -> Result<(), ArchiveError> {
+        let mut archive = tar::Archive::new(data);
+        for entry in archive.entries().map_err(|e| invalid("tar", e))? {
+            let entry = entry.map_err(|e| invalid("tar", e))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry
+                .path()
+                .map_err(|e| invalid("tar", e))?
+                .to_string_lossy()
+                .into_owned();
+            members.push(Member {
+                name,
+                data: budget.read("tar", entry)?,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// bzip2 files, with one member named by the file name.
+#[cfg(feature = "bzip2")]
+pub struct Bzip2;
+
+#[cfg(feature = "bzip2")]
+impl Extractor for Bzip2 {
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(b"BZh")
+    }
+
+    fn extract(
+        &self,
+        name: &str,
+        data: &[u8],
+        budget: &mut Budget,
+        members: &mut Vec<Member>,
+    ) -> Result<(), ArchiveError> {
+        members.push(Member {
+            name: decompressed_name(name, &[(".bz2", ""), (".tbz2", ".tar")]),
+            data: budget.read("bzip2", bzip2::read::MultiBzDecoder::new(data))?,
+        });
+        Ok(())
+    }
+}
+
+/// xz files, with one member named by the file name.
+#[cfg(feature = "xz")]
+pub struct Xz;
+
+#[cfg(feature = "xz")]
+impl Extractor for Xz {
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(b"\xfd7zXZ\x00")
+    }
+
+    fn extract(
+        &self,
+        name: &str,
+        data: &[u8],
+        budget: &mut Budget,
+        members: &mut Vec<Member>,
+    ) -> Result<(), ArchiveError> {
+        members.push(Member {
+            name: decompressed_name(name, &[(".xz", ""), (".txz", ".tar")]),
+            data: budget.read("xz", xz2::read::XzDecoder::new_multi_decoder(data))?,
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,213 @@
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use lazy_static::lazy_static;
+use tokio::sync::oneshot;
+
+use crate::{errors::*, initialize::InitializationToken, owned::OwnedRule, yara_sys, Rules};
+
+/// A scan, given whether libyara could be initialized in its thread.
+type Job = Box<dyn FnOnce(Result<(), YaraError>) + Send>;
+
+lazy_static! {
+    /// Threads running the scans of `AsyncRules`, one per scan slot of libyara.
+    static ref SCAN_POOL: ScanPool = ScanPool::new(yara_sys::YR_MAX_THREADS as usize);
+}
+
+struct ScanPool {
+    sender: Mutex<mpsc::Sender<Job>>,
+}
+
+impl ScanPool {
+    fn new(size: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..size {
+            let receiver = Arc::clone(&receiver);
+            thread::Builder::new()
+                .name(format!("rs_yara-scan-{}", i))
+                .spawn(move || scan_worker(receiver))
+                .expect("should have spawned a scan thread");
+        }
+
+        ScanPool {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn execute(&self, job: Job) {
+        self.sender
+            .lock()
+            .expect("mutex should not be poisoned")
+            .send(job)
+            .expect("scan threads should be running");
+    }
+}
+
+fn scan_worker(receiver: Arc<Mutex<mpsc::Receiver<Job>>>) {
+    // Keep the library initialized in this thread, instead of once per scan. If it cannot be,
+    // the scans of this thread fail with the error instead of running uninitialized.
+    let token = InitializationToken::new();
+    let initialized = token.as_ref().map(|_| ()).map_err(|e| *e);
+
+    loop {
+        let job = match receiver
+            .lock()
+            .expect("mutex should not be poisoned")
+            .recv()
+        {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        job(initialized);
+    }
+}
+
+/// Run `scan` on the scan pool.
+///
+/// If the returned future is dropped before `scan` started, it is never run.
+async fn run_scan<F>(scan: F) -> Result<Vec<OwnedRule>, Error>
+where
+    F: FnOnce() -> Result<Vec<OwnedRule>, Error> + Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    SCAN_POOL.execute(Box::new(move |initialized| {
+        if !sender.is_closed() {
+            // The panic of a scan is sent to the task, to be raised again there.
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                initialized.map_err(Error::from).and_then(|()| scan())
+            }));
+            let _ = sender.send(result);
+        }
+    }));
+
+    match receiver.await {
+        Ok(Ok(result)) => result,
+        Ok(Err(payload)) => panic::resume_unwind(payload),
+        Err(_) => unreachable!("scan jobs always send their result"),
+    }
+}
+
+/// Rules scanned asynchronously, from a tokio runtime.
+///
+/// Scans run on a dedicated pool of threads, as many as libyara can scan with at the same time
+/// (`YR_MAX_THREADS`). Scans started while all the threads are busy wait for a free one.
+/// Synchronous scans of the same rules still use scan slots of libyara, and can make a scan of
+/// the pool fail with [`TooManyScanThreads`](yara_sys/enum.Error.html).
+///
+/// Dropping the future of a scan which is still waiting for a thread cancels it. A scan which
+/// is already running cannot be interrupted: libyara only calls back once every condition was
+/// evaluated. It keeps its thread and scan slot until it is finished or timed out, and its
+/// result is discarded. Use a timeout to bound how long a dropped scan can hold them.
+///
+/// Results are [`OwnedRule`], because they outlive the borrow of the rules.
+#[derive(Clone)]
+pub struct AsyncRules {
+    rules: Arc<Rules>,
+}
+
+impl From<Rules> for AsyncRules {
+    fn from(rules: Rules) -> Self {
+        AsyncRules {
+            rules: Arc::new(rules),
+        }
+    }
+}
+
+impl From<Arc<Rules>> for AsyncRules {
+    fn from(rules: Arc<Rules>) -> Self {
+        AsyncRules { rules }
+    }
+}
+
+impl AsyncRules {
+    pub fn new(rules: Rules) -> Self {
+        Self::from(rules)
+    }
+
+    /// The rules, to scan synchronously.
+    pub fn rules(&self) -> &Rules {
+        &self.rules
+    }
+
+    /// Scan a buffer.
+    ///
+    /// See [`Rules::scan_mem`].
+    pub async fn scan_mem<M>(&self, mem: M, timeout: u16) -> Result<Vec<OwnedRule>, Error>
+    where
+        M: AsRef<[u8]> + Send + 'static,
+    {
+        let rules = Arc::clone(&self.rules);
+        run_scan(move || {
+            rules
+                .scan_mem(mem.as_ref(), timeout)
+                .map(|r| r.into_iter().map(OwnedRule::from).collect())
+                .map_err(|e| e.into())
+        })
+        .await
+    }
+
+    /// Scan a file.
+    ///
+    /// See [`Rules::scan_file`].
+    pub async fn scan_file<P>(&self, path: P, timeout: u16) -> Result<Vec<OwnedRule>, Error>
+    where
+        P: AsRef<Path> + Send + 'static,
+    {
+        let rules = Arc::clone(&self.rules);
+        run_scan(move || {
+            rules
+                .scan_file(path, timeout)
+                .map(|r| r.into_iter().map(OwnedRule::from).collect())
+        })
+        .await
+    }
+
+    /// Scan `len` bytes of a file, starting at `offset`.
+    ///
+    /// See [`Rules::scan_file_range`].
+    pub async fn scan_file_range<P>(
+        &self,
+        path: P,
+        offset: u64,
+        len: u64,
+        timeout: u16,
+    ) -> Result<Vec<OwnedRule>, Error>
+    where
+        P: AsRef<Path> + Send + 'static,
+    {
+        let rules = Arc::clone(&self.rules);
+        run_scan(move || {
+            rules
+                .scan_file_range(path, offset, len, timeout)
+                .map(|r| r.into_iter().map(OwnedRule::from).collect())
+        })
+        .await
+    }
+
+    /// Scan data from a blocking reader, in windows.
+    ///
+    /// The reader is read from the scan pool. See [`Rules::scan_reader`].
+    pub async fn scan_reader<R>(
+        &self,
+        reader: R,
+        window: usize,
+        overlap: usize,
+        timeout: u16,
+    ) -> Result<Vec<OwnedRule>, Error>
+    where
+        R: Read + Send + 'static,
+    {
+        let rules = Arc::clone(&self.rules);
+        run_scan(move || {
+            rules
+                .scan_reader(reader, window, overlap, timeout)
+                .map(|r| r.into_iter().map(OwnedRule::from).collect())
+        })
+        .await
+    }
+}
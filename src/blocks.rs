@@ -0,0 +1,132 @@
+use std::os::raw::c_void;
+use std::ptr;
+
+use crate::yara_sys::{YR_MEMORY_BLOCK, YR_MEMORY_BLOCK_ITERATOR};
+
+/// A source of memory blocks, scanned by libyara as one logical target.
+///
+/// Each block is a `(base, data)` pair. `base` is the address of the first byte of `data`,
+/// it is used for match offsets (`@str`) and for the `uintXX(addr)` functions in conditions.
+///
+/// # Implementation notes
+///
+/// libyara walks the blocks several times during a scan: once to search the strings, then again
+/// each time a condition reads data at an address. `first_block` must therefore rewind the
+/// source. The returned slice only needs to stay valid until the next call to `first_block` or
+/// `next_block`, so blocks can be read lazily.
+///
+/// `filesize` is the size of the first block.
+pub trait MemoryBlockSource {
+    /// Rewind the source and return its first block.
+    fn first_block(&mut self) -> Option<(u64, &[u8])>;
+
+    /// Return the block following the last one returned.
+    fn next_block(&mut self) -> Option<(u64, &[u8])>;
+}
+
+impl<S: MemoryBlockSource + ?Sized> MemoryBlockSource for &mut S {
+    fn first_block(&mut self) -> Option<(u64, &[u8])> {
+        (**self).first_block()
+    }
+
+    fn next_block(&mut self) -> Option<(u64, &[u8])> {
+        (**self).next_block()
+    }
+}
+
+/// A [`MemoryBlockSource`] over blocks already in memory.
+#[derive(Debug, Clone)]
+pub struct MemoryBlocks<'a> {
+    blocks: Vec<(u64, &'a [u8])>,
+    position: usize,
+}
+
+impl<'a> MemoryBlocks<'a> {
+    pub fn new<I>(blocks: I) -> Self
+    where
+        I: IntoIterator<Item = (u64, &'a [u8])>,
+    {
+        MemoryBlocks {
+            blocks: blocks.into_iter().collect(),
+            position: 0,
+        }
+    }
+}
+
+impl<'a> MemoryBlockSource for MemoryBlocks<'a> {
+    fn first_block(&mut self) -> Option<(u64, &[u8])> {
+        self.position = 0;
+        self.next_block()
+    }
+
+    fn next_block(&mut self) -> Option<(u64, &[u8])> {
+        let block = self.blocks.get(self.position).copied();
+        if block.is_some() {
+            self.position += 1;
+        }
+        block
+    }
+}
+
+/// Adapt a [`MemoryBlockSource`] to a `YR_MEMORY_BLOCK_ITERATOR`.
+///
+/// The data pointer of the current block is kept in the `context` of the `YR_MEMORY_BLOCK`,
+/// libyara only fetches the data of the last block returned by the iterator.
+pub struct BlockIterator<'s> {
+    source: &'s mut dyn MemoryBlockSource,
+    block: YR_MEMORY_BLOCK,
+}
+
+impl<'s> BlockIterator<'s> {
+    pub fn new(source: &'s mut dyn MemoryBlockSource) -> Self {
+        Self {
+            source,
+            block: YR_MEMORY_BLOCK {
+                size: 0,
+                base: 0,
+                context: ptr::null_mut(),
+                fetch_data: Some(block_fetch_data),
+            },
+        }
+    }
+
+    pub fn as_yara(&mut self) -> YR_MEMORY_BLOCK_ITERATOR {
+        YR_MEMORY_BLOCK_ITERATOR {
+            context: self as *mut Self as *mut c_void,
+            first: Some(block_iterator_first),
+            next: Some(block_iterator_next),
+        }
+    }
+
+    fn set_block(block: &mut YR_MEMORY_BLOCK, next: Option<(u64, &[u8])>) -> *mut YR_MEMORY_BLOCK {
+        match next {
+            Some((base, data)) => {
+                block.base = base;
+                block.size = data.len();
+                block.context = data.as_ptr() as *mut c_void;
+                block
+            }
+            None => ptr::null_mut(),
+        }
+    }
+}
+
+unsafe extern "C" fn block_iterator_first(
+    iterator: *mut YR_MEMORY_BLOCK_ITERATOR,
+) -> *mut YR_MEMORY_BLOCK {
+    let this: &mut BlockIterator = &mut *((*iterator).context as *mut BlockIterator);
+    let next = this.source.first_block();
+    BlockIterator::set_block(&mut this.block, next)
+}
+
+unsafe extern "C" fn block_iterator_next(
+    iterator: *mut YR_MEMORY_BLOCK_ITERATOR,
+) -> *mut YR_MEMORY_BLOCK {
+    let this: &mut BlockIterator = &mut *((*iterator).context as *mut BlockIterator);
+    let next = this.source.next_block();
+    BlockIterator::set_block(&mut this.block, next)
+}
+
+unsafe extern "C" fn block_fetch_data(block: *mut YR_MEMORY_BLOCK) -> *const u8 {
+    (*block).context as *const u8
+}
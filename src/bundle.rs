@@ -0,0 +1,371 @@
+use std::convert::{TryFrom, TryInto};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use crate::{errors::*, yara_sys, Rules};
+
+/// First bytes of a rules bundle.
+pub const BUNDLE_MAGIC: &[u8; 8] = b"RSYARBDL";
+
+/// Version of the bundle format written by this crate.
+pub const BUNDLE_FORMAT_VERSION: u16 = 1;
+
+/// SHA-256 of a rule source compiled in a bundle.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SourceHash {
+    /// Name of the source, usually its path.
+    pub name: String,
+    pub sha256: [u8; 32],
+}
+
+impl SourceHash {
+    pub fn new(name: &str, content: &[u8]) -> Self {
+        SourceHash {
+            name: name.to_owned(),
+            sha256: Sha256::digest(content).into(),
+        }
+    }
+
+    /// Hash a rule file, named by its path.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let content =
+            fs::read(path.as_ref()).map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile))?;
+        Ok(Self::new(&path.as_ref().to_string_lossy(), &content))
+    }
+}
+
+/// Provenance of the rules in a bundle.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BundleHeader {
+    pub format_version: u16,
+    /// Version of rs_yara which wrote the bundle.
+    pub rs_yara_version: String,
+    /// Version of libyara which compiled the rules.
+    pub libyara_version: String,
+    /// Creation time, in seconds since the Unix epoch.
+    pub created: u64,
+    /// Number of rules in the bundle.
+    pub rule_count: u32,
+    pub sources: Vec<SourceHash>,
+}
+
+/// A rules bundle.
+///
+/// # Format
+///
+/// All integers are little-endian.
+///
+/// - magic: [`BUNDLE_MAGIC`]
+/// - format version: `u16`
+/// - header length: `u32`, then the header
+/// - rules length: `u64`, then the rules, as written by [`Rules::save_to_stream`]
+/// - checksum: SHA-256 of everything before it
+/// - signature: `u8` 1 then the Ed25519 signature of the checksum, or `u8` 0
+///
+/// Nothing follows the signature.
+///
+/// The header holds strings as a `u16` length followed by UTF-8 bytes:
+/// rs_yara version, libyara version, creation time (`u64`), rule count (`u32`),
+/// source count (`u32`), then the name and SHA-256 of each source. Nothing follows the
+/// sources in the header.
+///
+/// Rules are only loaded by the libyara version which compiled them.
+pub struct Bundle {
+    pub header: BundleHeader,
+    rules: Vec<u8>,
+    checksum: [u8; 32],
+    signature: Option<[u8; 64]>,
+}
+
+impl Bundle {
+    /// Create a bundle of `rules`, compiled from `sources`.
+    ///
+    /// Note: this method takes the rules as mut because Yara modifies the Rule arena during
+    /// serialization.
+    pub fn new(rules: &mut Rules, sources: Vec<SourceHash>) -> Result<Self, Error> {
+        let rule_count = rules.stats()?.rules;
+        let mut serialized = Vec::new();
+        rules.save_to_stream(&mut serialized)?;
+
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let header = BundleHeader {
+            format_version: BUNDLE_FORMAT_VERSION,
+            rs_yara_version: env!("CARGO_PKG_VERSION").to_owned(),
+            libyara_version: libyara_version(),
+            created,
+            rule_count,
+            sources,
+        };
+        let mut bundle = Bundle {
+            header,
+            rules: serialized,
+            checksum: [0; 32],
+            signature: None,
+        };
+        bundle.checksum = Sha256::digest(bundle.signed_part()?).into();
+
+        Ok(bundle)
+    }
+
+    /// SHA-256 of the header and the rules.
+    pub fn checksum(&self) -> &[u8; 32] {
+        &self.checksum
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.signature.is_some()
+    }
+
+    /// Sign the checksum of the bundle.
+    #[cfg(feature = "signatures")]
+    pub fn sign(&mut self, key: &ed25519_dalek::SigningKey) {
+        use ed25519_dalek::Signer;
+
+        self.signature = Some(key.sign(&self.checksum).to_bytes());
+    }
+
+    /// Check the signature of the bundle.
+    #[cfg(feature = "signatures")]
+    pub fn verify(&self, key: &ed25519_dalek::VerifyingKey) -> Result<(), BundleError> {
+        let signature = self.signature.ok_or(BundleError::MissingSignature)?;
+        key.verify_strict(
+            &self.checksum,
+            &ed25519_dalek::Signature::from_bytes(&signature),
+        )
+        .map_err(|_| BundleError::BadSignature)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), Error> {
+        let mut buffer = self.signed_part()?;
+        buffer.extend_from_slice(&self.checksum);
+        match &self.signature {
+            Some(signature) => {
+                buffer.push(1);
+                buffer.extend_from_slice(signature);
+            }
+            None => buffer.push(0),
+        }
+
+        writer
+            .write_all(&buffer)
+            .map_err(|e| IoError::new(e, IoErrorKind::WritingRules).into())
+    }
+
+    /// Read a bundle and check its checksum.
+    ///
+    /// The signature is not checked.
+    pub fn read<R: Read>(mut reader: R) -> Result<Self, Error> {
+        let mut buffer = Vec::new();
+        reader
+            .read_to_end(&mut buffer)
+            .map_err(|e| IoError::new(e, IoErrorKind::ReadingRules))?;
+
+        let mut input = Input(&buffer);
+        if input.take(BUNDLE_MAGIC.len())? != BUNDLE_MAGIC {
+            return Err(BundleError::BadMagic.into());
+        }
+        let format_version = input.u16()?;
+        if format_version != BUNDLE_FORMAT_VERSION {
+            return Err(BundleError::UnsupportedVersion(format_version).into());
+        }
+        let header_len = input.u32()? as usize;
+        let header = parse_header(format_version, input.take(header_len)?)?;
+        let rules_len = input.u64()?;
+        let rules = input.take(rules_len.try_into().map_err(|_| BundleError::Truncated)?)?;
+        let signed_len = buffer.len() - input.0.len();
+
+        let checksum: [u8; 32] = input.take(32)?.try_into().unwrap();
+        if <[u8; 32]>::from(Sha256::digest(&buffer[..signed_len])) != checksum {
+            return Err(BundleError::BadChecksum.into());
+        }
+        let signature = match input.take(1)?[0] {
+            0 => None,
+            1 => Some(input.take(64)?.try_into().unwrap()),
+            _ => return Err(BundleError::BadTrailer.into()),
+        };
+        if !input.0.is_empty() {
+            return Err(BundleError::BadTrailer.into());
+        }
+
+        Ok(Bundle {
+            header,
+            rules: rules.to_vec(),
+            checksum,
+            signature,
+        })
+    }
+
+    /// Load the rules of the bundle.
+    ///
+    /// Fails if the rules were compiled by another version of libyara.
+    pub fn load_rules(&self) -> Result<Rules, Error> {
+        let running = libyara_version();
+        if self.header.libyara_version != running {
+            return Err(BundleError::LibyaraMismatch {
+                bundle: self.header.libyara_version.clone(),
+                running,
+            }
+            .into());
+        }
+        Rules::load_from_stream(&self.rules[..])
+    }
+
+    fn signed_part(&self) -> Result<Vec<u8>, BundleError> {
+        let header = encode_header(&self.header)?;
+        let mut buffer = Vec::with_capacity(header.len() + self.rules.len() + 32);
+        buffer.extend_from_slice(BUNDLE_MAGIC);
+        buffer.extend_from_slice(&self.header.format_version.to_le_bytes());
+        buffer.extend_from_slice(&field_len::<u32>(header.len())?.to_le_bytes());
+        buffer.extend_from_slice(&header);
+        buffer.extend_from_slice(&(self.rules.len() as u64).to_le_bytes());
+        buffer.extend_from_slice(&self.rules);
+        Ok(buffer)
+    }
+}
+
+impl Rules {
+    /// Save the rules in a bundle, with their provenance and a checksum.
+    ///
+    /// Note: this method is mut because Yara modifies the Rule arena during serialization.
+    pub fn save_bundle<W: Write>(
+        &mut self,
+        writer: W,
+        sources: Vec<SourceHash>,
+    ) -> Result<(), Error> {
+        Bundle::new(self, sources)?.write(writer)
+    }
+
+    /// Save the rules in a signed bundle.
+    ///
+    /// Note: this method is mut because Yara modifies the Rule arena during serialization.
+    #[cfg(feature = "signatures")]
+    pub fn save_signed_bundle<W: Write>(
+        &mut self,
+        writer: W,
+        sources: Vec<SourceHash>,
+        key: &ed25519_dalek::SigningKey,
+    ) -> Result<(), Error> {
+        let mut bundle = Bundle::new(self, sources)?;
+        bundle.sign(key);
+        bundle.write(writer)
+    }
+
+    /// Load rules from a bundle, after checking its checksum.
+    pub fn load_bundle<R: Read>(reader: R) -> Result<(Self, BundleHeader), Error> {
+        let bundle = Bundle::read(reader)?;
+        let rules = bundle.load_rules()?;
+        Ok((rules, bundle.header))
+    }
+
+    /// Load rules from a bundle, after checking its checksum and its signature.
+    #[cfg(feature = "signatures")]
+    pub fn load_signed_bundle<R: Read>(
+        reader: R,
+        key: &ed25519_dalek::VerifyingKey,
+    ) -> Result<(Self, BundleHeader), Error> {
+        let bundle = Bundle::read(reader)?;
+        bundle.verify(key)?;
+        let rules = bundle.load_rules()?;
+        Ok((rules, bundle.header))
+    }
+}
+
+fn libyara_version() -> String {
+    format!(
+        "{}.{}.{}",
+        yara_sys::YR_MAJOR_VERSION,
+        yara_sys::YR_MINOR_VERSION,
+        yara_sys::YR_MICRO_VERSION
+    )
+}
+
+fn encode_header(header: &BundleHeader) -> Result<Vec<u8>, BundleError> {
+    fn put_str(buffer: &mut Vec<u8>, s: &str) -> Result<(), BundleError> {
+        buffer.extend_from_slice(&field_len::<u16>(s.len())?.to_le_bytes());
+        buffer.extend_from_slice(s.as_bytes());
+        Ok(())
+    }
+
+    let mut buffer = Vec::new();
+    put_str(&mut buffer, &header.rs_yara_version)?;
+    put_str(&mut buffer, &header.libyara_version)?;
+    buffer.extend_from_slice(&header.created.to_le_bytes());
+    buffer.extend_from_slice(&header.rule_count.to_le_bytes());
+    buffer.extend_from_slice(&field_len::<u32>(header.sources.len())?.to_le_bytes());
+    for source in &header.sources {
+        put_str(&mut buffer, &source.name)?;
+        buffer.extend_from_slice(&source.sha256);
+    }
+    Ok(buffer)
+}
+
+/// A length, or a count, as encoded in a bundle.
+fn field_len<T: TryFrom<usize>>(len: usize) -> Result<T, BundleError> {
+    T::try_from(len).map_err(|_| BundleError::FieldTooLong)
+}
+
+fn parse_header(format_version: u16, header: &[u8]) -> Result<BundleHeader, BundleError> {
+    let mut input = Input(header);
+    let rs_yara_version = input.string()?;
+    let libyara_version = input.string()?;
+    let created = input.u64()?;
+    let rule_count = input.u32()?;
+    let source_count = input.u32()?;
+    let sources = (0..source_count)
+        .map(|_| {
+            Ok(SourceHash {
+                name: input.string()?,
+                sha256: input.take(32)?.try_into().unwrap(),
+            })
+        })
+        .collect::<Result<_, BundleError>>()?;
+    if !input.0.is_empty() {
+        return Err(BundleError::BadHeader);
+    }
+
+    Ok(BundleHeader {
+        format_version,
+        rs_yara_version,
+        libyara_version,
+        created,
+        rule_count,
+        sources,
+    })
+}
+
+/// Bytes remaining to parse.
+struct Input<'a>(&'a [u8]);
+
+impl<'a> Input<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BundleError> {
+        if self.0.len() < len {
+            return Err(BundleError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, BundleError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, BundleError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, BundleError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, BundleError> {
+        let len = self.u16()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| BundleError::BadHeader)
+    }
+}
@@ -0,0 +1,60 @@
+//! Helpers on byte strings, shared by the parsers and decoders of the crate.
+
+/// The offset of the first occurrence of `needle` in `haystack`.
+pub(crate) fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// The value of a hex digit.
+pub(crate) fn hex_digit(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// The byte of two hex digits, and only digits: `from_str_radix` accepts a sign too.
+pub(crate) fn hex_byte(hex: &[u8]) -> Option<u8> {
+    match hex {
+        [high, low] => Some((hex_digit(*high)? << 4) | hex_digit(*low)?),
+        _ => None,
+    }
+}
+
+/// The value of a base64 character, in the alphabet whose last two characters are `plus` and
+/// `slash`.
+pub(crate) fn base64_value(byte: u8, plus: u8, slash: u8) -> Option<u8> {
+    match byte {
+        b'A'..=b'Z' => Some(byte - b'A'),
+        b'a'..=b'z' => Some(byte - b'a' + 26),
+        b'0'..=b'9' => Some(byte - b'0' + 52),
+        b if b == plus => Some(62),
+        b if b == slash => Some(63),
+        _ => None,
+    }
+}
+
+/// Decode base64 up to its `=` padding and `max_size` bytes, ignoring line breaks and other
+/// characters out of the alphabet.
+pub(crate) fn decode_base64(data: &[u8], plus: u8, slash: u8, max_size: usize) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity((data.len() * 3 / 4).min(max_size));
+    let (mut buffer, mut bits) = (0u32, 0);
+    for &byte in data {
+        if decoded.len() >= max_size || byte == b'=' {
+            break;
+        }
+        let value = match base64_value(byte, plus, slash) {
+            Some(value) => value,
+            None => continue,
+        };
+        buffer = (buffer << 6) | u32::from(value);
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    decoded
+}
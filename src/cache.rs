@@ -0,0 +1,372 @@
+use std::cmp::Reverse;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::{errors::*, yara_sys, Compiler, Rules, SourceHash, VariableValue};
+
+/// Version of the cache key, to change when the way it is computed changes.
+const CACHE_KEY_VERSION: &[u8] = b"rs_yara-cache-2";
+
+const CACHE_EXTENSION: &str = "yarc";
+
+/// Deepest include followed when hashing the sources.
+pub(crate) const MAX_INCLUDE_DEPTH: usize = 16;
+
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+enum CompilerInput {
+    Str {
+        rule: String,
+        namespace: Option<String>,
+    },
+    File {
+        path: PathBuf,
+        /// The content read when the file was added, which is the one compiled.
+        content: String,
+        namespace: Option<String>,
+    },
+    Variable {
+        identifier: String,
+        value: VariableValue,
+    },
+}
+
+/// A [`Compiler`] which keeps the compiled rules in a cache directory.
+///
+/// The inputs of the compiler are only recorded until [`compile_rules`](#method.compile_rules)
+/// is called. They are hashed, with the files they include, into a key. If the cache directory
+/// has rules for this key, they are loaded instead of compiled.
+///
+/// Rule files are read once, when they are added: the content hashed is the one compiled. The
+/// compiled rules are not stored if an included file changed during the compilation.
+///
+/// Entries are stored as [`Bundle`](struct.Bundle.html)s, a corrupted entry is compiled again.
+/// Concurrent compilers can share a directory, they never read a partial entry.
+///
+/// After each compilation, entries not used for `max_age` are removed, and only the
+/// `max_entries` most recently used are kept.
+pub struct CachedCompiler {
+    cache_dir: PathBuf,
+    inputs: Vec<CompilerInput>,
+    max_entries: usize,
+    max_age: Duration,
+}
+
+impl CachedCompiler {
+    pub fn new<P: AsRef<Path>>(cache_dir: P) -> Self {
+        CachedCompiler {
+            cache_dir: cache_dir.as_ref().to_path_buf(),
+            inputs: Vec::new(),
+            max_entries: 16,
+            max_age: Duration::from_secs(30 * 24 * 3600),
+        }
+    }
+
+    /// Set the number of entries kept in the cache directory. Default: 16.
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Set how long an unused entry is kept in the cache directory. Default: 30 days.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn add_rules_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Error> {
+        self.add_file(path.as_ref(), None)
+    }
+
+    pub fn add_rules_file_with_namespace<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        namespace: &str,
+    ) -> Result<(), Error> {
+        self.add_file(path.as_ref(), Some(namespace))
+    }
+
+    pub fn add_rules_str(&mut self, rule: &str) {
+        self.add_str(rule, None)
+    }
+
+    pub fn add_rules_str_with_namespace(&mut self, rule: &str, namespace: &str) {
+        self.add_str(rule, Some(namespace))
+    }
+
+    pub fn define_variable<V: Into<VariableValue>>(&mut self, identifier: &str, value: V) {
+        self.inputs.push(CompilerInput::Variable {
+            identifier: identifier.to_owned(),
+            value: value.into(),
+        });
+    }
+
+    /// The cache key of the inputs added so far, with the current content of their includes.
+    pub fn key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(CACHE_KEY_VERSION);
+        hasher.update(format!(
+            "{}.{}.{}",
+            yara_sys::YR_MAJOR_VERSION,
+            yara_sys::YR_MINOR_VERSION,
+            yara_sys::YR_MICRO_VERSION
+        ));
+        for input in &self.inputs {
+            hash_input(&mut hasher, input);
+        }
+        to_hex(&hasher.finalize())
+    }
+
+    /// Whether the cache directory has rules for the inputs added so far.
+    pub fn is_cached(&self) -> bool {
+        self.entry_path(&self.key()).is_file()
+    }
+
+    /// Load the rules from the cache, or compile and store them.
+    pub fn compile_rules(self) -> Result<Rules, Error> {
+        let key = self.key();
+        let entry = self.entry_path(&key);
+        if let Ok(file) = fs::File::open(&entry) {
+            if let Ok((rules, _)) = Rules::load_bundle(file) {
+                // Keep the entry from being evicted.
+                let _ = fs::OpenOptions::new()
+                    .write(true)
+                    .open(&entry)
+                    .and_then(|f| f.set_modified(SystemTime::now()));
+                return Ok(rules);
+            }
+        }
+
+        let mut compiler = Compiler::new()?;
+        let mut sources = Vec::new();
+        for input in &self.inputs {
+            match input {
+                CompilerInput::Str { rule, namespace } => {
+                    sources.push(SourceHash::new("<string>", rule.as_bytes()));
+                    match namespace {
+                        Some(namespace) => {
+                            compiler.add_rules_str_with_namespace(rule, namespace)?
+                        }
+                        None => compiler.add_rules_str(rule)?,
+                    }
+                }
+                CompilerInput::File {
+                    path,
+                    content,
+                    namespace,
+                } => {
+                    sources.push(SourceHash::new(&path.to_string_lossy(), content.as_bytes()));
+                    self.add_file_content(&mut compiler, path, content, namespace.as_deref())?;
+                }
+                CompilerInput::Variable { identifier, value } => {
+                    compiler.define_variable(identifier, value.clone())?
+                }
+            }
+        }
+        let mut rules = compiler.compile_rules()?;
+
+        // The rules are usable even if they could not be cached.
+        if self.key() == key {
+            let _ = self.store(&key, &mut rules, sources);
+        }
+        let _ = self.evict();
+
+        Ok(rules)
+    }
+
+    fn add_str(&mut self, rule: &str, namespace: Option<&str>) {
+        self.inputs.push(CompilerInput::Str {
+            rule: rule.to_owned(),
+            namespace: namespace.map(str::to_owned),
+        });
+    }
+
+    fn add_file(&mut self, path: &Path, namespace: Option<&str>) -> Result<(), Error> {
+        let content =
+            fs::read_to_string(path).map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile))?;
+        self.inputs.push(CompilerInput::File {
+            path: path.to_path_buf(),
+            content,
+            namespace: namespace.map(str::to_owned),
+        });
+        Ok(())
+    }
+
+    /// Add `content`, read from the file at `path`, to `compiler`.
+    ///
+    /// It is compiled from a temporary copy, named `path` so that its includes are found, since
+    /// the file may have changed since it was read.
+    fn add_file_content(
+        &self,
+        compiler: &mut Compiler,
+        path: &Path,
+        content: &str,
+        namespace: Option<&str>,
+    ) -> Result<(), Error> {
+        let to_io_error = |e| IoError::new(e, IoErrorKind::OpenRulesFile);
+
+        fs::create_dir_all(&self.cache_dir).map_err(to_io_error)?;
+        let temp_path = temp_path(&self.cache_dir.join("source"));
+        let result = fs::write(&temp_path, content)
+            .and_then(|()| fs::File::open(&temp_path))
+            .map_err(|e| to_io_error(e).into())
+            .and_then(|file| compiler.add_rules_fd(&file, path, namespace));
+        let _ = fs::remove_file(&temp_path);
+        result
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.cache_dir.join(key).with_extension(CACHE_EXTENSION)
+    }
+
+    fn store(&self, key: &str, rules: &mut Rules, sources: Vec<SourceHash>) -> Result<(), Error> {
+        let to_io_error = |e| IoError::new(e, IoErrorKind::WritingRules);
+
+        let mut data = Vec::new();
+        rules.save_bundle(&mut data, sources)?;
+        fs::create_dir_all(&self.cache_dir).map_err(to_io_error)?;
+        write_atomically(&self.entry_path(key), &data).map_err(|e| to_io_error(e).into())
+    }
+
+    /// Remove the entries unused for `max_age`, then the least recently used ones.
+    fn evict(&self) -> std::io::Result<()> {
+        let now = SystemTime::now();
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.cache_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == CACHE_EXTENSION) {
+                let modified = fs::metadata(&path)?.modified()?;
+                entries.push((modified, path));
+            }
+        }
+        entries.sort_by_key(|(modified, _)| Reverse(*modified));
+
+        for (i, (modified, path)) in entries.iter().enumerate() {
+            let too_old = now
+                .duration_since(*modified)
+                .is_ok_and(|age| age > self.max_age);
+            if i >= self.max_entries || too_old {
+                // Another process may have removed it already.
+                let _ = fs::remove_file(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hash an input of a [`CachedCompiler`], with the files it includes.
+fn hash_input(hasher: &mut Sha256, input: &CompilerInput) {
+    match input {
+        CompilerInput::Str { rule, namespace } => {
+            hash_field(hasher, b"string");
+            hash_namespace(hasher, namespace.as_deref());
+            hash_field(hasher, rule.as_bytes());
+            let current_dir = std::env::current_dir().unwrap_or_default();
+            hash_includes(hasher, rule, &current_dir, 0);
+        }
+        CompilerInput::File {
+            path,
+            content,
+            namespace,
+        } => {
+            hash_field(hasher, b"file");
+            hash_namespace(hasher, namespace.as_deref());
+            hash_field(hasher, path.to_string_lossy().as_bytes());
+            hash_field(hasher, content.as_bytes());
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            hash_includes(hasher, content, dir, 0);
+        }
+        CompilerInput::Variable { identifier, value } => {
+            hash_field(hasher, b"variable");
+            hash_field(hasher, identifier.as_bytes());
+            match value {
+                VariableValue::Boolean(b) => hash_field(hasher, &[b'b', *b as u8]),
+                VariableValue::Float(f) => {
+                    hash_field(hasher, &[&b"f"[..], &f.to_le_bytes()].concat())
+                }
+                VariableValue::Integer(i) => {
+                    hash_field(hasher, &[&b"i"[..], &i.to_le_bytes()].concat())
+                }
+                VariableValue::String(s) => hash_field(hasher, &[b"s", s.as_bytes()].concat()),
+            }
+        }
+    }
+}
+
+/// Hash a namespace, so that no namespace and an empty one differ.
+fn hash_namespace(hasher: &mut Sha256, namespace: Option<&str>) {
+    match namespace {
+        Some(namespace) => {
+            hash_field(hasher, b"namespace");
+            hash_field(hasher, namespace.as_bytes());
+        }
+        None => hash_field(hasher, b"default namespace"),
+    }
+}
+
+/// Hash the files included by `source`, relative to `dir`.
+///
+/// A missing include is hashed by its name, the compilation reports it.
+pub(crate) fn hash_includes(hasher: &mut Sha256, source: &str, dir: &Path, depth: usize) {
+    if depth >= MAX_INCLUDE_DEPTH {
+        return;
+    }
+
+    for include in includes(source) {
+        let path = dir.join(include);
+        hash_field(hasher, b"include");
+        hash_field(hasher, path.to_string_lossy().as_bytes());
+        if let Ok(content) = fs::read_to_string(&path) {
+            hash_field(hasher, content.as_bytes());
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            hash_includes(hasher, &content, dir, depth + 1);
+        }
+    }
+}
+
+/// Hash a length-prefixed field, so that consecutive fields cannot be confused.
+pub(crate) fn hash_field(hasher: &mut Sha256, field: &[u8]) {
+    hasher.update((field.len() as u64).to_le_bytes());
+    hasher.update(field);
+}
+
+/// Write `data` to the file at `path`.
+///
+/// It is written to a temporary file next to it first, then renamed, so that readers never see
+/// a partial file, and an interrupted write keeps the previous one.
+pub(crate) fn write_atomically(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let temp_path = temp_path(path);
+    let result = fs::write(&temp_path, data).and_then(|()| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// A hidden path next to `path`, unique to this process and call.
+fn temp_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// The files included by a rule source.
+pub(crate) fn includes(source: &str) -> impl Iterator<Item = &str> {
+    source.lines().filter_map(|line| {
+        let rest = line.trim_start().strip_prefix("include")?.trim_start();
+        let rest = rest.strip_prefix('"')?;
+        rest.find('"').map(|end| &rest[..end])
+    })
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+
+use rs_yara::daemon::{self, Daemon};
+use rs_yara::verdict::VerdictCache;
+use rs_yara::{RuleSet, RuleSetWatcher, RuleSource};
+
+use super::{split_options, usage_error, Opt, EXIT_ERROR, EXIT_OK};
+
+/// `daemon [--timeout SECONDS] [--max-size BYTES] [--watch SECONDS] [--cache DIR] RULES SOCKET`
+///
+/// Loads the rules once, then serves scans on a Unix socket until killed. With `--watch`, the
+/// rules are also reloaded when their files change. With `--cache`, scans are answered from
+/// the verdict cache in `DIR` when it has the results of the same data with the same rules.
+pub fn run(args: &[String]) -> i32 {
+    let (options, operands) =
+        match split_options(args, &["--timeout", "--max-size", "--watch", "--cache"]) {
+            Ok(split) => split,
+            Err(e) => return usage_error(&e),
+        };
+    if operands.len() != 2 {
+        return usage_error("daemon expects RULES and SOCKET");
+    }
+
+    let (daemon, _watcher) = match start(options, operands[0]) {
+        Ok(started) => started,
+        Err(status) => return status,
+    };
+    let listener = match daemon::bind(operands[1]) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}: {}", operands[1], e);
+            return EXIT_ERROR;
+        }
+    };
+    match daemon.serve(listener) {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("{}: {}", operands[1], e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Load the rules of a daemon and apply the `--timeout`, `--max-size`, `--watch` and `--cache`
+/// options.
+///
+/// The rules are watched until the returned watcher is dropped.
+pub fn start(options: Vec<Opt>, rules: &str) -> Result<(Daemon, Option<RuleSetWatcher>), i32> {
+    let rule_set = match RuleSet::new(RuleSource::from_path(rules)) {
+        Ok(rule_set) => rule_set,
+        Err(e) => {
+            eprintln!("{}: {}", rules, e);
+            return Err(EXIT_ERROR);
+        }
+    };
+    rule_set.on_reload(|result| {
+        if let Err(e) = result {
+            eprintln!("reload failed: {}", e);
+        }
+    });
+    let mut daemon = Daemon::new(rule_set);
+    let mut watch = None;
+    for (option, value) in options {
+        let value = value.unwrap_or_default();
+        let invalid = || usage_error(&format!("invalid value `{}` for `{}`", value, option));
+        match option {
+            "--timeout" => match value.parse() {
+                Ok(timeout) => daemon.set_timeout(timeout),
+                Err(_) => return Err(invalid()),
+            },
+            "--max-size" => match value.parse() {
+                Ok(max_size) => daemon.set_max_size(max_size),
+                Err(_) => return Err(invalid()),
+            },
+            // A watch interval of 0 would check the rules in a busy loop.
+            "--watch" => match value.parse() {
+                Ok(seconds) if seconds > 0 => watch = Some(Duration::from_secs(seconds)),
+                _ => return Err(invalid()),
+            },
+            "--cache" => {
+                let cache = VerdictCache::new(value);
+                if let Err(e) = cache.init() {
+                    eprintln!("{}: {}", value, e);
+                    return Err(EXIT_ERROR);
+                }
+                daemon.set_verdict_cache(cache);
+            }
+            _ => return Err(usage_error(&format!("unknown option `{}`", option))),
+        }
+    }
+
+    let watcher = watch.map(|interval| daemon.rule_set().watch(interval));
+    Ok((daemon, watcher))
+}
@@ -0,0 +1,51 @@
+use std::path::PathBuf;
+
+use rs_yara::{diff, RuleSource};
+
+use super::{split_options, usage_error, EXIT_CHECK_FAILED, EXIT_ERROR, EXIT_OK};
+
+/// `diff [--json] OLD NEW CORPUS...`
+///
+/// Compares two rule sets, sources or compiled rules, on a corpus of samples. The exit code
+/// is 1 when a sample gained or lost matches.
+pub fn run(args: &[String]) -> i32 {
+    let (options, operands) = match split_options(args, &[]) {
+        Ok(split) => split,
+        Err(e) => return usage_error(&e),
+    };
+    let mut json = false;
+    for (option, _) in options {
+        match option {
+            "--json" => json = true,
+            _ => return usage_error(&format!("unknown option `{}` for diff", option)),
+        }
+    }
+    if operands.len() < 3 {
+        return usage_error("diff expects OLD and NEW rules, and a corpus");
+    }
+
+    let load = |path: &str| {
+        RuleSource::from_path(path)
+            .load()
+            .map_err(|e| eprintln!("{}: {}", path, e))
+    };
+    let (old, new) = match (load(operands[0]), load(operands[1])) {
+        (Ok(old), Ok(new)) => (old, new),
+        _ => return EXIT_ERROR,
+    };
+    let corpus: Vec<PathBuf> = operands[2..].iter().map(PathBuf::from).collect();
+
+    let report = diff::diff(&old, &new, &corpus);
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        println!("{}", report);
+    }
+    if !report.errors.is_empty() {
+        EXIT_ERROR
+    } else if !report.same_matches() {
+        EXIT_CHECK_FAILED
+    } else {
+        EXIT_OK
+    }
+}
@@ -0,0 +1,78 @@
+use std::fs;
+use std::io::{self, Read};
+
+use rs_yara::syntax;
+
+use super::{split_options, usage_error, EXIT_CHECK_FAILED, EXIT_ERROR, EXIT_OK};
+
+/// `fmt [--check] [FILE...]`
+///
+/// Formats the files in place, or stdin to stdout. With `--check`, nothing is written, the
+/// files which are not formatted are listed and the exit code is 1.
+pub fn run(args: &[String]) -> i32 {
+    let (options, files) = match split_options(args, &[]) {
+        Ok(split) => split,
+        Err(e) => return usage_error(&e),
+    };
+    let mut check = false;
+    for (option, _) in options {
+        match option {
+            "--check" => check = true,
+            _ => return usage_error(&format!("unknown option `{}` for fmt", option)),
+        }
+    }
+
+    if files.is_empty() {
+        let mut source = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut source) {
+            eprintln!("error: cannot read stdin: {}", e);
+            return EXIT_ERROR;
+        }
+        return match syntax::format(&source) {
+            Ok(formatted) if check => {
+                if formatted == source {
+                    EXIT_OK
+                } else {
+                    println!("<stdin>");
+                    EXIT_CHECK_FAILED
+                }
+            }
+            Ok(formatted) => {
+                print!("{}", formatted);
+                EXIT_OK
+            }
+            Err(e) => {
+                eprintln!("<stdin>: {}", e);
+                EXIT_ERROR
+            }
+        };
+    }
+
+    let mut status = EXIT_OK;
+    for file in files {
+        let formatted = fs::read_to_string(file)
+            .map_err(|e| e.to_string())
+            .and_then(|source| {
+                let formatted = syntax::format(&source).map_err(|e| e.to_string())?;
+                Ok((formatted != source, formatted))
+            });
+        match formatted {
+            Ok((false, _)) => {}
+            Ok((true, _)) if check => {
+                println!("{}", file);
+                status = status.max(EXIT_CHECK_FAILED);
+            }
+            Ok((true, formatted)) => {
+                if let Err(e) = fs::write(file, formatted) {
+                    eprintln!("{}: {}", file, e);
+                    status = EXIT_ERROR;
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                status = EXIT_ERROR;
+            }
+        }
+    }
+    status
+}
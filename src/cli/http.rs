@@ -0,0 +1,58 @@
+use std::net::TcpListener;
+
+use super::{daemon, split_options, usage_error, EXIT_ERROR, EXIT_OK};
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8390";
+
+/// `http [--timeout SECONDS] [--max-size BYTES] [--watch SECONDS] [--cache DIR] [--public]
+/// RULES [ADDRESS]`
+///
+/// Loads the rules once, then serves the REST API on `ADDRESS`, localhost by default, until
+/// killed. Since the API is not authenticated, an address which is not a loopback address is
+/// refused without `--public`.
+pub fn run(args: &[String]) -> i32 {
+    let (mut options, operands) =
+        match split_options(args, &["--timeout", "--max-size", "--watch", "--cache"]) {
+            Ok(split) => split,
+            Err(e) => return usage_error(&e),
+        };
+    if operands.is_empty() || operands.len() > 2 {
+        return usage_error("http expects RULES and an optional ADDRESS");
+    }
+    let options_len = options.len();
+    options.retain(|(option, _)| *option != "--public");
+    let public = options.len() != options_len;
+
+    let (daemon, _watcher) = match daemon::start(options, operands[0]) {
+        Ok(started) => started,
+        Err(status) => return status,
+    };
+    let address = operands.get(1).copied().unwrap_or(DEFAULT_ADDRESS);
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("{}: {}", address, e);
+            return EXIT_ERROR;
+        }
+    };
+    match listener.local_addr() {
+        Ok(local) if public || local.ip().is_loopback() => {}
+        Ok(_) => {
+            return usage_error(&format!(
+                "{} is not a loopback address, --public serves the API to the network",
+                address
+            ))
+        }
+        Err(e) => {
+            eprintln!("{}: {}", address, e);
+            return EXIT_ERROR;
+        }
+    }
+    match rs_yara::http::serve(&daemon, listener) {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("{}: {}", address, e);
+            EXIT_ERROR
+        }
+    }
+}
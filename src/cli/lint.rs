@@ -0,0 +1,48 @@
+use rs_yara::lint::{self, LintConfig, Severity};
+
+use super::{split_options, usage_error, EXIT_CHECK_FAILED, EXIT_ERROR, EXIT_OK};
+
+/// `lint [--config TOML] FILE...`
+///
+/// Prints the findings of the files. The exit code is 1 when a finding is an error.
+pub fn run(args: &[String]) -> i32 {
+    let (options, files) = match split_options(args, &["--config"]) {
+        Ok(split) => split,
+        Err(e) => return usage_error(&e),
+    };
+    let mut config = LintConfig::default();
+    for (option, value) in options {
+        match (option, value) {
+            ("--config", Some(path)) => match LintConfig::from_file(path) {
+                Ok(c) => config = c,
+                Err(e) => {
+                    eprintln!("{}: {}", path, e);
+                    return EXIT_ERROR;
+                }
+            },
+            _ => return usage_error(&format!("unknown option `{}` for lint", option)),
+        }
+    }
+    if files.is_empty() {
+        return usage_error("missing rules file for lint");
+    }
+
+    let mut status = EXIT_OK;
+    for file in files {
+        match lint::lint_file(file, &config) {
+            Ok(findings) => {
+                for finding in findings {
+                    println!("{}:{}", file, finding);
+                    if finding.severity == Severity::Error {
+                        status = status.max(EXIT_CHECK_FAILED);
+                    }
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                status = EXIT_ERROR;
+            }
+        }
+    }
+    status
+}
@@ -0,0 +1,105 @@
+//! The `rs_yara` command line.
+//!
+//! Exit codes: 0 on success, 1 when a check fails, 2 on usage or input errors.
+
+mod daemon;
+mod diff;
+mod fmt;
+#[cfg(feature = "http")]
+mod http;
+mod lint;
+mod scan;
+mod test;
+
+const USAGE: &str = "Usage: rs_yara <command> [options]
+
+Commands:
+  daemon [options] RULES SOCKET      Serve scans on a Unix socket
+  diff [--json] OLD NEW CORPUS...    Compare two rule sets and their matches on a corpus
+  fmt [--check] [FILE...]            Format rules files, or stdin to stdout
+  http [options] RULES [ADDRESS]     Serve scans over HTTP, with the http feature
+  lint [--config TOML] FILE...       Check rules files against a lint config
+  scan [options] RULES PATH...       Scan files, and directories recursively, with rules
+  test MANIFEST...                   Run the test cases of rules test manifests
+
+Scan options:
+  -s                                 Print the matches of the strings
+  --context N                        Print N bytes around each match
+  --view ascii|wide|hex              How matched data is printed, ascii by default
+  --json                             Print one JSON report per scanned file
+  --archives                         Scan the members of zip, gzip and tar files too
+  --decode                           Scan the base64, hex, gzip and zlib runs of files too
+  --eml                              Scan email messages part by part
+  --image                            Scan the files of docker save or OCI image tarballs
+  --info                             Report the size, hashes, entropy and type of files
+  --pcap packets|streams             Scan the TCP and UDP payloads of packet captures
+  --cache DIR                        Reuse the results of files already scanned with the rules
+  --state FILE                       Skip the files unchanged since the last scan with the rules
+  --full                             With --state, scan unchanged files too
+  --timeout SECONDS                  Timeout of each scan, 10 by default
+
+Daemon and http options:
+  --timeout SECONDS                  Timeout of each scan, 10 by default
+  --max-size BYTES                   Size limit of requests, 64 MiB by default
+  --watch SECONDS                    Reload the rules when they change, checked every SECONDS
+                                     (at least 1)
+  --cache DIR                        Reuse the results of data already scanned with the rules
+  --public                           With http, serve on an ADDRESS which is not localhost";
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_CHECK_FAILED: i32 = 1;
+pub const EXIT_ERROR: i32 = 2;
+
+pub fn run(args: &[String]) -> i32 {
+    match args.first().map(String::as_str) {
+        Some("daemon") => daemon::run(&args[1..]),
+        Some("diff") => diff::run(&args[1..]),
+        Some("fmt") => fmt::run(&args[1..]),
+        #[cfg(feature = "http")]
+        Some("http") => http::run(&args[1..]),
+        Some("lint") => lint::run(&args[1..]),
+        Some("scan") => scan::run(&args[1..]),
+        Some("test") => test::run(&args[1..]),
+        Some("-h") | Some("--help") | Some("help") => {
+            println!("{}", USAGE);
+            EXIT_OK
+        }
+        Some(command) => usage_error(&format!("unknown command `{}`", command)),
+        None => usage_error("missing command"),
+    }
+}
+
+/// Print an error and the usage.
+pub fn usage_error(message: &str) -> i32 {
+    eprintln!("error: {}\n\n{}", message, USAGE);
+    EXIT_ERROR
+}
+
+/// An option, with its value for the options in `with_value` of [`split_options`].
+pub type Opt<'a> = (&'a str, Option<&'a str>);
+
+/// Split options from other arguments. `--` ends the options.
+pub fn split_options<'a>(
+    args: &'a [String],
+    with_value: &[&str],
+) -> Result<(Vec<Opt<'a>>, Vec<&'a str>), String> {
+    let mut options = Vec::new();
+    let mut operands = Vec::new();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            operands.extend(args);
+            break;
+        } else if with_value.contains(&arg) {
+            match args.next() {
+                Some(value) => options.push((arg, Some(value))),
+                None => return Err(format!("missing value for `{}`", arg)),
+            }
+        } else if arg.starts_with('-') && arg != "-" {
+            options.push((arg, None));
+        } else {
+            operands.push(arg);
+        }
+    }
+    Ok((options, operands))
+}
@@ -0,0 +1,556 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rs_yara::archive::ArchiveScanner;
+use rs_yara::decode::{DecodeScanner, Gzip, Layer, LayerReport, Zlib};
+use rs_yara::eml;
+use rs_yara::errors::{Error, IoError, IoErrorKind, YaraError};
+use rs_yara::image::{ImageFile, ImageFileReport, ImageScanner};
+use rs_yara::incremental::{Change, FileState, ScanState, SkipReport};
+use rs_yara::pcap::{self, Mode, Payload, PayloadReport};
+use rs_yara::render::View;
+use rs_yara::report::FileReport;
+use rs_yara::verdict::VerdictCache;
+use rs_yara::walk;
+use rs_yara::{FileInfo, Match, OwnedRule, Rule, RuleSource, Rules};
+
+use super::{split_options, usage_error, EXIT_ERROR, EXIT_OK};
+
+const DEFAULT_TIMEOUT: u16 = 10;
+
+/// Files scanned between two saves of the state file, so that an interrupted scan keeps most
+/// of its work.
+const STATE_CHECKPOINT: usize = 1000;
+
+#[derive(Default)]
+struct Options {
+    strings: bool,
+    context: usize,
+    view: View,
+    json: bool,
+    archives: bool,
+    decode: bool,
+    eml: bool,
+    image: bool,
+    info: bool,
+    pcap: Option<Mode>,
+    cache: Option<PathBuf>,
+    state: Option<PathBuf>,
+    full: bool,
+    timeout: Option<u16>,
+}
+
+/// `scan [-s] [--context N] [--view VIEW] [--json] [--archives | --decode | --eml | --image |
+/// --pcap MODE] [--info] [--cache DIR] [--state FILE [--full]] [--timeout SECONDS] RULES PATH...`
+///
+/// Scans files, and the files of directories recursively. Prints the matching rules of each
+/// file, with `-s` their matches too, or with `--json` one JSON report per line. With `--info`,
+/// the size, hashes, entropy and type of whole files are added to JSON reports, and printed
+/// after the rules of matching files.
+///
+/// At most one of these modes splits files into parts, scanned and reported one by one. With
+/// `--archives`, the members of archives are scanned and reported too. With `--decode`, the
+/// base64, hex, gzip and zlib runs of files are decoded, scanned, and reported as
+/// `FILE!OFFSET:DECODER`, with the offsets of matches in the decoded data. With `--eml`, files
+/// are email messages, reported part by part as `FILE#PART`, with `:FILENAME` for attachments.
+/// With `--image`, files are `docker save` or OCI image tarballs, and the files of the image
+/// are reported as `FILE!PATH@LAYER`. With `--pcap packets` or `--pcap streams`, files are
+/// packet captures, reported payload by payload as `FILE#FLOW+OFFSET`; JSON reports carry the
+/// flow, and the offsets of matches in the stream.
+///
+/// With `--cache DIR`, files are not scanned again when the verdict cache in `DIR` has the
+/// results of their content with the same rules; archives, messages, images and captures are
+/// always scanned. With `--state FILE`, files which did not change since they were scanned with
+/// the same rules, as recorded in `FILE`, are skipped; their last verdict is printed, marked as
+/// skipped, and a summary of what was scanned and skipped is printed to stderr. The state file
+/// is saved every 1000 scanned files, and at the end. `--full` scans all the files, and records
+/// them in the state file too.
+pub fn run(args: &[String]) -> i32 {
+    let (options, operands) = match split_options(
+        args,
+        &[
+            "--context",
+            "--view",
+            "--pcap",
+            "--cache",
+            "--state",
+            "--timeout",
+        ],
+    ) {
+        Ok(split) => split,
+        Err(e) => return usage_error(&e),
+    };
+    let options = match parse_options(options) {
+        Ok(options) => options,
+        Err(e) => return usage_error(&e),
+    };
+    if operands.len() < 2 {
+        return usage_error("scan expects RULES and a path to scan");
+    }
+    let modes = [
+        options.archives,
+        options.decode,
+        options.eml,
+        options.image,
+        options.pcap.is_some(),
+    ];
+    let by_part = modes.contains(&true);
+    if modes.iter().filter(|&&mode| mode).count() > 1 {
+        return usage_error("--archives, --decode, --eml, --image and --pcap are exclusive");
+    }
+    if options.info && by_part {
+        return usage_error("--info only applies to scans of whole files");
+    }
+    if options.state.is_some() && by_part {
+        return usage_error("--state only applies to scans of whole files");
+    }
+    if options.full && options.state.is_none() {
+        return usage_error("--full expects --state");
+    }
+
+    let source = RuleSource::from_path(operands[0]);
+    // Hashed before loading, so that the rules are never older than their fingerprint.
+    let fingerprint = if options.cache.is_some() || options.state.is_some() {
+        match source.fingerprint() {
+            Ok(fingerprint) => fingerprint,
+            Err(e) => {
+                eprintln!("{}: {}", operands[0], e);
+                return EXIT_ERROR;
+            }
+        }
+    } else {
+        String::new()
+    };
+    let verdicts = match &options.cache {
+        Some(dir) => {
+            let cache = VerdictCache::new(dir);
+            if let Err(e) = cache.init() {
+                eprintln!("{}: {}", dir.display(), e);
+                return EXIT_ERROR;
+            }
+            Some(cache)
+        }
+        None => None,
+    };
+    let mut state = match &options.state {
+        Some(path) => match ScanState::load(path) {
+            Ok(state) => Some(state),
+            Err(e) => {
+                eprintln!("{}: {}", path.display(), e);
+                return EXIT_ERROR;
+            }
+        },
+        None => None,
+    };
+    let mut rules = match source.load() {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("{}: {}", operands[0], e);
+            return EXIT_ERROR;
+        }
+    };
+    rules.set_context(options.context);
+    let paths: Vec<PathBuf> = operands[1..].iter().map(PathBuf::from).collect();
+    let (files, walk_errors) = walk::files(&paths);
+
+    let timeout = options.timeout.unwrap_or(DEFAULT_TIMEOUT);
+    let mut status = EXIT_OK;
+    for (dir, e) in &walk_errors {
+        eprintln!("{}: {}", dir.display(), e);
+        status = EXIT_ERROR;
+    }
+    let archives = ArchiveScanner::new();
+    let images = ImageScanner::new();
+    let mut decoders = DecodeScanner::new();
+    decoders.add_decoder(Gzip);
+    decoders.add_decoder(Zlib);
+    let mut changes: BTreeMap<Change, usize> = BTreeMap::new();
+    let seen: HashSet<PathBuf> = files.iter().cloned().collect();
+    let mut recorded = 0;
+    for file in files {
+        // Taken before the scan, so that changes during the scan are seen by the next one.
+        let mut metadata = None;
+        if let Some(state) = &state {
+            if let Ok(file_metadata) = fs::metadata(&file) {
+                let change = state.change(&file, &file_metadata, &fingerprint);
+                *changes.entry(change).or_default() += 1;
+                if change == Change::Unchanged && !options.full {
+                    if let Some(file_state) = state.get(&file) {
+                        print_skipped(&SkipReport::new(&file, file_state, change), &options);
+                    }
+                    continue;
+                }
+                metadata = Some(file_metadata);
+            }
+        }
+
+        if options.image {
+            let result = images.scan_file(&rules, &file, timeout, |image_file, result| {
+                status = status.max(print_image_result(&file, image_file, result, &options))
+            });
+            if let Err(e) = result {
+                status = status.max(print_result::<Error>(&file, Err(e), &options));
+            }
+        } else if let Some(mode) = options.pcap {
+            let result = pcap::scan_file(&rules, &file, mode, timeout, |payload, result| {
+                status = status.max(print_payload_result(&file, payload, result, &options))
+            });
+            if let Err(e) = result {
+                status = status.max(print_result::<Error>(&file, Err(e), &options));
+            }
+        } else if options.eml {
+            let result = eml::scan_file(&rules, &file, timeout, |part, result| {
+                let mut path = format!("{}#{}", file.display(), part.id);
+                if let Some(filename) = &part.filename {
+                    path = format!("{}:{}", path, filename);
+                }
+                status = status.max(print_result(Path::new(&path), result, &options))
+            });
+            if let Err(e) = result {
+                status = status.max(print_result::<Error>(&file, Err(e), &options));
+            }
+        } else if options.decode {
+            let result = decoders.scan_file(&rules, &file, timeout, |layer, result| {
+                status = status.max(print_layer_result(&file, layer, result, &options))
+            });
+            if let Err(e) = result {
+                status = status.max(print_result::<Error>(&file, Err(e), &options));
+            }
+        } else if options.archives {
+            archives.scan_file(&rules, &file, timeout, |path, result| {
+                status = status.max(print_result(Path::new(path), result, &options))
+            });
+        } else {
+            let cache = verdicts.as_ref().map(|cache| (cache, fingerprint.as_str()));
+            let (file_status, verdict) = scan_whole(&file, &rules, cache, timeout, &options);
+            status = status.max(file_status);
+            if let (Some(state), Some(metadata), Some(verdict)) = (&mut state, metadata, verdict) {
+                state.record(file, FileState::new(&metadata, &fingerprint, verdict));
+                recorded += 1;
+                if let (0, Some(path)) = (recorded % STATE_CHECKPOINT, &options.state) {
+                    status = status.max(save_state(state, path));
+                }
+            }
+        }
+    }
+
+    if let (Some(state), Some(path)) = (&mut state, &options.state) {
+        // Forget the files removed from the scanned trees, but not the files of the directories
+        // which could not be read.
+        let unread = |file: &Path| walk_errors.iter().any(|(dir, _)| file.starts_with(dir));
+        state.retain(|file, _| {
+            seen.contains(file) || unread(file) || !paths.iter().any(|p| file.starts_with(p))
+        });
+        status = status.max(save_state(state, path));
+        print_summary(path, &changes, options.full);
+    }
+    status
+}
+
+/// Save the state of an incremental scan, return the exit status.
+fn save_state(state: &ScanState, path: &Path) -> i32 {
+    match state.save(path) {
+        Ok(()) => EXIT_OK,
+        Err(e) => {
+            eprintln!("{}: {}", path.display(), e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Print the last verdict of a file skipped by an incremental scan.
+fn print_skipped(report: &SkipReport, options: &Options) {
+    if options.json {
+        println!("{}", report.to_json());
+    } else {
+        for rule in &report.rules {
+            println!("{} {} (skipped)", rule, report.path.display());
+        }
+    }
+}
+
+/// Scan a whole file, print its result, return its exit status and the rules which matched.
+fn scan_whole(
+    file: &Path,
+    rules: &Rules,
+    cache: Option<(&VerdictCache, &str)>,
+    timeout: u16,
+    options: &Options,
+) -> (i32, Option<Vec<String>>) {
+    let identifiers = |matches: &[Rule]| matches.iter().map(|r| r.identifier.to_owned()).collect();
+    if let Some((cache, fingerprint)) = cache {
+        return scan_cached(file, rules, cache, fingerprint, timeout, options);
+    }
+    if options.info {
+        let result = rules.scan_file_with_info(file, timeout);
+        let verdict = result
+            .as_ref()
+            .ok()
+            .map(|(matches, _)| identifiers(matches));
+        (print_info_result(file, result, options), verdict)
+    } else {
+        let result = rules.scan_file(file, timeout);
+        let verdict = result.as_ref().ok().map(|matches| identifiers(matches));
+        (print_result(file, result, options), verdict)
+    }
+}
+
+/// Print what an incremental scan scanned and skipped, and why, to stderr.
+fn print_summary(state: &Path, changes: &BTreeMap<Change, usize>, full: bool) {
+    let count = |change| changes.get(&change).copied().unwrap_or(0);
+    let unchanged = count(Change::Unchanged);
+    let mut scanned = format!(
+        "{} new, {} modified, {} scanned with other rules",
+        count(Change::New),
+        count(Change::Modified),
+        count(Change::RulesChanged)
+    );
+    if full {
+        scanned = format!("{}, {} unchanged with --full", scanned, unchanged);
+    }
+    eprintln!(
+        "{}: scanned {}; skipped {} unchanged",
+        state.display(),
+        scanned,
+        if full { 0 } else { unchanged }
+    );
+}
+
+fn parse_options(options: Vec<super::Opt>) -> Result<Options, String> {
+    let mut parsed = Options::default();
+    for (option, value) in options {
+        match (option, value) {
+            ("-s", _) => parsed.strings = true,
+            ("--json", _) => parsed.json = true,
+            ("--archives", _) => parsed.archives = true,
+            ("--decode", _) => parsed.decode = true,
+            ("--eml", _) => parsed.eml = true,
+            ("--image", _) => parsed.image = true,
+            ("--info", _) => parsed.info = true,
+            ("--context", Some(value)) => {
+                parsed.context = value
+                    .parse()
+                    .map_err(|_| format!("invalid context `{}`", value))?
+            }
+            ("--view", Some(value)) => parsed.view = value.parse()?,
+            ("--pcap", Some(value)) => parsed.pcap = Some(value.parse()?),
+            ("--cache", Some(value)) => parsed.cache = Some(PathBuf::from(value)),
+            ("--state", Some(value)) => parsed.state = Some(PathBuf::from(value)),
+            ("--full", _) => parsed.full = true,
+            ("--timeout", Some(value)) => {
+                parsed.timeout = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid timeout `{}`", value))?,
+                )
+            }
+            _ => return Err(format!("unknown option `{}` for scan", option)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Print the result of the scan of a file, return its exit status.
+fn print_result<E: Into<Error>>(
+    file: &Path,
+    result: Result<Vec<Rule>, E>,
+    options: &Options,
+) -> i32 {
+    match result.map_err(Into::into) {
+        Ok(matches) if options.json => {
+            println!(
+                "{}",
+                FileReport::new(file, &matches, options.view).to_json()
+            );
+            EXIT_OK
+        }
+        Ok(matches) => {
+            print_matches(file, &matches, options);
+            EXIT_OK
+        }
+        Err(e) => {
+            if options.json {
+                println!("{}", FileReport::error(file, &e).to_json());
+            }
+            eprintln!("{}: {}", file.display(), e);
+            EXIT_ERROR
+        }
+    }
+}
+
+/// Scan a file unless the verdict cache has its results, print them, return its exit status
+/// and the rules which matched.
+fn scan_cached(
+    file: &Path,
+    rules: &Rules,
+    cache: &VerdictCache,
+    fingerprint: &str,
+    timeout: u16,
+    options: &Options,
+) -> (i32, Option<Vec<String>>) {
+    // Only `--info` needs the data in memory, the cache hashes files in a streaming pass.
+    let (verdict, info) = if options.info {
+        let data = match fs::read(file) {
+            Ok(data) => data,
+            Err(e) => {
+                let e = IoError::new(e, IoErrorKind::ReadingScanFile);
+                return (print_result::<IoError>(file, Err(e), options), None);
+            }
+        };
+        match cache.scan_mem(rules, fingerprint, &data, timeout) {
+            Ok(verdict) => (verdict, Some(FileInfo::new(&data))),
+            Err(e) => return (print_result::<YaraError>(file, Err(e), options), None),
+        }
+    } else {
+        match cache.scan_file(rules, fingerprint, file, timeout) {
+            Ok(verdict) => (verdict, None),
+            Err(e) => return (print_result::<Error>(file, Err(e), options), None),
+        }
+    };
+    let identifiers = verdict.rules.iter().map(|r| r.identifier.clone()).collect();
+    let matches: Vec<Rule> = verdict.rules.iter().map(OwnedRule::as_rule).collect();
+    let status = match info {
+        Some(info) => print_info_result(file, Ok((matches, info)), options),
+        None => print_result::<Error>(file, Ok(matches), options),
+    };
+    (status, Some(identifiers))
+}
+
+/// Print the result of the scan of a file with its identity, return its exit status.
+fn print_info_result(
+    file: &Path,
+    result: Result<(Vec<Rule>, FileInfo), Error>,
+    options: &Options,
+) -> i32 {
+    match result {
+        Ok((matches, info)) if options.json => {
+            let report = FileReport::new(file, &matches, options.view).with_info(info);
+            println!("{}", report.to_json());
+            EXIT_OK
+        }
+        Ok((matches, info)) => {
+            print_matches(file, &matches, options);
+            if !matches.is_empty() {
+                println!(
+                    "  size={} md5={} sha1={} sha256={} entropy={} type={}",
+                    info.size, info.md5, info.sha1, info.sha256, info.entropy, info.file_type
+                );
+            }
+            EXIT_OK
+        }
+        Err(e) => print_result::<Error>(file, Err(e), options),
+    }
+}
+
+/// Print the result of the scan of a file of an image, return its exit status.
+fn print_image_result(
+    image: &Path,
+    file: &ImageFile,
+    result: Result<Vec<Rule>, YaraError>,
+    options: &Options,
+) -> i32 {
+    match result {
+        Ok(matches) if options.json => {
+            println!(
+                "{}",
+                ImageFileReport::new(image, file, &matches, options.view).to_json()
+            );
+            EXIT_OK
+        }
+        result => {
+            let path = format!("{}!{}@{}", image.display(), file.path, file.layer);
+            print_result(Path::new(&path), result, options)
+        }
+    }
+}
+
+/// Print the result of the scan of a decoded layer, return its exit status.
+fn print_layer_result(
+    file: &Path,
+    layer: &Layer,
+    result: Result<Vec<Rule>, YaraError>,
+    options: &Options,
+) -> i32 {
+    match result {
+        Ok(matches) if options.json => {
+            println!(
+                "{}",
+                LayerReport::new(file, layer, &matches, options.view).to_json()
+            );
+            EXIT_OK
+        }
+        result if layer.provenance.is_empty() => print_result(file, result, options),
+        result => {
+            let path = format!("{}!{}", file.display(), layer);
+            print_result(Path::new(&path), result, options)
+        }
+    }
+}
+
+/// Print the result of the scan of a capture payload, return its exit status.
+fn print_payload_result(
+    file: &Path,
+    payload: &Payload,
+    result: Result<Vec<Rule>, YaraError>,
+    options: &Options,
+) -> i32 {
+    match result {
+        Ok(matches) if options.json => {
+            println!(
+                "{}",
+                PayloadReport::new(file, payload, &matches, options.view).to_json()
+            );
+            EXIT_OK
+        }
+        result => {
+            let path = format!("{}#{}+{}", file.display(), payload.flow, payload.offset);
+            print_result(Path::new(&path), result, options)
+        }
+    }
+}
+
+fn print_matches(file: &Path, rules: &[Rule], options: &Options) {
+    for rule in rules {
+        println!("{} {}", rule.identifier, file.display());
+        if !options.strings {
+            continue;
+        }
+        for string in &rule.strings {
+            for m in &string.matches {
+                print_match(string.identifier, m, options.view);
+            }
+        }
+    }
+}
+
+/// `0xOFFSET:$string: data`, the context around the data in brackets. Hex dumps are printed on
+/// the next lines.
+fn print_match(string: &str, m: &Match, view: View) {
+    if view == View::Hex {
+        let dump = match &m.context {
+            Some(context) => view.render(&context.bytes(), context.offset),
+            None => view.render(&m.data, m.offset),
+        };
+        println!("0x{:x}:{}:\n{}", m.offset, string, indent(&dump));
+        return;
+    }
+
+    let data = match &m.context {
+        Some(context) => format!(
+            "{}[{}]{}",
+            view.render(&context.before, context.offset),
+            view.render(&context.data, m.offset),
+            view.render(&context.after, m.offset + context.data.len())
+        ),
+        None => view.render(&m.data, m.offset),
+    };
+    println!("0x{:x}:{}: {}", m.offset, string, data);
+}
+
+fn indent(text: &str) -> String {
+    text.lines()
+        .map(|line| format!("    {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
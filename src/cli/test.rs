@@ -0,0 +1,36 @@
+use rs_yara::harness::TestManifest;
+
+use super::{split_options, usage_error, EXIT_CHECK_FAILED, EXIT_ERROR, EXIT_OK};
+
+/// `test MANIFEST...`
+///
+/// Runs the test cases of the manifests. The exit code is 1 when a test case fails.
+pub fn run(args: &[String]) -> i32 {
+    let (options, manifests) = match split_options(args, &[]) {
+        Ok(split) => split,
+        Err(e) => return usage_error(&e),
+    };
+    if let Some((option, _)) = options.first() {
+        return usage_error(&format!("unknown option `{}` for test", option));
+    }
+    if manifests.is_empty() {
+        return usage_error("missing manifest for test");
+    }
+
+    let mut status = EXIT_OK;
+    for manifest in manifests {
+        match TestManifest::from_file(manifest).and_then(|m| m.run()) {
+            Ok(report) => {
+                println!("{}\n{}", manifest, report);
+                if !report.passed() {
+                    status = status.max(EXIT_CHECK_FAILED);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}: {}", manifest, e);
+                status = EXIT_ERROR;
+            }
+        }
+    }
+    status
+}
@@ -29,7 +29,7 @@ pub fn compiler_add_string(
     compiler: *mut YR_COMPILER,
     string: &str,
     namespace: Option<&str>,
-) -> Result<(), Error> {
+) -> Result<Vec<CompileError>, Error> {
     let string = CString::new(string).unwrap();
     let namespace = namespace.map(|n| CString::new(n).unwrap());
     let mut errors = Vec::<CompileError>::new();
@@ -56,7 +56,7 @@ pub fn compiler_add_file<P: AsRef<Path>>(
     file: &File,
     path: P,
     namespace: Option<&str>,
-) -> Result<(), Error> {
+) -> Result<Vec<CompileError>, Error> {
     // TODO: Improve. WTF.
     let path = CString::new(path.as_ref().as_os_str().to_str().unwrap()).unwrap();
     let namespace = namespace.map(|n| CString::new(n).unwrap());
@@ -73,9 +73,13 @@ pub fn compiler_add_file<P: AsRef<Path>>(
     compile_result(result, errors)
 }
 
-fn compile_result(compile_result: i32, messages: Vec<CompileError>) -> Result<(), Error> {
+/// The warnings on success, the errors and warnings otherwise.
+fn compile_result(
+    compile_result: i32,
+    messages: Vec<CompileError>,
+) -> Result<Vec<CompileError>, Error> {
     if compile_result == 0 || messages.iter().all(|c| c.level != CompileErrorLevel::Error) {
-        Ok(())
+        Ok(messages)
     } else {
         Err(CompileErrors::new(messages).into())
     }
@@ -195,6 +199,7 @@ pub fn compiler_get_rules(compiler: *mut YR_COMPILER) -> Result<*mut YR_RULES, Y
 /// Yara rules compiler
 pub struct Compiler {
     inner: *mut yara_sys::YR_COMPILER,
+    warnings: Vec<CompileError>,
     _token: InitializationToken,
 }
 
@@ -205,6 +210,7 @@ impl Compiler {
 
         compiler_create().map(|inner| Compiler {
             inner,
+            warnings: Vec::new(),
             _token: token,
         })
     }
@@ -213,6 +219,7 @@ impl Compiler {
         File::open(path.as_ref())
             .map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile).into())
             .and_then(|file| compiler_add_file(self.inner, &file, path, None))
+            .map(|warnings| self.warnings.extend(warnings))
     }
 
 
@@ -224,10 +231,23 @@ impl Compiler {
         File::open(path.as_ref())
             .map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile).into())
             .and_then(|file| compiler_add_file(self.inner, &file, path, Some(namespace)))
+            .map(|warnings| self.warnings.extend(warnings))
+    }
+
+    /// Add the rules of `file`, named `path`: includes are relative to the directory of `path`,
+    /// and errors refer to it.
+    pub(crate) fn add_rules_fd<P: AsRef<Path>>(
+        &mut self,
+        file: &File,
+        path: P,
+        namespace: Option<&str>,
+    ) -> Result<(), Error> {
+        compiler_add_file(self.inner, file, path, namespace)
+            .map(|warnings| self.warnings.extend(warnings))
     }
 
     pub fn add_rules_str(&mut self, rule: &str) -> Result<(), Error> {
-        compiler_add_string(self.inner, rule, None)
+        compiler_add_string(self.inner, rule, None).map(|warnings| self.warnings.extend(warnings))
     }
 
     pub fn add_rules_str_with_namespace(
@@ -236,6 +256,12 @@ impl Compiler {
         namespace: &str,
     ) -> Result<(), Error> {
         compiler_add_string(self.inner, rule, Some(namespace))
+            .map(|warnings| self.warnings.extend(warnings))
+    }
+
+    /// The warnings of the rules added so far.
+    pub fn warnings(&self) -> &[CompileError] {
+        &self.warnings
     }
 
     
@@ -316,3 +342,57 @@ impl CompilerVariableValue for &CStr {
         compiler_define_cstr_variable(compiler, identifier, *self)
     }
 }
+
+/// An owned value of an external variable.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VariableValue {
+    Boolean(bool),
+    Float(f64),
+    Integer(i64),
+    String(String),
+}
+
+impl CompilerVariableValue for VariableValue {
+    fn add_to_compiler(
+        &self,
+        compiler: *mut yara_sys::YR_COMPILER,
+        identifier: &str,
+    ) -> Result<(), YaraError> {
+        match self {
+            VariableValue::Boolean(b) => b.add_to_compiler(compiler, identifier),
+            VariableValue::Float(f) => f.add_to_compiler(compiler, identifier),
+            VariableValue::Integer(i) => i.add_to_compiler(compiler, identifier),
+            VariableValue::String(s) => s.as_str().add_to_compiler(compiler, identifier),
+        }
+    }
+}
+
+impl From<bool> for VariableValue {
+    fn from(value: bool) -> Self {
+        VariableValue::Boolean(value)
+    }
+}
+
+impl From<f64> for VariableValue {
+    fn from(value: f64) -> Self {
+        VariableValue::Float(value)
+    }
+}
+
+impl From<i64> for VariableValue {
+    fn from(value: i64) -> Self {
+        VariableValue::Integer(value)
+    }
+}
+
+impl From<&str> for VariableValue {
+    fn from(value: &str) -> Self {
+        VariableValue::String(value.to_owned())
+    }
+}
+
+impl From<String> for VariableValue {
+    fn from(value: String) -> Self {
+        VariableValue::String(value)
+    }
+}
@@ -0,0 +1,86 @@
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Match, Rule};
+
+/// The bytes around a match, captured by scans when [`Rules::set_context`] is set.
+///
+/// [`Rules::set_context`]: crate::Rules::set_context
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct MatchContext {
+    /// Offset of the first byte of `before`.
+    pub offset: usize,
+    /// Bytes before the match.
+    pub before: Vec<u8>,
+    /// The matched bytes. Unlike [`Match::data`], they are not truncated.
+    pub data: Vec<u8>,
+    /// Bytes after the match.
+    pub after: Vec<u8>,
+}
+
+impl MatchContext {
+    /// The context of a match in `data`, which starts at offset `base` of the scanned target.
+    ///
+    /// Return `None` if the match is not in `data`.
+    pub fn from_slice(data: &[u8], base: usize, m: &Match, size: usize) -> Option<Self> {
+        let start = m.offset.checked_sub(base)?;
+        if start > data.len() {
+            return None;
+        }
+        let end = data.len().min(start + m.length);
+        let before = start.saturating_sub(size);
+
+        Some(MatchContext {
+            offset: base + before,
+            before: data[before..start].to_vec(),
+            data: data[start..end].to_vec(),
+            after: data[end..data.len().min(end + size)].to_vec(),
+        })
+    }
+
+    /// The context of a match in a file.
+    pub fn from_file(file: &File, m: &Match, size: usize) -> io::Result<Self> {
+        let start = m.offset.saturating_sub(size);
+        let mut region = vec![0; m.offset - start + m.length + size];
+        let mut read = 0;
+        while read < region.len() {
+            match file.read_at(&mut region[read..], (start + read) as u64) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        region.truncate(read);
+
+        Ok(
+            Self::from_slice(&region, start, m, size).unwrap_or_else(|| MatchContext {
+                offset: m.offset,
+                before: Vec::new(),
+                data: Vec::new(),
+                after: Vec::new(),
+            }),
+        )
+    }
+
+    /// The bytes before, of and after the match, starting at `offset`.
+    pub fn bytes(&self) -> Vec<u8> {
+        [&self.before[..], &self.data[..], &self.after[..]].concat()
+    }
+}
+
+/// Set the context of the matches of `rules`.
+pub(crate) fn add_context<F, E>(rules: &mut [Rule], mut capture: F) -> Result<(), E>
+where
+    F: FnMut(&Match) -> Result<Option<MatchContext>, E>,
+{
+    for string in rules.iter_mut().flat_map(|r| r.strings.iter_mut()) {
+        for m in &mut string.matches {
+            m.context = capture(m)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,490 @@
+//! A scanning daemon, serving loaded rules over a Unix socket.
+//!
+//! Clients send requests and get responses as frames: a 4 bytes big endian length, then the
+//! payload. A request is a JSON object with a `command`:
+//!
+//! - `{"command": "scan_path", "path": "/tmp/sample"}` scans a file readable by the daemon.
+//! - `{"command": "scan_bytes", "name": "upload"}` scans the payload of the next frame. The
+//!   optional `name` is the path of the report.
+//! - Scan requests can have a `timeout` in seconds, which can only lower the timeout of the
+//!   daemon. A timeout of 0 is no timeout: it is ignored in requests, and a daemon without
+//!   timeout uses the one of the request.
+//! - `{"command": "reload"}` reloads the rules from their source.
+//! - `{"command": "stats"}` returns the counters of the daemon.
+//!
+//! Each request gets one JSON response, with a `status`: `scanned` with the fields of a
+//! [`FileReport`], `reloaded`, `stats` with the fields of [`Stats`], or `error` with a
+//! `message`. A connection can send requests until it closes.
+//!
+//! With a [`VerdictCache`], scans of data the daemon already scanned with the same rules are
+//! answered from the cache.
+//!
+//! ```no_run
+//! use std::os::unix::net::UnixListener;
+//!
+//! use rs_yara::daemon::Daemon;
+//! use rs_yara::RuleSet;
+//!
+//! let daemon = Daemon::new(RuleSet::from_directory("rules")?);
+//! daemon.serve(UnixListener::bind("/run/rs_yara.sock").unwrap()).unwrap();
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::render::View;
+use crate::report::FileReport;
+use crate::verdict::VerdictCache;
+use crate::{yara_sys, OwnedRule, Rule, RuleSet};
+
+/// Default scan timeout, in seconds.
+pub const DEFAULT_TIMEOUT: u16 = 10;
+/// Default size limit of a frame, so of the bytes of a scan.
+pub const DEFAULT_MAX_SIZE: usize = 64 * 1024 * 1024;
+
+/// A request of a client.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    ScanPath {
+        path: PathBuf,
+        #[serde(default)]
+        timeout: Option<u16>,
+    },
+    /// The bytes to scan are the next frame.
+    ScanBytes {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        timeout: Option<u16>,
+    },
+    Reload,
+    Stats,
+}
+
+/// The response to a request.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Response {
+    Scanned(FileReport),
+    /// The rules were reloaded, `rules` is their number.
+    Reloaded {
+        rules: usize,
+    },
+    Stats(Stats),
+    Error {
+        message: String,
+    },
+}
+
+impl Response {
+    /// The response as JSON, on one line.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("response should serialize")
+    }
+}
+
+/// Counters of a [`Daemon`], since it started.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct Stats {
+    /// Number of rules currently loaded, private ones included.
+    pub rules: usize,
+    /// Completed scans, successful or not.
+    pub scans: u64,
+    /// Scans with at least one matching rule.
+    pub matched: u64,
+    /// Scans which failed.
+    pub errors: u64,
+    /// Bytes of the scanned buffers, files are not counted.
+    pub bytes: u64,
+    /// Successful reloads.
+    pub reloads: u64,
+    /// Scans answered from the verdict cache.
+    pub cached: u64,
+    /// Scans in progress.
+    pub active: u64,
+    pub uptime_seconds: u64,
+}
+
+/// Serves scans of a [`RuleSet`].
+///
+/// At most `YR_MAX_THREADS` scans run at the same time, the other ones wait for a free scan
+/// slot of libyara. Clones share the rules, the counters and the scan slots.
+#[derive(Clone)]
+pub struct Daemon {
+    shared: Arc<Shared>,
+    timeout: u16,
+    max_size: usize,
+    verdicts: Option<Arc<VerdictCache>>,
+}
+
+struct Shared {
+    rule_set: RuleSet,
+    slots: ScanSlots,
+    scans: AtomicU64,
+    matched: AtomicU64,
+    errors: AtomicU64,
+    bytes: AtomicU64,
+    reloads: AtomicU64,
+    cached: AtomicU64,
+    started: Instant,
+}
+
+impl Daemon {
+    pub fn new(rule_set: RuleSet) -> Self {
+        Daemon {
+            shared: Arc::new(Shared {
+                rule_set,
+                slots: ScanSlots::new(yara_sys::YR_MAX_THREADS as usize),
+                scans: AtomicU64::new(0),
+                matched: AtomicU64::new(0),
+                errors: AtomicU64::new(0),
+                bytes: AtomicU64::new(0),
+                reloads: AtomicU64::new(0),
+                cached: AtomicU64::new(0),
+                started: Instant::now(),
+            }),
+            timeout: DEFAULT_TIMEOUT,
+            max_size: DEFAULT_MAX_SIZE,
+            verdicts: None,
+        }
+    }
+
+    /// Set the timeout of scans, in seconds, 0 for no timeout.
+    pub fn set_timeout(&mut self, timeout: u16) {
+        self.timeout = timeout
+    }
+
+    pub fn timeout(&self) -> u16 {
+        self.timeout
+    }
+
+    /// Set the size limit of request frames.
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Consult `cache` before scanning, and store the results of scans in it.
+    pub fn set_verdict_cache(&mut self, cache: VerdictCache) {
+        self.verdicts = Some(Arc::new(cache))
+    }
+
+    pub fn verdict_cache(&self) -> Option<&VerdictCache> {
+        self.verdicts.as_deref()
+    }
+
+    pub fn rule_set(&self) -> &RuleSet {
+        &self.shared.rule_set
+    }
+
+    /// Answer a request. `data` is the frame following a `scan_bytes` request.
+    pub fn handle(&self, request: &Request, data: Option<&[u8]>) -> Response {
+        match request {
+            Request::ScanPath { path, timeout } => self.scan_path(path, *timeout),
+            Request::ScanBytes { name, timeout } => self.scan_bytes(
+                name.as_deref().unwrap_or("-"),
+                data.unwrap_or_default(),
+                *timeout,
+            ),
+            Request::Reload => self.reload(),
+            Request::Stats => Response::Stats(self.stats()),
+        }
+    }
+
+    /// Scan a file, with the current rules.
+    ///
+    /// `timeout` is capped by the timeout of the daemon.
+    pub fn scan_path(&self, path: &Path, timeout: Option<u16>) -> Response {
+        let (rules, fingerprint) = self.shared.rule_set.rules_with_fingerprint();
+        let timeout = self.scan_timeout(timeout);
+        let result = {
+            let _slot = self.shared.slots.acquire();
+            match &self.verdicts {
+                Some(verdicts) => verdicts
+                    .scan_file(&rules, &fingerprint, path, timeout)
+                    .map(|verdict| self.verdict_report(path, verdict.rules, verdict.cached)),
+                None => rules
+                    .scan_file(path, timeout)
+                    .map(|matches| FileReport::new(path, &matches, View::Ascii)),
+            }
+        };
+        self.scanned(result.map_err(|e| e.to_string()))
+    }
+
+    /// Scan bytes, with the current rules. `name` is the path of the report.
+    ///
+    /// `timeout` is capped by the timeout of the daemon.
+    pub fn scan_bytes(&self, name: &str, data: &[u8], timeout: Option<u16>) -> Response {
+        let (rules, fingerprint) = self.shared.rule_set.rules_with_fingerprint();
+        let timeout = self.scan_timeout(timeout);
+        let result = {
+            let _slot = self.shared.slots.acquire();
+            match &self.verdicts {
+                Some(verdicts) => verdicts
+                    .scan_mem(&rules, &fingerprint, data, timeout)
+                    .map(|verdict| self.verdict_report(name, verdict.rules, verdict.cached)),
+                None => rules
+                    .scan_mem(data, timeout)
+                    .map(|matches| FileReport::new(name, &matches, View::Ascii)),
+            }
+        };
+        self.shared
+            .bytes
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        self.scanned(result.map_err(|e| e.to_string()))
+    }
+
+    /// Reload the rules from their source, even if it did not change.
+    pub fn reload(&self) -> Response {
+        match self.shared.rule_set.force_reload() {
+            Ok(()) => {
+                self.shared.reloads.fetch_add(1, Ordering::Relaxed);
+                Response::Reloaded {
+                    rules: self.rule_count(),
+                }
+            }
+            Err(e) => Response::Error {
+                message: e.to_string(),
+            },
+        }
+    }
+
+    /// The number of rules, from libyara, without building their descriptions.
+    fn rule_count(&self) -> usize {
+        self.shared
+            .rule_set
+            .rules()
+            .stats()
+            .map_or(0, |stats| stats.rules as usize)
+    }
+
+    pub fn stats(&self) -> Stats {
+        let shared = &self.shared;
+        Stats {
+            rules: self.rule_count(),
+            scans: shared.scans.load(Ordering::Relaxed),
+            matched: shared.matched.load(Ordering::Relaxed),
+            errors: shared.errors.load(Ordering::Relaxed),
+            bytes: shared.bytes.load(Ordering::Relaxed),
+            reloads: shared.reloads.load(Ordering::Relaxed),
+            cached: shared.cached.load(Ordering::Relaxed),
+            active: shared.slots.active() as u64,
+            uptime_seconds: shared.started.elapsed().as_secs(),
+        }
+    }
+
+    /// Accept connections, each served by its own thread. Only return on accept errors.
+    pub fn serve(&self, listener: UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let daemon = self.clone();
+            thread::Builder::new()
+                .name("rs_yara-daemon".to_owned())
+                .spawn(move || {
+                    // A client which went away is not an error of the daemon.
+                    let _ = daemon.serve_connection(stream);
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Answer the requests of a connection, until the client closes it.
+    ///
+    /// Frames larger than the size limit are answered with an error, then the connection is
+    /// closed.
+    pub fn serve_connection(&self, stream: UnixStream) -> io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = BufWriter::new(stream);
+        loop {
+            let response = match self.read_request(&mut reader) {
+                Ok(Some(response)) => response,
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    let message = e.to_string();
+                    write_frame(
+                        &mut writer,
+                        Response::Error { message }.to_json().as_bytes(),
+                    )?;
+                    writer.flush()?;
+                    return Err(e);
+                }
+            };
+            write_frame(&mut writer, response.to_json().as_bytes())?;
+            writer.flush()?;
+        }
+    }
+
+    /// Read and answer a request. `None` at the end of the connection.
+    fn read_request<R: Read>(&self, reader: &mut R) -> io::Result<Option<Response>> {
+        let frame = match read_frame(reader, self.max_size)? {
+            Some(frame) => frame,
+            None => return Ok(None),
+        };
+        let request: Request = match serde_json::from_slice(&frame) {
+            Ok(request) => request,
+            Err(e) => {
+                return Ok(Some(Response::Error {
+                    message: format!("invalid request: {}", e),
+                }))
+            }
+        };
+        let data = match request {
+            Request::ScanBytes { .. } => {
+                Some(read_frame(reader, self.max_size)?.ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::UnexpectedEof, "missing bytes to scan")
+                })?)
+            }
+            _ => None,
+        };
+        Ok(Some(self.handle(&request, data.as_deref())))
+    }
+
+    /// The timeout of a scan, the lower of the daemon's and the request's, 0 being no timeout.
+    fn scan_timeout(&self, timeout: Option<u16>) -> u16 {
+        match timeout.filter(|&timeout| timeout > 0) {
+            Some(timeout) if self.timeout == 0 => timeout,
+            Some(timeout) => timeout.min(self.timeout),
+            None => self.timeout,
+        }
+    }
+
+    fn verdict_report<P: AsRef<Path>>(
+        &self,
+        path: P,
+        rules: Vec<OwnedRule>,
+        cached: bool,
+    ) -> FileReport {
+        if cached {
+            self.shared.cached.fetch_add(1, Ordering::Relaxed);
+        }
+        let matches: Vec<Rule> = rules.iter().map(OwnedRule::as_rule).collect();
+        FileReport::new(path.as_ref(), &matches, View::Ascii)
+    }
+
+    fn scanned(&self, result: Result<FileReport, String>) -> Response {
+        let shared = &self.shared;
+        shared.scans.fetch_add(1, Ordering::Relaxed);
+        match result {
+            Ok(report) => {
+                if !report.rules.is_empty() {
+                    shared.matched.fetch_add(1, Ordering::Relaxed);
+                }
+                Response::Scanned(report)
+            }
+            Err(message) => {
+                shared.errors.fetch_add(1, Ordering::Relaxed);
+                Response::Error { message }
+            }
+        }
+    }
+}
+
+/// Bind a socket at `path`, replacing the socket of a daemon which is not running anymore.
+///
+/// Only a socket nobody listens on is replaced: any other file at `path` is an error.
+pub fn bind<P: AsRef<Path>>(path: P) -> io::Result<UnixListener> {
+    let path = path.as_ref();
+    match UnixListener::bind(path) {
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            let stale = fs::symlink_metadata(path)
+                .map(|metadata| metadata.file_type().is_socket())
+                .unwrap_or(false)
+                && UnixStream::connect(path).is_err();
+            if !stale {
+                return Err(e);
+            }
+            fs::remove_file(path)?;
+            UnixListener::bind(path)
+        }
+        result => result,
+    }
+}
+
+/// Read a frame. `None` if the stream ends before the frame.
+pub fn read_frame<R: Read>(reader: &mut R, max_size: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut length = [0; 4];
+    match reader.read_exact(&mut length) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let length = u32::from_be_bytes(length) as usize;
+    if length > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes exceeds the limit of {}",
+                length, max_size
+            ),
+        ));
+    }
+
+    let mut frame = vec![0; length];
+    reader.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+/// Write a frame.
+pub fn write_frame<W: Write>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let length = u32::try_from(payload.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "frame is too large"))?;
+    writer.write_all(&length.to_be_bytes())?;
+    writer.write_all(payload)
+}
+
+/// A counting semaphore of the scan slots of libyara.
+struct ScanSlots {
+    active: Mutex<usize>,
+    released: Condvar,
+    size: usize,
+}
+
+struct ScanSlot<'a>(&'a ScanSlots);
+
+impl ScanSlots {
+    fn new(size: usize) -> Self {
+        ScanSlots {
+            active: Mutex::new(0),
+            released: Condvar::new(),
+            size,
+        }
+    }
+
+    fn acquire(&self) -> ScanSlot<'_> {
+        let mut active = self.active.lock().expect("mutex should not be poisoned");
+        while *active >= self.size {
+            active = self
+                .released
+                .wait(active)
+                .expect("mutex should not be poisoned");
+        }
+        *active += 1;
+        ScanSlot(self)
+    }
+
+    fn active(&self) -> usize {
+        *self.active.lock().expect("mutex should not be poisoned")
+    }
+}
+
+impl Drop for ScanSlot<'_> {
+    fn drop(&mut self) {
+        *self.0.active.lock().expect("mutex should not be poisoned") -= 1;
+        self.0.released.notify_one();
+    }
+}
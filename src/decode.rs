@@ -0,0 +1,451 @@
+//! Scans of encoded content embedded in scanned data, such as base64 blobs in scripts.
+//!
+//! A [`DecodeScanner`] scans data, then each run found and decoded by a [`Decoder`], then the
+//! runs found in the decoded data, up to a depth. Each decoded buffer comes with its
+//! provenance: the offset of the run in its parent buffer and the decoder, from the scanned
+//! data down. Match offsets are offsets in the decoded buffer.
+//!
+//! base64, base64url and hex runs are decoded by default; [`Gzip`] and [`Zlib`] streams can be
+//! added.
+//!
+//! ```
+//! use rs_yara::decode::DecodeScanner;
+//! use rs_yara::Compiler;
+//!
+//! let mut compiler = Compiler::new()?;
+//! compiler.add_rules_str(r#"rule rust { strings: $a = "I love Rust" condition: $a }"#)?;
+//! let rules = compiler.compile_rules()?;
+//!
+//! let script = b"eval(atob('SSBsb3ZlIFJ1c3QsIGRvbid0IHlvdT8='))";
+//! let mut matched = Vec::new();
+//! DecodeScanner::new().scan_mem(&rules, script, 10, |layer, result| {
+//!     for m in result.unwrap().iter().flat_map(|r| &r.strings).flat_map(|s| &s.matches) {
+//!         matched.push(format!("{} {}", layer, m.offset));
+//!     }
+//! });
+//! assert_eq!(vec!["0xb:base64 0"], matched);
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::bytes::{base64_value, decode_base64, hex_byte};
+use crate::errors::*;
+use crate::render::View;
+use crate::report::RuleReport;
+use crate::{Rule, Rules};
+
+/// A run of encoded data found by a decoder, and its decoded content.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Decoded {
+    /// The offset of the run in the searched data.
+    pub offset: usize,
+    /// The length of the run.
+    pub length: usize,
+    pub data: Vec<u8>,
+}
+
+/// Finds and decodes the runs of an encoding.
+pub trait Decoder: Send + Sync {
+    /// The name of the encoding, used in provenances.
+    fn name(&self) -> &'static str;
+
+    /// The runs of `data` in the encoding, at least `min_length` bytes long, decoded.
+    /// `max_size` bounds the size of each decoded run.
+    fn decode(&self, data: &[u8], min_length: usize, max_size: usize) -> Vec<Decoded>;
+}
+
+/// Limits of the decoding of a scanned buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Limits {
+    /// Runs shorter than this are not decoded.
+    pub min_length: usize,
+    /// Decoded data nested deeper is not decoded. The scanned data has depth 0.
+    pub max_depth: usize,
+    /// Total size of the decoded data. Decoding stops when it is reached.
+    pub max_total_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            min_length: 24,
+            max_depth: 3,
+            max_total_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// A step of a provenance: a run of its parent buffer, decoded.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+pub struct Step {
+    /// The offset of the run in the parent buffer.
+    pub offset: usize,
+    /// The length of the run in the parent buffer.
+    pub length: usize,
+    pub decoder: &'static str,
+}
+
+impl fmt::Display for Step {
+    /// `0x1a:base64`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:x}:{}", self.offset, self.decoder)
+    }
+}
+
+/// A buffer scanned: the scanned data, or data decoded from it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Layer {
+    /// The steps from the scanned data to this buffer, empty for the scanned data.
+    pub provenance: Vec<Step>,
+    pub data: Vec<u8>,
+}
+
+impl fmt::Display for Layer {
+    /// The steps separated by `!`, as in `0x1a:base64!0x0:gzip`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let steps: Vec<_> = self.provenance.iter().map(Step::to_string).collect();
+        f.write_str(&steps.join("!"))
+    }
+}
+
+/// The report of the scan of a layer. Match offsets are offsets in the decoded buffer.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct LayerReport {
+    pub path: PathBuf,
+    pub provenance: Vec<Step>,
+    pub rules: Vec<RuleReport>,
+}
+
+impl LayerReport {
+    pub fn new<P: Into<PathBuf>>(path: P, layer: &Layer, rules: &[Rule], view: View) -> Self {
+        LayerReport {
+            path: path.into(),
+            provenance: layer.provenance.clone(),
+            rules: rules.iter().map(|r| RuleReport::new(r, view)).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("layer reports are serializable")
+    }
+}
+
+/// Scans data and its decoded runs with the registered decoders.
+pub struct DecodeScanner {
+    decoders: Vec<Box<dyn Decoder>>,
+    limits: Limits,
+}
+
+impl Default for DecodeScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecodeScanner {
+    /// A scanner of base64, base64url and hex runs, with the default limits.
+    pub fn new() -> Self {
+        let mut scanner = Self::empty();
+        scanner.add_decoder(Base64);
+        scanner.add_decoder(Base64Url);
+        scanner.add_decoder(Hex);
+        scanner
+    }
+
+    /// A scanner without decoders.
+    pub fn empty() -> Self {
+        DecodeScanner {
+            decoders: Vec::new(),
+            limits: Limits::default(),
+        }
+    }
+
+    /// Add a decoder, run after the ones already added.
+    pub fn add_decoder<D: Decoder + 'static>(&mut self, decoder: D) {
+        self.decoders.push(Box::new(decoder));
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// `data`, then its decoded runs, depth first in the order of their offsets.
+    pub fn layers(&self, data: &[u8]) -> Vec<Layer> {
+        let mut layers = vec![Layer {
+            provenance: Vec::new(),
+            data: data.to_vec(),
+        }];
+        let mut remaining = self.limits.max_total_size;
+        self.visit(0, 0, &mut remaining, &mut layers);
+        layers
+    }
+
+    /// Scan `data` and its decoded runs, calling `callback` with each layer and the result of
+    /// its scan.
+    pub fn scan_mem<'r, F>(&self, rules: &'r Rules, data: &[u8], timeout: u16, mut callback: F)
+    where
+        F: FnMut(&Layer, Result<Vec<Rule<'r>>, YaraError>),
+    {
+        for layer in self.layers(data) {
+            callback(&layer, rules.scan_mem(&layer.data, timeout));
+        }
+    }
+
+    /// Scan a file and its decoded runs, see [`scan_mem`](#method.scan_mem).
+    pub fn scan_file<'r, P, F>(
+        &self,
+        rules: &'r Rules,
+        path: P,
+        timeout: u16,
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&Layer, Result<Vec<Rule<'r>>, YaraError>),
+    {
+        let data = fs::read(path).map_err(|e| IoError::new(e, IoErrorKind::ReadingScanFile))?;
+        self.scan_mem(rules, &data, timeout, callback);
+        Ok(())
+    }
+
+    /// Add the decoded runs of `layers[index]`, and theirs.
+    fn visit(&self, index: usize, depth: usize, remaining: &mut usize, layers: &mut Vec<Layer>) {
+        if depth >= self.limits.max_depth {
+            return;
+        }
+        let mut decoded = Vec::new();
+        for decoder in &self.decoders {
+            let runs = decoder.decode(&layers[index].data, self.limits.min_length, *remaining);
+            for run in runs {
+                if run.data.is_empty() || run.data.len() > *remaining {
+                    continue;
+                }
+                *remaining -= run.data.len();
+                let step = Step {
+                    offset: run.offset,
+                    length: run.length,
+                    decoder: decoder.name(),
+                };
+                decoded.push((step, run.data));
+            }
+        }
+        decoded.sort_by_key(|(step, _)| step.offset);
+
+        for (step, data) in decoded {
+            let mut provenance = layers[index].provenance.clone();
+            provenance.push(step);
+            layers.push(Layer { provenance, data });
+            self.visit(layers.len() - 1, depth + 1, remaining, layers);
+        }
+    }
+}
+
+/// Standard base64 runs, with `+` and `/`.
+pub struct Base64;
+
+impl Decoder for Base64 {
+    fn name(&self) -> &'static str {
+        "base64"
+    }
+
+    fn decode(&self, data: &[u8], min_length: usize, max_size: usize) -> Vec<Decoded> {
+        base64_runs(data, min_length, max_size, b'+', b'/')
+    }
+}
+
+/// URL-safe base64 runs, with `-` and `_`. Runs without them are left to [`Base64`].
+pub struct Base64Url;
+
+impl Decoder for Base64Url {
+    fn name(&self) -> &'static str {
+        "base64url"
+    }
+
+    fn decode(&self, data: &[u8], min_length: usize, max_size: usize) -> Vec<Decoded> {
+        let mut runs = base64_runs(data, min_length, max_size, b'-', b'_');
+        runs.retain(|run| {
+            data[run.offset..run.offset + run.length]
+                .iter()
+                .any(|&b| b == b'-' || b == b'_')
+        });
+        runs
+    }
+}
+
+/// Runs of hex digits, of even length.
+pub struct Hex;
+
+impl Decoder for Hex {
+    fn name(&self) -> &'static str {
+        "hex"
+    }
+
+    fn decode(&self, data: &[u8], min_length: usize, max_size: usize) -> Vec<Decoded> {
+        runs(data, |b| b.is_ascii_hexdigit())
+            .filter_map(|(offset, length)| {
+                let length = (length / 2 * 2).min(max_size.saturating_mul(2));
+                if length < min_length.max(2) {
+                    return None;
+                }
+                let data = data[offset..offset + length]
+                    .chunks(2)
+                    .filter_map(hex_byte)
+                    .collect();
+                Some(Decoded {
+                    offset,
+                    length,
+                    data,
+                })
+            })
+            .collect()
+    }
+}
+
+/// gzip streams.
+pub struct Gzip;
+
+impl Decoder for Gzip {
+    fn name(&self) -> &'static str {
+        "gzip"
+    }
+
+    fn decode(&self, data: &[u8], min_length: usize, max_size: usize) -> Vec<Decoded> {
+        streams(
+            data,
+            min_length,
+            max_size,
+            |d| d.starts_with(b"\x1f\x8b\x08"),
+            |input| Box::new(flate2::bufread::GzDecoder::new(input)),
+        )
+    }
+}
+
+/// zlib streams.
+pub struct Zlib;
+
+impl Decoder for Zlib {
+    fn name(&self) -> &'static str {
+        "zlib"
+    }
+
+    fn decode(&self, data: &[u8], min_length: usize, max_size: usize) -> Vec<Decoded> {
+        let header = |d: &[u8]| match d {
+            [cmf, flg, ..] => {
+                cmf & 0x0f == 8 && ((u16::from(*cmf) << 8) | u16::from(*flg)) % 31 == 0
+            }
+            _ => false,
+        };
+        streams(data, min_length, max_size, header, |input| {
+            Box::new(flate2::bufread::ZlibDecoder::new(input))
+        })
+    }
+}
+
+/// The offsets and lengths of the runs of bytes accepted by `accept`.
+fn runs<'d, A>(data: &'d [u8], accept: A) -> impl Iterator<Item = (usize, usize)> + 'd
+where
+    A: Fn(u8) -> bool + 'd,
+{
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let start = offset + data[offset..].iter().position(|&b| accept(b))?;
+        let length = data[start..]
+            .iter()
+            .position(|&b| !accept(b))
+            .unwrap_or(data.len() - start);
+        offset = start + length;
+        Some((start, length))
+    })
+}
+
+/// The base64 runs of an alphabet, with their `=` padding.
+///
+/// Words, identifiers and hex are made of the same characters, so only runs shaped like base64
+/// are decoded: whole groups of 4 characters with the padding, with upper and lower case
+/// letters and digits, and not only hex digits, which are left to [`Hex`].
+fn base64_runs(
+    data: &[u8],
+    min_length: usize,
+    max_size: usize,
+    plus: u8,
+    slash: u8,
+) -> Vec<Decoded> {
+    runs(data, |b| base64_value(b, plus, slash).is_some())
+        .filter_map(|(offset, length)| {
+            let run = &data[offset..offset + length];
+            let padding = data[offset + length..]
+                .iter()
+                .take(2)
+                .take_while(|&&b| b == b'=')
+                .count();
+            if length < min_length.max(2) || !is_base64_shaped(run, padding) {
+                return None;
+            }
+            Some(Decoded {
+                offset,
+                length: length + padding,
+                data: decode_base64(run, plus, slash, max_size),
+            })
+        })
+        .collect()
+}
+
+fn is_base64_shaped(run: &[u8], padding: usize) -> bool {
+    let has = |class: fn(&u8) -> bool| run.iter().any(class);
+    let partial_group_len = (run.len() + padding) % 4;
+    partial_group_len == 0
+        && has(u8::is_ascii_uppercase)
+        && has(u8::is_ascii_lowercase)
+        && has(u8::is_ascii_digit)
+        && !run.iter().all(u8::is_ascii_hexdigit)
+}
+
+/// The streams starting where `detect` accepts the data, decompressed.
+fn streams<D, R>(
+    data: &[u8],
+    min_length: usize,
+    max_size: usize,
+    detect: D,
+    reader: R,
+) -> Vec<Decoded>
+where
+    D: Fn(&[u8]) -> bool,
+    R: for<'a> Fn(&'a mut &[u8]) -> Box<dyn Read + 'a>,
+{
+    let mut decoded = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        if !detect(&data[offset..]) {
+            offset += 1;
+            continue;
+        }
+        let mut input = &data[offset..];
+        let mut output = Vec::new();
+        let result = reader(&mut input)
+            .take(max_size as u64)
+            .read_to_end(&mut output);
+        let length = data.len() - offset - input.len();
+        // Corrupt streams keep what was decompressed before the corruption.
+        if output.len() >= min_length || (result.is_ok() && !output.is_empty()) {
+            decoded.push(Decoded {
+                offset,
+                length,
+                data: output,
+            });
+            offset += length.max(1);
+        } else {
+            offset += 1;
+        }
+    }
+    decoded
+}
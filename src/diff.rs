@@ -0,0 +1,331 @@
+//! Compare two rule sets, before deploying a change.
+//!
+//! [`diff`] compares the rules of the two sets, then scans a corpus of samples with both and
+//! reports the samples which gained or lost matches, the rules whose hit counts changed, and
+//! the scan throughput of each set.
+//!
+//! ```
+//! use rs_yara::{diff, Compiler};
+//!
+//! let compile = |source: &str| -> Result<_, rs_yara::errors::Error> {
+//!     let mut compiler = Compiler::new()?;
+//!     compiler.add_rules_str(source)?;
+//!     Ok(compiler.compile_rules()?)
+//! };
+//! let old = compile(r#"rule rust { strings: $a = "Rust" condition: $a }"#)?;
+//! let new = compile(r#"rule rust { strings: $a = "rust" nocase condition: $a }"#)?;
+//!
+//! let report = diff::diff(&old, &new, &["tests/scanfile.txt".into()]);
+//! assert_eq!(vec!["default:rust"], report.structure.changed.iter().map(|c| &c.rule).collect::<Vec<_>>());
+//! assert!(report.new_matches.is_empty() && report.lost_matches.is_empty());
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::errors::*;
+use crate::{walk, RuleInfo, Rules};
+
+/// Timeout of the scans, in seconds.
+const TIMEOUT: u16 = 10;
+
+/// The differences between the rules of two rule sets.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct StructuralDiff {
+    /// Rules only in the new set, as `namespace:identifier`.
+    pub added: Vec<String>,
+    /// Rules only in the old set.
+    pub removed: Vec<String>,
+    pub changed: Vec<RuleChange>,
+}
+
+/// A rule in both sets, with different strings, metadata or tags.
+///
+/// Regular expressions are not kept by the compiler, a changed regular expression is only
+/// seen by the scans.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RuleChange {
+    pub rule: String,
+    pub strings: bool,
+    pub metadata: bool,
+    pub tags: bool,
+}
+
+/// A sample and the rules it gained or lost.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SampleChange {
+    pub path: PathBuf,
+    pub rules: Vec<String>,
+}
+
+/// The number of samples matched by a rule, with each set.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct HitCount {
+    pub rule: String,
+    pub old: usize,
+    pub new: usize,
+}
+
+/// Time spent scanning the corpus with each set.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Throughput {
+    /// Bytes of the scanned samples.
+    pub bytes: u64,
+    pub old_seconds: f64,
+    pub new_seconds: f64,
+}
+
+impl Throughput {
+    pub fn old_bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.old_seconds.max(f64::EPSILON)
+    }
+
+    pub fn new_bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.new_seconds.max(f64::EPSILON)
+    }
+}
+
+/// A sample which could not be scanned with one of the sets.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DiffReport {
+    pub structure: StructuralDiff,
+    /// Number of scanned samples.
+    pub samples: usize,
+    /// Samples matched by rules which did not match them with the old set.
+    pub new_matches: Vec<SampleChange>,
+    /// Samples not matched anymore by rules which matched them with the old set.
+    pub lost_matches: Vec<SampleChange>,
+    /// Rules whose number of matched samples changed.
+    pub hit_counts: Vec<HitCount>,
+    pub throughput: Throughput,
+    pub errors: Vec<ScanError>,
+}
+
+impl DiffReport {
+    /// Whether the scans of the corpus gave the same matches with both sets.
+    pub fn same_matches(&self) -> bool {
+        self.new_matches.is_empty() && self.lost_matches.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report should serialize")
+    }
+}
+
+/// Compare the rules of two sets.
+pub fn diff_rules(old: &Rules, new: &Rules) -> StructuralDiff {
+    let by_name = |rules: &Rules| -> BTreeMap<String, RuleInfo> {
+        rules
+            .get_rules()
+            .into_iter()
+            .map(|r| (format!("{}:{}", r.namespace, r.identifier), r))
+            .collect()
+    };
+    let old = by_name(old);
+    let new = by_name(new);
+
+    let mut diff = StructuralDiff::default();
+    for (name, new_rule) in &new {
+        match old.get(name) {
+            None => diff.added.push(name.clone()),
+            Some(old_rule) => {
+                let change = RuleChange {
+                    rule: name.clone(),
+                    strings: old_rule.strings != new_rule.strings,
+                    metadata: old_rule.metadatas != new_rule.metadatas,
+                    tags: old_rule.tags != new_rule.tags,
+                };
+                if change.strings || change.metadata || change.tags {
+                    diff.changed.push(change);
+                }
+            }
+        }
+    }
+    diff.removed = old
+        .keys()
+        .filter(|n| !new.contains_key(*n))
+        .cloned()
+        .collect();
+    diff
+}
+
+/// Compare two sets, and their matches on the samples of `corpus`.
+///
+/// Directories of the corpus are scanned recursively, see [`walk::files`].
+pub fn diff(old: &Rules, new: &Rules, corpus: &[PathBuf]) -> DiffReport {
+    let mut report = DiffReport {
+        structure: diff_rules(old, new),
+        ..DiffReport::default()
+    };
+    let (files, walk_errors) = walk::files(corpus);
+    for (path, e) in walk_errors {
+        report.errors.push(ScanError {
+            path,
+            message: e.to_string(),
+        });
+    }
+
+    let mut old_time = Duration::default();
+    let mut new_time = Duration::default();
+    let mut old_hits: BTreeMap<String, usize> = BTreeMap::new();
+    let mut new_hits: BTreeMap<String, usize> = BTreeMap::new();
+    for path in files {
+        let old_matches = scan(old, &path, &mut old_time);
+        let new_matches = scan(new, &path, &mut new_time);
+        let (old_matches, new_matches) = match (old_matches, new_matches) {
+            (Ok(old_matches), Ok(new_matches)) => (old_matches, new_matches),
+            (Err(e), _) | (_, Err(e)) => {
+                report.errors.push(ScanError {
+                    path,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        report.samples += 1;
+        report.throughput.bytes += fs::metadata(&path).map_or(0, |m| m.len());
+        for rule in &old_matches {
+            *old_hits.entry(rule.clone()).or_default() += 1;
+        }
+        for rule in &new_matches {
+            *new_hits.entry(rule.clone()).or_default() += 1;
+        }
+
+        let gained: Vec<_> = new_matches.difference(&old_matches).cloned().collect();
+        if !gained.is_empty() {
+            report.new_matches.push(SampleChange {
+                path: path.clone(),
+                rules: gained,
+            });
+        }
+        let lost: Vec<_> = old_matches.difference(&new_matches).cloned().collect();
+        if !lost.is_empty() {
+            report.lost_matches.push(SampleChange { path, rules: lost });
+        }
+    }
+
+    let rules: BTreeSet<_> = old_hits.keys().chain(new_hits.keys()).collect();
+    for rule in rules {
+        let old = old_hits.get(rule).copied().unwrap_or(0);
+        let new = new_hits.get(rule).copied().unwrap_or(0);
+        if old != new {
+            report.hit_counts.push(HitCount {
+                rule: rule.clone(),
+                old,
+                new,
+            });
+        }
+    }
+    report.throughput.old_seconds = old_time.as_secs_f64();
+    report.throughput.new_seconds = new_time.as_secs_f64();
+    report
+}
+
+/// The matched rules of a sample, as `namespace:identifier`.
+fn scan(rules: &Rules, path: &Path, time: &mut Duration) -> Result<BTreeSet<String>, Error> {
+    let start = Instant::now();
+    let matches = rules.scan_file(path, TIMEOUT);
+    *time += start.elapsed();
+    Ok(matches?
+        .iter()
+        .map(|r| format!("{}:{}", r.namespace, r.identifier))
+        .collect())
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let structure = &self.structure;
+        writeln!(
+            f,
+            "Rules: {} added, {} removed, {} changed",
+            structure.added.len(),
+            structure.removed.len(),
+            structure.changed.len()
+        )?;
+        for rule in &structure.added {
+            writeln!(f, "  + {}", rule)?;
+        }
+        for rule in &structure.removed {
+            writeln!(f, "  - {}", rule)?;
+        }
+        for change in &structure.changed {
+            let parts: Vec<_> = [
+                (change.strings, "strings"),
+                (change.metadata, "metadata"),
+                (change.tags, "tags"),
+            ]
+            .iter()
+            .filter(|(changed, _)| *changed)
+            .map(|(_, part)| *part)
+            .collect();
+            writeln!(f, "  ~ {} ({})", change.rule, parts.join(", "))?;
+        }
+
+        writeln!(
+            f,
+            "Samples: {} scanned, {} with new matches, {} with lost matches",
+            self.samples,
+            self.new_matches.len(),
+            self.lost_matches.len()
+        )?;
+        for change in &self.new_matches {
+            writeln!(
+                f,
+                "  + {}: {}",
+                change.path.display(),
+                change.rules.join(", ")
+            )?;
+        }
+        for change in &self.lost_matches {
+            writeln!(
+                f,
+                "  - {}: {}",
+                change.path.display(),
+                change.rules.join(", ")
+            )?;
+        }
+
+        if !self.hit_counts.is_empty() {
+            writeln!(f, "Hit counts:")?;
+            for count in &self.hit_counts {
+                writeln!(f, "  {}: {} -> {}", count.rule, count.old, count.new)?;
+            }
+        }
+
+        let throughput = &self.throughput;
+        let (old, new) = (
+            throughput.old_bytes_per_second(),
+            throughput.new_bytes_per_second(),
+        );
+        write!(
+            f,
+            "Throughput: {:.1} MB/s -> {:.1} MB/s",
+            old / 1e6,
+            new / 1e6
+        )?;
+        if throughput.bytes > 0 {
+            write!(f, " ({:+.1}%)", (new - old) / old * 100.0)?;
+        }
+
+        if !self.errors.is_empty() {
+            write!(f, "\nErrors:")?;
+            for error in &self.errors {
+                write!(f, "\n  {}: {}", error.path.display(), error.message)?;
+            }
+        }
+        Ok(())
+    }
+}
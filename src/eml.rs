@@ -0,0 +1,411 @@
+//! Scans of email messages (RFC 822 / MIME), part by part.
+//!
+//! [`parse`] splits a message into its headers and the leaf parts of its MIME tree, with their
+//! base64 or quoted-printable transfer encoding decoded. [`scan_mem`] scans each part
+//! separately. When the rules define the [`FILENAME_VARIABLE`] external variable, it is set to
+//! the file name of each attachment.
+//!
+//! ```
+//! use rs_yara::eml::{self, PartKind};
+//! use rs_yara::Compiler;
+//!
+//! let mut compiler = Compiler::new()?;
+//! compiler.define_variable(eml::FILENAME_VARIABLE, "")?;
+//! compiler.add_rules_str(r#"rule exe { condition: attachment_filename matches /\.exe$/ }"#)?;
+//! let rules = compiler.compile_rules()?;
+//!
+//! let message = b"Subject: hi\r\n\
+//! Content-Type: application/octet-stream; name=\"tool.exe\"\r\n\
+//! Content-Transfer-Encoding: base64\r\n\r\n\
+//! TVqQAA==\r\n";
+//! let mut matched = Vec::new();
+//! eml::scan_mem(&rules, message, 10, |part, result| {
+//!     assert_eq!(part.kind == PartKind::Attachment, !result.unwrap().is_empty());
+//!     matched.push(part.data.clone());
+//! });
+//! assert_eq!(b"MZ\x90\x00".to_vec(), matched[1]);
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::bytes::{decode_base64, find, hex_byte};
+use crate::errors::*;
+use crate::{Rule, Rules, VariableValue};
+
+/// The external variable set to the file name of attachments.
+pub const FILENAME_VARIABLE: &str = "attachment_filename";
+
+/// Deepest nesting of multipart entities which are split. Deeper ones are leaf parts.
+pub const MAX_NESTING: usize = 32;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartKind {
+    /// The header block of the message.
+    Headers,
+    /// A part shown in the message, as its text.
+    Body,
+    /// A part with a file name, or with an `attachment` disposition.
+    Attachment,
+}
+
+/// A part of a message.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Part {
+    /// `headers`, or the MIME part number, as `1` or `2.1`.
+    pub id: String,
+    pub kind: PartKind,
+    /// The media type, in lowercase.
+    pub content_type: String,
+    pub filename: Option<String>,
+    /// The content, with its transfer encoding decoded.
+    pub data: Vec<u8>,
+}
+
+/// The headers and the leaf parts of a message, in the order of the message.
+///
+/// Nested `message/rfc822` messages are parts, not parsed further, as are multipart entities
+/// nested more than [`MAX_NESTING`] levels deep.
+pub fn parse(message: &[u8]) -> Vec<Part> {
+    let (headers, _) = split_entity(message);
+    let mut parts = vec![Part {
+        id: "headers".to_owned(),
+        kind: PartKind::Headers,
+        content_type: "text/rfc822-headers".to_owned(),
+        filename: None,
+        data: headers.to_vec(),
+    }];
+    walk(message, "", 0, &mut parts);
+    parts
+}
+
+/// Scan the parts of a message, calling `callback` with each part and the result of its scan.
+pub fn scan_mem<'r, F>(rules: &'r Rules, message: &[u8], timeout: u16, mut callback: F)
+where
+    F: FnMut(&Part, Result<Vec<Rule<'r>>, YaraError>),
+{
+    let has_filename = rules.variables().contains(&FILENAME_VARIABLE);
+    for part in parse(message) {
+        let result = match &part.filename {
+            Some(filename) if has_filename => {
+                let variables = [(FILENAME_VARIABLE, VariableValue::from(filename.as_str()))];
+                rules.scan_mem_with_variables(&part.data, &variables, timeout)
+            }
+            _ => rules.scan_mem(&part.data, timeout),
+        };
+        callback(&part, result);
+    }
+}
+
+/// Scan the parts of a message file, see [`scan_mem`].
+pub fn scan_file<'r, P, F>(
+    rules: &'r Rules,
+    path: P,
+    timeout: u16,
+    callback: F,
+) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Part, Result<Vec<Rule<'r>>, YaraError>),
+{
+    let message = fs::read(path).map_err(|e| IoError::new(e, IoErrorKind::ReadingScanFile))?;
+    scan_mem(rules, &message, timeout, callback);
+    Ok(())
+}
+
+/// Add the leaf parts of an entity, numbered under `id`, `depth` multipart entities deep.
+fn walk(entity: &[u8], id: &str, depth: usize, parts: &mut Vec<Part>) {
+    let (header_block, body) = split_entity(entity);
+    let headers = parse_headers(header_block);
+    let (content_type, type_params) =
+        parse_value(header(&headers, "content-type").unwrap_or("text/plain"));
+
+    if content_type.starts_with("multipart/") && depth < MAX_NESTING {
+        if let Some(boundary) = param(&type_params, "boundary") {
+            for (i, child) in split_multipart(body, &boundary).into_iter().enumerate() {
+                let child_id = if id.is_empty() {
+                    (i + 1).to_string()
+                } else {
+                    format!("{}.{}", id, i + 1)
+                };
+                walk(child, &child_id, depth + 1, parts);
+            }
+            return;
+        }
+    }
+
+    let (disposition, disposition_params) =
+        parse_value(header(&headers, "content-disposition").unwrap_or(""));
+    let filename = param(&disposition_params, "filename").or_else(|| param(&type_params, "name"));
+    let kind = if disposition == "attachment" || filename.is_some() {
+        PartKind::Attachment
+    } else {
+        PartKind::Body
+    };
+    let data = match header(&headers, "content-transfer-encoding")
+        .map(|e| e.trim().to_ascii_lowercase())
+    {
+        Some(ref e) if e == "base64" => decode_base64(body, b'+', b'/', usize::MAX),
+        Some(ref e) if e == "quoted-printable" => decode_quoted_printable(body, false),
+        _ => body.to_vec(),
+    };
+    parts.push(Part {
+        id: if id.is_empty() {
+            "1".to_owned()
+        } else {
+            id.to_owned()
+        },
+        kind,
+        content_type,
+        filename,
+        data,
+    });
+}
+
+/// Split an entity into its header block and its body, at the first empty line.
+fn split_entity(entity: &[u8]) -> (&[u8], &[u8]) {
+    if entity.starts_with(b"\r\n") {
+        return (&[], &entity[2..]);
+    }
+    if entity.starts_with(b"\n") {
+        return (&[], &entity[1..]);
+    }
+    let crlf = find(entity, b"\r\n\r\n").map(|i| (i, i + 4));
+    // Only an earlier empty line matters.
+    let lf_end = crlf.map_or(entity.len(), |(_, start)| start);
+    let lf = find(&entity[..lf_end], b"\n\n").map(|i| (i, i + 2));
+    match (crlf, lf) {
+        (Some(crlf), Some(lf)) if lf.0 < crlf.0 => (&entity[..lf.0], &entity[lf.1..]),
+        (Some((end, start)), _) | (None, Some((end, start))) => (&entity[..end], &entity[start..]),
+        (None, None) => (entity, &[]),
+    }
+}
+
+/// The headers of a header block, with lowercase names and unfolded values.
+fn parse_headers(block: &[u8]) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(block).lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some(colon) = line.find(':') {
+            headers.push((
+                line[..colon].trim().to_ascii_lowercase(),
+                line[colon + 1..].trim().to_owned(),
+            ));
+        }
+    }
+    headers
+}
+
+fn header<'h>(headers: &'h [(String, String)], name: &str) -> Option<&'h str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Split a structured header value into its lowercase value and its parameters.
+fn parse_value(value: &str) -> (String, Vec<(String, String)>) {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => field.extend(chars.next()),
+            ';' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    let main = fields[0].trim().to_ascii_lowercase();
+    let params = fields[1..]
+        .iter()
+        .filter_map(|f| {
+            let eq = f.find('=')?;
+            Some((
+                f[..eq].trim().to_ascii_lowercase(),
+                f[eq + 1..].trim().to_owned(),
+            ))
+        })
+        .collect();
+    (main, params)
+}
+
+/// A parameter, decoded from RFC 2231 (`name*`, `name*0*`) or RFC 2047 encoded words.
+fn param(params: &[(String, String)], name: &str) -> Option<String> {
+    let get = |key: &str| {
+        params
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    };
+
+    if let Some(value) = get(name) {
+        return Some(decode_words(value));
+    }
+    if let Some(value) = get(&format!("{}*", name)) {
+        return Some(decode_extended(value, true));
+    }
+    let mut value = String::new();
+    let mut charset_value = Vec::new();
+    for i in 0.. {
+        if let Some(section) = get(&format!("{}*{}*", name, i)) {
+            charset_value.push((section, i == 0));
+        } else if let Some(section) = get(&format!("{}*{}", name, i)) {
+            value.push_str(section);
+        } else {
+            break;
+        }
+    }
+    if !charset_value.is_empty() {
+        let joined: String = charset_value.iter().map(|(s, _)| *s).collect();
+        value.insert_str(0, &decode_extended(&joined, true));
+    }
+    if value.is_empty() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Decode an RFC 2231 value: `charset'language'percent-encoded`.
+fn decode_extended(value: &str, with_charset: bool) -> String {
+    let (charset, encoded) = match value.splitn(3, '\'').collect::<Vec<_>>()[..] {
+        [charset, _, encoded] if with_charset => (charset, encoded),
+        _ => ("utf-8", value),
+    };
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1..i + 3).and_then(hex_byte)) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decode_charset(&decoded, charset)
+}
+
+/// Decode the RFC 2047 encoded words of a header text, `=?charset?B|Q?text?=`.
+fn decode_words(text: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = text;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let word = &rest[start + 2..];
+        let fields: Vec<&str> = word.splitn(3, '?').collect();
+        let end = match fields.get(2).and_then(|f| f.find("?=")) {
+            Some(end) if fields.len() == 3 => end,
+            _ => break,
+        };
+        let (charset, encoding, encoded) = (fields[0], fields[1], &fields[2][..end]);
+        let bytes = match encoding {
+            "B" | "b" => decode_base64(encoded.as_bytes(), b'+', b'/', usize::MAX),
+            "Q" | "q" => decode_quoted_printable(encoded.as_bytes(), true),
+            _ => break,
+        };
+        // Whitespace between encoded words is not part of the text.
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            decoded.push_str(between);
+        }
+        decoded.push_str(&decode_charset(&bytes, charset));
+        after_word = true;
+        rest = &word[charset.len() + encoding.len() + 2 + end + 2..];
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Text in a charset. Latin-1 is decoded, other charsets are read as UTF-8.
+fn decode_charset(bytes: &[u8], charset: &str) -> String {
+    match charset.to_ascii_lowercase().as_str() {
+        "iso-8859-1" | "latin1" | "windows-1252" => bytes.iter().map(|&b| b as char).collect(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// The parts of a multipart body, without the preamble and the epilogue.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let close = format!("{}--", delimiter);
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut offset = 0;
+    while offset < body.len() {
+        let line_end = find(&body[offset..], b"\n").map_or(body.len(), |i| offset + i + 1);
+        let line = body[offset..line_end].trim_ascii_end();
+        if line == delimiter.as_bytes() || line == close.as_bytes() {
+            if let Some(start) = start {
+                // The line break before the delimiter belongs to the delimiter.
+                let mut end = offset.max(start);
+                if end > start && body[end - 1] == b'\n' {
+                    end -= 1;
+                    if end > start && body[end - 1] == b'\r' {
+                        end -= 1;
+                    }
+                }
+                parts.push(&body[start..end]);
+            }
+            if line == close.as_bytes() {
+                return parts;
+            }
+            start = Some(line_end);
+        }
+        offset = line_end;
+    }
+    // An unterminated last part.
+    if let Some(start) = start {
+        if start < body.len() {
+            parts.push(&body[start..]);
+        }
+    }
+    parts
+}
+
+/// Decode quoted-printable. In headers, `_` is a space.
+fn decode_quoted_printable(data: &[u8], header: bool) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'=' if data[i + 1..].starts_with(b"\r\n") => i += 3,
+            b'=' if data[i + 1..].starts_with(b"\n") => i += 2,
+            b'=' => match data.get(i + 1..i + 3).and_then(hex_byte) {
+                Some(byte) => {
+                    decoded.push(byte);
+                    i += 3;
+                }
+                None => {
+                    decoded.push(b'=');
+                    i += 1;
+                }
+            },
+            b'_' if header => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
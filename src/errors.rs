@@ -1,5 +1,6 @@
 use std::fmt;
 
+use crate::syntax::Span;
 use crate::yara_sys;
 pub use crate::yara_sys::CompileErrorLevel;
 
@@ -21,6 +22,24 @@ pub enum Error {
     /// A rule compilation error.
     #[error("{0}")]
     Compile(#[from] CompileErrors),
+    /// An invalid rules bundle.
+    #[error("{0}")]
+    Bundle(#[from] BundleError),
+    /// A syntax error in a rules source.
+    #[error("{0}")]
+    Parse(#[from] ParseError),
+    /// An invalid configuration file.
+    #[error("{0}")]
+    Config(#[from] ConfigError),
+    /// An archive which could not be extracted.
+    #[error("{0}")]
+    Archive(#[from] ArchiveError),
+    /// A packet capture which could not be read.
+    #[error("{0}")]
+    Pcap(#[from] PcapError),
+    /// An argument out of the values accepted by a function.
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
 }
 
 #[derive(Debug, ThisError)]
@@ -45,12 +64,53 @@ impl IoError {
 pub enum IoErrorKind {
     #[error("Error while opening scan file")]
     OpenScanFile,
+    #[error("Error while reading scan file")]
+    ReadingScanFile,
+    #[error("Error while reading scan stream")]
+    ReadingScanStream,
+    #[error("Error while reading scan directory")]
+    ReadingScanDirectory,
     #[error("Error while opening rules file")]
     OpenRulesFile,
+    #[error("Error while reading rules directory")]
+    ReadingRulesDirectory,
     #[error("Error while reading rules stream")]
     ReadingRules,
     #[error("Error while writing rules stream")]
     WritingRules,
+    #[error("Error while reading config file")]
+    ReadingConfigFile,
+    #[error("Error while writing verdict cache")]
+    WritingVerdictCache,
+    #[error("Error while reading state file")]
+    ReadingStateFile,
+    #[error("Error while writing state file")]
+    WritingStateFile,
+}
+
+/// The errors found while reading a rules bundle.
+#[derive(Clone, Debug, Eq, PartialEq, ThisError)]
+pub enum BundleError {
+    #[error("Not a rules bundle")]
+    BadMagic,
+    #[error("Unsupported rules bundle version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Rules bundle is truncated")]
+    Truncated,
+    #[error("Rules bundle header is invalid")]
+    BadHeader,
+    #[error("Rules bundle header field is too long")]
+    FieldTooLong,
+    #[error("Rules bundle has an invalid signature flag or data after its signature")]
+    BadTrailer,
+    #[error("Rules bundle checksum does not match")]
+    BadChecksum,
+    #[error("Rules bundle is not signed")]
+    MissingSignature,
+    #[error("Rules bundle signature is invalid")]
+    BadSignature,
+    #[error("Rules bundle was compiled by libyara {bundle}, not by the running libyara {running}")]
+    LibyaraMismatch { bundle: String, running: String },
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq, ThisError)]
@@ -101,7 +161,7 @@ impl fmt::Display for CompileErrors {
     }
 }
 
-#[derive(Debug, ThisError)]
+#[derive(Clone, Debug, ThisError)]
 pub struct CompileError {
     pub level: CompileErrorLevel,
     pub filename: Option<String>,
@@ -125,3 +185,45 @@ impl fmt::Display for CompileError {
         write!(f, "at line {}: {}", self.line, self.message)
     }
 }
+
+/// A syntax error found by [`syntax::parse`](../syntax/fn.parse.html).
+#[derive(Clone, Debug, Eq, PartialEq, ThisError)]
+#[error("Syntax error at line {line}: {message}")]
+pub struct ParseError {
+    /// The line reported by libyara for the same error.
+    pub line: usize,
+    pub span: Span,
+    pub message: String,
+}
+
+/// An invalid TOML configuration.
+#[derive(Clone, Debug, Eq, PartialEq, ThisError)]
+#[error("Invalid config: {message}")]
+pub struct ConfigError {
+    pub message: String,
+}
+
+/// The errors found while extracting an archive.
+#[derive(Clone, Debug, Eq, PartialEq, ThisError)]
+pub enum ArchiveError {
+    #[error("Archive nesting exceeds the depth limit of {0}")]
+    TooDeep(usize),
+    #[error("Archive members exceed the size limit of {0} bytes")]
+    TooLarge(u64),
+    #[error("Archive members exceed the limit of {0} members")]
+    TooManyMembers(usize),
+    #[error("Invalid {format} archive: {message}")]
+    Invalid {
+        format: &'static str,
+        message: String,
+    },
+}
+
+/// The errors found while reading a packet capture.
+#[derive(Clone, Debug, Eq, PartialEq, ThisError)]
+pub enum PcapError {
+    #[error("Not a pcap or pcapng capture")]
+    UnknownFormat,
+    #[error("Capture truncated at offset {0}")]
+    Truncated(usize),
+}
@@ -0,0 +1,139 @@
+//! The identity of scanned data, to tell files apart beyond their path.
+//!
+//! A [`FileInfo`] holds the size of data, its MD5, SHA-1 and SHA-256 digests, its entropy and a
+//! guess of its type from its magic bytes.
+//!
+//! ```
+//! use rs_yara::FileInfo;
+//!
+//! let info = FileInfo::new(b"%PDF-1.7");
+//! assert_eq!("pdf", info.file_type);
+//! assert_eq!(8, info.size);
+//! ```
+
+use md5::Md5;
+use serde::Serialize;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::cache::to_hex;
+
+/// The identity of scanned data: its size, hashes, entropy and type.
+///
+/// See [`Rules::scan_file_with_info`](crate::Rules::scan_file_with_info).
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FileInfo {
+    pub size: u64,
+    /// Lowercase hex digests.
+    pub md5: String,
+    pub sha1: String,
+    pub sha256: String,
+    /// Shannon entropy, in bits per byte, from 0 to 8.
+    pub entropy: f64,
+    /// A guess from the magic bytes, as `elf`, `pe`, `pdf` or `zip`, else `text` or `data`.
+    pub file_type: &'static str,
+}
+
+// The entropy is never NaN.
+impl Eq for FileInfo {}
+
+impl FileInfo {
+    /// The identity of `data`, computed in one pass.
+    pub fn new(data: &[u8]) -> Self {
+        let (mut md5, mut sha1, mut sha256) = (Md5::new(), Sha1::new(), Sha256::new());
+        let mut counts = [0u64; 256];
+        for chunk in data.chunks(64 * 1024) {
+            md5.update(chunk);
+            sha1.update(chunk);
+            sha256.update(chunk);
+            for &byte in chunk {
+                counts[usize::from(byte)] += 1;
+            }
+        }
+        FileInfo {
+            size: data.len() as u64,
+            md5: to_hex(&md5.finalize()),
+            sha1: to_hex(&sha1.finalize()),
+            sha256: to_hex(&sha256.finalize()),
+            entropy: entropy(&counts, data.len()),
+            file_type: file_type(data),
+        }
+    }
+}
+
+fn entropy(counts: &[u64; 256], total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    let total = total as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum();
+    // Three decimals are enough to compare files, and keep reports stable.
+    (entropy * 1000.0).round() / 1000.0
+}
+
+/// Magic bytes at an offset, and the type they identify.
+const MAGICS: &[(usize, &[u8], &str)] = &[
+    (0, b"\x7fELF", "elf"),
+    (0, b"MZ", "pe"),
+    (0, b"\xfe\xed\xfa\xce", "macho"),
+    (0, b"\xfe\xed\xfa\xcf", "macho"),
+    (0, b"\xce\xfa\xed\xfe", "macho"),
+    (0, b"\xcf\xfa\xed\xfe", "macho"),
+    (0, b"\xca\xfe\xba\xbe", "java-class"),
+    (0, b"\0asm", "wasm"),
+    (0, b"dex\n", "dex"),
+    (0, b"%PDF-", "pdf"),
+    (0, b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1", "ole"),
+    (0, b"{\\rtf", "rtf"),
+    (0, b"PK\x03\x04", "zip"),
+    (0, b"PK\x05\x06", "zip"),
+    (0, b"\x1f\x8b", "gzip"),
+    (0, b"BZh", "bzip2"),
+    (0, b"\xfd7zXZ\0", "xz"),
+    (0, b"\x28\xb5\x2f\xfd", "zstd"),
+    (0, b"7z\xbc\xaf\x27\x1c", "7z"),
+    (0, b"Rar!\x1a\x07", "rar"),
+    (0, b"MSCF", "cab"),
+    (257, b"ustar", "tar"),
+    (0, b"\x89PNG\r\n\x1a\n", "png"),
+    (0, b"\xff\xd8\xff", "jpeg"),
+    (0, b"GIF87a", "gif"),
+    (0, b"GIF89a", "gif"),
+    (0, b"\xd4\xc3\xb2\xa1", "pcap"),
+    (0, b"\xa1\xb2\xc3\xd4", "pcap"),
+    (0, b"\x0a\x0d\x0d\x0a", "pcapng"),
+    (0, b"SQLite format 3\0", "sqlite"),
+    (0, b"#!", "script"),
+];
+
+/// The type of data from its magic bytes, else `text` for UTF-8 without control characters
+/// other than whitespace, else `data`.
+fn file_type(data: &[u8]) -> &'static str {
+    let magic = MAGICS
+        .iter()
+        .find(|(offset, magic, _)| data.get(*offset..offset + magic.len()) == Some(magic));
+    if let Some((_, _, file_type)) = magic {
+        return file_type;
+    }
+    // The start of the data is enough, up to a character cut at the end.
+    let start = &data[..data.len().min(4096)];
+    let text = match std::str::from_utf8(start) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => {
+            std::str::from_utf8(&start[..e.valid_up_to()]).unwrap()
+        }
+        Err(_) => return "data",
+    };
+    if !data.is_empty() && !text.chars().any(|c| c.is_control() && !c.is_whitespace()) {
+        "text"
+    } else {
+        "data"
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt;
+
+use serde::de::{Deserialize, Deserializer, Error as _};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+use crate::yara_sys;
+
+/// The kind of a string of a rule.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StringKind {
+    /// A text string, `"..."`.
+    Text,
+    /// A hexadecimal string, `{ ... }`.
+    Hex,
+    /// A regular expression, `/.../`.
+    Regex,
+}
+
+impl StringKind {
+    pub(crate) fn from_flags(g_flags: i32) -> Self {
+        let g_flags = g_flags as u32;
+        if g_flags & yara_sys::STRING_GFLAGS_HEXADECIMAL != 0 {
+            StringKind::Hex
+        } else if g_flags & yara_sys::STRING_GFLAGS_REGEXP != 0 {
+            StringKind::Regex
+        } else {
+            StringKind::Text
+        }
+    }
+}
+
+/// The modifiers of a string of a rule.
+///
+/// As set by libyara: strings without `wide` are `ascii`, except `xor` ones. Serialized as the
+/// list of the names of the modifiers, displayed as the names separated by spaces.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StringModifiers {
+    pub ascii: bool,
+    pub wide: bool,
+    pub nocase: bool,
+    pub fullword: bool,
+    /// The matches of the string are not reported.
+    pub private: bool,
+    pub xor: bool,
+}
+
+impl StringModifiers {
+    pub(crate) fn from_flags(g_flags: i32) -> Self {
+        let flag = |flag: u32| g_flags as u32 & flag != 0;
+        StringModifiers {
+            ascii: flag(yara_sys::STRING_GFLAGS_ASCII),
+            wide: flag(yara_sys::STRING_GFLAGS_WIDE),
+            nocase: flag(yara_sys::STRING_GFLAGS_NO_CASE),
+            fullword: flag(yara_sys::STRING_GFLAGS_FULL_WORD),
+            private: flag(yara_sys::STRING_GFLAGS_PRIVATE),
+            xor: flag(yara_sys::STRING_GFLAGS_XOR),
+        }
+    }
+
+    /// The names of the modifiers, in the order of the rule formatter.
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            (self.ascii, "ascii"),
+            (self.wide, "wide"),
+            (self.nocase, "nocase"),
+            (self.fullword, "fullword"),
+            (self.private, "private"),
+            (self.xor, "xor"),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, name)| *name)
+        .collect()
+    }
+}
+
+impl fmt::Display for StringModifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.names().join(" "))
+    }
+}
+
+impl Serialize for StringModifiers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let names = self.names();
+        let mut seq = serializer.serialize_seq(Some(names.len()))?;
+        for name in names {
+            seq.serialize_element(name)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for StringModifiers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut modifiers = StringModifiers::default();
+        for name in Vec::<String>::deserialize(deserializer)? {
+            let modifier = match name.as_str() {
+                "ascii" => &mut modifiers.ascii,
+                "wide" => &mut modifiers.wide,
+                "nocase" => &mut modifiers.nocase,
+                "fullword" => &mut modifiers.fullword,
+                "private" => &mut modifiers.private,
+                "xor" => &mut modifiers.xor,
+                _ => return Err(D::Error::custom(format!("unknown modifier `{}`", name))),
+            };
+            *modifier = true;
+        }
+        Ok(modifiers)
+    }
+}
+
+/// The flags of a rule.
+///
+/// Scans do not report private rules, but [`Rules::get_rules`](crate::Rules::get_rules) does.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RuleFlags {
+    pub private: bool,
+    pub global: bool,
+}
+
+impl RuleFlags {
+    pub(crate) fn from_flags(g_flags: i32) -> Self {
+        let flag = |flag: u32| g_flags as u32 & flag != 0;
+        RuleFlags {
+            private: flag(yara_sys::RULE_GFLAGS_PRIVATE),
+            global: flag(yara_sys::RULE_GFLAGS_GLOBAL),
+        }
+    }
+}
+
+/// The literal value of a string, when libyara keeps it: text strings, and hexadecimal
+/// strings and regular expressions without wildcards or alternatives.
+pub(crate) fn literal(string: &yara_sys::YR_STRING) -> Option<&[u8]> {
+    let g_flags = string.g_flags as u32;
+    if g_flags & yara_sys::STRING_GFLAGS_LITERAL == 0
+        || g_flags & yara_sys::STRING_GFLAGS_CHAIN_PART != 0
+        || string.get_string().is_null()
+        || string.length < 0
+    {
+        return None;
+    }
+    Some(unsafe {
+        std::slice::from_raw_parts(string.get_string() as *const u8, string.length as usize)
+    })
+}
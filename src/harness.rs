@@ -0,0 +1,401 @@
+//! A test harness for rules.
+//!
+//! A manifest lists rules files and test cases: a sample file or inline bytes, with the rules
+//! expected to match or not, and the offsets expected for some strings. Paths are relative to
+//! the manifest.
+//!
+//! ```toml
+//! rules = ["rules/rust.yar"]
+//!
+//! [[test]]
+//! name = "rust sample"
+//! file = "samples/rust.txt"
+//! matches = ["is_awesome"]
+//! not-matches = ["is_ok"]
+//! strings = [{ rule = "is_awesome", string = "$rust", offsets = [7] }]
+//!
+//! [[test]]
+//! name = "mz header"
+//! hex = "4d5a9000"
+//! not-matches = ["is_awesome"]
+//! ```
+//!
+//! Test cases can also be written in code, to check rules from `cargo test`:
+//!
+//! ```
+//! use rs_yara::harness::TestCase;
+//! use rs_yara::Compiler;
+//!
+//! let mut compiler = Compiler::new()?;
+//! compiler.add_rules_str(r#"rule is_awesome { strings: $rust = "Rust" condition: $rust }"#)?;
+//! let rules = compiler.compile_rules()?;
+//!
+//! let tests = vec![
+//!     TestCase::data("rust", "I love Rust!")
+//!         .matches(&["is_awesome"])
+//!         .string_at("is_awesome", "$rust", 7),
+//!     TestCase::data("go", "I love Go!").not_matches(&["is_awesome"]),
+//! ];
+//! rs_yara::harness::run_tests(&rules, &tests).assert_passed();
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::errors::*;
+use crate::{Compiler, Rule, Rules};
+
+/// Timeout of the scans, in seconds.
+const DEFAULT_TIMEOUT: u16 = 10;
+
+/// Rules files and their test cases.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestManifest {
+    pub rules: Vec<PathBuf>,
+    pub tests: Vec<TestCase>,
+    /// Timeout of each scan, in seconds.
+    pub timeout: u16,
+}
+
+/// What is scanned by a test case.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Fixture {
+    File(PathBuf),
+    Data(Vec<u8>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    pub fixture: Fixture,
+    /// Rules which must match.
+    pub matches: Vec<String>,
+    /// Rules which must not match.
+    pub not_matches: Vec<String>,
+    pub strings: Vec<StringExpectation>,
+}
+
+/// Offsets where a string of a rule must match.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StringExpectation {
+    pub rule: String,
+    /// Name of the string, with the `$`.
+    pub string: String,
+    pub offsets: Vec<usize>,
+}
+
+impl TestCase {
+    pub fn file<P: Into<PathBuf>>(name: &str, path: P) -> Self {
+        Self::new(name, Fixture::File(path.into()))
+    }
+
+    pub fn data<D: AsRef<[u8]>>(name: &str, data: D) -> Self {
+        Self::new(name, Fixture::Data(data.as_ref().to_vec()))
+    }
+
+    fn new(name: &str, fixture: Fixture) -> Self {
+        TestCase {
+            name: name.to_owned(),
+            fixture,
+            matches: Vec::new(),
+            not_matches: Vec::new(),
+            strings: Vec::new(),
+        }
+    }
+
+    pub fn matches(mut self, rules: &[&str]) -> Self {
+        self.matches.extend(rules.iter().map(|&r| r.to_owned()));
+        self
+    }
+
+    pub fn not_matches(mut self, rules: &[&str]) -> Self {
+        self.not_matches.extend(rules.iter().map(|&r| r.to_owned()));
+        self
+    }
+
+    /// Expect a match of `string` of `rule` at `offset`.
+    pub fn string_at(mut self, rule: &str, string: &str, offset: usize) -> Self {
+        match self
+            .strings
+            .iter_mut()
+            .find(|s| s.rule == rule && s.string == string)
+        {
+            Some(expectation) => expectation.offsets.push(offset),
+            None => self.strings.push(StringExpectation {
+                rule: rule.to_owned(),
+                string: string.to_owned(),
+                offsets: vec![offset],
+            }),
+        }
+        self
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    rules: Vec<PathBuf>,
+    #[serde(default)]
+    timeout: Option<u16>,
+    #[serde(default, rename = "test")]
+    tests: Vec<RawTestCase>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct RawTestCase {
+    name: String,
+    file: Option<PathBuf>,
+    data: Option<String>,
+    hex: Option<String>,
+    #[serde(default)]
+    matches: Vec<String>,
+    #[serde(default)]
+    not_matches: Vec<String>,
+    #[serde(default)]
+    strings: Vec<StringExpectation>,
+}
+
+impl TestManifest {
+    /// Parse a manifest. Paths are kept as written.
+    pub fn from_toml(manifest: &str) -> Result<Self, ConfigError> {
+        let error = |message: String| ConfigError { message };
+        let raw: RawManifest = toml::from_str(manifest).map_err(|e| error(e.to_string()))?;
+
+        let mut tests = Vec::with_capacity(raw.tests.len());
+        for test in raw.tests {
+            let name = &test.name;
+            let fixture = match (test.file, test.data, test.hex) {
+                (Some(file), None, None) => Fixture::File(file),
+                (None, Some(data), None) => Fixture::Data(data.into_bytes()),
+                (None, None, Some(hex)) => Fixture::Data(
+                    parse_hex(&hex)
+                        .ok_or_else(|| error(format!("test {}: invalid hex data", name)))?,
+                ),
+                _ => {
+                    return Err(error(format!(
+                        "test {}: expected one of file, data or hex",
+                        name
+                    )))
+                }
+            };
+            tests.push(TestCase {
+                name: test.name,
+                fixture,
+                matches: test.matches,
+                not_matches: test.not_matches,
+                strings: test.strings,
+            });
+        }
+
+        Ok(TestManifest {
+            rules: raw.rules,
+            tests,
+            timeout: raw.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        })
+    }
+
+    /// Read a manifest, with paths relative to its directory.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let manifest = fs::read_to_string(path.as_ref())
+            .map_err(|e| IoError::new(e, IoErrorKind::ReadingConfigFile))?;
+        let mut manifest = Self::from_toml(&manifest)?;
+
+        let dir = path.as_ref().parent().unwrap_or_else(|| Path::new(""));
+        for rules in &mut manifest.rules {
+            *rules = dir.join(&*rules);
+        }
+        for test in &mut manifest.tests {
+            if let Fixture::File(file) = &mut test.fixture {
+                *file = dir.join(&*file);
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// Compile the rules and run the test cases.
+    pub fn run(&self) -> Result<TestReport, Error> {
+        let mut compiler = Compiler::new()?;
+        for rules in &self.rules {
+            compiler.add_rules_file(rules)?;
+        }
+        let rules = compiler.compile_rules()?;
+        Ok(run_tests_with_timeout(&rules, &self.tests, self.timeout))
+    }
+}
+
+/// Run test cases on compiled rules.
+pub fn run_tests(rules: &Rules, tests: &[TestCase]) -> TestReport {
+    run_tests_with_timeout(rules, tests, DEFAULT_TIMEOUT)
+}
+
+fn run_tests_with_timeout(rules: &Rules, tests: &[TestCase], timeout: u16) -> TestReport {
+    let results = tests
+        .iter()
+        .map(|test| {
+            let scanned = match &test.fixture {
+                Fixture::File(path) => rules.scan_file(path, timeout),
+                Fixture::Data(data) => rules.scan_mem(data, timeout).map_err(Error::from),
+            };
+            let mismatches = match scanned {
+                Ok(matches) => check(test, &matches),
+                Err(e) => vec![Mismatch::Scan(e.to_string())],
+            };
+            TestResult {
+                name: test.name.clone(),
+                mismatches,
+            }
+        })
+        .collect();
+    TestReport { results }
+}
+
+fn check(test: &TestCase, matches: &[Rule]) -> Vec<Mismatch> {
+    let find = |name: &str| matches.iter().find(|r| r.identifier == name);
+    let mut mismatches = Vec::new();
+
+    for rule in &test.matches {
+        if find(rule).is_none() {
+            mismatches.push(Mismatch::NotMatched { rule: rule.clone() });
+        }
+    }
+    for rule in &test.not_matches {
+        if find(rule).is_some() {
+            mismatches.push(Mismatch::UnexpectedMatch { rule: rule.clone() });
+        }
+    }
+    for expectation in &test.strings {
+        let actual: Vec<usize> = find(&expectation.rule)
+            .and_then(|r| {
+                r.strings
+                    .iter()
+                    .find(|s| s.identifier == expectation.string)
+            })
+            .map(|s| s.matches.iter().map(|m| m.offset).collect())
+            .unwrap_or_default();
+        for &offset in &expectation.offsets {
+            if !actual.contains(&offset) {
+                mismatches.push(Mismatch::MissingOffset {
+                    rule: expectation.rule.clone(),
+                    string: expectation.string.clone(),
+                    offset,
+                    actual: actual.clone(),
+                });
+            }
+        }
+    }
+    mismatches
+}
+
+/// A difference between the expected and the actual result of a test case.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Mismatch {
+    NotMatched {
+        rule: String,
+    },
+    UnexpectedMatch {
+        rule: String,
+    },
+    MissingOffset {
+        rule: String,
+        string: String,
+        offset: usize,
+        /// The offsets of the matches of the string.
+        actual: Vec<usize>,
+    },
+    /// The fixture could not be scanned.
+    Scan(String),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::NotMatched { rule } => write!(f, "rule {} did not match", rule),
+            Mismatch::UnexpectedMatch { rule } => write!(f, "rule {} matched", rule),
+            Mismatch::MissingOffset {
+                rule,
+                string,
+                offset,
+                actual,
+            } => {
+                write!(f, "{} of rule {} did not match at {}", string, rule, offset)?;
+                if actual.is_empty() {
+                    write!(f, " (no match)")
+                } else {
+                    let actual: Vec<_> = actual.iter().map(usize::to_string).collect();
+                    write!(f, " (matched at {})", actual.join(", "))
+                }
+            }
+            Mismatch::Scan(error) => write!(f, "scan failed: {}", error),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(TestResult::passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+        self.results.iter().filter(|r| !r.passed())
+    }
+
+    /// Panic with the mismatches if a test case failed.
+    pub fn assert_passed(&self) {
+        if !self.passed() {
+            panic!("Rule tests failed:\n{}", self);
+        }
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in &self.results {
+            let status = if result.passed() { "ok" } else { "FAILED" };
+            writeln!(f, "test {} ... {}", result.name, status)?;
+            for mismatch in &result.mismatches {
+                writeln!(f, "    {}", mismatch)?;
+            }
+        }
+        let failed = self.failures().count();
+        write!(
+            f,
+            "{} passed, {} failed",
+            self.results.len() - failed,
+            failed
+        )
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = hex.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    let pairs = digits.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+    pairs
+        .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,402 @@
+//! A REST API over a [`Daemon`], enabled by the `http` feature.
+//!
+//! - `POST /scan` scans the request body. A `multipart/form-data` body is scanned part by part,
+//!   and answered with an array of responses. The `name` query parameter is the path of the
+//!   report of a raw body.
+//! - `POST /scan/path` scans the file of the JSON body `{"path": "/tmp/sample"}`.
+//! - `GET /rules` lists the loaded rules, with their strings.
+//! - `POST /reload` reloads the rules.
+//! - `GET /metrics` returns the counters of the daemon in the Prometheus text format.
+//!
+//! Responses are the JSON [`Response`]s of the Unix socket protocol. Scans accept a `timeout`
+//! query parameter, in seconds, at least 1 and capped by the timeout of the daemon. Bodies
+//! larger than the size limit of the daemon are rejected with 413.
+//!
+//! The API is not authenticated, and `POST /scan/path` reads any file the server can read: it
+//! is meant to listen on localhost.
+//!
+//! ```no_run
+//! use std::net::TcpListener;
+//!
+//! use rs_yara::daemon::Daemon;
+//! use rs_yara::RuleSet;
+//!
+//! let daemon = Daemon::new(RuleSet::from_directory("rules")?);
+//! rs_yara::http::serve(&daemon, TcpListener::bind("127.0.0.1:8390").unwrap()).unwrap();
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Read};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Server};
+
+use crate::bytes::find;
+use crate::daemon::{Daemon, Response};
+use crate::{OwnedMetadata, RuleFlags, RuleInfo, StringKind, StringModifiers};
+
+/// A rule of the catalogue of `GET /rules`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RuleEntry {
+    pub identifier: String,
+    pub namespace: String,
+    pub tags: Vec<String>,
+    pub metadata: Vec<OwnedMetadata>,
+    pub flags: RuleFlags,
+    pub strings: Vec<StringEntry>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct StringEntry {
+    pub identifier: String,
+    pub kind: StringKind,
+    pub modifiers: StringModifiers,
+}
+
+impl From<RuleInfo> for RuleEntry {
+    fn from(rule: RuleInfo) -> Self {
+        RuleEntry {
+            identifier: rule.identifier,
+            namespace: rule.namespace,
+            tags: rule.tags,
+            metadata: rule.metadatas,
+            flags: rule.flags,
+            strings: rule
+                .strings
+                .into_iter()
+                .map(|s| StringEntry {
+                    identifier: s.identifier,
+                    kind: s.kind,
+                    modifiers: s.modifiers,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PathRequest {
+    path: PathBuf,
+}
+
+/// Counts of the answered requests, by status code.
+type Requests = Arc<Mutex<BTreeMap<u16, u64>>>;
+
+/// Serve the API of `daemon` on `listener`, each request from its own thread.
+///
+/// Only return on errors of the listener.
+pub fn serve(daemon: &Daemon, listener: TcpListener) -> io::Result<()> {
+    let server = Server::from_listener(listener, None).map_err(io::Error::other)?;
+    let requests = Requests::default();
+    loop {
+        let request = server.recv()?;
+        let daemon = daemon.clone();
+        let requests = Arc::clone(&requests);
+        thread::Builder::new()
+            .name("rs_yara-http".to_owned())
+            .spawn(move || {
+                let mut request = request;
+                let (status, body, content_type) = route(&daemon, &requests, &mut request);
+                *requests
+                    .lock()
+                    .expect("mutex should not be poisoned")
+                    .entry(status)
+                    .or_default() += 1;
+                let header = Header::from_bytes("Content-Type", content_type)
+                    .expect("content type should be a valid header");
+                let response = tiny_http::Response::from_data(body)
+                    .with_status_code(status)
+                    .with_header(header);
+                // A client which went away is not an error of the server.
+                let _ = request.respond(response);
+            })?;
+    }
+}
+
+const JSON: &str = "application/json";
+
+/// Answer a request: its status, body and content type.
+fn route(
+    daemon: &Daemon,
+    requests: &Requests,
+    request: &mut tiny_http::Request,
+) -> (u16, Vec<u8>, &'static str) {
+    let url = request.url().to_owned();
+    let (path, query) = match url.find('?') {
+        Some(i) => (&url[..i], &url[i + 1..]),
+        None => (&url[..], ""),
+    };
+    let query = parse_query(query);
+    let timeout = match query.get("timeout").map(|t| t.parse()) {
+        None => None,
+        Some(Ok(timeout)) if timeout > 0 => Some(timeout),
+        Some(_) => return error(400, "invalid timeout"),
+    };
+
+    match (request.method(), path) {
+        (Method::Post, "/scan") => {
+            let content_type = header(request, "Content-Type").unwrap_or_default();
+            let body = match read_body(daemon, request) {
+                Ok(body) => body,
+                Err(e) => return e,
+            };
+            if content_type.starts_with("multipart/form-data") {
+                let parts = match multipart_parts(&content_type, &body) {
+                    Ok(parts) => parts,
+                    Err(message) => return error(400, &message),
+                };
+                let responses: Vec<_> = parts
+                    .iter()
+                    .map(|(name, data)| daemon.scan_bytes(name, data, timeout))
+                    .collect();
+                (200, to_json(&responses), JSON)
+            } else {
+                let name = query.get("name").map_or("-", String::as_str);
+                respond(daemon.scan_bytes(name, &body, timeout))
+            }
+        }
+        (Method::Post, "/scan/path") => {
+            let body = match read_body(daemon, request) {
+                Ok(body) => body,
+                Err(e) => return e,
+            };
+            match serde_json::from_slice::<PathRequest>(&body) {
+                Ok(body) => respond(daemon.scan_path(&body.path, timeout)),
+                Err(e) => error(400, &format!("invalid request: {}", e)),
+            }
+        }
+        (Method::Get, "/rules") => {
+            let rules: Vec<RuleEntry> = daemon
+                .rule_set()
+                .rules()
+                .get_rules()
+                .into_iter()
+                .map(RuleEntry::from)
+                .collect();
+            (200, to_json(&rules), JSON)
+        }
+        (Method::Post, "/reload") => match daemon.reload() {
+            response @ Response::Error { .. } => (500, to_json(&response), JSON),
+            response => respond(response),
+        },
+        (Method::Get, "/metrics") => (
+            200,
+            metrics(daemon, requests).into_bytes(),
+            "text/plain; version=0.0.4",
+        ),
+        (_, "/scan") | (_, "/scan/path") | (_, "/rules") | (_, "/reload") | (_, "/metrics") => {
+            error(405, "method not allowed")
+        }
+        _ => error(404, "not found"),
+    }
+}
+
+/// The status of a daemon response: failed scans are unprocessable.
+fn respond(response: Response) -> (u16, Vec<u8>, &'static str) {
+    let status = match response {
+        Response::Error { .. } => 422,
+        _ => 200,
+    };
+    (status, to_json(&response), JSON)
+}
+
+fn error(status: u16, message: &str) -> (u16, Vec<u8>, &'static str) {
+    let response = Response::Error {
+        message: message.to_owned(),
+    };
+    (status, to_json(&response), JSON)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Vec<u8> {
+    serde_json::to_vec(value).expect("response should serialize")
+}
+
+fn header(request: &tiny_http::Request, name: &'static str) -> Option<String> {
+    request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv(name))
+        .map(|h| h.value.as_str().to_owned())
+}
+
+/// Read the body, up to the size limit of the daemon.
+fn read_body(
+    daemon: &Daemon,
+    request: &mut tiny_http::Request,
+) -> Result<Vec<u8>, (u16, Vec<u8>, &'static str)> {
+    let too_large = || error(413, &format!("body exceeds {} bytes", daemon.max_size()));
+    if request.body_length().is_some_and(|l| l > daemon.max_size()) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    request
+        .as_reader()
+        .take(daemon.max_size() as u64 + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| error(400, &e.to_string()))?;
+    if body.len() > daemon.max_size() {
+        return Err(too_large());
+    }
+    Ok(body)
+}
+
+fn parse_query(query: &str) -> BTreeMap<String, String> {
+    query
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.find('=') {
+            Some(i) => (percent_decode(&p[..i]), percent_decode(&p[i + 1..])),
+            None => (percent_decode(p), String::new()),
+        })
+        .collect()
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The parts of a `multipart/form-data` body, named by their file name, or else field name.
+fn multipart_parts<'a>(
+    content_type: &str,
+    body: &'a [u8],
+) -> Result<Vec<(String, &'a [u8])>, String> {
+    let boundary = content_type
+        .split(';')
+        .filter_map(|p| p.trim().strip_prefix("boundary="))
+        .next()
+        .map(|b| b.trim_matches('"'))
+        .ok_or("missing multipart boundary")?;
+    let delimiter = format!("--{}", boundary);
+    let delimiter = delimiter.as_bytes();
+
+    let mut parts = Vec::new();
+    let mut rest = match find(body, delimiter) {
+        Some(i) => &body[i + delimiter.len()..],
+        None => return Err("missing multipart delimiter".to_owned()),
+    };
+    while !rest.starts_with(b"--") {
+        let rest_start = rest.strip_prefix(b"\r\n").ok_or("invalid multipart part")?;
+        let headers_end = find(rest_start, b"\r\n\r\n").ok_or("invalid multipart headers")?;
+        let headers = String::from_utf8_lossy(&rest_start[..headers_end]);
+        let content = &rest_start[headers_end + 4..];
+
+        let mut end_delimiter = b"\r\n".to_vec();
+        end_delimiter.extend_from_slice(delimiter);
+        let end = find(content, &end_delimiter).ok_or("unterminated multipart part")?;
+        parts.push((part_name(&headers), &content[..end]));
+        rest = &content[end + end_delimiter.len()..];
+    }
+    Ok(parts)
+}
+
+/// The file name, or else the field name, of the `Content-Disposition` of a part.
+fn part_name(headers: &str) -> String {
+    let disposition = headers
+        .lines()
+        .find(|l| l.to_ascii_lowercase().starts_with("content-disposition:"))
+        .unwrap_or_default();
+    let parameter = |name: &str| {
+        disposition
+            .split(';')
+            .filter_map(|p| p.trim().strip_prefix(name))
+            .map(|v| v.trim_matches('"').to_owned())
+            .next()
+    };
+    parameter("filename=")
+        .or_else(|| parameter("name="))
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+/// The counters of the daemon and of the requests, in the Prometheus text format.
+fn metrics(daemon: &Daemon, requests: &Requests) -> String {
+    let stats = daemon.stats();
+    let mut text = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: u64| {
+        let _ = writeln!(text, "# HELP rs_yara_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE rs_yara_{} {}", name, kind);
+        let _ = writeln!(text, "rs_yara_{} {}", name, value);
+    };
+    metric("rules", "gauge", "Loaded rules.", stats.rules as u64);
+    metric("scans_total", "counter", "Completed scans.", stats.scans);
+    metric(
+        "scans_matched_total",
+        "counter",
+        "Scans with matches.",
+        stats.matched,
+    );
+    metric(
+        "scan_errors_total",
+        "counter",
+        "Failed scans.",
+        stats.errors,
+    );
+    metric(
+        "scanned_bytes_total",
+        "counter",
+        "Scanned bytes.",
+        stats.bytes,
+    );
+    metric(
+        "reloads_total",
+        "counter",
+        "Successful reloads.",
+        stats.reloads,
+    );
+    metric(
+        "cached_scans_total",
+        "counter",
+        "Scans answered from the verdict cache.",
+        stats.cached,
+    );
+    metric("active_scans", "gauge", "Scans in progress.", stats.active);
+    metric(
+        "uptime_seconds",
+        "gauge",
+        "Seconds since start.",
+        stats.uptime_seconds,
+    );
+
+    text.push_str("# HELP rs_yara_http_requests_total Answered HTTP requests.\n");
+    text.push_str("# TYPE rs_yara_http_requests_total counter\n");
+    for (status, count) in requests
+        .lock()
+        .expect("mutex should not be poisoned")
+        .iter()
+    {
+        let _ = writeln!(
+            text,
+            "rs_yara_http_requests_total{{code=\"{}\"}} {}",
+            status, count
+        );
+    }
+    text
+}
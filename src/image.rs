@@ -0,0 +1,378 @@
+//! Scans of the files of container images, from `docker save` or OCI layout tarballs.
+//!
+//! The layers of the image are applied in order, with their whiteout files, to compute the
+//! filesystem of the image. Each regular file of the filesystem is then scanned, and reported
+//! with its path in the image and the digest of the layer it comes from. Only local tarballs
+//! are read, gzip-compressed or not; there is no registry access.
+//!
+//! Reading the layers counts against the [`Limits`] of archives, as with
+//! [`ArchiveScanner`](crate::archive::ArchiveScanner), with defaults sized for images. When an
+//! image exceeds them, the files read before are still scanned.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::archive::{Budget, Limits};
+use crate::errors::*;
+use crate::render::View;
+use crate::report::RuleReport;
+use crate::{Rule, Rules};
+
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// A regular file of an image.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ImageFile {
+    /// The absolute path of the file in the image.
+    pub path: String,
+    /// The digest of the layer which last wrote the file, as `sha256:…`.
+    pub layer: String,
+    pub data: Vec<u8>,
+}
+
+/// Scans the files of images.
+#[derive(Clone, Debug)]
+pub struct ImageScanner {
+    limits: Limits,
+}
+
+impl Default for ImageScanner {
+    /// A scanner with the limits of archives raised to 1,000,000 files and 4 GiB.
+    fn default() -> Self {
+        ImageScanner {
+            limits: Limits {
+                max_total_size: 4 * 1024 * 1024 * 1024,
+                max_members: 1_000_000,
+                ..Limits::default()
+            },
+        }
+    }
+}
+
+impl ImageScanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// The files of the filesystem of the first image of a tarball, sorted by path, and
+    /// whether the image could be read to its end.
+    ///
+    /// The files of an image which could not be read are the ones read before the error, from
+    /// its lower layers.
+    pub fn files(&self, tarball: &[u8]) -> (Vec<ImageFile>, Result<(), ArchiveError>) {
+        let mut filesystem: BTreeMap<String, ImageFile> = BTreeMap::new();
+        let result = self.read_files(tarball, &mut filesystem);
+        (filesystem.into_values().collect(), result)
+    }
+
+    fn read_files(
+        &self,
+        tarball: &[u8],
+        filesystem: &mut BTreeMap<String, ImageFile>,
+    ) -> Result<(), ArchiveError> {
+        let mut budget = Budget::new(self.limits);
+        let tarball = if tarball.starts_with(b"\x1f\x8b") {
+            budget.read("gzip", flate2::read::MultiGzDecoder::new(tarball))?
+        } else {
+            tarball.to_vec()
+        };
+        let blobs = read_tar(&tarball)?;
+
+        for (digest, name) in layers(&blobs)? {
+            let blob = blobs
+                .get(&name)
+                .ok_or_else(|| invalid(format!("missing layer {}", name)))?;
+            apply_layer(filesystem, &digest, blob, &mut budget)?;
+        }
+        Ok(())
+    }
+
+    /// Scan the files of an image, calling `callback` with each file and the result of its
+    /// scan. The files read before an error are scanned, then the error is returned.
+    pub fn scan_mem<'r, F>(
+        &self,
+        rules: &'r Rules,
+        tarball: &[u8],
+        timeout: u16,
+        mut callback: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(&ImageFile, Result<Vec<Rule<'r>>, YaraError>),
+    {
+        let (files, result) = self.files(tarball);
+        for file in files {
+            callback(&file, rules.scan_mem(&file.data, timeout));
+        }
+        result
+    }
+
+    /// Scan the files of an image tarball, see [`scan_mem`](#method.scan_mem).
+    pub fn scan_file<'r, P, F>(
+        &self,
+        rules: &'r Rules,
+        path: P,
+        timeout: u16,
+        callback: F,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: FnMut(&ImageFile, Result<Vec<Rule<'r>>, YaraError>),
+    {
+        let tarball = fs::read(path).map_err(|e| IoError::new(e, IoErrorKind::ReadingScanFile))?;
+        self.scan_mem(rules, &tarball, timeout, callback)?;
+        Ok(())
+    }
+}
+
+/// The report of the scan of a file of an image.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ImageFileReport {
+    /// The image tarball.
+    pub image: PathBuf,
+    pub layer: String,
+    pub path: String,
+    pub rules: Vec<RuleReport>,
+}
+
+impl ImageFileReport {
+    pub fn new<P: Into<PathBuf>>(image: P, file: &ImageFile, rules: &[Rule], view: View) -> Self {
+        ImageFileReport {
+            image: image.into(),
+            layer: file.layer.clone(),
+            path: file.path.clone(),
+            rules: rules.iter().map(|r| RuleReport::new(r, view)).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("image file reports are serializable")
+    }
+}
+
+/// The digests and the tarball entries of the layers, bottom first, from `manifest.json` or
+/// else from the OCI `index.json`.
+fn layers(blobs: &HashMap<String, Vec<u8>>) -> Result<Vec<(String, String)>, ArchiveError> {
+    if let Some(manifest) = blobs.get("manifest.json") {
+        let manifest = parse_json(manifest)?;
+        let image = manifest
+            .get(0)
+            .ok_or_else(|| invalid("empty manifest.json".to_owned()))?;
+        // The config lists the digests of the uncompressed layers.
+        let diff_ids = image["Config"]
+            .as_str()
+            .and_then(|config| blobs.get(config))
+            .and_then(|config| parse_json(config).ok())
+            .and_then(|config| config["rootfs"]["diff_ids"].as_array().cloned())
+            .unwrap_or_default();
+        let names = image["Layers"]
+            .as_array()
+            .ok_or_else(|| invalid("no Layers in manifest.json".to_owned()))?;
+        return names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let name = name
+                    .as_str()
+                    .ok_or_else(|| invalid("invalid layer in manifest.json".to_owned()))?;
+                let digest = match diff_ids.get(i).and_then(Value::as_str) {
+                    Some(digest) => digest.to_owned(),
+                    None => blob_digest(name).unwrap_or_else(|| name.to_owned()),
+                };
+                Ok((digest, name.to_owned()))
+            })
+            .collect();
+    }
+
+    let index = blobs
+        .get("index.json")
+        .ok_or_else(|| invalid("no manifest.json nor index.json".to_owned()))?;
+    let mut manifest = parse_json(index)?;
+    // Nested indexes, as for multi-platform images, are followed through their first manifest.
+    let mut followed = HashSet::new();
+    while let Some(descriptors) = manifest["manifests"].as_array() {
+        let digest = descriptors
+            .first()
+            .and_then(|d| d["digest"].as_str())
+            .ok_or_else(|| invalid("empty index".to_owned()))?;
+        if !followed.insert(digest.to_owned()) {
+            return Err(invalid(format!("index cycle through {}", digest)));
+        }
+        manifest = parse_json(blob(blobs, digest)?)?;
+    }
+    manifest["layers"]
+        .as_array()
+        .ok_or_else(|| invalid("no layers in image manifest".to_owned()))?
+        .iter()
+        .map(|layer| {
+            let digest = layer["digest"]
+                .as_str()
+                .ok_or_else(|| invalid("layer without digest".to_owned()))?;
+            Ok((digest.to_owned(), blob_name(digest)))
+        })
+        .collect()
+}
+
+/// Apply the whiteouts and the other entries of a layer to the lower layers, then add its files,
+/// the ones read before an error too.
+fn apply_layer(
+    filesystem: &mut BTreeMap<String, ImageFile>,
+    digest: &str,
+    blob: &[u8],
+    budget: &mut Budget,
+) -> Result<(), ArchiveError> {
+    let decompressed;
+    let blob = if blob.starts_with(b"\x1f\x8b") {
+        decompressed = budget.read("gzip", flate2::read::MultiGzDecoder::new(blob))?;
+        &decompressed[..]
+    } else {
+        blob
+    };
+
+    let mut files = Vec::new();
+    let result = read_layer(filesystem, digest, blob, budget, &mut files);
+    for (path, data, link_target) in files {
+        let data = match (data, link_target) {
+            (Some(data), _) => data,
+            (None, Some(target)) => match filesystem.get(&target) {
+                Some(file) => file.data.clone(),
+                None => continue,
+            },
+            (None, None) => continue,
+        };
+        filesystem.insert(
+            path.clone(),
+            ImageFile {
+                path,
+                layer: digest.to_owned(),
+                data,
+            },
+        );
+    }
+    result
+}
+
+/// A file of a layer: its path, and its data or the target of its hard link.
+type LayerFile = (String, Option<Vec<u8>>, Option<String>);
+
+/// Apply the whiteouts and the entries which are not files of a layer, and push its files to
+/// `files`.
+fn read_layer(
+    filesystem: &mut BTreeMap<String, ImageFile>,
+    digest: &str,
+    blob: &[u8],
+    budget: &mut Budget,
+    files: &mut Vec<LayerFile>,
+) -> Result<(), ArchiveError> {
+    let mut archive = tar::Archive::new(blob);
+    for entry in archive.entries().map_err(|e| invalid_layer(digest, e))? {
+        let entry = entry.map_err(|e| invalid_layer(digest, e))?;
+        let path = entry.path().map_err(|e| invalid_layer(digest, e))?;
+        let path = normalize(&path.to_string_lossy());
+        let (dir, name) = match path.rfind('/') {
+            Some(slash) => (&path[..slash], &path[slash + 1..]),
+            None => ("", &path[..]),
+        };
+
+        if name == OPAQUE_WHITEOUT {
+            let prefix = format!("{}/", dir);
+            filesystem.retain(|p, _| !p.starts_with(&prefix));
+        } else if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            let hidden = format!("{}/{}", dir, hidden);
+            let prefix = format!("{}/", hidden);
+            filesystem.retain(|p, _| p != &hidden && !p.starts_with(&prefix));
+        } else if entry.header().entry_type().is_hard_link() {
+            let target = entry
+                .link_name()
+                .map_err(|e| invalid_layer(digest, e))?
+                .map(|target| normalize(&target.to_string_lossy()));
+            files.push((path, None, target));
+        } else if entry.header().entry_type().is_file() {
+            let data = budget.read("tar", entry)?;
+            files.push((path, Some(data), None));
+        } else if entry.header().entry_type().is_dir() {
+            // A directory replaces a lower file, but keeps the files of a lower directory.
+            filesystem.remove(&path);
+        } else {
+            // A symlink or a special file replaces whatever was at its path.
+            let prefix = format!("{}/", path);
+            filesystem.retain(|p, _| p != &path && !p.starts_with(&prefix));
+        }
+    }
+    Ok(())
+}
+
+/// The entries of the image tarball, by normalized name.
+fn read_tar(tarball: &[u8]) -> Result<HashMap<String, Vec<u8>>, ArchiveError> {
+    let mut entries = HashMap::new();
+    let mut archive = tar::Archive::new(tarball);
+    for entry in archive.entries().map_err(|e| invalid(e.to_string()))? {
+        let mut entry = entry.map_err(|e| invalid(e.to_string()))?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path().map_err(|e| invalid(e.to_string()))?;
+        let name = normalize(&name.to_string_lossy())[1..].to_owned();
+        let mut data = Vec::new();
+        entry
+            .read_to_end(&mut data)
+            .map_err(|e| invalid(e.to_string()))?;
+        entries.insert(name, data);
+    }
+    Ok(entries)
+}
+
+/// `/` and the path without `.` components nor leading and trailing slashes.
+fn normalize(path: &str) -> String {
+    let components: Vec<_> = path
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .collect();
+    format!("/{}", components.join("/"))
+}
+
+/// `blobs/sha256/…` for `sha256:…`.
+fn blob_name(digest: &str) -> String {
+    format!("blobs/{}", digest.replacen(':', "/", 1))
+}
+
+/// `sha256:…` for `blobs/sha256/…`.
+fn blob_digest(name: &str) -> Option<String> {
+    let mut components = name.strip_prefix("blobs/")?.splitn(2, '/');
+    Some(format!("{}:{}", components.next()?, components.next()?))
+}
+
+fn blob<'b>(blobs: &'b HashMap<String, Vec<u8>>, digest: &str) -> Result<&'b [u8], ArchiveError> {
+    blobs
+        .get(&blob_name(digest))
+        .map(Vec::as_slice)
+        .ok_or_else(|| invalid(format!("missing blob {}", digest)))
+}
+
+fn parse_json(data: &[u8]) -> Result<Value, ArchiveError> {
+    serde_json::from_slice(data).map_err(|e| invalid(e.to_string()))
+}
+
+fn invalid(message: String) -> ArchiveError {
+    ArchiveError::Invalid {
+        format: "image",
+        message,
+    }
+}
+
+fn invalid_layer<E: ToString>(digest: &str, error: E) -> ArchiveError {
+    invalid(format!("layer {}: {}", digest, error.to_string()))
+}
@@ -0,0 +1,346 @@
+//! State of incremental scans of directory trees.
+//!
+//! A [`ScanState`] records, for each scanned file, its size, modification time and inode, the
+//! [fingerprint](crate::RuleSource::fingerprint) of the rules it was scanned with, and the rules
+//! which matched. Later scans skip the files which did not change since they were scanned with
+//! the same rules.
+//!
+//! State files are JSON. Paths which are not UTF-8 are stored as their bytes, and modification
+//! times as signed seconds and nanoseconds since 1970, so that any file can be recorded.
+//!
+//! ```no_run
+//! use std::fs;
+//!
+//! use rs_yara::incremental::{Change, FileState, ScanState};
+//! use rs_yara::RuleSource;
+//!
+//! let source = RuleSource::from_path("rules");
+//! let fingerprint = source.fingerprint()?;
+//! let rules = source.load()?;
+//! let mut state = ScanState::load("sweep.state")?;
+//! let metadata = fs::metadata("/srv/share/tool.exe").unwrap();
+//! if state.change("/srv/share/tool.exe", &metadata, &fingerprint) != Change::Unchanged {
+//!     let matches = rules.scan_file("/srv/share/tool.exe", 10)?;
+//!     let verdict = matches.iter().map(|r| r.identifier.to_owned()).collect();
+//!     state.record("/srv/share/tool.exe", FileState::new(&metadata, &fingerprint, verdict));
+//! }
+//! state.save("sweep.state")?;
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::cache::write_atomically;
+use crate::errors::*;
+
+/// Version of the state file format, to change when it changes incompatibly.
+const STATE_VERSION: u32 = 2;
+
+/// What is recorded of a scanned file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FileState {
+    pub size: u64,
+    #[serde(with = "mtime")]
+    pub mtime: Option<SystemTime>,
+    pub inode: u64,
+    /// The fingerprint of the rules the file was scanned with.
+    pub fingerprint: String,
+    /// The identifiers of the rules which matched.
+    pub verdict: Vec<String>,
+}
+
+impl FileState {
+    pub fn new(metadata: &fs::Metadata, fingerprint: &str, verdict: Vec<String>) -> Self {
+        FileState {
+            size: metadata.len(),
+            mtime: metadata.modified().ok(),
+            inode: metadata.ino(),
+            fingerprint: fingerprint.to_owned(),
+            verdict,
+        }
+    }
+}
+
+/// How a file changed since it was last scanned.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    /// Never scanned.
+    New,
+    /// Its size, modification time or inode changed.
+    Modified,
+    /// It was scanned with other rules.
+    RulesChanged,
+    Unchanged,
+}
+
+/// The state of the files of incremental scans, by path.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ScanState {
+    version: u32,
+    #[serde(with = "files")]
+    files: BTreeMap<PathBuf, FileState>,
+}
+
+/// Only the version of a state file, read first since the format of the files changes with it.
+#[derive(Deserialize)]
+struct Version {
+    version: u32,
+}
+
+impl ScanState {
+    pub fn new() -> Self {
+        ScanState {
+            version: STATE_VERSION,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Load the state file at `path`. A missing file, or one of another version, is an empty
+    /// state.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let to_io_error = |e| IoError::new(e, IoErrorKind::ReadingStateFile);
+
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(to_io_error(e).into()),
+        };
+        let invalid = |e| to_io_error(io::Error::new(io::ErrorKind::InvalidData, e));
+        let version: Version = serde_json::from_slice(&data).map_err(invalid)?;
+        if version.version != STATE_VERSION {
+            return Ok(Self::new());
+        }
+        Ok(serde_json::from_slice(&data).map_err(invalid)?)
+    }
+
+    /// Save the state to the file at `path`. An interrupted save keeps the previous state.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let to_io_error = |e| IoError::new(e, IoErrorKind::WritingStateFile);
+
+        let data = serde_json::to_vec(self)
+            .map_err(|e| to_io_error(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        write_atomically(path.as_ref(), &data).map_err(|e| to_io_error(e).into())
+    }
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&FileState> {
+        self.files.get(path.as_ref())
+    }
+
+    /// How the file at `path`, with `metadata`, changed since it was recorded, for the rules of
+    /// `fingerprint`.
+    pub fn change<P: AsRef<Path>>(
+        &self,
+        path: P,
+        metadata: &fs::Metadata,
+        fingerprint: &str,
+    ) -> Change {
+        let state = match self.files.get(path.as_ref()) {
+            Some(state) => state,
+            None => return Change::New,
+        };
+        if state.size != metadata.len()
+            || state.mtime.is_none()
+            || state.mtime != metadata.modified().ok()
+            || state.inode != metadata.ino()
+        {
+            Change::Modified
+        } else if state.fingerprint != fingerprint {
+            Change::RulesChanged
+        } else {
+            Change::Unchanged
+        }
+    }
+
+    /// Record the state of the file at `path`, after a scan.
+    pub fn record<P: Into<PathBuf>>(&mut self, path: P, state: FileState) {
+        self.files.insert(path.into(), state);
+    }
+
+    /// Keep only the files for which `keep` returns true, e.g. to forget removed files.
+    pub fn retain<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&Path, &FileState) -> bool,
+    {
+        self.files.retain(|path, state| keep(path, state))
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl Default for ScanState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The report of a file skipped by an incremental scan, with its last verdict.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct SkipReport {
+    pub path: PathBuf,
+    /// How the file changed since it was scanned, which made the scan skip it.
+    pub skipped: Change,
+    /// The identifiers of the rules which matched when the file was scanned.
+    pub rules: Vec<String>,
+}
+
+impl SkipReport {
+    pub fn new<P: Into<PathBuf>>(path: P, state: &FileState, change: Change) -> Self {
+        SkipReport {
+            path: path.into(),
+            skipped: change,
+            rules: state.verdict.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("skip reports are serializable")
+    }
+}
+
+/// A path, as a string if it is UTF-8, else as its bytes.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StoredPath {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<&Path> for StoredPath {
+    fn from(path: &Path) -> Self {
+        match path.to_str() {
+            Some(text) => StoredPath::Text(text.to_owned()),
+            None => StoredPath::Bytes(path.as_os_str().as_bytes().to_vec()),
+        }
+    }
+}
+
+impl From<StoredPath> for PathBuf {
+    fn from(path: StoredPath) -> Self {
+        match path {
+            StoredPath::Text(text) => PathBuf::from(text),
+            StoredPath::Bytes(bytes) => PathBuf::from(OsString::from_vec(bytes)),
+        }
+    }
+}
+
+/// Files are stored as a list of paths and states, since the keys of JSON objects are strings.
+mod files {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        files: &BTreeMap<PathBuf, FileState>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            files
+                .iter()
+                .map(|(path, state)| (StoredPath::from(path.as_path()), state)),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BTreeMap<PathBuf, FileState>, D::Error> {
+        let files: Vec<(StoredPath, FileState)> = Vec::deserialize(deserializer)?;
+        Ok(files
+            .into_iter()
+            .map(|(path, state)| (path.into(), state))
+            .collect())
+    }
+}
+
+/// Modification times are stored as the seconds since 1970, negative before, and the
+/// nanoseconds after them.
+mod mtime {
+    use std::convert::TryFrom;
+
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+
+    use super::*;
+
+    const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+    #[derive(Serialize, Deserialize)]
+    struct Timestamp {
+        secs: i64,
+        nanos: u32,
+    }
+
+    fn to_timestamp(time: SystemTime) -> Option<Timestamp> {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => Some(Timestamp {
+                secs: i64::try_from(after.as_secs()).ok()?,
+                nanos: after.subsec_nanos(),
+            }),
+            Err(e) => {
+                let before = e.duration();
+                let secs = i64::try_from(before.as_secs()).ok()?;
+                Some(match before.subsec_nanos() {
+                    0 => Timestamp {
+                        secs: -secs,
+                        nanos: 0,
+                    },
+                    nanos => Timestamp {
+                        secs: -secs - 1,
+                        nanos: NANOS_PER_SEC - nanos,
+                    },
+                })
+            }
+        }
+    }
+
+    fn from_timestamp(timestamp: &Timestamp) -> Option<SystemTime> {
+        if timestamp.nanos >= NANOS_PER_SEC {
+            return None;
+        }
+        let secs = Duration::from_secs(timestamp.secs.unsigned_abs());
+        let whole_secs = if timestamp.secs < 0 {
+            UNIX_EPOCH.checked_sub(secs)?
+        } else {
+            UNIX_EPOCH.checked_add(secs)?
+        };
+        whole_secs.checked_add(Duration::from_nanos(timestamp.nanos.into()))
+    }
+
+    pub fn serialize<S: Serializer>(
+        mtime: &Option<SystemTime>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let timestamp = match mtime {
+            Some(time) => Some(
+                to_timestamp(*time)
+                    .ok_or_else(|| S::Error::custom("modification time out of range"))?,
+            ),
+            None => None,
+        };
+        timestamp.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<SystemTime>, D::Error> {
+        match Option::<Timestamp>::deserialize(deserializer)? {
+            Some(timestamp) => from_timestamp(&timestamp)
+                .map(Some)
+                .ok_or_else(|| D::Error::custom("invalid modification time")),
+            None => Ok(None),
+        }
+    }
+}
@@ -29,8 +29,13 @@ lazy_static! {
 /// To call yr_finalize_thread (required until Yara 3.8.0), we store a number of
 /// `InitializationToken` living in each thread. When this number reaches 0, we call
 /// yr_finalize_thread.
+///
+/// A token is counted in the thread that created it, even if it is dropped in another one.
+/// In that case, yr_finalize_thread is not called.
 #[derive(Debug)]
-pub struct InitializationToken;
+pub struct InitializationToken {
+    thread_id: thread::ThreadId,
+}
 
 impl InitializationToken {
     /// Create and initialize the library.
@@ -39,14 +44,15 @@ impl InitializationToken {
         let mut thread_counters = THREAD_COUNTERS
             .lock()
             .expect("mutex should not be poisoned");
-        let counter = thread_counters.entry(thread::current().id()).or_insert(0);
+        let thread_id = thread::current().id();
+        let counter = thread_counters.entry(thread_id).or_insert(0);
         *counter += 1;
 
         if *counter == 1 {
             initialize()?;
         }
 
-        Ok(InitializationToken)
+        Ok(InitializationToken { thread_id })
     }
 }
 
@@ -54,19 +60,20 @@ impl InitializationToken {
 impl Drop for InitializationToken {
     fn drop(&mut self) {
         // Decrement the thread counter, and call yr_finalize_thread if it reaches 0.
-        let thread_id = thread::current().id();
         let mut thread_counters = THREAD_COUNTERS
             .lock()
             .expect("mutex should not be poisoned");
         let n_threads = thread_counters
-            .get_mut(&thread_id)
+            .get_mut(&self.thread_id)
             .expect("incorrect use of THREAD_COUNTERS");
 
         if *n_threads > 1 {
             *n_threads -= 1;
         } else {
-            thread_counters.remove(&thread_id);
-            finalize_thread();
+            thread_counters.remove(&self.thread_id);
+            if self.thread_id == thread::current().id() {
+                finalize_thread();
+            }
             finalize().expect("Expect correct Yara finalization");
         }
     }
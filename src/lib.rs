@@ -12,12 +12,41 @@ lazy_static! {
 pub mod yara_sys;
 pub mod meta;
 pub mod errors;
-
-
+pub mod syntax;
+pub mod lint;
+pub mod harness;
+pub mod diff;
+pub mod render;
+pub mod report;
+pub mod archive;
+pub mod daemon;
+pub mod decode;
+pub mod eml;
+pub mod image;
+pub mod incremental;
+pub mod pcap;
+pub mod verdict;
+pub mod walk;
+#[cfg(feature = "http")]
+pub mod http;
+
+
+#[cfg(feature = "tokio")]
+mod async_scan;
+mod blocks;
+mod bundle;
+mod bytes;
+mod cache;
 mod compiler;
+mod context;
+mod file_info;
+mod flags;
 mod initialize;
 mod matches;
+mod owned;
+mod rule_info;
 mod rules;
+mod ruleset;
 mod string;
 mod scan;
 mod stream;
@@ -26,9 +55,22 @@ mod stream;
 
 use crate::initialize::InitializationToken;
 
+#[cfg(feature = "tokio")]
+pub use self::async_scan::AsyncRules;
+pub use self::blocks::{MemoryBlockSource, MemoryBlocks};
+pub use self::bundle::*;
+pub use self::cache::CachedCompiler;
 pub use self::compiler::*;
+pub use self::context::MatchContext;
+pub use self::file_info::FileInfo;
+pub use self::flags::{RuleFlags, StringKind, StringModifiers};
+pub use self::matches::Match;
+pub use self::owned::*;
+pub use self::rule_info::*;
 pub use self::rules::*;
+pub use self::ruleset::*;
 pub use self::scan::*;
+pub use self::string::YrString;
 
 use crate::errors::*;
 
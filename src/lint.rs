@@ -0,0 +1,617 @@
+//! A linter of YARA rules.
+//!
+//! [`lint`] checks a rules source against a [`LintConfig`], which is usually read from a TOML
+//! file:
+//!
+//! ```toml
+//! [checks]
+//! required-meta = "error"
+//! duplicate-string = "off"
+//!
+//! [meta]
+//! required = ["author", "description"]
+//! types = { author = "string", score = "integer" }
+//!
+//! [tags]
+//! allowed = ["malware", "packer"]
+//!
+//! [strings]
+//! min-length = 4
+//! nocase-wide-min-length = 8
+//! ```
+//!
+//! The findings of the source are combined with the warnings and errors of the compiler.
+//!
+//! ```
+//! use rs_yara::lint::{self, Check, LintConfig};
+//!
+//! let config = LintConfig::from_toml(r#"meta = { required = ["author"] }"#)?;
+//! let findings = lint::lint(r#"rule a { strings: $a = "ab" condition: $a }"#, &config)?;
+//! let checks: Vec<_> = findings.iter().map(|f| f.check).collect();
+//! assert_eq!(vec![Check::RequiredMeta, Check::ShortString], checks);
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::errors::*;
+use crate::syntax::{self, *};
+use crate::Compiler;
+
+/// The checks of the linter.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// A metadata of `meta.required` is missing.
+    RequiredMeta,
+    /// A metadata has not the type of `meta.types`.
+    MetaType,
+    /// A tag is not in `tags.allowed`.
+    DisallowedTag,
+    /// A text string, or the known bytes of a hex string, is shorter than `strings.min-length`.
+    ShortString,
+    /// A string is not used in the condition.
+    UnreferencedString,
+    /// A regular expression repeats `.` or a negated class without bound.
+    UnboundedRegex,
+    /// A `nocase wide` string is shorter than `strings.nocase-wide-min-length`.
+    NocaseWideShort,
+    /// A string has the value and modifiers of a previous one.
+    DuplicateString,
+    /// A warning of the compiler.
+    CompilerWarning,
+    /// An error of the compiler.
+    CompilerError,
+}
+
+impl Check {
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::RequiredMeta => "required-meta",
+            Check::MetaType => "meta-type",
+            Check::DisallowedTag => "disallowed-tag",
+            Check::ShortString => "short-string",
+            Check::UnreferencedString => "unreferenced-string",
+            Check::UnboundedRegex => "unbounded-regex",
+            Check::NocaseWideShort => "nocase-wide-short",
+            Check::DuplicateString => "duplicate-string",
+            Check::CompilerWarning => "compiler-warning",
+            Check::CompilerError => "compiler-error",
+        }
+    }
+
+    fn default_severity(self) -> Severity {
+        match self {
+            Check::MetaType | Check::UnreferencedString | Check::CompilerError => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The severity of a check. `Off` disables it.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Off,
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Off => "off",
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// The type of a metadata, as in [`MetadataValue`](../enum.MetadataValue.html).
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetaType {
+    String,
+    Integer,
+    Boolean,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LintConfig {
+    /// The severity of the checks, when not the default one.
+    pub checks: HashMap<Check, Severity>,
+    pub meta: MetaConfig,
+    pub tags: TagsConfig,
+    pub strings: StringsConfig,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct MetaConfig {
+    /// The metadata every rule must have.
+    pub required: Vec<String>,
+    /// The types of metadata.
+    pub types: BTreeMap<String, MetaType>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TagsConfig {
+    /// The allowed tags, any tag when `None`.
+    pub allowed: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct StringsConfig {
+    pub min_length: usize,
+    pub nocase_wide_min_length: usize,
+}
+
+impl Default for StringsConfig {
+    fn default() -> Self {
+        StringsConfig {
+            min_length: 4,
+            nocase_wide_min_length: 8,
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn from_toml(config: &str) -> Result<Self, ConfigError> {
+        toml::from_str(config).map_err(|e| ConfigError {
+            message: e.to_string(),
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let config = fs::read_to_string(path)
+            .map_err(|e| IoError::new(e, IoErrorKind::ReadingConfigFile))?;
+        Ok(Self::from_toml(&config)?)
+    }
+
+    pub fn severity(&self, check: Check) -> Severity {
+        self.checks
+            .get(&check)
+            .copied()
+            .unwrap_or_else(|| check.default_severity())
+    }
+}
+
+/// A problem found by the linter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    pub check: Check,
+    pub severity: Severity,
+    /// The rule of the finding, if any.
+    pub rule: Option<String>,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {}[{}]: {}",
+            self.line, self.severity, self.check, self.message
+        )?;
+        if let Some(rule) = &self.rule {
+            write!(f, " (rule {})", rule)?;
+        }
+        Ok(())
+    }
+}
+
+/// Lint a rules source. Includes are relative to the current directory.
+///
+/// Findings are sorted by line. A syntax error is an error, not a finding.
+pub fn lint(source: &str, config: &LintConfig) -> Result<Vec<Finding>, Error> {
+    let mut compiler = Compiler::new()?;
+    let compiled = compiler.add_rules_str(source);
+    lint_compiled(source, config, None, compiled, &compiler)
+}
+
+/// Lint a rules file. Includes are relative to the file.
+pub fn lint_file<P: AsRef<Path>>(path: P, config: &LintConfig) -> Result<Vec<Finding>, Error> {
+    let source = fs::read_to_string(path.as_ref())
+        .map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile))?;
+    let mut compiler = Compiler::new()?;
+    let compiled = compiler.add_rules_file(path.as_ref());
+    lint_compiled(&source, config, Some(path.as_ref()), compiled, &compiler)
+}
+
+fn lint_compiled(
+    source: &str,
+    config: &LintConfig,
+    main_file: Option<&Path>,
+    compiled: Result<(), Error>,
+    compiler: &Compiler,
+) -> Result<Vec<Finding>, Error> {
+    let file = syntax::parse(source)?;
+    let mut linter = Linter {
+        source,
+        config,
+        findings: Vec::new(),
+    };
+    linter.source_file(&file);
+
+    let messages = match compiled {
+        Ok(()) => compiler.warnings().to_vec(),
+        Err(Error::Compile(errors)) => errors.iter().cloned().collect(),
+        Err(e) => return Err(e),
+    };
+    for message in messages {
+        linter.compiler_message(&file, main_file, &message);
+    }
+
+    let mut findings = linter.findings;
+    findings.retain(|f| f.severity != Severity::Off);
+    findings.sort_by_key(|f| f.line);
+    Ok(findings)
+}
+
+struct Linter<'a> {
+    source: &'a str,
+    config: &'a LintConfig,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, check: Check, rule: &Rule, span: Span, message: String) {
+        self.findings.push(Finding {
+            check,
+            severity: self.config.severity(check),
+            rule: Some(rule.identifier.name.clone()),
+            line: syntax::line_of(self.source, span.start),
+            message,
+        });
+    }
+
+    fn source_file(&mut self, file: &SourceFile) {
+        // The first string with a value, by value.
+        let mut values: HashMap<String, (&str, &str)> = HashMap::new();
+        for rule in file.rules() {
+            self.rule(rule);
+            for string in &rule.strings {
+                let key = string_key(string);
+                match values.get(&key) {
+                    Some((first_rule, first)) => {
+                        let message = format!(
+                            "string {} is the same as {} of rule {}",
+                            string.identifier.name, first, first_rule
+                        );
+                        self.report(Check::DuplicateString, rule, string.span, message);
+                    }
+                    None => {
+                        values.insert(key, (&rule.identifier.name, &string.identifier.name));
+                    }
+                }
+            }
+        }
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        for required in &self.config.meta.required {
+            if !rule.metas.iter().any(|m| &m.identifier.name == required) {
+                let message = format!("missing metadata {}", required);
+                self.report(Check::RequiredMeta, rule, rule.identifier.span, message);
+            }
+        }
+
+        for meta in &rule.metas {
+            let expected = match self.config.meta.types.get(&meta.identifier.name) {
+                Some(expected) => *expected,
+                None => continue,
+            };
+            let actual = match meta.value {
+                MetaValue::String(_) => MetaType::String,
+                MetaValue::Integer(_) => MetaType::Integer,
+                MetaValue::Boolean(_) => MetaType::Boolean,
+            };
+            if actual != expected {
+                let message = format!(
+                    "metadata {} should be {:?}, not {:?}",
+                    meta.identifier.name, expected, actual
+                );
+                self.report(Check::MetaType, rule, meta.span, message);
+            }
+        }
+
+        if let Some(allowed) = &self.config.tags.allowed {
+            for tag in &rule.tags {
+                if !allowed.contains(&tag.name) {
+                    let message = format!("tag {} is not allowed", tag.name);
+                    self.report(Check::DisallowedTag, rule, tag.span, message);
+                }
+            }
+        }
+
+        let references = references(&rule.condition);
+        for string in &rule.strings {
+            self.string(rule, string, &references);
+        }
+    }
+
+    fn string(&mut self, rule: &Rule, string: &StringDeclaration, references: &[String]) {
+        let name = &string.identifier.name;
+        let has = |kind: StringModifierKind| string.modifiers.iter().any(|m| m.kind == kind);
+
+        let length = match &string.value {
+            StringValue::Text(text) => Some(text.value().len()),
+            StringValue::Hex(hex) => Some(known_bytes(&hex.tokens)),
+            StringValue::Regex(regex) => {
+                if is_unbounded(&regex.pattern) {
+                    let message = format!("regular expression {} is unbounded", name);
+                    self.report(Check::UnboundedRegex, rule, string.span, message);
+                }
+                None
+            }
+        };
+        if let Some(length) = length {
+            let min_length = self.config.strings.min_length;
+            if length < min_length {
+                let message = format!(
+                    "string {} has {} bytes, less than {}",
+                    name, length, min_length
+                );
+                self.report(Check::ShortString, rule, string.span, message);
+            }
+            let min_length = self.config.strings.nocase_wide_min_length;
+            if has(StringModifierKind::Nocase)
+                && has(StringModifierKind::Wide)
+                && length < min_length
+            {
+                let message = format!(
+                    "nocase wide string {} has {} bytes, less than {}",
+                    name, length, min_length
+                );
+                self.report(Check::NocaseWideShort, rule, string.span, message);
+            }
+        }
+
+        let referenced = references.iter().any(|r| match r.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            // Anonymous strings are only referenced by sets.
+            None => r == name && name != "$",
+        });
+        if !referenced {
+            let message = format!("string {} is not used in the condition", name);
+            self.report(Check::UnreferencedString, rule, string.span, message);
+        }
+    }
+
+    fn compiler_message(
+        &mut self,
+        file: &SourceFile,
+        main_file: Option<&Path>,
+        message: &CompileError,
+    ) {
+        let check = match message.level {
+            CompileErrorLevel::Error => Check::CompilerError,
+            CompileErrorLevel::Warning => Check::CompilerWarning,
+        };
+        // The messages of included files are not about the rules of the source.
+        let included = message
+            .filename
+            .as_deref()
+            .filter(|f| main_file != Some(Path::new(f)));
+        let rule = file.rules().filter(|_| included.is_none()).find(|r| {
+            let start = syntax::line_of(self.source, r.span.start);
+            let end = syntax::line_of(self.source, r.span.end);
+            (start..=end).contains(&message.line)
+        });
+
+        // Some messages are already reported by a check, with the severity of the config.
+        let duplicated = if message.message.starts_with("unreferenced string") {
+            Some(Check::UnreferencedString)
+        } else if message.message.contains("contains .* or .+") {
+            Some(Check::UnboundedRegex)
+        } else {
+            None
+        };
+        let rule_name = rule.map(|r| r.identifier.name.clone());
+        if let Some(duplicated) = duplicated {
+            if rule_name.is_some()
+                && self
+                    .findings
+                    .iter()
+                    .any(|f| f.check == duplicated && f.rule == rule_name)
+            {
+                return;
+            }
+        }
+
+        let mut text = message.message.clone();
+        if let Some(filename) = included {
+            text = format!("{} (in {})", text, filename);
+        }
+        self.findings.push(Finding {
+            check,
+            severity: self.config.severity(check),
+            rule: rule_name,
+            line: message.line,
+            message: text,
+        });
+    }
+}
+
+/// The strings referenced by a condition, with a `$` sigil. Sets are `$*` for `them`, and keep
+/// the `*` of their wildcards.
+fn references(condition: &Expr) -> Vec<String> {
+    let mut references = Vec::new();
+    walk(condition, &mut |expr| match &expr.kind {
+        ExprKind::String(id)
+        | ExprKind::StringAt { identifier: id, .. }
+        | ExprKind::StringIn { identifier: id, .. }
+        | ExprKind::StringCount(id)
+        | ExprKind::StringOffset { identifier: id, .. }
+        | ExprKind::StringLength { identifier: id, .. } => {
+            references.push(format!("${}", &id.name[1..]));
+        }
+        ExprKind::Of { strings, .. } | ExprKind::ForOf { strings, .. } => match strings {
+            StringSet::Them => references.push("$*".to_owned()),
+            StringSet::List(ids) => references.extend(ids.iter().map(|id| id.name.clone())),
+        },
+        _ => {}
+    });
+    references
+}
+
+/// Call `f` on an expression and its subexpressions.
+fn walk(expr: &Expr, f: &mut dyn FnMut(&Expr)) {
+    f(expr);
+    match &expr.kind {
+        ExprKind::StringAt { offset: e, .. }
+        | ExprKind::IntegerFunction { offset: e, .. }
+        | ExprKind::Field { object: e, .. }
+        | ExprKind::Unary { operand: e, .. }
+        | ExprKind::Paren(e) => walk(e, f),
+        ExprKind::StringIn { start, end, .. } => {
+            walk(start, f);
+            walk(end, f);
+        }
+        ExprKind::StringOffset { index, .. } | ExprKind::StringLength { index, .. } => {
+            if let Some(index) = index {
+                walk(index, f);
+            }
+        }
+        ExprKind::Index { object, index } => {
+            walk(object, f);
+            walk(index, f);
+        }
+        ExprKind::Call {
+            function,
+            arguments,
+        } => {
+            walk(function, f);
+            arguments.iter().for_each(|a| walk(a, f));
+        }
+        ExprKind::Binary { lhs, rhs, .. } => {
+            walk(lhs, f);
+            walk(rhs, f);
+        }
+        ExprKind::Of { quantifier, .. } => walk_quantifier(quantifier, f),
+        ExprKind::ForOf {
+            quantifier, body, ..
+        } => {
+            walk_quantifier(quantifier, f);
+            walk(body, f);
+        }
+        ExprKind::ForIn {
+            quantifier,
+            integers,
+            body,
+            ..
+        } => {
+            walk_quantifier(quantifier, f);
+            match integers {
+                IntegerSet::Range { start, end } => {
+                    walk(start, f);
+                    walk(end, f);
+                }
+                IntegerSet::Enumeration(integers) => integers.iter().for_each(|i| walk(i, f)),
+            }
+            walk(body, f);
+        }
+        _ => {}
+    }
+}
+
+fn walk_quantifier(quantifier: &Quantifier, f: &mut dyn FnMut(&Expr)) {
+    if let Quantifier::Expr(expr) = quantifier {
+        walk(expr, f);
+    }
+}
+
+/// The bytes of a hex string which are not `??`, on its shortest alternatives.
+fn known_bytes(tokens: &[HexToken]) -> usize {
+    tokens
+        .iter()
+        .map(|token| match token {
+            HexToken::Byte { mask: 0, .. } | HexToken::Skip(_) | HexToken::Jump { .. } => 0,
+            HexToken::Byte { .. } => 1,
+            HexToken::Alternative(alternatives) => alternatives
+                .iter()
+                .map(|a| known_bytes(a))
+                .min()
+                .unwrap_or(0),
+        })
+        .sum()
+}
+
+/// Whether a pattern has `.` or a negated class followed by `*`, `+` or `{n,}`.
+fn is_unbounded(pattern: &str) -> bool {
+    let bytes = pattern.as_bytes();
+    let mut wildcard = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                wildcard = false;
+                i += 1;
+            }
+            b'.' => wildcard = true,
+            b'[' => {
+                let negated = bytes.get(i + 1) == Some(&b'^');
+                i += if negated { 2 } else { 1 };
+                // A `]` first in the class is part of it.
+                if bytes.get(i) == Some(&b']') {
+                    i += 1;
+                }
+                while i < bytes.len() && bytes[i] != b']' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                wildcard = negated;
+            }
+            b'*' | b'+' if wildcard => return true,
+            b'{' if wildcard => {
+                let end = pattern[i..].find('}').map_or(bytes.len(), |end| i + end);
+                if pattern[i..end].ends_with(',') {
+                    return true;
+                }
+                wildcard = false;
+                i = end;
+            }
+            _ => wildcard = false,
+        }
+        i += 1;
+    }
+    false
+}
+
+/// A key of the value of a string, with its modifiers.
+fn string_key(string: &StringDeclaration) -> String {
+    let mut modifiers: Vec<_> = string
+        .modifiers
+        .iter()
+        .map(|m| format!("{:?}", m.kind))
+        .collect();
+    modifiers.sort();
+    let value = match &string.value {
+        StringValue::Text(text) => format!("text {:?}", text.value()),
+        StringValue::Hex(hex) => format!("hex {:?}", hex.tokens),
+        StringValue::Regex(regex) => format!(
+            "regex {} {} {}",
+            regex.pattern, regex.case_insensitive, regex.dot_all
+        ),
+    };
+    format!("{} {}", value, modifiers.join(" "))
+}
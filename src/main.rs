@@ -1,36 +1,9 @@
-#[macro_use]
-extern crate lazy_static;
+use std::env;
+use std::process;
 
-use rs_yara::*;
+mod cli;
 
-const RULES: &str = r#"
-rule is_awesome {
-  strings:
-    $rust = /[Rr]ust/
-
-  condition:
-    $rust
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    process::exit(cli::run(&args));
 }
-
-rule is_ok {
-  strings:
-    $go = "go"
-
-  condition:
-    $go
-}"#;
-
-fn main(){
-    let mut compiler = Compiler::new().unwrap();
-    compiler.add_rules_str(RULES).expect("Should be Ok");
-    let rules = compiler.compile_rules().unwrap();
-
-    let result = rules
-        .scan_file("tests/scanfile.txt", 10)
-        .expect("Should have scanned file");
-    assert_eq!(1, result.len());
-
-    
-
-
-}
\ No newline at end of file
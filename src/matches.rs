@@ -1,17 +1,25 @@
 use std::marker;
 use std::slice;
 
-use crate::yara_sys;
+use serde::{Deserialize, Serialize};
+
+use crate::{yara_sys, MatchContext};
 
 /// A match within a scan.
-#[derive(Debug)]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Match {
-    /// Offset of the match within the scanning area.
+    /// Base address of the memory block the match was found in.
+    ///
+    /// Always 0, except when scanning memory blocks.
+    pub base: usize,
+    /// Offset of the match within the scanning area, `base` included.
     pub offset: usize,
     /// Length of the file. Can be useful if the matcher string has not a fixed length.
     pub length: usize,
-    /// Matched data.
+    /// Matched data, truncated by libyara to `YR_MAX_MATCH_DATA` bytes.
     pub data: Vec<u8>,
+    /// Bytes around the match, for scans with [`Rules::set_context`](crate::Rules::set_context).
+    pub context: Option<MatchContext>,
 }
 
 
@@ -35,11 +43,12 @@ impl<'a> From<&'a yara_sys::YR_MATCHES> for MatchIterator<'a> {
 }
 
 impl<'a> Iterator for MatchIterator<'a> {
-    type Item = &'a yara_sys::YR_MATCH;
+    type Item = yara_sys::YR_MATCH;
 
     fn next(&mut self) -> Option<Self::Item> {
         if !self.head.is_null() {
-            let m = unsafe { &*self.head };
+            // The matches arena does not keep YR_MATCH aligned.
+            let m = unsafe { self.head.read_unaligned() };
             self.head = m.next;
             Some(m)
         } else {
@@ -48,12 +57,14 @@ impl<'a> Iterator for MatchIterator<'a> {
     }
 }
 
-impl<'a> From<&'a yara_sys::YR_MATCH> for Match {
-    fn from(m: &yara_sys::YR_MATCH) -> Self {
+impl From<yara_sys::YR_MATCH> for Match {
+    fn from(m: yara_sys::YR_MATCH) -> Self {
         Match {
-            offset: m.offset as usize,
+            base: m.base as usize,
+            offset: (m.base + m.offset) as usize,
             length: m.match_length as usize,
             data: Vec::from(unsafe { slice::from_raw_parts(m.data, m.data_length as usize) }),
+            context: None,
         }
     }
 }
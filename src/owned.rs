@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    matches::Match, string::YrString, Metadata, MetadataValue, Rule, RuleFlags, StringKind,
+    StringModifiers,
+};
+
+/// A rule that matched during a scan, which does not borrow from the `Rules`.
+///
+/// See [`Rule`]. It can be serialized, as by [`VerdictCache`](crate::verdict::VerdictCache).
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OwnedRule {
+    /// Name of the rule.
+    pub identifier: String,
+    /// Namespace of the rule.
+    pub namespace: String,
+    /// Metadatas of the rule.
+    pub metadatas: Vec<OwnedMetadata>,
+    /// Tags of the rule.
+    pub tags: Vec<String>,
+    /// Private and global flags of the rule.
+    pub flags: RuleFlags,
+    /// Matcher strings of the rule.
+    pub strings: Vec<OwnedYrString>,
+}
+
+/// Metadata specified in a rule.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OwnedMetadata {
+    pub identifier: String,
+    pub value: OwnedMetadataValue,
+}
+
+/// Type of the value in [OwnedMetadata](struct.OwnedMetadata.html)
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OwnedMetadataValue {
+    Integer(i64),
+    String(String),
+    Boolean(bool),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OwnedYrString {
+    /// Name of the string, with the '$'.
+    pub identifier: String,
+    /// Text, hexadecimal string or regular expression.
+    pub kind: StringKind,
+    pub modifiers: StringModifiers,
+    /// The literal value of the string, when libyara keeps it.
+    pub literal: Option<Vec<u8>>,
+    /// Matches of the string for the scan.
+    pub matches: Vec<Match>,
+}
+
+impl OwnedRule {
+    /// The rule, borrowing from this one, e.g. to build a [`RuleReport`](crate::report::RuleReport).
+    pub fn as_rule(&self) -> Rule<'_> {
+        Rule {
+            identifier: &self.identifier,
+            namespace: &self.namespace,
+            metadatas: self
+                .metadatas
+                .iter()
+                .map(|metadata| Metadata {
+                    identifier: &metadata.identifier,
+                    value: match &metadata.value {
+                        OwnedMetadataValue::Integer(i) => MetadataValue::Integer(*i),
+                        OwnedMetadataValue::String(s) => MetadataValue::String(s),
+                        OwnedMetadataValue::Boolean(b) => MetadataValue::Boolean(*b),
+                    },
+                })
+                .collect(),
+            tags: self.tags.iter().map(String::as_str).collect(),
+            flags: self.flags,
+            strings: self
+                .strings
+                .iter()
+                .map(|string| YrString {
+                    identifier: &string.identifier,
+                    kind: string.kind,
+                    modifiers: string.modifiers,
+                    literal: string.literal.as_deref(),
+                    matches: string.matches.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl<'r> From<Rule<'r>> for OwnedRule {
+    fn from(rule: Rule<'r>) -> Self {
+        OwnedRule {
+            identifier: rule.identifier.to_owned(),
+            namespace: rule.namespace.to_owned(),
+            metadatas: rule
+                .metadatas
+                .into_iter()
+                .map(OwnedMetadata::from)
+                .collect(),
+            tags: rule.tags.into_iter().map(str::to_owned).collect(),
+            flags: rule.flags,
+            strings: rule.strings.into_iter().map(OwnedYrString::from).collect(),
+        }
+    }
+}
+
+impl<'r> From<Metadata<'r>> for OwnedMetadata {
+    fn from(metadata: Metadata<'r>) -> Self {
+        let value = match metadata.value {
+            MetadataValue::Integer(i) => OwnedMetadataValue::Integer(i),
+            MetadataValue::String(s) => OwnedMetadataValue::String(s.to_owned()),
+            MetadataValue::Boolean(b) => OwnedMetadataValue::Boolean(b),
+        };
+
+        OwnedMetadata {
+            identifier: metadata.identifier.to_owned(),
+            value,
+        }
+    }
+}
+
+impl<'r> From<YrString<'r>> for OwnedYrString {
+    fn from(string: YrString<'r>) -> Self {
+        OwnedYrString {
+            identifier: string.identifier.to_owned(),
+            kind: string.kind,
+            modifiers: string.modifiers,
+            literal: string.literal.map(<[u8]>::to_vec),
+            matches: string.matches,
+        }
+    }
+}
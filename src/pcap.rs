@@ -0,0 +1,604 @@
+//! Scans of the TCP and UDP payloads of packet captures, in pcap or pcapng format.
+//!
+//! With [`Mode::Packets`], the payload of each packet is scanned. With [`Mode::Streams`], the
+//! TCP segments of each direction of a connection are reassembled in sequence order and scanned
+//! as a whole; UDP datagrams are still scanned one by one. Each [`Payload`] carries its [`Flow`],
+//! the time of its first packet, and its offset in the TCP stream.
+//!
+//! Ethernet (with VLAN tags), raw IP, BSD loopback and Linux cooked captures are decoded, over
+//! IPv4 and IPv6. Fragmented IP packets are skipped. The records of a truncated capture are
+//! scanned up to the truncation, which is then returned as an error.
+//!
+//! ```no_run
+//! use rs_yara::pcap::{self, Mode};
+//! use rs_yara::Compiler;
+//!
+//! let mut compiler = Compiler::new()?;
+//! compiler.add_rules_str(r#"rule get { strings: $a = "GET /" condition: $a }"#)?;
+//! let rules = compiler.compile_rules()?;
+//!
+//! pcap::scan_file(&rules, "capture.pcapng", Mode::Streams, 10, |payload, result| {
+//!     for rule in result.unwrap() {
+//!         println!("{} {} @{}", rule.identifier, payload.flow, payload.offset);
+//!     }
+//! })?;
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::errors::*;
+use crate::render::View;
+use crate::report::RuleReport;
+use crate::{Rule, Rules};
+
+/// What is scanned: each packet payload, or each reassembled TCP stream.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Mode {
+    #[default]
+    Packets,
+    Streams,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "packets" => Ok(Mode::Packets),
+            "streams" => Ok(Mode::Streams),
+            _ => Err(format!("unknown pcap mode `{}`", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+/// A direction of a connection, the 5-tuple of its packets.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+pub struct Flow {
+    pub protocol: Protocol,
+    pub src: IpAddr,
+    pub src_port: u16,
+    pub dst: IpAddr,
+    pub dst_port: u16,
+}
+
+impl fmt::Display for Flow {
+    /// `tcp 10.0.0.1:49152 > 10.0.0.2:80`, IPv6 addresses in brackets.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let protocol = match self.protocol {
+            Protocol::Tcp => "tcp",
+            Protocol::Udp => "udp",
+        };
+        let address = |ip: &IpAddr, port: u16| match ip {
+            IpAddr::V4(ip) => format!("{}:{}", ip, port),
+            IpAddr::V6(ip) => format!("[{}]:{}", ip, port),
+        };
+        write!(
+            f,
+            "{} {} > {}",
+            protocol,
+            address(&self.src, self.src_port),
+            address(&self.dst, self.dst_port)
+        )
+    }
+}
+
+/// Data scanned: a packet payload or a reassembled stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Payload {
+    pub flow: Flow,
+    /// The time of the first packet, in seconds since the epoch.
+    pub timestamp: f64,
+    /// The offset of the data in the TCP stream, `0` for UDP.
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// A captured link-layer frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame<'a> {
+    /// In seconds since the epoch.
+    pub timestamp: f64,
+    /// The `LINKTYPE_*` of the capture interface.
+    pub link_type: u32,
+    pub data: &'a [u8],
+}
+
+/// The frames of a pcap or pcapng capture, and whether it could be read to its end.
+///
+/// The frames of a truncated capture are the ones before the truncation.
+pub fn frames(capture: &[u8]) -> (Vec<Frame<'_>>, Result<(), PcapError>) {
+    let mut frames = Vec::new();
+    let result = match capture.get(..4) {
+        Some([0x0a, 0x0d, 0x0d, 0x0a]) => pcapng_frames(capture, &mut frames),
+        Some(_) => pcap_frames(capture, &mut frames),
+        None => Err(PcapError::UnknownFormat),
+    };
+    (frames, result)
+}
+
+/// The payloads to scan of a capture, in the order of their first packet, and whether it could
+/// be read to its end, see [`frames`].
+pub fn payloads(capture: &[u8], mode: Mode) -> (Vec<Payload>, Result<(), PcapError>) {
+    let mut payloads = Vec::new();
+    let mut streams: HashMap<Flow, Stream> = HashMap::new();
+    let mut order = Vec::new();
+
+    let (frames, result) = frames(capture);
+    for frame in frames {
+        let segment = match decode(&frame) {
+            Some(segment) => segment,
+            None => continue,
+        };
+        let (flow, data) = (segment.flow, segment.data);
+        let stream = match segment.tcp {
+            Some(tcp) => {
+                let stream = streams.entry(flow).or_insert_with(|| {
+                    order.push((payloads.len(), flow));
+                    Stream::default()
+                });
+                let offset = stream.offset(tcp.seq, tcp.syn);
+                Some((stream, offset))
+            }
+            None => None,
+        };
+        if data.is_empty() {
+            continue;
+        }
+        match (mode, stream) {
+            (Mode::Streams, Some((stream, Some(offset)))) => {
+                stream
+                    .segments
+                    .push((offset, frame.timestamp, data.to_vec()))
+            }
+            (Mode::Streams, Some((_, None))) => {}
+            (_, stream) => payloads.push(Payload {
+                flow,
+                timestamp: frame.timestamp,
+                offset: stream.and_then(|(_, offset)| offset).unwrap_or(0),
+                data: data.to_vec(),
+            }),
+        }
+    }
+
+    // Insert the streams where their first packet was, after the datagrams before it.
+    for (position, flow) in order.into_iter().rev() {
+        let stream = streams.remove(&flow).unwrap();
+        let assembled = stream
+            .assemble()
+            .into_iter()
+            .map(|(offset, timestamp, data)| Payload {
+                flow,
+                timestamp,
+                offset,
+                data,
+            });
+        payloads.splice(position..position, assembled);
+    }
+    (payloads, result)
+}
+
+/// Scan the payloads of a capture, calling `callback` with each payload and the result of its
+/// scan.
+///
+/// The payloads of a truncated capture are scanned before the truncation is returned.
+pub fn scan_mem<'r, F>(
+    rules: &'r Rules,
+    capture: &[u8],
+    mode: Mode,
+    timeout: u16,
+    mut callback: F,
+) -> Result<(), PcapError>
+where
+    F: FnMut(&Payload, Result<Vec<Rule<'r>>, YaraError>),
+{
+    let (payloads, result) = payloads(capture, mode);
+    for payload in payloads {
+        callback(&payload, rules.scan_mem(&payload.data, timeout));
+    }
+    result
+}
+
+/// Scan the payloads of a capture file, see [`scan_mem`].
+pub fn scan_file<'r, P, F>(
+    rules: &'r Rules,
+    path: P,
+    mode: Mode,
+    timeout: u16,
+    callback: F,
+) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Payload, Result<Vec<Rule<'r>>, YaraError>),
+{
+    let capture = fs::read(path).map_err(|e| IoError::new(e, IoErrorKind::ReadingScanFile))?;
+    scan_mem(rules, &capture, mode, timeout, callback)?;
+    Ok(())
+}
+
+/// The report of the scan of a payload. Match offsets are offsets in the stream.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PayloadReport {
+    /// The capture file.
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub flow: Flow,
+    pub timestamp: f64,
+    pub offset: u64,
+    pub length: usize,
+    pub rules: Vec<RuleReport>,
+}
+
+impl PayloadReport {
+    pub fn new<P: Into<PathBuf>>(path: P, payload: &Payload, rules: &[Rule], view: View) -> Self {
+        let mut rules: Vec<_> = rules.iter().map(|r| RuleReport::new(r, view)).collect();
+        for m in rules
+            .iter_mut()
+            .flat_map(|r| r.strings.iter_mut())
+            .flat_map(|s| s.matches.iter_mut())
+        {
+            m.offset += payload.offset as usize;
+        }
+        PayloadReport {
+            path: path.into(),
+            flow: payload.flow,
+            timestamp: payload.timestamp,
+            offset: payload.offset,
+            length: payload.data.len(),
+            rules,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("payload reports are serializable")
+    }
+}
+
+/// The segments of a direction of a TCP connection.
+#[derive(Default)]
+struct Stream {
+    /// The sequence number of the first byte of the stream.
+    start: Option<u32>,
+    /// Offset, timestamp and data of the segments.
+    segments: Vec<(u64, f64, Vec<u8>)>,
+}
+
+impl Stream {
+    /// The stream offset of the data of a segment, `None` before the start of the stream.
+    fn offset(&mut self, seq: u32, syn: bool) -> Option<u64> {
+        let data_seq = if syn { seq.wrapping_add(1) } else { seq };
+        let start = *self.start.get_or_insert(data_seq);
+        let offset = data_seq.wrapping_sub(start);
+        if offset < 1 << 31 {
+            Some(u64::from(offset))
+        } else {
+            None
+        }
+    }
+
+    /// The contiguous runs of data of the stream, without retransmitted bytes, with the time of
+    /// their first packet.
+    fn assemble(mut self) -> Vec<(u64, f64, Vec<u8>)> {
+        self.segments.sort_by_key(|(offset, _, _)| *offset);
+        let mut runs: Vec<(u64, f64, Vec<u8>)> = Vec::new();
+        for (offset, timestamp, data) in self.segments {
+            if let Some((start, first, run)) = runs.last_mut() {
+                let end = *start + run.len() as u64;
+                if offset <= end {
+                    *first = first.min(timestamp);
+                    let overlap = (end - offset) as usize;
+                    if overlap < data.len() {
+                        run.extend_from_slice(&data[overlap..]);
+                    }
+                    continue;
+                }
+            }
+            runs.push((offset, timestamp, data));
+        }
+        runs
+    }
+}
+
+struct Segment<'a> {
+    flow: Flow,
+    tcp: Option<Tcp>,
+    data: &'a [u8],
+}
+
+struct Tcp {
+    seq: u32,
+    syn: bool,
+}
+
+fn pcap_frames<'a>(capture: &'a [u8], frames: &mut Vec<Frame<'a>>) -> Result<(), PcapError> {
+    let (endian, nanos) = match capture[..4] {
+        [0xd4, 0xc3, 0xb2, 0xa1] => (Endian::Little, false),
+        [0xa1, 0xb2, 0xc3, 0xd4] => (Endian::Big, false),
+        [0x4d, 0x3c, 0xb2, 0xa1] => (Endian::Little, true),
+        [0xa1, 0xb2, 0x3c, 0x4d] => (Endian::Big, true),
+        _ => return Err(PcapError::UnknownFormat),
+    };
+    let link_type = endian.u32(capture, 20)?;
+    let fraction = if nanos { 1e-9 } else { 1e-6 };
+
+    let mut offset = 24;
+    while offset < capture.len() {
+        let seconds = endian.u32(capture, offset)?;
+        let fractions = endian.u32(capture, offset + 4)?;
+        let length = endian.u32(capture, offset + 8)? as usize;
+        let data = slice(capture, offset + 16, length)?;
+        frames.push(Frame {
+            timestamp: f64::from(seconds) + f64::from(fractions) * fraction,
+            link_type,
+            data,
+        });
+        offset += 16 + length;
+    }
+    Ok(())
+}
+
+fn pcapng_frames<'a>(capture: &'a [u8], frames: &mut Vec<Frame<'a>>) -> Result<(), PcapError> {
+    const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+    const INTERFACE_DESCRIPTION: u32 = 1;
+    const SIMPLE_PACKET: u32 = 3;
+    const ENHANCED_PACKET: u32 = 6;
+
+    let mut endian = Endian::Little;
+    // Link type and timestamp resolution of the interfaces of the section.
+    let mut interfaces: Vec<(u32, f64)> = Vec::new();
+    let mut offset = 0;
+    while offset < capture.len() {
+        if slice(capture, offset, 4)? == [0x0a, 0x0d, 0x0d, 0x0a] {
+            endian = match slice(capture, offset + 8, 4)? {
+                [0x4d, 0x3c, 0x2b, 0x1a] => Endian::Little,
+                [0x1a, 0x2b, 0x3c, 0x4d] => Endian::Big,
+                _ => return Err(PcapError::UnknownFormat),
+            };
+            interfaces.clear();
+        }
+        let block_type = endian.u32(capture, offset)?;
+        let length = endian.u32(capture, offset + 4)? as usize;
+        if length < 12 {
+            return Err(PcapError::Truncated(offset));
+        }
+        let body = slice(capture, offset + 8, length - 12)?;
+
+        match block_type {
+            SECTION_HEADER => {}
+            INTERFACE_DESCRIPTION => {
+                let link_type = u32::from(
+                    endian
+                        .u16(body, 0)
+                        .map_err(|_| PcapError::Truncated(offset))?,
+                );
+                interfaces.push((
+                    link_type,
+                    timestamp_resolution(body.get(8..).unwrap_or_default(), endian),
+                ));
+            }
+            ENHANCED_PACKET => {
+                let field = |at| {
+                    endian
+                        .u32(body, at)
+                        .map_err(|_| PcapError::Truncated(offset))
+                };
+                let (link_type, resolution) = match interfaces.get(field(0)? as usize) {
+                    Some(interface) => *interface,
+                    None => (0, 1e-6),
+                };
+                let timestamp = (u64::from(field(4)?) << 32) | u64::from(field(8)?);
+                let data = slice(body, 20, field(12)? as usize)
+                    .map_err(|_| PcapError::Truncated(offset))?;
+                frames.push(Frame {
+                    timestamp: timestamp as f64 * resolution,
+                    link_type,
+                    data,
+                });
+            }
+            SIMPLE_PACKET => {
+                let original = endian
+                    .u32(body, 0)
+                    .map_err(|_| PcapError::Truncated(offset))?
+                    as usize;
+                let data = &body[4..];
+                frames.push(Frame {
+                    timestamp: 0.0,
+                    link_type: interfaces.first().map_or(0, |i| i.0),
+                    data: &data[..original.min(data.len())],
+                });
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+    Ok(())
+}
+
+/// The resolution of the timestamps of an interface, from its `if_tsresol` option.
+fn timestamp_resolution(mut options: &[u8], endian: Endian) -> f64 {
+    while let (Ok(code), Ok(length)) = (endian.u16(options, 0), endian.u16(options, 2)) {
+        let length = length as usize;
+        match (code, options.get(4)) {
+            (0, _) | (_, None) => break,
+            (9, Some(&resolution)) if resolution & 0x80 == 0 => {
+                return 10f64.powi(-i32::from(resolution))
+            }
+            (9, Some(&resolution)) => return 2f64.powi(-i32::from(resolution & 0x7f)),
+            _ => {}
+        }
+        options = options.get(4 + length.div_ceil(4) * 4..).unwrap_or_default();
+    }
+    1e-6
+}
+
+/// The flow and the payload of a TCP or UDP packet.
+fn decode<'a>(frame: &Frame<'a>) -> Option<Segment<'a>> {
+    const ETHERNET: u32 = 1;
+    const NULL: u32 = 0;
+    const RAW: [u32; 3] = [101, 228, 229];
+    const LINUX_SLL: u32 = 113;
+    const LINUX_SLL2: u32 = 276;
+
+    let data = frame.data;
+    // The offset of the EtherType and of the packet.
+    let (ether_type, packet) = match frame.link_type {
+        ETHERNET => {
+            let mut offset = 12;
+            // 802.1Q and 802.1ad tags.
+            while let Some(0x8100) | Some(0x88a8) = be16(data, offset) {
+                offset += 4;
+            }
+            (Some(offset), offset + 2)
+        }
+        LINUX_SLL => (Some(14), 16),
+        LINUX_SLL2 => (Some(0), 20),
+        NULL => (None, 4),
+        link_type if RAW.contains(&link_type) => (None, 0),
+        _ => return None,
+    };
+    if let Some(offset) = ether_type {
+        if !matches!(be16(data, offset)?, 0x0800 | 0x86dd) {
+            return None;
+        }
+    }
+    let packet = data.get(packet..)?;
+
+    let (src, dst, protocol, transport) = match packet.first()? >> 4 {
+        4 => {
+            let header = usize::from(packet[0] & 0x0f) * 4;
+            let length = usize::from(be16(packet, 2)?);
+            // More fragments, or a fragment offset.
+            if be16(packet, 6)? & 0x3fff != 0 {
+                return None;
+            }
+            let address = |at: usize| {
+                packet
+                    .get(at..at + 4)
+                    .map(|a| Ipv4Addr::new(a[0], a[1], a[2], a[3]))
+            };
+            (
+                IpAddr::V4(address(12)?),
+                IpAddr::V4(address(16)?),
+                packet[9],
+                packet.get(header..length.min(packet.len()))?,
+            )
+        }
+        6 => {
+            let address = |at: usize| {
+                let mut bytes = [0; 16];
+                bytes.copy_from_slice(packet.get(at..at + 16)?);
+                Some(Ipv6Addr::from(bytes))
+            };
+            let length = usize::from(be16(packet, 4)?);
+            let mut next = *packet.get(6)?;
+            let mut payload = packet.get(40..(40 + length).min(packet.len()))?;
+            loop {
+                let header = match next {
+                    // Hop-by-hop, routing and destination options.
+                    0 | 43 | 60 => (usize::from(*payload.get(1)?) + 1) * 8,
+                    // Authentication header.
+                    51 => (usize::from(*payload.get(1)?) + 2) * 4,
+                    // Fragment header.
+                    44 => return None,
+                    _ => break,
+                };
+                next = payload[0];
+                payload = payload.get(header..)?;
+            }
+            (
+                IpAddr::V6(address(8)?),
+                IpAddr::V6(address(24)?),
+                next,
+                payload,
+            )
+        }
+        _ => return None,
+    };
+
+    let (protocol, tcp, data) = match protocol {
+        6 => {
+            // The flags end the fixed part of the header, which the data offset covers.
+            transport.get(13)?;
+            let header = usize::from(transport[12] >> 4) * 4;
+            if header < 20 {
+                return None;
+            }
+            let seq = u32::from_be_bytes([transport[4], transport[5], transport[6], transport[7]]);
+            let tcp = Tcp {
+                seq,
+                syn: transport[13] & 0x02 != 0,
+            };
+            (Protocol::Tcp, Some(tcp), transport.get(header..)?)
+        }
+        17 => {
+            let length = usize::from(be16(transport, 4)?).max(8);
+            (
+                Protocol::Udp,
+                None,
+                transport.get(8..length.min(transport.len()))?,
+            )
+        }
+        _ => return None,
+    };
+    Some(Segment {
+        flow: Flow {
+            protocol,
+            src,
+            src_port: be16(transport, 0)?,
+            dst,
+            dst_port: be16(transport, 2)?,
+        },
+        tcp,
+        data,
+    })
+}
+
+#[derive(Clone, Copy)]
+enum Endian {
+    Little,
+    Big,
+}
+
+impl Endian {
+    fn u16(self, data: &[u8], at: usize) -> Result<u16, PcapError> {
+        let s = slice(data, at, 2)?;
+        let bytes = [s[0], s[1]];
+        Ok(match self {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(self, data: &[u8], at: usize) -> Result<u32, PcapError> {
+        let s = slice(data, at, 4)?;
+        let bytes = [s[0], s[1], s[2], s[3]];
+        Ok(match self {
+            Endian::Little => u32::from_le_bytes(bytes),
+            Endian::Big => u32::from_be_bytes(bytes),
+        })
+    }
+}
+
+fn slice(data: &[u8], at: usize, length: usize) -> Result<&[u8], PcapError> {
+    data.get(at..at.saturating_add(length))
+        .ok_or(PcapError::Truncated(at))
+}
+
+fn be16(data: &[u8], at: usize) -> Option<u16> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+}
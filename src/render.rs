@@ -0,0 +1,135 @@
+//! Text views of matched data.
+//!
+//! ```
+//! use rs_yara::render::{self, View};
+//!
+//! assert_eq!(r#"Rust\x00\n"#, render::escape_ascii(b"Rust\0\n"));
+//! assert_eq!("Rust", render::escape_wide(b"R\0u\0s\0t\0"));
+//! assert_eq!(
+//!     "00000010  52 75 73 74                                       |Rust|",
+//!     View::Hex.render(b"Rust", 0x10)
+//! );
+//! ```
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+/// Bytes per line of [`hexdump`].
+const HEXDUMP_WIDTH: usize = 16;
+
+/// A view of matched data.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum View {
+    /// See [`escape_ascii`].
+    #[default]
+    Ascii,
+    /// See [`escape_wide`].
+    Wide,
+    /// See [`hexdump`].
+    Hex,
+}
+
+impl View {
+    /// Render `data`, found at `offset` of the scanned target.
+    pub fn render(self, data: &[u8], offset: usize) -> String {
+        match self {
+            View::Ascii => escape_ascii(data),
+            View::Wide => escape_wide(data),
+            View::Hex => hexdump(data, offset),
+        }
+    }
+}
+
+impl FromStr for View {
+    type Err = String;
+
+    fn from_str(view: &str) -> Result<Self, Self::Err> {
+        match view {
+            "ascii" => Ok(View::Ascii),
+            "wide" => Ok(View::Wide),
+            "hex" => Ok(View::Hex),
+            _ => Err(format!(
+                "unknown view `{}`, expected ascii, wide or hex",
+                view
+            )),
+        }
+    }
+}
+
+/// Printable ASCII as is, other bytes escaped as in rule strings: `\n`, `\t`, `\r`, `\"`, `\\`
+/// and `\xNN`.
+pub fn escape_ascii(data: &[u8]) -> String {
+    let mut escaped = String::with_capacity(data.len());
+    for &byte in data {
+        escape_char(&mut escaped, char::from(byte));
+    }
+    escaped
+}
+
+/// `data` decoded as UTF-16LE, the encoding of `wide` strings, and escaped as by
+/// [`escape_ascii`].
+///
+/// Characters out of ASCII are escaped as `\u{NNNN}`, a trailing odd byte as `\xNN`.
+pub fn escape_wide(data: &[u8]) -> String {
+    let units = data
+        .chunks_exact(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+    let mut escaped = String::with_capacity(data.len() / 2);
+    for c in char::decode_utf16(units) {
+        match c {
+            Ok(c) if c.is_ascii() => escape_char(&mut escaped, c),
+            Ok(c) => write!(escaped, "\\u{{{:04x}}}", u32::from(c)).unwrap(),
+            Err(e) => write!(escaped, "\\u{{{:04x}}}", e.unpaired_surrogate()).unwrap(),
+        }
+    }
+    if data.len() % 2 == 1 {
+        escape_char(&mut escaped, char::from(data[data.len() - 1]));
+    }
+    escaped
+}
+
+fn escape_char(escaped: &mut String, c: char) {
+    match c {
+        '\n' => escaped.push_str("\\n"),
+        '\t' => escaped.push_str("\\t"),
+        '\r' => escaped.push_str("\\r"),
+        '"' => escaped.push_str("\\\""),
+        '\\' => escaped.push_str("\\\\"),
+        ' '..='~' => escaped.push(c),
+        _ => write!(escaped, "\\x{:02x}", u32::from(c)).unwrap(),
+    }
+}
+
+/// Lines of 16 bytes: the offset, the bytes in hexadecimal and the printable ASCII, as
+/// `hexdump -C`.
+///
+/// `offset` is the offset of the first byte of `data`. Lines are separated by `\n`, without a
+/// trailing one.
+pub fn hexdump(data: &[u8], offset: usize) -> String {
+    let mut dump = String::new();
+    for (i, line) in data.chunks(HEXDUMP_WIDTH).enumerate() {
+        if i > 0 {
+            dump.push('\n');
+        }
+        write!(dump, "{:08x} ", offset + i * HEXDUMP_WIDTH).unwrap();
+        for column in 0..HEXDUMP_WIDTH {
+            if column % 8 == 0 {
+                dump.push(' ');
+            }
+            match line.get(column) {
+                Some(byte) => write!(dump, "{:02x} ", byte).unwrap(),
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str(" |");
+        dump.extend(line.iter().map(|&b| match b {
+            b' '..=b'~' => char::from(b),
+            _ => '.',
+        }));
+        dump.push('|');
+    }
+    dump
+}
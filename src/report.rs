@@ -0,0 +1,164 @@
+//! Serializable reports of scans, for JSON output.
+//!
+//! Matched data is rendered with a [`View`], the context of matches is reported when the
+//! scan captured it, see [`Rules::set_context`](crate::Rules::set_context).
+//!
+//! ```
+//! use rs_yara::render::View;
+//! use rs_yara::report::FileReport;
+//! use rs_yara::Compiler;
+//!
+//! let mut compiler = Compiler::new()?;
+//! compiler.add_rules_str(r#"rule rust { strings: $a = "Rust" condition: $a }"#)?;
+//! let mut rules = compiler.compile_rules()?;
+//! rules.set_context(7);
+//!
+//! let matches = rules.scan_mem(b"I love Rust!", 10)?;
+//! let report = FileReport::new("inline", &matches, View::Ascii);
+//! let context = report.rules[0].strings[0].matches[0].context.as_ref().unwrap();
+//! assert_eq!(("I love ", "!"), (context.before.as_str(), context.after.as_str()));
+//! assert!(report.to_json().starts_with(r#"{"path":"inline","rules":[{"identifier":"rust""#));
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::render::View;
+use crate::{FileInfo, Match, OwnedMetadata, Rule, StringKind, StringModifiers, YrString};
+
+/// The matching rules of a scanned file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub rules: Vec<RuleReport>,
+    /// The identity of the file, when the scan computed it.
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub info: Option<FileInfo>,
+    /// Why the file could not be scanned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct RuleReport {
+    pub identifier: String,
+    pub namespace: String,
+    pub tags: Vec<String>,
+    pub metadata: Vec<OwnedMetadata>,
+    /// Strings with matches, so never the private ones.
+    pub strings: Vec<StringReport>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct StringReport {
+    /// Name of the string, with the `$`.
+    pub identifier: String,
+    pub kind: StringKind,
+    /// The modifiers the string matched with, e.g. `["wide", "nocase"]`.
+    pub modifiers: StringModifiers,
+    pub matches: Vec<MatchReport>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct MatchReport {
+    pub offset: usize,
+    pub length: usize,
+    /// The matched data, rendered.
+    pub data: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<ContextReport>,
+}
+
+/// The bytes around a match, rendered.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ContextReport {
+    pub before: String,
+    pub after: String,
+}
+
+impl FileReport {
+    pub fn new<P: Into<PathBuf>>(path: P, rules: &[Rule], view: View) -> Self {
+        FileReport {
+            path: path.into(),
+            rules: rules.iter().map(|r| RuleReport::new(r, view)).collect(),
+            info: None,
+            error: None,
+        }
+    }
+
+    /// The report with the identity of the file.
+    pub fn with_info(mut self, info: FileInfo) -> Self {
+        self.info = Some(info);
+        self
+    }
+
+    /// The report of a file which could not be scanned.
+    pub fn error<P: Into<PathBuf>>(path: P, error: &dyn std::error::Error) -> Self {
+        FileReport {
+            path: path.into(),
+            rules: Vec::new(),
+            info: None,
+            error: Some(error.to_string()),
+        }
+    }
+
+    /// The report as JSON, on one line.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("report should serialize")
+    }
+}
+
+impl RuleReport {
+    pub fn new(rule: &Rule, view: View) -> Self {
+        RuleReport {
+            identifier: rule.identifier.to_owned(),
+            namespace: rule.namespace.to_owned(),
+            tags: rule.tags.iter().map(|&t| t.to_owned()).collect(),
+            metadata: rule
+                .metadatas
+                .iter()
+                .cloned()
+                .map(OwnedMetadata::from)
+                .collect(),
+            strings: rule
+                .strings
+                .iter()
+                .filter(|s| !s.matches.is_empty())
+                .map(|s| StringReport::new(s, view))
+                .collect(),
+        }
+    }
+}
+
+impl StringReport {
+    pub fn new(string: &YrString, view: View) -> Self {
+        StringReport {
+            identifier: string.identifier.to_owned(),
+            kind: string.kind,
+            modifiers: string.modifiers,
+            matches: string
+                .matches
+                .iter()
+                .map(|m| MatchReport::new(m, view))
+                .collect(),
+        }
+    }
+}
+
+impl MatchReport {
+    /// The report of a match, with the whole matched data when the context was captured.
+    pub fn new(m: &Match, view: View) -> Self {
+        let data = m.context.as_ref().map_or(&m.data, |c| &c.data);
+        MatchReport {
+            offset: m.offset,
+            length: m.length,
+            data: view.render(data, m.offset),
+            context: m.context.as_ref().map(|c| ContextReport {
+                before: view.render(&c.before, c.offset),
+                after: view.render(&c.after, m.offset + c.data.len()),
+            }),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::ffi::CStr;
+use std::marker;
+use std::slice;
+
+use crate::meta::MetadataIterator;
+use crate::rules::TagIterator;
+use crate::string::YrStringIterator;
+use crate::{yara_sys, Metadata, OwnedMetadata, RuleFlags, StringKind, StringModifiers};
+
+/// A rule of compiled rules, which does not borrow from the `Rules`.
+///
+/// See [`Rules::get_rules`](struct.Rules.html#method.get_rules).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RuleInfo {
+    /// Name of the rule.
+    pub identifier: String,
+    /// Namespace of the rule.
+    pub namespace: String,
+    /// Metadatas of the rule.
+    pub metadatas: Vec<OwnedMetadata>,
+    /// Tags of the rule.
+    pub tags: Vec<String>,
+    /// Private and global flags of the rule.
+    pub flags: RuleFlags,
+    /// Strings of the rule.
+    pub strings: Vec<StringInfo>,
+}
+
+/// A string of a compiled rule.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StringInfo {
+    /// Name of the string, with the '$'.
+    pub identifier: String,
+    /// Text, hexadecimal string or regular expression.
+    pub kind: StringKind,
+    pub modifiers: StringModifiers,
+    /// The value of the string, as stored by the compiler.
+    pub data: Vec<u8>,
+}
+
+/// Iterate over the YR_RULE of a YR_RULES, until the null rule.
+pub(crate) struct RuleIterator<'a> {
+    head: *const yara_sys::YR_RULE,
+    _marker: marker::PhantomData<&'a yara_sys::YR_RULE>,
+}
+
+impl<'a> From<&'a yara_sys::YR_RULES> for RuleIterator<'a> {
+    fn from(rules: &'a yara_sys::YR_RULES) -> Self {
+        RuleIterator {
+            head: rules.rules_list_head,
+            _marker: marker::PhantomData,
+        }
+    }
+}
+
+impl<'a> Iterator for RuleIterator<'a> {
+    type Item = &'a yara_sys::YR_RULE;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.head.is_null() {
+            return None;
+        }
+
+        let rule = unsafe { &*self.head };
+        if rule.g_flags as u32 & yara_sys::RULE_GFLAGS_NULL != 0 {
+            None
+        } else {
+            self.head = unsafe { self.head.offset(1) };
+            Some(rule)
+        }
+    }
+}
+
+impl<'a> From<&'a yara_sys::YR_RULE> for RuleInfo {
+    fn from(rule: &'a yara_sys::YR_RULE) -> Self {
+        let identifier = unsafe { CStr::from_ptr(rule.get_identifier()) };
+        let namespace = unsafe { CStr::from_ptr((*rule.get_ns()).get_name()) };
+        let metadatas = MetadataIterator::from(rule)
+            .map(|m| OwnedMetadata::from(Metadata::from(m)))
+            .collect();
+        let tags = TagIterator::from(rule)
+            .map(|t| t.to_string_lossy().into_owned())
+            .collect();
+        let strings = YrStringIterator::from(rule).map(StringInfo::from).collect();
+
+        RuleInfo {
+            identifier: identifier.to_string_lossy().into_owned(),
+            namespace: namespace.to_string_lossy().into_owned(),
+            metadatas,
+            tags,
+            flags: RuleFlags::from_flags(rule.g_flags),
+            strings,
+        }
+    }
+}
+
+impl<'a> From<&'a yara_sys::YR_STRING> for StringInfo {
+    fn from(string: &'a yara_sys::YR_STRING) -> Self {
+        let identifier = unsafe { CStr::from_ptr(string.get_identifier()) };
+        let data = if string.get_string().is_null() || string.length <= 0 {
+            Vec::new()
+        } else {
+            unsafe {
+                slice::from_raw_parts(string.get_string() as *const u8, string.length as usize)
+            }
+            .to_vec()
+        };
+
+        StringInfo {
+            identifier: identifier.to_string_lossy().into_owned(),
+            kind: StringKind::from_flags(string.g_flags),
+            modifiers: StringModifiers::from_flags(string.g_flags),
+            data,
+        }
+    }
+}
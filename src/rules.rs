@@ -1,25 +1,35 @@
 use std::ffi::{CStr, CString};
-use std::io::{Read, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
 use std::marker;
 use std::os::raw::c_char;
 use std::ptr;
 
 use std::convert::TryFrom;
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::Path;
-use  crate::{initialize::InitializationToken, meta::MetadataIterator, rules_scan_file, rules_scan_mem, string::{YrString, YrStringIterator}, yara_sys::{self, scan_flags::*}};
+use  crate::{blocks::{MemoryBlockSource, MemoryBlocks}, initialize::InitializationToken, meta::MetadataIterator, rules_scan_file, rules_scan_mem, rules_scan_mem_blocks, rules_scan_reader, string::{YrString, YrStringIterator}, yara_sys::{self, scan_flags::*}};
 
+use crate::context::add_context;
+use crate::scan::rules_scan_mem_with_variables;
+use crate::{FileInfo, RuleFlags, VariableValue};
 use crate::errors::*;
+use crate::MatchContext;
+use crate::rule_info::{RuleInfo, RuleIterator};
 
 pub struct Rules {
     inner: *mut yara_sys::YR_RULES,
     pub(crate) _token: InitializationToken,
     flags: u32,
+    context: usize,
 }
 
 /// This is safe because Yara have a mutex on the YR_RULES
 unsafe impl std::marker::Sync for Rules {}
 
+/// This is safe because the YR_RULES are not tied to a thread, and the `InitializationToken`
+/// keeps track of the thread it was created in.
+unsafe impl std::marker::Send for Rules {}
+
 impl TryFrom<*mut yara_sys::YR_RULES> for Rules {
     type Error = YaraError;
 
@@ -30,6 +40,7 @@ impl TryFrom<*mut yara_sys::YR_RULES> for Rules {
             inner: rules,
             _token: token,
             flags: 0,
+            context: 0,
         })
     }
 }
@@ -40,27 +51,212 @@ impl Rules {
         // storage before 3.8.
         let _token = InitializationToken::new()?;
 
-        rules_scan_mem(self.inner, mem, i32::from(timeout), self.flags as i32)
+        let mut rules = rules_scan_mem(self.inner, mem, i32::from(timeout), self.flags as i32)?;
+        if self.context > 0 {
+            add_context(&mut rules, |m| {
+                Ok::<_, YaraError>(MatchContext::from_slice(mem, 0, m, self.context))
+            })?;
+        }
+        Ok(rules)
+    }
+
+    /// Scan memory, with values of external variables for this scan only.
+    ///
+    /// The variables must have been defined when compiling the rules, see
+    /// [`variables`](#method.variables).
+    pub fn scan_mem_with_variables(
+        &self,
+        mem: &[u8],
+        variables: &[(&str, VariableValue)],
+        timeout: u16,
+    ) -> Result<Vec<Rule<'_>>, YaraError> {
+        let _token = InitializationToken::new()?;
+
+        let mut rules = rules_scan_mem_with_variables(
+            self.inner,
+            mem,
+            variables,
+            i32::from(timeout),
+            self.flags as i32,
+        )?;
+        if self.context > 0 {
+            add_context(&mut rules, |m| {
+                Ok::<_, YaraError>(MatchContext::from_slice(mem, 0, m, self.context))
+            })?;
+        }
+        Ok(rules)
+    }
+
+    /// The identifiers of the external variables of the rules.
+    pub fn variables(&self) -> Vec<&str> {
+        let mut variables = Vec::new();
+        let mut head: *const yara_sys::YR_EXTERNAL_VARIABLE =
+            unsafe { (*self.inner).externals_list_head };
+        while !head.is_null() {
+            let variable = unsafe { &*head };
+            if variable.type_ as u32 == yara_sys::EXTERNAL_VARIABLE_TYPE_NULL {
+                break;
+            }
+            let identifier = unsafe { CStr::from_ptr(variable.get_identifier()) };
+            variables.push(identifier.to_str().unwrap());
+            head = unsafe { head.offset(1) };
+        }
+        variables
     }
 
     /// Scan a file.
     ///
     /// Return a `Vec` of matching rules.
-    pub fn scan_file<'r, P: AsRef<Path>>(
+    pub fn scan_file<P: AsRef<Path>>(
         &self,
         path: P,
         timeout: u16,
-    ) -> Result<Vec<Rule<'r>>, Error> {
+    ) -> Result<Vec<Rule<'_>>, Error> {
         // The token needed here because scanning allocate space for regexp on the thread_local
         // storage before 3.8.
         let _token = InitializationToken::new()?;
 
-        File::open(path)
-            .map_err(|e| IoError::new(e, IoErrorKind::OpenScanFile).into())
-            .and_then(|file| {
-                rules_scan_file(self.inner, &file, i32::from(timeout), self.flags as i32)
-                    .map_err(|e| e.into())
-            })
+        let file = File::open(path).map_err(|e| IoError::new(e, IoErrorKind::OpenScanFile))?;
+        let mut rules = rules_scan_file(self.inner, &file, i32::from(timeout), self.flags as i32)?;
+        if self.context > 0 {
+            add_context(&mut rules, |m| {
+                MatchContext::from_file(&file, m, self.context)
+                    .map(Some)
+                    .map_err(|e| IoError::new(e, IoErrorKind::ReadingScanFile))
+            })?;
+        }
+        Ok(rules)
+    }
+
+    /// Scan a file, and identify it with its [`FileInfo`], computed from the data read for the
+    /// scan.
+    ///
+    /// The file is read in memory, unlike with [`scan_file`](#method.scan_file).
+    pub fn scan_file_with_info<P: AsRef<Path>>(
+        &self,
+        path: P,
+        timeout: u16,
+    ) -> Result<(Vec<Rule<'_>>, FileInfo), Error> {
+        let data = fs::read(path).map_err(|e| IoError::new(e, IoErrorKind::ReadingScanFile))?;
+        let info = FileInfo::new(&data);
+        Ok((self.scan_mem(&data, timeout)?, info))
+    }
+
+    /// Scan `len` bytes of a file, starting at `offset`.
+    ///
+    /// Only the region is read. Match offsets and `uintXX(addr)` in conditions are relative to
+    /// the start of the file, while `filesize` is the size of the region.
+    /// A region past the end of the file is truncated.
+    ///
+    /// Return a `Vec` of matching rules.
+    pub fn scan_file_range<P: AsRef<Path>>(
+        &self,
+        path: P,
+        offset: u64,
+        len: u64,
+        timeout: u16,
+    ) -> Result<Vec<Rule<'_>>, Error> {
+        self.scan_file_region(path, offset, len, offset, timeout)
+    }
+
+    /// Scan `len` bytes of a file, starting at `offset`.
+    ///
+    /// Same as [`scan_file_range`](#method.scan_file_range), but offsets are relative to the
+    /// start of the region.
+    pub fn scan_file_range_relative<P: AsRef<Path>>(
+        &self,
+        path: P,
+        offset: u64,
+        len: u64,
+        timeout: u16,
+    ) -> Result<Vec<Rule<'_>>, Error> {
+        self.scan_file_region(path, offset, len, 0, timeout)
+    }
+
+    fn scan_file_region<P: AsRef<Path>>(
+        &self,
+        path: P,
+        offset: u64,
+        len: u64,
+        base: u64,
+        timeout: u16,
+    ) -> Result<Vec<Rule<'_>>, Error> {
+        let mut file =
+            File::open(path).map_err(|e| IoError::new(e, IoErrorKind::OpenScanFile))?;
+        let mut region = Vec::new();
+        file.seek(SeekFrom::Start(offset))
+            .and_then(|_| file.take(len).read_to_end(&mut region))
+            .map_err(|e| IoError::new(e, IoErrorKind::ReadingScanFile))?;
+
+        let mut rules = self.scan_blocks(MemoryBlocks::new(vec![(base, &region[..])]), timeout)?;
+        if self.context > 0 {
+            add_context(&mut rules, |m| {
+                Ok::<_, YaraError>(MatchContext::from_slice(
+                    &region,
+                    base as usize,
+                    m,
+                    self.context,
+                ))
+            })?;
+        }
+        Ok(rules)
+    }
+
+    /// Scan data from a reader, without buffering all of it in memory.
+    ///
+    /// The data is scanned in windows of `window` bytes. Each window starts `window - overlap`
+    /// bytes after the previous one, so a string match up to `overlap` bytes long is never lost
+    /// at a window boundary. Matches found in several windows are reported once, and every
+    /// offset is relative to the start of the stream.
+    ///
+    /// Conditions are evaluated on each window independently, which changes the meaning of:
+    ///
+    /// - `filesize`: the size of the current window, not of the stream.
+    /// - `$a at N`, `$a in (N..M)` and `uintXX(N)`: absolute offsets are supported, but only
+    ///   the data of the current window can be read.
+    /// - `#a`, `@a[i]`, `!a[i]`, `all of them`, `not $a`: they only see the matches of the current
+    ///   window, so a rule whose strings are far apart in the stream may not match, and a rule
+    ///   negating a string may match.
+    /// - `entrypoint` and modules: they parse the current window as if it was a whole file.
+    ///
+    /// Return a `Vec` of matching rules, or [`Error::InvalidArgument`] if `window` is 0 or
+    /// `overlap` is not smaller than `window`.
+    pub fn scan_reader<R: Read>(
+        &self,
+        mut reader: R,
+        window: usize,
+        overlap: usize,
+        timeout: u16,
+    ) -> Result<Vec<Rule<'_>>, Error> {
+        // The token needed here because scanning allocate space for regexp on the thread_local
+        // storage before 3.8.
+        let _token = InitializationToken::new()?;
+
+        rules_scan_reader(
+            self.inner,
+            &mut reader,
+            window,
+            overlap,
+            i32::from(timeout),
+            self.flags as i32,
+        )
+    }
+
+    /// Scan memory blocks as one logical target.
+    ///
+    /// Match offsets and `uintXX(addr)` in conditions use the base addresses given by `source`.
+    ///
+    /// Return a `Vec` of matching rules.
+    pub fn scan_blocks<S: MemoryBlockSource>(
+        &self,
+        mut source: S,
+        timeout: u16,
+    ) -> Result<Vec<Rule<'_>>, YaraError> {
+        // The token needed here because scanning allocate space for regexp on the thread_local
+        // storage before 3.8.
+        let _token = InitializationToken::new()?;
+
+        rules_scan_mem_blocks(self.inner, &mut source, i32::from(timeout), self.flags as i32)
     }
 
     /// Save the rules to a file.
@@ -75,6 +271,8 @@ impl Rules {
     /// Save the rules in a Writer.
     ///
     /// Note: this method is mut because Yara modifies the Rule arena during serialization.
+    ///
+    /// Only compiled rules can be saved: libyara aborts on rules loaded from a file or stream.
     pub fn save_to_stream<W>(&mut self, writer: W) -> Result<(), Error>
     where
         W: Write,
@@ -90,6 +288,7 @@ impl Rules {
             inner,
             _token: token,
             flags: 0,
+            context: 0,
         })
     }
 
@@ -102,12 +301,41 @@ impl Rules {
             inner,
             _token: token,
             flags: 0,
+            context: 0,
         })
     }
 
+    /// Get statistics on the rules, such as the number of rules and strings.
+    pub fn stats(&self) -> Result<yara_sys::YR_RULES_STATS, YaraError> {
+        rules_get_stats(self.inner)
+    }
+
     pub fn set_flags(&mut self, flags: u32) {
         self.flags = flags
     }
+
+    pub fn flags(&self) -> u32 {
+        self.flags
+    }
+
+    /// Capture `bytes` bytes before and after each match, in [`Match::context`](crate::Match::context).
+    ///
+    /// Only done by the scans of memory and files, `scan_mem`, `scan_file` and
+    /// `scan_file_range*`. 0, the default, captures nothing.
+    pub fn set_context(&mut self, bytes: usize) {
+        self.context = bytes
+    }
+
+    pub fn context(&self) -> usize {
+        self.context
+    }
+
+    /// Get the rules of the rule set, in the order of their declaration.
+    pub fn get_rules(&self) -> Vec<RuleInfo> {
+        RuleIterator::from(unsafe { &*self.inner })
+            .map(RuleInfo::from)
+            .collect()
+    }
 }
 
 impl Drop for Rules {
@@ -127,19 +355,21 @@ pub struct Rule<'r> {
     pub metadatas: Vec<Metadata<'r>>,
     /// Tags of the rule.
     pub tags: Vec<&'r str>,
+    /// Private and global flags of the rule.
+    pub flags: RuleFlags,
     /// Matcher strings of the rule.
     pub strings: Vec<YrString<'r>>,
 }
 
 /// Metadata specified in a rule.
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct Metadata<'r> {
     pub identifier: &'r str,
     pub value: MetadataValue<'r>,
 }
 
 /// Type of the value in [MetaData](struct.Metadata.html)
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum MetadataValue<'r> {
     Integer(i64),
     String(&'r str),
@@ -153,6 +383,16 @@ pub fn rules_destroy(rules: *mut yara_sys::YR_RULES) {
     }
 }
 
+pub(crate) fn rules_get_stats(
+    rules: *mut yara_sys::YR_RULES,
+) -> Result<yara_sys::YR_RULES_STATS, YaraError> {
+    let mut stats = std::mem::MaybeUninit::<yara_sys::YR_RULES_STATS>::uninit();
+    let result = unsafe { yara_sys::yr_rules_get_stats(rules, stats.as_mut_ptr()) };
+    yara_sys::Error::from_code(result)
+        .map(|()| unsafe { stats.assume_init() })
+        .map_err(|e| e.into())
+}
+
 // TODO Check if non mut
 pub fn rules_save(rules: *mut yara_sys::YR_RULES, filename: &str) -> Result<(), YaraError> {
     let filename = CString::new(filename).unwrap();
@@ -227,12 +467,13 @@ impl<'a> From<&'a yara_sys::YR_RULE> for Rule<'a> {
             namespace,
             metadatas,
             tags,
+            flags: RuleFlags::from_flags(rule.g_flags),
             strings,
         }
     }
 }
 
-struct TagIterator<'a> {
+pub(crate) struct TagIterator<'a> {
     head: *const c_char,
     _marker: marker::PhantomData<&'a c_char>,
 }
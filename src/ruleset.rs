@@ -0,0 +1,385 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use sha2::{Digest, Sha256};
+
+use crate::cache::{hash_field, hash_includes, includes, to_hex, MAX_INCLUDE_DEPTH};
+use crate::{errors::*, yara_sys, Compiler, Rules};
+
+/// Version of the rules fingerprint, to change when the way it is computed changes.
+const FINGERPRINT_VERSION: &[u8] = b"rs_yara-rules-2";
+
+/// Where the rules of a [`RuleSet`] come from.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RuleSource {
+    /// A compiled rules file, loaded with [`Rules::load_from_file`].
+    Compiled(PathBuf),
+    /// A directory of rule sources (`.yar` and `.yara` files), compiled together.
+    Directory(PathBuf),
+    /// A rule source file, with its includes.
+    File(PathBuf),
+}
+
+impl RuleSource {
+    /// The source of a path: a directory, a compiled rules file (`.yarc`), or a rule source.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        if path.is_dir() {
+            RuleSource::Directory(path)
+        } else if path.extension().is_some_and(|ext| ext == "yarc") {
+            RuleSource::Compiled(path)
+        } else {
+            RuleSource::File(path)
+        }
+    }
+
+    /// Load or compile the rules.
+    pub fn load(&self) -> Result<Rules, Error> {
+        match self {
+            RuleSource::Compiled(path) => {
+                let file = fs::File::open(path)
+                    .map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile))?;
+                Rules::load_from_stream(file)
+            }
+            RuleSource::Directory(path) => {
+                let mut compiler = Compiler::new()?;
+                for file in rule_files(path)? {
+                    compiler.add_rules_file(check_utf8(&file)?)?;
+                }
+                compiler.compile_rules().map_err(|e| e.into())
+            }
+            RuleSource::File(path) => {
+                let mut compiler = Compiler::new()?;
+                compiler.add_rules_file(check_utf8(path)?)?;
+                compiler.compile_rules().map_err(|e| e.into())
+            }
+        }
+    }
+
+    /// A hash of the content of the files of the source, their includes, and the version of
+    /// libyara, as lowercase hex.
+    ///
+    /// Rules loaded from sources with the same fingerprint match the same data.
+    pub fn fingerprint(&self) -> Result<String, Error> {
+        let mut hasher = Sha256::new();
+        hash_field(&mut hasher, FINGERPRINT_VERSION);
+        hash_field(
+            &mut hasher,
+            format!(
+                "{}.{}.{}",
+                yara_sys::YR_MAJOR_VERSION,
+                yara_sys::YR_MINOR_VERSION,
+                yara_sys::YR_MICRO_VERSION
+            )
+            .as_bytes(),
+        );
+        let (files, sources) = match self {
+            RuleSource::Compiled(path) => (vec![path.clone()], false),
+            RuleSource::Directory(path) => (rule_files(path)?, true),
+            RuleSource::File(path) => (vec![path.clone()], true),
+        };
+        for file in files {
+            let content =
+                fs::read(&file).map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile))?;
+            hash_field(&mut hasher, file.to_string_lossy().as_bytes());
+            hash_field(&mut hasher, &content);
+            if sources {
+                let dir = file.parent().unwrap_or_else(|| Path::new(""));
+                hash_includes(&mut hasher, &String::from_utf8_lossy(&content), dir, 0);
+            }
+        }
+        Ok(to_hex(&hasher.finalize()))
+    }
+}
+
+/// Modification times and sizes of the files of a [`RuleSource`], and of their includes.
+type Fingerprint = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+type ReloadCallback = Arc<dyn Fn(Result<(), &Error>) + Send + Sync>;
+
+/// Rules which can be reloaded while they are used.
+///
+/// Scans use a snapshot of the rules, returned by [`rules`](#method.rules). A reload swaps the
+/// snapshot atomically and only if the new rules were loaded successfully, scans in flight
+/// finish with the rules they started with.
+///
+/// A reload happens when the modification time or the size of a file of the source, or of a
+/// file it includes, changed.
+/// It can be checked manually with [`reload`](#method.reload), or periodically from a
+/// background thread with [`watch`](#method.watch).
+#[derive(Clone)]
+pub struct RuleSet {
+    inner: Arc<RuleSetInner>,
+}
+
+struct RuleSetInner {
+    source: RuleSource,
+    rules: RwLock<(Arc<Rules>, String)>,
+    fingerprint: Mutex<Fingerprint>,
+    callback: Mutex<Option<ReloadCallback>>,
+    /// The last error reported by [`RuleSet::reload`], not reported again while it persists.
+    last_error: Mutex<Option<String>>,
+}
+
+impl RuleSet {
+    /// Load the rules from `source`.
+    pub fn new(source: RuleSource) -> Result<Self, Error> {
+        let fingerprint = source_fingerprint(&source)?;
+        let rules_fingerprint = source.fingerprint()?;
+        let rules = source.load()?;
+
+        Ok(RuleSet {
+            inner: Arc::new(RuleSetInner {
+                source,
+                rules: RwLock::new((Arc::new(rules), rules_fingerprint)),
+                fingerprint: Mutex::new(fingerprint),
+                callback: Mutex::new(None),
+                last_error: Mutex::new(None),
+            }),
+        })
+    }
+
+    /// Load the rules from a compiled rules file.
+    pub fn from_compiled_file<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::new(RuleSource::Compiled(path.as_ref().to_path_buf()))
+    }
+
+    /// Compile the rule files of a directory.
+    pub fn from_directory<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::new(RuleSource::Directory(path.as_ref().to_path_buf()))
+    }
+
+    pub fn source(&self) -> &RuleSource {
+        &self.inner.source
+    }
+
+    /// The current rules.
+    pub fn rules(&self) -> Arc<Rules> {
+        self.rules_with_fingerprint().0
+    }
+
+    /// The current rules, with the [fingerprint](RuleSource::fingerprint) of the source they
+    /// were loaded from.
+    pub fn rules_with_fingerprint(&self) -> (Arc<Rules>, String) {
+        let rules = self
+            .inner
+            .rules
+            .read()
+            .expect("lock should not be poisoned");
+        (Arc::clone(&rules.0), rules.1.clone())
+    }
+
+    /// Set the function called after each reload, with its outcome.
+    ///
+    /// It is called without holding the locks of the rule set, so it can reload the rules too.
+    pub fn on_reload<F>(&self, callback: F)
+    where
+        F: Fn(Result<(), &Error>) + Send + Sync + 'static,
+    {
+        *self
+            .inner
+            .callback
+            .lock()
+            .expect("mutex should not be poisoned") = Some(Arc::new(callback));
+    }
+
+    /// Reload the rules if the source changed since the last reload.
+    ///
+    /// A source which failed to load is not reloaded until it changes again, and the callback is
+    /// not called again while the same error persists.
+    /// Return `Ok(true)` if the rules were swapped.
+    pub fn reload(&self) -> Result<bool, Error> {
+        let (result, new_error) = {
+            let mut fingerprint = self
+                .inner
+                .fingerprint
+                .lock()
+                .expect("mutex should not be poisoned");
+            let new_fingerprint = source_fingerprint(&self.inner.source);
+            if new_fingerprint.as_ref().ok() == Some(&*fingerprint) {
+                return Ok(false);
+            }
+
+            let result = new_fingerprint.and_then(|new_fingerprint| {
+                *fingerprint = new_fingerprint;
+                self.swap()
+            });
+            let error = result.as_ref().err().map(ToString::to_string);
+            let mut last_error = self
+                .inner
+                .last_error
+                .lock()
+                .expect("mutex should not be poisoned");
+            let new_error = error.is_none() || error != *last_error;
+            *last_error = error;
+            (result, new_error)
+        };
+        if new_error {
+            self.report(&result);
+        }
+        result.map(|()| true)
+    }
+
+    /// Reload the rules, even if the source did not change.
+    pub fn force_reload(&self) -> Result<(), Error> {
+        let result = {
+            let mut fingerprint = self
+                .inner
+                .fingerprint
+                .lock()
+                .expect("mutex should not be poisoned");
+            source_fingerprint(&self.inner.source).and_then(|new_fingerprint| {
+                *fingerprint = new_fingerprint;
+                self.swap()
+            })
+        };
+        *self
+            .inner
+            .last_error
+            .lock()
+            .expect("mutex should not be poisoned") =
+            result.as_ref().err().map(ToString::to_string);
+        self.report(&result);
+        result
+    }
+
+    /// Check the source for changes every `interval`, in a background thread.
+    ///
+    /// The thread stops when the returned watcher is dropped.
+    pub fn watch(&self, interval: Duration) -> RuleSetWatcher {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let rule_set = self.clone();
+        let thread = thread::Builder::new()
+            .name("rs_yara-ruleset".to_owned())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    // Errors are given to the callback.
+                    let _ = rule_set.reload();
+                }
+            })
+            .expect("should have spawned the watcher thread");
+
+        RuleSetWatcher {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    fn swap(&self) -> Result<(), Error> {
+        // Hashed first, so that rules are never older than their fingerprint.
+        let fingerprint = self.inner.source.fingerprint()?;
+        let rules = self.inner.source.load()?;
+        *self
+            .inner
+            .rules
+            .write()
+            .expect("lock should not be poisoned") = (Arc::new(rules), fingerprint);
+        Ok(())
+    }
+
+    fn report(&self, result: &Result<(), Error>) {
+        let callback = self
+            .inner
+            .callback
+            .lock()
+            .expect("mutex should not be poisoned")
+            .clone();
+        if let Some(callback) = callback {
+            callback(result.as_ref().map(|_| ()));
+        }
+    }
+}
+
+/// Handle on the background thread of [`RuleSet::watch`].
+pub struct RuleSetWatcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for RuleSetWatcher {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The rule files of a directory, sorted by path.
+fn rule_files(path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let mut files = Vec::new();
+    for entry in
+        fs::read_dir(path).map_err(|e| IoError::new(e, IoErrorKind::ReadingRulesDirectory))?
+    {
+        let path = entry
+            .map_err(|e| IoError::new(e, IoErrorKind::ReadingRulesDirectory))?
+            .path();
+        let is_rule = path
+            .extension()
+            .is_some_and(|ext| ext == "yar" || ext == "yara");
+        if is_rule && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// The path of a rules file, which the compiler needs as UTF-8.
+fn check_utf8(path: &Path) -> Result<&str, Error> {
+    path.to_str().ok_or_else(|| {
+        let e = std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not valid UTF-8", path.display()),
+        );
+        IoError::new(e, IoErrorKind::OpenRulesFile).into()
+    })
+}
+
+fn source_fingerprint(source: &RuleSource) -> Result<Fingerprint, Error> {
+    let (files, sources) = match source {
+        RuleSource::Compiled(path) => (vec![path.clone()], false),
+        RuleSource::Directory(path) => (rule_files(path)?, true),
+        RuleSource::File(path) => (vec![path.clone()], true),
+    };
+
+    let mut fingerprint = Vec::new();
+    for file in files {
+        let metadata =
+            fs::metadata(&file).map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile))?;
+        fingerprint.push((file.clone(), metadata.modified().ok(), metadata.len()));
+        if sources {
+            let content =
+                fs::read(&file).map_err(|e| IoError::new(e, IoErrorKind::OpenRulesFile))?;
+            let dir = file.parent().unwrap_or_else(|| Path::new(""));
+            stat_includes(&mut fingerprint, &String::from_utf8_lossy(&content), dir, 0);
+        }
+    }
+    Ok(fingerprint)
+}
+
+/// Add the modification times and sizes of the files included by `source`, relative to `dir`.
+/// A missing include is recorded too, so that creating it is a change.
+fn stat_includes(fingerprint: &mut Fingerprint, source: &str, dir: &Path, depth: usize) {
+    if depth >= MAX_INCLUDE_DEPTH {
+        return;
+    }
+
+    for include in includes(source) {
+        let path = dir.join(include);
+        match fs::metadata(&path) {
+            Ok(metadata) => {
+                fingerprint.push((path.clone(), metadata.modified().ok(), metadata.len()));
+                if let Ok(content) = fs::read_to_string(&path) {
+                    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+                    stat_includes(fingerprint, &content, dir, depth + 1);
+                }
+            }
+            Err(_) => fingerprint.push((path, None, 0)),
+        }
+    }
+}
@@ -1,11 +1,15 @@
+use std::ffi::CString;
 use std::fs::File;
+use std::io::{self, Read};
 use std::os::raw::c_void;
 use std::os::unix::io::AsRawFd;
+use std::ptr;
 
 
 use crate::yara_sys;
+use crate::blocks::{BlockIterator, MemoryBlockSource, MemoryBlocks};
 use crate::errors::*;
-use crate::Rule;
+use crate::{Rule, VariableValue};
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 enum CallbackMsg {
@@ -93,6 +97,219 @@ pub fn rules_scan_file<'a>(
         .map(|_| results)
 }
 
+pub(crate) fn rules_scan_mem_blocks<'a>(
+    rules: *mut yara_sys::YR_RULES,
+    source: &mut dyn MemoryBlockSource,
+    timeout: i32,
+    flags: i32,
+) -> Result<Vec<Rule<'a>>, YaraError> {
+    let mut results = Vec::<Rule<'a>>::new();
+    let mut block_iterator = BlockIterator::new(source);
+    let mut yr_iterator = block_iterator.as_yara();
+
+    let mut scanner: *mut yara_sys::YR_SCANNER = ptr::null_mut();
+    let result = unsafe { yara_sys::yr_scanner_create(rules, &mut scanner) };
+    yara_sys::Error::from_code(result)?;
+
+    let result = unsafe {
+        yara_sys::yr_scanner_set_callback(
+            scanner,
+            Some(scan_callback),
+            &mut results as *mut Vec<_> as *mut c_void,
+        );
+        yara_sys::yr_scanner_set_timeout(scanner, timeout);
+        yara_sys::yr_scanner_set_flags(scanner, flags);
+        let result = yara_sys::yr_scanner_scan_mem_blocks(scanner, &mut yr_iterator);
+        yara_sys::yr_scanner_destroy(scanner);
+        result
+    };
+
+    yara_sys::Error::from_code(result)
+        .map_err(|e| e.into())
+        .map(|_| results)
+}
+
+/// Scan memory with values of external variables for this scan only.
+pub(crate) fn rules_scan_mem_with_variables<'a>(
+    rules: *mut yara_sys::YR_RULES,
+    mem: &[u8],
+    variables: &[(&str, VariableValue)],
+    timeout: i32,
+    flags: i32,
+) -> Result<Vec<Rule<'a>>, YaraError> {
+    let mut results = Vec::<Rule<'a>>::new();
+    let mut scanner: *mut yara_sys::YR_SCANNER = ptr::null_mut();
+    let result = unsafe { yara_sys::yr_scanner_create(rules, &mut scanner) };
+    yara_sys::Error::from_code(result)?;
+
+    let result = unsafe {
+        yara_sys::yr_scanner_set_callback(
+            scanner,
+            Some(scan_callback),
+            &mut results as *mut Vec<_> as *mut c_void,
+        );
+        yara_sys::yr_scanner_set_timeout(scanner, timeout);
+        yara_sys::yr_scanner_set_flags(scanner, flags);
+        let mut result = yara_sys::ERROR_SUCCESS as i32;
+        for (identifier, value) in variables {
+            result = scanner_define_variable(scanner, identifier, value);
+            if result != yara_sys::ERROR_SUCCESS as i32 {
+                break;
+            }
+        }
+        if result == yara_sys::ERROR_SUCCESS as i32 {
+            result = yara_sys::yr_scanner_scan_mem(scanner, mem.as_ptr(), mem.len());
+        }
+        yara_sys::yr_scanner_destroy(scanner);
+        result
+    };
+
+    yara_sys::Error::from_code(result)
+        .map_err(|e| e.into())
+        .map(|_| results)
+}
+
+/// Define an external variable of a scanner. Strings are cut at their first NUL.
+unsafe fn scanner_define_variable(
+    scanner: *mut yara_sys::YR_SCANNER,
+    identifier: &str,
+    value: &VariableValue,
+) -> i32 {
+    let identifier = CString::new(identifier).unwrap();
+    match value {
+        VariableValue::Boolean(b) => yara_sys::yr_scanner_define_boolean_variable(
+            scanner,
+            identifier.as_ptr(),
+            i32::from(*b),
+        ),
+        VariableValue::Float(f) => {
+            yara_sys::yr_scanner_define_float_variable(scanner, identifier.as_ptr(), *f)
+        }
+        VariableValue::Integer(i) => {
+            yara_sys::yr_scanner_define_integer_variable(scanner, identifier.as_ptr(), *i)
+        }
+        VariableValue::String(s) => {
+            let value = CString::new(s.split('\0').next().unwrap_or_default()).unwrap();
+            yara_sys::yr_scanner_define_string_variable(
+                scanner,
+                identifier.as_ptr(),
+                value.as_ptr(),
+            )
+        }
+    }
+}
+
+/// Scan a reader in windows of `window` bytes, each window starting `window - overlap` bytes
+/// after the previous one.
+///
+/// The matches of each window are merged into the results, de-duplicated by offset.
+pub(crate) fn rules_scan_reader<'a>(
+    rules: *mut yara_sys::YR_RULES,
+    reader: &mut dyn Read,
+    window: usize,
+    overlap: usize,
+    timeout: i32,
+    flags: i32,
+) -> Result<Vec<Rule<'a>>, Error> {
+    if window == 0 || overlap >= window {
+        return Err(Error::InvalidArgument(format!(
+            "overlap of {} bytes should be smaller than the window of {} bytes",
+            overlap, window
+        )));
+    }
+
+    let mut results = Vec::<Rule<'a>>::new();
+    let mut buffer = Vec::with_capacity(window);
+    let mut base = 0u64;
+
+    loop {
+        let read = fill_window(reader, &mut buffer, window)
+            .map_err(|e| IoError::new(e, IoErrorKind::ReadingScanStream))?;
+        if read == 0 && base != 0 {
+            break;
+        }
+
+        let mut source = MemoryBlocks::new(vec![(base, &buffer[..])]);
+        let rules = rules_scan_mem_blocks(rules, &mut source, timeout, flags)?;
+        merge_rules(&mut results, rules);
+
+        if buffer.len() < window {
+            break;
+        }
+
+        let step = window - overlap;
+        buffer.drain(..step);
+        base += step as u64;
+    }
+
+    Ok(results)
+}
+
+/// Read until `buffer` holds `window` bytes or the reader is exhausted.
+///
+/// Return the number of bytes read.
+fn fill_window(reader: &mut dyn Read, buffer: &mut Vec<u8>, window: usize) -> io::Result<usize> {
+    let start = buffer.len();
+    buffer.resize(window, 0);
+    let mut filled = start;
+
+    while filled < window {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                buffer.truncate(filled);
+                return Err(e);
+            }
+        }
+    }
+
+    buffer.truncate(filled);
+    Ok(filled - start)
+}
+
+/// Merge the rules matching in a window into the previous results.
+fn merge_rules<'a>(results: &mut Vec<Rule<'a>>, rules: Vec<Rule<'a>>) {
+    for rule in rules {
+        let existing = results
+            .iter_mut()
+            .find(|r| r.namespace == rule.namespace && r.identifier == rule.identifier);
+        let existing = match existing {
+            Some(existing) => existing,
+            None => {
+                results.push(rule);
+                continue;
+            }
+        };
+
+        for string in rule.strings {
+            let existing = match existing
+                .strings
+                .iter_mut()
+                .find(|s| s.identifier == string.identifier)
+            {
+                Some(existing) => existing,
+                None => {
+                    existing.strings.push(string);
+                    continue;
+                }
+            };
+
+            for m in string.matches {
+                // A match cut by the end of a window is shorter than the same match found in
+                // the next window.
+                match existing.matches.iter_mut().find(|e| e.offset == m.offset) {
+                    Some(e) if e.length < m.length => *e = m,
+                    Some(_) => {}
+                    None => existing.matches.push(m),
+                }
+            }
+            existing.matches.sort_by_key(|m| m.offset);
+        }
+    }
+}
+
 #[cfg(unix)]
 pub fn rules_scan_raw(
     rules: *mut yara_sys::YR_RULES,
@@ -1,13 +1,27 @@
 use std::ffi::CStr;
 use std::marker;
 
-use crate::{get_tidx, matches::{Match, MatchIterator}, yara_sys};
+use crate::{
+    flags, get_tidx,
+    matches::{Match, MatchIterator},
+    yara_sys, StringKind, StringModifiers,
+};
 
 #[derive(Debug)]
 pub struct YrString<'a> {
     /// Name of the string, with the '$'.
     pub identifier: &'a str,
+    /// Text, hexadecimal string or regular expression.
+    pub kind: StringKind,
+    pub modifiers: StringModifiers,
+    /// The literal value of the string, when libyara keeps it.
+    ///
+    /// Hexadecimal strings and regular expressions only have one without wildcards, jumps or
+    /// alternatives.
+    pub literal: Option<&'a [u8]>,
     /// Matches of the string for the scan.
+    ///
+    /// Always empty for private strings.
     pub matches: Vec<Match>,
 }
 
@@ -45,7 +59,7 @@ impl<'a> Iterator for YrStringIterator<'a> {
 }
 
 impl<'a> From<&'a yara_sys::YR_STRING> for YrString<'a> {
-    fn from(string: &yara_sys::YR_STRING) -> Self {
+    fn from(string: &'a yara_sys::YR_STRING) -> Self {
         let identifier = unsafe { CStr::from_ptr(string.get_identifier()) }
             .to_str()
             .unwrap();
@@ -56,6 +70,9 @@ impl<'a> From<&'a yara_sys::YR_STRING> for YrString<'a> {
 
         YrString {
             identifier,
+            kind: StringKind::from_flags(string.g_flags),
+            modifiers: StringModifiers::from_flags(string.g_flags),
+            literal: flags::literal(string),
             matches,
         }
     }
@@ -0,0 +1,387 @@
+use super::Span;
+
+/// A parsed rules source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceFile {
+    pub items: Vec<Item>,
+    /// Comments of the source, in order. Comments inside hex strings are part of the hex string.
+    pub comments: Vec<Comment>,
+}
+
+impl SourceFile {
+    /// Iterate over the rules of the source.
+    pub fn rules(&self) -> impl Iterator<Item = &Rule> {
+        self.items.iter().filter_map(|item| match item {
+            Item::Rule(rule) => Some(rule),
+            _ => None,
+        })
+    }
+}
+
+/// A comment, with its delimiters.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+impl Comment {
+    /// Whether this is a `/* */` comment.
+    pub fn is_block(&self) -> bool {
+        self.text.starts_with("/*")
+    }
+}
+
+/// A top-level item of a rules source.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Item {
+    Import(Import),
+    Include(Include),
+    Rule(Rule),
+}
+
+impl Item {
+    pub fn span(&self) -> Span {
+        match self {
+            Item::Import(import) => import.span,
+            Item::Include(include) => include.span,
+            Item::Rule(rule) => rule.span,
+        }
+    }
+}
+
+/// `import "module"`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Import {
+    pub module: TextString,
+    pub span: Span,
+}
+
+/// `include "path"`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Include {
+    /// The path, as written.
+    pub path: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub is_private: bool,
+    pub is_global: bool,
+    pub identifier: Ident,
+    pub tags: Vec<Ident>,
+    pub metas: Vec<Meta>,
+    pub strings: Vec<StringDeclaration>,
+    pub condition: Expr,
+    pub span: Span,
+}
+
+/// An identifier, as written.
+///
+/// String identifiers keep their sigil (`$a`, `#a`, `@a`, `!a`), and the `*` of wildcards.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Meta {
+    pub identifier: Ident,
+    pub value: MetaValue,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MetaValue {
+    String(TextString),
+    Integer(i64),
+    Boolean(bool),
+}
+
+/// A text string, as written between the quotes.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TextString {
+    /// The text with its escape sequences.
+    pub raw: String,
+    pub span: Span,
+}
+
+impl TextString {
+    /// The bytes of the text, escape sequences decoded.
+    pub fn value(&self) -> Vec<u8> {
+        unescape(&self.raw)
+    }
+}
+
+/// Decode the escape sequences of a text string. The lexer only accepts valid ones.
+fn unescape(raw: &str) -> Vec<u8> {
+    let mut value = Vec::with_capacity(raw.len());
+    let mut bytes = raw.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            value.push(b);
+            continue;
+        }
+        match bytes.next() {
+            Some(b't') => value.push(b'\t'),
+            Some(b'n') => value.push(b'\n'),
+            Some(b'x') => {
+                let digits = [bytes.next().unwrap_or(b'0'), bytes.next().unwrap_or(b'0')];
+                let digits = std::str::from_utf8(&digits).unwrap_or("00");
+                value.push(u8::from_str_radix(digits, 16).unwrap_or(0));
+            }
+            Some(other) => value.push(other),
+            None => value.push(b'\\'),
+        }
+    }
+    value
+}
+
+/// `$name = value modifiers`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StringDeclaration {
+    pub identifier: Ident,
+    pub value: StringValue,
+    pub modifiers: Vec<StringModifier>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StringValue {
+    Text(TextString),
+    Hex(HexString),
+    Regex(Regex),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StringModifier {
+    pub kind: StringModifierKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StringModifierKind {
+    Ascii,
+    Wide,
+    Nocase,
+    Fullword,
+    Private,
+    /// `xor`, `xor(n)` or `xor(min-max)`, with the written bounds.
+    Xor(Option<(u8, u8)>),
+}
+
+/// `/pattern/is`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Regex {
+    /// The pattern between the slashes, as written.
+    pub pattern: String,
+    pub case_insensitive: bool,
+    pub dot_all: bool,
+    pub span: Span,
+}
+
+/// `{ 4D 5A ?? [2-4] ( 01 | 02 ) }`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HexString {
+    pub tokens: Vec<HexToken>,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HexToken {
+    /// A byte, with the mask of its known nibbles: `4D` is 0xFF, `4?` is 0xF0, `??` is 0x00.
+    Byte { value: u8, mask: u8 },
+    /// `[n]`, kept apart from `[n-n]` which libyara compiles differently for small `n`.
+    Skip(u32),
+    /// `[n-m]`, `[n-]` or `[-]`.
+    Jump {
+        start: Option<u32>,
+        end: Option<u32>,
+    },
+    /// `( a | b )`
+    Alternative(Vec<Vec<HexToken>>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprKind {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    Text(TextString),
+    Regex(Regex),
+    Filesize,
+    Entrypoint,
+    /// `$a`
+    String(Ident),
+    /// `$a at offset`
+    StringAt {
+        identifier: Ident,
+        offset: Box<Expr>,
+    },
+    /// `$a in (start..end)`
+    StringIn {
+        identifier: Ident,
+        start: Box<Expr>,
+        end: Box<Expr>,
+    },
+    /// `#a`
+    StringCount(Ident),
+    /// `@a` or `@a[index]`
+    StringOffset {
+        identifier: Ident,
+        index: Option<Box<Expr>>,
+    },
+    /// `!a` or `!a[index]`
+    StringLength {
+        identifier: Ident,
+        index: Option<Box<Expr>>,
+    },
+    /// `uint32be(offset)`, and the other integer functions.
+    IntegerFunction {
+        function: Ident,
+        offset: Box<Expr>,
+    },
+    Identifier(Ident),
+    /// `object.field`
+    Field {
+        object: Box<Expr>,
+        field: Ident,
+    },
+    /// `object[index]`
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `function(arguments)`
+    Call {
+        function: Box<Expr>,
+        arguments: Vec<Expr>,
+    },
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    /// `quantifier of strings`
+    Of {
+        quantifier: Quantifier,
+        strings: StringSet,
+    },
+    /// `for quantifier of strings : ( body )`
+    ForOf {
+        quantifier: Quantifier,
+        strings: StringSet,
+        body: Box<Expr>,
+    },
+    /// `for quantifier variable in integers : ( body )`
+    ForIn {
+        quantifier: Quantifier,
+        variable: Ident,
+        integers: IntegerSet,
+        body: Box<Expr>,
+    },
+    /// `( expr )`
+    Paren(Box<Expr>),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+    BitNot,
+}
+
+impl UnaryOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UnaryOp::Not => "not",
+            UnaryOp::Neg => "-",
+            UnaryOp::BitNot => "~",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Matches,
+    Contains,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+}
+
+impl BinaryOp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BinaryOp::Or => "or",
+            BinaryOp::And => "and",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Matches => "matches",
+            BinaryOp::Contains => "contains",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "\\",
+            BinaryOp::Mod => "%",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Quantifier {
+    All,
+    Any,
+    Expr(Box<Expr>),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StringSet {
+    Them,
+    /// `($a, $b*)`
+    List(Vec<Ident>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IntegerSet {
+    /// `(start..end)`
+    Range { start: Box<Expr>, end: Box<Expr> },
+    /// `(a, b, c)`
+    Enumeration(Vec<Expr>),
+}
@@ -0,0 +1,636 @@
+use super::lexer::{tokenize, Token, TokenKind};
+use super::*;
+use crate::errors::ParseError;
+
+/// Indentation of the sections of a rule.
+const SECTION_INDENT: usize = 2;
+/// Indentation of the content of the sections.
+const CONTENT_INDENT: usize = 4;
+/// Conditions longer than this are split on their `and` and `or`.
+const MAX_WIDTH: usize = 100;
+
+/// Format a rules source in the canonical style.
+///
+/// - one blank line between rules, and between sections of a rule;
+/// - `=` aligned in the `meta` and `strings` sections;
+/// - string modifiers in a fixed order, hex strings in uppercase with single spaces;
+/// - conditions on one line, or split on their `and` and `or` when longer than 100
+///   characters, and around parentheses and loops which are still too long.
+///
+/// Comments are kept next to the code they were next to. An element with a comment inside it
+/// is kept as written. Formatting a formatted source gives the same source, and a formatted
+/// source compiles to the same rules as the original.
+pub fn format(source: &str) -> Result<String, ParseError> {
+    let file = parse(source)?;
+    let (tokens, _) = tokenize(source);
+    let mut formatter = Formatter {
+        source,
+        line_starts: std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect(),
+        tokens,
+        comments: &file.comments,
+        next_comment: 0,
+        lines: Vec::new(),
+        last_end: 0,
+        after_comment: false,
+        line_comment: false,
+        opener: false,
+        blank: false,
+    };
+    formatter.source_file(&file);
+
+    let mut output = formatter.lines.join("\n");
+    // A block comment left open runs to the end of the source, its newlines included.
+    let unterminated = file
+        .comments
+        .last()
+        .is_some_and(|c| c.is_block() && (c.text.len() < 4 || !c.text.ends_with("*/")));
+    if !output.is_empty() && !unterminated {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+struct Formatter<'s> {
+    source: &'s str,
+    line_starts: Vec<usize>,
+    tokens: Vec<Token>,
+    comments: &'s [Comment],
+    /// First comment not written yet.
+    next_comment: usize,
+    lines: Vec<String>,
+    /// End of the source written so far.
+    last_end: usize,
+    /// Whether the last line is a comment on its own.
+    after_comment: bool,
+    /// Whether the last line ends with a `//` comment, after which nothing can be appended.
+    line_comment: bool,
+    /// Whether the last line opens a block, which is never followed by a blank line.
+    opener: bool,
+    /// Whether a blank line goes before the next line.
+    blank: bool,
+}
+
+impl<'s> Formatter<'s> {
+    fn line_of(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        }
+    }
+
+    fn text(&self, span: Span) -> &'s str {
+        &self.source[span.start..span.end]
+    }
+
+    /// Whether the source has a blank line between two offsets.
+    fn blank_between(&self, start: usize, end: usize) -> bool {
+        start < end && self.line_of(end) > self.line_of(start) + 1
+    }
+
+    fn has_comments(&self, span: Span) -> bool {
+        self.comments[self.next_comment..]
+            .iter()
+            .take_while(|c| c.span.start < span.end)
+            .any(|c| c.span.start >= span.start)
+    }
+
+    /// The first token of a kind in a span.
+    fn find_token(&self, kind: TokenKind, span: Span) -> Option<Span> {
+        self.tokens
+            .iter()
+            .skip_while(|t| t.span.start < span.start)
+            .take_while(|t| t.span.end <= span.end)
+            .find(|t| t.kind == kind)
+            .map(|t| t.span)
+    }
+
+    /// Write the comments starting before `offset`.
+    ///
+    /// Comments on the line of the code written last are appended to it.
+    fn flush_comments(&mut self, offset: usize, indent: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= offset {
+                break;
+            }
+            self.next_comment += 1;
+
+            let trailing = self.lines.last().is_some_and(|l| !l.is_empty())
+                && !self.line_comment
+                && self.line_of(comment.span.start) == self.line_of(self.last_end);
+            if trailing {
+                let last = self.lines.last_mut().unwrap();
+                last.push(' ');
+                last.push_str(&comment.text);
+            } else {
+                if self.blank
+                    || (self.blank_between(self.last_end, comment.span.start) && !self.opener)
+                {
+                    self.push_blank();
+                }
+                self.lines
+                    .push(format!("{}{}", " ".repeat(indent), comment.text));
+                self.after_comment = true;
+                self.opener = false;
+            }
+            self.line_comment = !comment.is_block();
+            self.last_end = self.last_end.max(comment.span.end);
+        }
+    }
+
+    /// Put a blank line before the next line, after the comments trailing the last one.
+    fn blank(&mut self) {
+        self.blank = true;
+    }
+
+    fn push_blank(&mut self) {
+        if self.lines.last().is_some_and(|l| !l.is_empty()) {
+            self.lines.push(String::new());
+        }
+        self.blank = false;
+    }
+
+    /// Write a line of code for `span`, after the comments before it.
+    fn line(&mut self, indent: usize, text: &str, span: Span) {
+        self.flush_comments(span.start, indent);
+        if self.blank || (self.after_comment && self.blank_between(self.last_end, span.start)) {
+            self.push_blank();
+        }
+        self.lines.push(format!("{}{}", " ".repeat(indent), text));
+        self.after_comment = false;
+        self.line_comment = false;
+        self.opener = false;
+        self.last_end = self.last_end.max(span.end);
+    }
+
+    /// Write the source of `span` as is, re-indenting its lines.
+    fn verbatim(&mut self, indent: usize, span: Span, suffix: &str) {
+        let mut text = String::new();
+        for (i, line) in self.text(span).lines().enumerate() {
+            if i > 0 {
+                text.push('\n');
+                text.push_str(&" ".repeat(indent + 2));
+            }
+            text.push_str(if i > 0 { line.trim() } else { line });
+        }
+        text.push_str(suffix);
+        self.line(indent, &text, span);
+
+        // The comments inside the span were written with it.
+        while self
+            .comments
+            .get(self.next_comment)
+            .is_some_and(|c| c.span.start < span.end)
+        {
+            self.next_comment += 1;
+        }
+    }
+
+    fn source_file(&mut self, file: &SourceFile) {
+        let mut previous: Option<&Item> = None;
+        for item in &file.items {
+            let same_group = matches!(
+                (previous, item),
+                (Some(Item::Import(_)), Item::Import(_))
+                    | (Some(Item::Include(_)), Item::Include(_))
+            );
+            if previous.is_some() && !same_group {
+                self.blank();
+            }
+
+            match item {
+                Item::Import(import) if !self.has_comments(import.span) => {
+                    self.line(0, &format!("import \"{}\"", import.module.raw), import.span)
+                }
+                Item::Include(include) if !self.has_comments(include.span) => {
+                    self.line(0, &format!("include \"{}\"", include.path), include.span)
+                }
+                Item::Rule(rule) => self.rule(rule),
+                _ => self.verbatim(0, item.span(), ""),
+            }
+            previous = Some(item);
+        }
+        self.flush_comments(usize::MAX, 0);
+    }
+
+    fn rule(&mut self, rule: &Rule) {
+        let mut header = String::new();
+        if rule.is_private {
+            header.push_str("private ");
+        }
+        if rule.is_global {
+            header.push_str("global ");
+        }
+        header.push_str("rule ");
+        header.push_str(&rule.identifier.name);
+        if !rule.tags.is_empty() {
+            header.push_str(" :");
+            for tag in &rule.tags {
+                header.push(' ');
+                header.push_str(&tag.name);
+            }
+        }
+        header.push_str(" {");
+        let open = self
+            .find_token(TokenKind::Char(b'{'), rule.span)
+            .unwrap_or(rule.identifier.span);
+        // Comments inside the header go before it.
+        self.flush_comments(open.start, 0);
+        self.line(0, &header, Span::new(rule.span.start, open.end));
+        self.opener = true;
+
+        if !rule.metas.is_empty() {
+            self.section_header(TokenKind::Meta, "meta:", rule.span);
+            let width = rule
+                .metas
+                .iter()
+                .map(|m| m.identifier.name.len())
+                .max()
+                .unwrap_or(0);
+            for meta in &rule.metas {
+                self.meta(meta, width);
+            }
+        }
+
+        if !rule.strings.is_empty() {
+            if !rule.metas.is_empty() {
+                self.blank();
+            }
+            self.section_header(TokenKind::Strings, "strings:", rule.span);
+            let width = rule
+                .strings
+                .iter()
+                .map(|s| s.identifier.name.len())
+                .max()
+                .unwrap_or(0);
+            for string in &rule.strings {
+                self.string(string, width);
+            }
+        }
+
+        if !rule.metas.is_empty() || !rule.strings.is_empty() {
+            self.blank();
+        }
+        self.section_header(TokenKind::Condition, "condition:", rule.span);
+        self.expression(&rule.condition, CONTENT_INDENT, "");
+
+        let close = Span::new(rule.span.end - 1, rule.span.end);
+        self.flush_comments(close.start, CONTENT_INDENT);
+        self.line(0, "}", close);
+    }
+
+    fn section_header(&mut self, keyword: TokenKind, text: &str, rule: Span) {
+        let span = self.find_token(keyword, rule).unwrap_or(rule);
+        // The header goes up to its colon.
+        let span = Span::new(span.start, span.end + 1);
+        self.line(SECTION_INDENT, text, span);
+        self.opener = true;
+    }
+
+    fn meta(&mut self, meta: &Meta, width: usize) {
+        if self.has_comments(meta.span) {
+            return self.verbatim(CONTENT_INDENT, meta.span, "");
+        }
+
+        let value = match &meta.value {
+            MetaValue::String(s) => format!("\"{}\"", s.raw),
+            MetaValue::Boolean(b) => b.to_string(),
+            MetaValue::Integer(_) => {
+                // Keep the number as written, in hexadecimal or with a unit.
+                let text = self.text(meta.span);
+                let value = &text[text.find('=').map_or(0, |i| i + 1)..];
+                value.split_whitespace().collect()
+            }
+        };
+        let text = format!("{:width$} = {}", meta.identifier.name, value, width = width);
+        self.line(CONTENT_INDENT, &text, meta.span);
+    }
+
+    fn string(&mut self, string: &StringDeclaration, width: usize) {
+        if self.has_comments(string.span) {
+            return self.verbatim(CONTENT_INDENT, string.span, "");
+        }
+
+        let value = match &string.value {
+            StringValue::Text(s) => format!("\"{}\"", s.raw),
+            StringValue::Regex(r) => self.text(r.span).to_owned(),
+            StringValue::Hex(hex) => {
+                let text = self.text(hex.span);
+                if text.contains("//") || text.contains("/*") {
+                    // Keep the comments of the hex string.
+                    let lines: Vec<_> = text.lines().map(str::trim).collect();
+                    lines.join(&format!("\n{}", " ".repeat(CONTENT_INDENT + 2)))
+                } else {
+                    format!("{{ {} }}", format_hex_tokens(&hex.tokens))
+                }
+            }
+        };
+
+        let mut modifiers: Vec<_> = string.modifiers.iter().map(|m| m.kind).collect();
+        modifiers.sort_by_key(|kind| match kind {
+            StringModifierKind::Ascii => 0,
+            StringModifierKind::Wide => 1,
+            StringModifierKind::Nocase => 2,
+            StringModifierKind::Fullword => 3,
+            StringModifierKind::Private => 4,
+            StringModifierKind::Xor(_) => 5,
+        });
+        let mut text = format!(
+            "{:width$} = {}",
+            string.identifier.name,
+            value,
+            width = width
+        );
+        for modifier in modifiers {
+            text.push(' ');
+            text.push_str(&format_modifier(modifier));
+        }
+        self.line(CONTENT_INDENT, &text, string.span);
+    }
+
+    /// Write an expression followed by `suffix`, split on several lines if needed.
+    fn expression(&mut self, expr: &Expr, indent: usize, suffix: &str) {
+        let has_comments = self.has_comments(expr.span);
+        if !has_comments {
+            let inline = self.inline(expr);
+            if indent + inline.len() + suffix.len() <= MAX_WIDTH {
+                return self.line(indent, &format!("{}{}", inline, suffix), expr.span);
+            }
+        }
+
+        match &expr.kind {
+            ExprKind::Binary {
+                op: op @ (BinaryOp::And | BinaryOp::Or),
+                ..
+            } => {
+                let mut operands = Vec::new();
+                flatten(expr, *op, &mut operands);
+                let last = operands.len() - 1;
+                for (i, operand) in operands.into_iter().enumerate() {
+                    if i == last {
+                        self.expression(operand, indent, suffix);
+                    } else {
+                        self.expression(operand, indent, &format!(" {}", op.as_str()));
+                    }
+                }
+            }
+            ExprKind::Paren(inner) => {
+                let open = Span::new(expr.span.start, inner.span.start);
+                self.block("(", open, inner, expr.span, indent, suffix);
+            }
+            ExprKind::Unary {
+                op: UnaryOp::Not,
+                operand,
+            } => match &operand.kind {
+                ExprKind::Paren(inner) => {
+                    let open = Span::new(expr.span.start, inner.span.start);
+                    self.block("not (", open, inner, expr.span, indent, suffix);
+                }
+                _ if has_comments => self.verbatim(indent, expr.span, suffix),
+                _ => self.line(
+                    indent,
+                    &format!("{}{}", self.inline(expr), suffix),
+                    expr.span,
+                ),
+            },
+            ExprKind::ForOf { body, .. } | ExprKind::ForIn { body, .. } => {
+                let head = self.for_head(expr);
+                let open = Span::new(expr.span.start, body.span.start);
+                self.block(&format!("{}(", head), open, body, expr.span, indent, suffix);
+            }
+            _ if has_comments => self.verbatim(indent, expr.span, suffix),
+            _ => self.line(
+                indent,
+                &format!("{}{}", self.inline(expr), suffix),
+                expr.span,
+            ),
+        }
+    }
+
+    /// Write `open`, then `inner` indented, then the closing parenthesis.
+    fn block(
+        &mut self,
+        open: &str,
+        open_span: Span,
+        inner: &Expr,
+        span: Span,
+        indent: usize,
+        suffix: &str,
+    ) {
+        // The line ends at the parenthesis, so comments after it trail it or not depending on
+        // where they are in the source only.
+        let paren_end = self
+            .tokens
+            .iter()
+            .skip_while(|t| t.span.start < open_span.start)
+            .take_while(|t| t.span.end <= open_span.end)
+            .filter(|t| t.kind == TokenKind::Char(b'('))
+            .last()
+            .map_or(open_span.end, |t| t.span.end);
+        self.line(indent, open, Span::new(open_span.start, paren_end));
+        self.opener = true;
+        self.expression(inner, indent + 2, "");
+        let close = Span::new(span.end - 1, span.end);
+        self.flush_comments(close.start, indent + 2);
+        self.line(indent, &format!("){}", suffix), close);
+    }
+
+    /// `for quantifier of strings : ` or `for quantifier variable in integers : `
+    fn for_head(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::ForOf {
+                quantifier,
+                strings,
+                ..
+            } => format!(
+                "for {} of {} : ",
+                self.quantifier(quantifier),
+                format_string_set(strings)
+            ),
+            ExprKind::ForIn {
+                quantifier,
+                variable,
+                integers,
+                ..
+            } => {
+                let integers = match integers {
+                    IntegerSet::Range { start, end } => {
+                        format!("({}..{})", self.inline(start), self.inline(end))
+                    }
+                    IntegerSet::Enumeration(integers) => format!(
+                        "({})",
+                        integers
+                            .iter()
+                            .map(|i| self.inline(i))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                };
+                format!(
+                    "for {} {} in {} : ",
+                    self.quantifier(quantifier),
+                    variable.name,
+                    integers
+                )
+            }
+            _ => String::new(),
+        }
+    }
+
+    fn quantifier(&self, quantifier: &Quantifier) -> String {
+        match quantifier {
+            Quantifier::All => "all".to_owned(),
+            Quantifier::Any => "any".to_owned(),
+            Quantifier::Expr(expr) => self.inline(expr),
+        }
+    }
+
+    /// An expression on a single line.
+    fn inline(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Boolean(b) => b.to_string(),
+            ExprKind::Integer(_) | ExprKind::Float(_) | ExprKind::Regex(_) => {
+                self.text(expr.span).to_owned()
+            }
+            ExprKind::Text(s) => format!("\"{}\"", s.raw),
+            ExprKind::Filesize => "filesize".to_owned(),
+            ExprKind::Entrypoint => "entrypoint".to_owned(),
+            ExprKind::String(id) | ExprKind::StringCount(id) | ExprKind::Identifier(id) => {
+                id.name.clone()
+            }
+            ExprKind::StringAt { identifier, offset } => {
+                format!("{} at {}", identifier.name, self.inline(offset))
+            }
+            ExprKind::StringIn {
+                identifier,
+                start,
+                end,
+            } => format!(
+                "{} in ({}..{})",
+                identifier.name,
+                self.inline(start),
+                self.inline(end)
+            ),
+            ExprKind::StringOffset { identifier, index }
+            | ExprKind::StringLength { identifier, index } => match index {
+                Some(index) => format!("{}[{}]", identifier.name, self.inline(index)),
+                None => identifier.name.clone(),
+            },
+            ExprKind::IntegerFunction { function, offset } => {
+                format!("{}({})", function.name, self.inline(offset))
+            }
+            ExprKind::Field { object, field } => {
+                format!("{}.{}", self.inline(object), field.name)
+            }
+            ExprKind::Index { object, index } => {
+                format!("{}[{}]", self.inline(object), self.inline(index))
+            }
+            ExprKind::Call {
+                function,
+                arguments,
+            } => format!(
+                "{}({})",
+                self.inline(function),
+                arguments
+                    .iter()
+                    .map(|a| self.inline(a))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            ExprKind::Unary { op, operand } => match op {
+                UnaryOp::Not => format!("not {}", self.inline(operand)),
+                _ => format!("{}{}", op.as_str(), self.inline(operand)),
+            },
+            ExprKind::Binary { op, lhs, rhs } => {
+                format!("{} {} {}", self.inline(lhs), op.as_str(), self.inline(rhs))
+            }
+            ExprKind::Of {
+                quantifier,
+                strings,
+            } => format!(
+                "{} of {}",
+                self.quantifier(quantifier),
+                format_string_set(strings)
+            ),
+            ExprKind::ForOf { body, .. } | ExprKind::ForIn { body, .. } => {
+                format!("{}({})", self.for_head(expr), self.inline(body))
+            }
+            ExprKind::Paren(inner) => format!("({})", self.inline(inner)),
+        }
+    }
+}
+
+/// The operands of a chain of `op`.
+fn flatten<'e>(expr: &'e Expr, op: BinaryOp, operands: &mut Vec<&'e Expr>) {
+    match &expr.kind {
+        ExprKind::Binary {
+            op: expr_op,
+            lhs,
+            rhs,
+        } if *expr_op == op => {
+            flatten(lhs, op, operands);
+            flatten(rhs, op, operands);
+        }
+        _ => operands.push(expr),
+    }
+}
+
+fn format_string_set(strings: &StringSet) -> String {
+    match strings {
+        StringSet::Them => "them".to_owned(),
+        StringSet::List(strings) => format!(
+            "({})",
+            strings
+                .iter()
+                .map(|s| s.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn format_modifier(modifier: StringModifierKind) -> String {
+    match modifier {
+        StringModifierKind::Ascii => "ascii".to_owned(),
+        StringModifierKind::Wide => "wide".to_owned(),
+        StringModifierKind::Nocase => "nocase".to_owned(),
+        StringModifierKind::Fullword => "fullword".to_owned(),
+        StringModifierKind::Private => "private".to_owned(),
+        StringModifierKind::Xor(None) => "xor".to_owned(),
+        StringModifierKind::Xor(Some((min, max))) if min == max => format!("xor({})", min),
+        StringModifierKind::Xor(Some((min, max))) => format!("xor({}-{})", min, max),
+    }
+}
+
+fn format_hex_tokens(tokens: &[HexToken]) -> String {
+    tokens
+        .iter()
+        .map(|token| match token {
+            HexToken::Byte { value, mask: 0xFF } => format!("{:02X}", value),
+            HexToken::Byte { value, mask: 0xF0 } => format!("{:X}?", value >> 4),
+            HexToken::Byte { value, mask: 0x0F } => format!("?{:X}", value & 0x0F),
+            HexToken::Byte { .. } => "??".to_owned(),
+            HexToken::Skip(n) => format!("[{}]", n),
+            // libyara compiles `[n-n]` as `[n]` except for `[0-0]` and `[1-1]`.
+            HexToken::Jump {
+                start: Some(start),
+                end: Some(end),
+            } if start == end && *start > 1 => format!("[{}]", start),
+            HexToken::Jump { start, end } => format!(
+                "[{}-{}]",
+                start.map(|n| n.to_string()).unwrap_or_default(),
+                end.map(|n| n.to_string()).unwrap_or_default()
+            ),
+            HexToken::Alternative(alternatives) => format!(
+                "( {} )",
+                alternatives
+                    .iter()
+                    .map(|a| format_hex_tokens(a))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,492 @@
+use super::{Comment, Span};
+use crate::bytes::find;
+
+/// Longest identifier accepted by libyara.
+const MAX_IDENTIFIER_LENGTH: usize = 128;
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum TokenKind {
+    All,
+    And,
+    Any,
+    Ascii,
+    At,
+    Condition,
+    Contains,
+    Entrypoint,
+    False,
+    Filesize,
+    For,
+    Fullword,
+    Global,
+    Import,
+    In,
+    Matches,
+    Meta,
+    Nocase,
+    Not,
+    Of,
+    Or,
+    Private,
+    Rule,
+    Strings,
+    Them,
+    True,
+    Wide,
+    Xor,
+    /// `include "path"`, as a whole.
+    Include,
+    Identifier,
+    StringIdentifier,
+    StringIdentifierWithWildcard,
+    StringCount,
+    StringOffset,
+    StringLength,
+    IntegerFunction,
+    Number(i64),
+    Double(f64),
+    /// A text string, with its quotes.
+    Text,
+    /// A hex string, with its braces.
+    Hex,
+    /// A regular expression, with its slashes and flags.
+    Regex,
+    DotDot,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+    Shl,
+    Shr,
+    /// Any other printable character.
+    Char(u8),
+    /// An invalid token, with the error message.
+    Error(&'static str),
+    Eof,
+}
+
+impl TokenKind {
+    /// The name of the token in the errors of libyara.
+    pub(crate) fn describe(&self) -> String {
+        let name = match self {
+            TokenKind::All => "<all>",
+            TokenKind::And => "<and>",
+            TokenKind::Any => "<any>",
+            TokenKind::Ascii => "<ascii>",
+            TokenKind::At => "<at>",
+            TokenKind::Condition => "<condition>",
+            TokenKind::Contains => "<contains>",
+            TokenKind::Entrypoint => "<entrypoint>",
+            TokenKind::False => "<false",
+            TokenKind::Filesize => "<filesize>",
+            TokenKind::For => "<for>",
+            TokenKind::Fullword => "<fullword>",
+            TokenKind::Global => "<global>",
+            TokenKind::Import => "<import>",
+            TokenKind::In => "<in>",
+            TokenKind::Matches => "<matches>",
+            TokenKind::Meta => "<meta>",
+            TokenKind::Nocase => "<nocase>",
+            TokenKind::Not => "<not>",
+            TokenKind::Of => "<of>",
+            TokenKind::Or => "<or>",
+            TokenKind::Private => "<private>",
+            TokenKind::Rule => "<rule>",
+            TokenKind::Strings => "<strings>",
+            TokenKind::Them => "<them>",
+            TokenKind::True => "<true>",
+            TokenKind::Wide => "<wide>",
+            TokenKind::Xor => "<xor>",
+            TokenKind::Include => "include",
+            TokenKind::Identifier => "identifier",
+            TokenKind::StringIdentifier => "string identifier",
+            TokenKind::StringIdentifierWithWildcard => "_STRING_IDENTIFIER_WITH_WILDCARD_",
+            TokenKind::StringCount => "string count",
+            TokenKind::StringOffset => "string offset",
+            TokenKind::StringLength => "string length",
+            TokenKind::IntegerFunction => "integer function",
+            TokenKind::Number(_) => "integer number",
+            TokenKind::Double(_) => "floating point number",
+            TokenKind::Text => "text string",
+            TokenKind::Hex => "hex string",
+            TokenKind::Regex => "regular expression",
+            TokenKind::DotDot => "..",
+            TokenKind::Lt => "<",
+            TokenKind::Gt => ">",
+            TokenKind::Le => "<=",
+            TokenKind::Ge => ">=",
+            TokenKind::Eq => "==",
+            TokenKind::Ne => "!=",
+            TokenKind::Shl => "<<",
+            TokenKind::Shr => ">>",
+            TokenKind::Char(c) if is_grammar_char(*c) => return format!("'{}'", *c as char),
+            TokenKind::Char(_) => "$undefined",
+            TokenKind::Error(message) => message,
+            TokenKind::Eof => "end of file",
+        };
+        name.to_owned()
+    }
+}
+
+/// The characters used by the grammar of libyara.
+fn is_grammar_char(c: u8) -> bool {
+    b"{}:=()[],.-+*\\%^&|~".contains(&c)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Split a source in tokens, the same way as libyara.
+///
+/// Lexing stops at the first invalid token, which is returned as a [`TokenKind::Error`].
+/// The last token is always [`TokenKind::Eof`] or an error.
+pub(crate) fn tokenize(source: &str) -> (Vec<Token>, Vec<Comment>) {
+    let mut lexer = Lexer {
+        source: source.as_bytes(),
+        pos: 0,
+        comments: Vec::new(),
+    };
+    let mut tokens = Vec::new();
+    loop {
+        let token = lexer.next_token();
+        let done = matches!(token.kind, TokenKind::Eof | TokenKind::Error(_));
+        tokens.push(token);
+        if done {
+            return (tokens, lexer.comments);
+        }
+    }
+}
+
+struct Lexer<'s> {
+    source: &'s [u8],
+    pos: usize,
+    comments: Vec<Comment>,
+}
+
+impl<'s> Lexer<'s> {
+    fn peek_at(&self, offset: usize) -> Option<u8> {
+        self.source.get(self.pos + offset).copied()
+    }
+
+    fn rest(&self) -> &'s [u8] {
+        &self.source[self.pos..]
+    }
+
+    fn token(&mut self, kind: TokenKind, start: usize) -> Token {
+        Token {
+            kind,
+            span: Span::new(start, self.pos),
+        }
+    }
+
+    fn next_token(&mut self) -> Token {
+        self.skip_trivia();
+        let start = self.pos;
+        let c = match self.peek_at(0) {
+            Some(c) => c,
+            None => return self.token(TokenKind::Eof, start),
+        };
+
+        if let Some(token) = self.operator(start) {
+            return token;
+        }
+        match c {
+            b'"' => self.text(start),
+            b'/' => self.regex(start),
+            b'{' => self.hex_or_brace(start),
+            b'$' | b'#' | b'@' | b'!' => self.string_identifier(c, start),
+            b'0'..=b'9' => self.number(start),
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.word(start),
+            32..=126 => {
+                self.pos += 1;
+                self.token(TokenKind::Char(c), start)
+            }
+            _ => {
+                // Point at the whole character.
+                let len = std::str::from_utf8(&self.rest()[..self.rest().len().min(4)])
+                    .ok()
+                    .or_else(|| std::str::from_utf8(&self.rest()[..1]).ok())
+                    .and_then(|s| s.chars().next())
+                    .map_or(1, char::len_utf8);
+                self.pos += len;
+                self.token(TokenKind::Error("non-ascii character"), start)
+            }
+        }
+    }
+
+    /// Skip whitespaces and comments, recording the comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            let rest = self.rest();
+            if rest.starts_with(b"//") {
+                let len = rest.iter().position(|&c| c == b'\n').unwrap_or(rest.len());
+                self.comment(len);
+            } else if rest.starts_with(b"/*") {
+                // An unterminated comment runs to the end of the source.
+                let len = find(&rest[2..], b"*/").map_or(rest.len(), |end| end + 4);
+                self.comment(len);
+            } else if let Some(b' ' | b'\t' | b'\r' | b'\n') = rest.first() {
+                self.pos += 1;
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn comment(&mut self, len: usize) {
+        let span = Span::new(self.pos, self.pos + len);
+        self.comments.push(Comment {
+            text: String::from_utf8_lossy(&self.source[span.start..span.end]).into_owned(),
+            span,
+        });
+        self.pos += len;
+    }
+
+    fn operator(&mut self, start: usize) -> Option<Token> {
+        let (kind, len) = match self.rest() {
+            [b'.', b'.', ..] => (TokenKind::DotDot, 2),
+            [b'<', b'=', ..] => (TokenKind::Le, 2),
+            [b'>', b'=', ..] => (TokenKind::Ge, 2),
+            [b'=', b'=', ..] => (TokenKind::Eq, 2),
+            [b'!', b'=', ..] => (TokenKind::Ne, 2),
+            [b'<', b'<', ..] => (TokenKind::Shl, 2),
+            [b'>', b'>', ..] => (TokenKind::Shr, 2),
+            [b'<', ..] => (TokenKind::Lt, 1),
+            [b'>', ..] => (TokenKind::Gt, 1),
+            _ => return None,
+        };
+        self.pos += len;
+        Some(self.token(kind, start))
+    }
+
+    fn text(&mut self, start: usize) -> Token {
+        self.pos += 1;
+        loop {
+            match self.rest() {
+                [] => return self.token(TokenKind::Error("unterminated string"), start),
+                [b'"', ..] => {
+                    self.pos += 1;
+                    return self.token(TokenKind::Text, start);
+                }
+                [b'\n', ..] => {
+                    self.pos += 1;
+                    return self.token(TokenKind::Error("unterminated string"), start);
+                }
+                [b'\\', b't' | b'n' | b'"' | b'\\', ..] => self.pos += 2,
+                [b'\\', b'x', a, b, ..] if a.is_ascii_hexdigit() && b.is_ascii_hexdigit() => {
+                    self.pos += 4
+                }
+                [b'\\', ..] => {
+                    let escape_start = self.pos;
+                    self.pos = (self.pos + 2).min(self.source.len());
+                    return self.token(TokenKind::Error("illegal escape sequence"), escape_start);
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn regex(&mut self, start: usize) -> Token {
+        self.pos += 1;
+        loop {
+            match self.rest() {
+                [] | [b'\\'] | [b'\\', b'\n', ..] => {
+                    return self.token(TokenKind::Error("unterminated regular expression"), start)
+                }
+                [b'\n', ..] => {
+                    self.pos += 1;
+                    return self.token(TokenKind::Error("unterminated regular expression"), start);
+                }
+                [b'/', ..] => {
+                    let empty = self.pos == start + 1;
+                    self.pos += 1;
+                    if self.peek_at(0) == Some(b'i') {
+                        self.pos += 1;
+                    }
+                    if self.peek_at(0) == Some(b's') {
+                        self.pos += 1;
+                    }
+                    let kind = if empty {
+                        TokenKind::Error("empty regular expression")
+                    } else {
+                        TokenKind::Regex
+                    };
+                    return self.token(kind, start);
+                }
+                [b'\\', _, ..] => self.pos += 2,
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    /// A hex string is only lexed when everything up to the closing brace can be part of one,
+    /// otherwise the brace is a token on its own.
+    fn hex_or_brace(&mut self, start: usize) -> Token {
+        let rest = self.rest();
+        let mut i = 1;
+        let mut content = false;
+        while i < rest.len() {
+            match rest[i] {
+                b'}' if content => {
+                    self.pos += i + 1;
+                    return self.token(TokenKind::Hex, start);
+                }
+                b'/' if rest[i..].starts_with(b"//") => {
+                    i += rest[i..]
+                        .iter()
+                        .position(|&c| c == b'\n')
+                        .unwrap_or(rest.len() - i);
+                }
+                b'/' if rest[i..].starts_with(b"/*") => match find(&rest[i + 2..], b"*/") {
+                    Some(end) => i += end + 4,
+                    None => break,
+                },
+                c if c.is_ascii_hexdigit() || b" -|?[]()\n\r\t".contains(&c) => i += 1,
+                _ => break,
+            }
+            content = true;
+        }
+
+        self.pos += 1;
+        self.token(TokenKind::Char(b'{'), start)
+    }
+
+    fn string_identifier(&mut self, sigil: u8, start: usize) -> Token {
+        self.pos += 1;
+        self.skip_identifier_chars();
+        let kind = match sigil {
+            b'$' if self.peek_at(0) == Some(b'*') => {
+                self.pos += 1;
+                TokenKind::StringIdentifierWithWildcard
+            }
+            b'$' => TokenKind::StringIdentifier,
+            b'#' => TokenKind::StringCount,
+            b'@' => TokenKind::StringOffset,
+            _ => TokenKind::StringLength,
+        };
+        self.token(kind, start)
+    }
+
+    fn skip_identifier_chars(&mut self) {
+        while let Some(b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'_') = self.peek_at(0) {
+            self.pos += 1;
+        }
+    }
+
+    fn number(&mut self, start: usize) -> Token {
+        let rest = self.rest();
+        let digits = |radix: u32, from: usize| {
+            rest[from..]
+                .iter()
+                .take_while(|&&c| (c as char).is_digit(radix))
+                .count()
+        };
+
+        let (value, len) = if rest.starts_with(b"0x") && digits(16, 2) > 0 {
+            let len = 2 + digits(16, 2);
+            (parse_int(&rest[2..len], 16), len)
+        } else if rest.starts_with(b"0o") && digits(8, 2) > 0 {
+            let len = 2 + digits(8, 2);
+            (parse_int(&rest[2..len], 8), len)
+        } else {
+            let len = digits(10, 0);
+            if rest.get(len) == Some(&b'.') && digits(10, len + 1) > 0 {
+                let len = len + 1 + digits(10, len + 1);
+                self.pos += len;
+                let value = std::str::from_utf8(&rest[..len])
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0.0);
+                return self.token(TokenKind::Double(value), start);
+            }
+            let value = parse_int(&rest[..len], 10);
+            match &rest[len..] {
+                [b'K', b'B', ..] => (value.and_then(|v| v.checked_mul(1024)), len + 2),
+                [b'M', b'B', ..] => (value.and_then(|v| v.checked_mul(1_048_576)), len + 2),
+                _ => (value, len),
+            }
+        };
+
+        self.pos += len;
+        match value {
+            Some(value) => self.token(TokenKind::Number(value), start),
+            None => self.token(TokenKind::Error("integer overflow"), start),
+        }
+    }
+
+    fn word(&mut self, start: usize) -> Token {
+        self.skip_identifier_chars();
+        let word = &self.source[start..self.pos];
+        let kind = match word {
+            b"all" => TokenKind::All,
+            b"and" => TokenKind::And,
+            b"any" => TokenKind::Any,
+            b"ascii" => TokenKind::Ascii,
+            b"at" => TokenKind::At,
+            b"condition" => TokenKind::Condition,
+            b"contains" => TokenKind::Contains,
+            b"entrypoint" => TokenKind::Entrypoint,
+            b"false" => TokenKind::False,
+            b"filesize" => TokenKind::Filesize,
+            b"for" => TokenKind::For,
+            b"fullword" => TokenKind::Fullword,
+            b"global" => TokenKind::Global,
+            b"import" => TokenKind::Import,
+            b"in" => TokenKind::In,
+            b"matches" => TokenKind::Matches,
+            b"meta" => TokenKind::Meta,
+            b"nocase" => TokenKind::Nocase,
+            b"not" => TokenKind::Not,
+            b"of" => TokenKind::Of,
+            b"or" => TokenKind::Or,
+            b"private" => TokenKind::Private,
+            b"rule" => TokenKind::Rule,
+            b"strings" => TokenKind::Strings,
+            b"them" => TokenKind::Them,
+            b"true" => TokenKind::True,
+            b"wide" => TokenKind::Wide,
+            b"xor" => TokenKind::Xor,
+            b"include" => return self.include(start),
+            b"int8" | b"int16" | b"int32" | b"uint8" | b"uint16" | b"uint32" | b"int8be"
+            | b"int16be" | b"int32be" | b"uint8be" | b"uint16be" | b"uint32be" => {
+                TokenKind::IntegerFunction
+            }
+            _ if word.len() > MAX_IDENTIFIER_LENGTH => TokenKind::Error("identifier too long"),
+            _ => TokenKind::Identifier,
+        };
+        self.token(kind, start)
+    }
+
+    /// `include` is a directive only when followed by blanks and a quote.
+    fn include(&mut self, start: usize) -> Token {
+        let blanks = self
+            .rest()
+            .iter()
+            .take_while(|&&c| c == b' ' || c == b'\t')
+            .count();
+        if blanks == 0 || self.peek_at(blanks) != Some(b'"') {
+            return self.token(TokenKind::Identifier, start);
+        }
+
+        self.pos += blanks + 1;
+        match self.rest().iter().position(|&c| c == b'"') {
+            Some(end) => {
+                self.pos += end + 1;
+                self.token(TokenKind::Include, start)
+            }
+            None => {
+                self.pos = self.source.len();
+                self.token(TokenKind::Error("unterminated include"), start)
+            }
+        }
+    }
+}
+
+fn parse_int(digits: &[u8], radix: u32) -> Option<i64> {
+    i64::from_str_radix(std::str::from_utf8(digits).ok()?, radix).ok()
+}
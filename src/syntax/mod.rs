@@ -0,0 +1,66 @@
+//! A parser of YARA rules, written in Rust.
+//!
+//! [`parse`] turns a rules source into a [`SourceFile`], an AST where every node has the
+//! [`Span`] of its text. It follows the grammar of libyara 3.11, and a source is accepted by
+//! [`parse`] when it is syntactically valid for [`Compiler`](../struct.Compiler.html).
+//!
+//! Only the syntax is checked: undefined identifiers, unreferenced strings or type errors are
+//! reported by the compiler. Includes are not followed.
+//!
+//! [`format`] pretty-prints a source in the canonical style.
+//!
+//! ```
+//! use rs_yara::syntax::{self, StringValue};
+//!
+//! let source = syntax::parse(r#"rule a { strings: $a = { 4D 5A } condition: $a at 0 }"#)?;
+//! let rule = source.rules().next().unwrap();
+//! assert_eq!("a", rule.identifier.name);
+//! assert!(matches!(rule.strings[0].value, StringValue::Hex(_)));
+//! # Ok::<(), rs_yara::errors::ParseError>(())
+//! ```
+
+mod ast;
+mod format;
+mod lexer;
+mod parser;
+
+pub use self::ast::*;
+pub use self::format::format;
+use crate::errors::ParseError;
+
+/// A range of bytes of a rules source.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// Parse a rules source.
+///
+/// The error has the line libyara reports for the same source, except at the end of the
+/// source, where libyara reports line 0.
+pub fn parse(source: &str) -> Result<SourceFile, ParseError> {
+    parser::parse_source(source)
+}
+
+/// The line of an offset of a source, starting at 1.
+pub fn line_of(source: &str, offset: usize) -> usize {
+    1 + source.as_bytes()[..offset.min(source.len())]
+        .iter()
+        .filter(|&&c| c == b'\n')
+        .count()
+}
@@ -0,0 +1,1086 @@
+use super::lexer::{tokenize, Token, TokenKind};
+use super::*;
+use crate::errors::ParseError;
+
+/// Longest jump accepted inside an alternative of a hex string.
+const MAX_JUMP_IN_ALTERNATIVE: i64 = 200;
+
+type PResult<T> = Result<T, ParseError>;
+
+pub(crate) fn parse_source(source: &str) -> PResult<SourceFile> {
+    let (tokens, comments) = tokenize(source);
+    let mut parser = Parser {
+        source,
+        tokens,
+        pos: 0,
+    };
+
+    let mut items = Vec::new();
+    loop {
+        let item = match parser.peek() {
+            TokenKind::Eof => break,
+            TokenKind::Import => Item::Import(parser.import()?),
+            TokenKind::Include => Item::Include(parser.include()),
+            TokenKind::Rule | TokenKind::Private | TokenKind::Global => Item::Rule(parser.rule()?),
+            _ => return Err(parser.unexpected(None)),
+        };
+        items.push(item);
+    }
+
+    Ok(SourceFile { items, comments })
+}
+
+/// Recursive descent parser following the grammar of libyara 3.11.
+///
+/// The grammar distinguishes boolean expressions from primary (arithmetic) expressions:
+/// comparisons take primary operands and do not chain, `not` binds tighter than `and` and
+/// `or` but applies to a whole comparison, and a parenthesis inside a primary expression can
+/// only hold a primary expression.
+struct Parser<'s> {
+    source: &'s str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn token(&self) -> &Token {
+        // The last token is the end of file or an error, which always stop the parsing.
+        &self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn peek(&self) -> &TokenKind {
+        &self.token().kind
+    }
+
+    fn peek_char(&self, c: u8) -> bool {
+        *self.peek() == TokenKind::Char(c)
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.token().clone();
+        self.pos += 1;
+        token
+    }
+
+    /// End of the last token consumed.
+    fn prev_end(&self) -> usize {
+        self.pos
+            .checked_sub(1)
+            .map_or(0, |pos| self.tokens[pos].span.end)
+    }
+
+    fn text(&self, span: Span) -> &'s str {
+        &self.source[span.start..span.end]
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        line_of(self.source, offset)
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> PResult<Token> {
+        if *self.peek() == kind {
+            Ok(self.advance())
+        } else {
+            Err(self.unexpected(Some(&kind)))
+        }
+    }
+
+    fn expect_char(&mut self, c: u8) -> PResult<Token> {
+        self.expect(TokenKind::Char(c))
+    }
+
+    /// An error on the current token.
+    ///
+    /// libyara reports the line of the end of the token, where its lexer stopped.
+    fn unexpected(&self, expected: Option<&TokenKind>) -> ParseError {
+        let token = self.token();
+        let message = match &token.kind {
+            TokenKind::Error(message) => (*message).to_owned(),
+            kind => {
+                let mut message = format!("syntax error, unexpected {}", kind.describe());
+                if let Some(expected) = expected {
+                    message.push_str(", expecting ");
+                    message.push_str(&expected.describe());
+                }
+                message
+            }
+        };
+        ParseError {
+            line: self.line(token.span.end),
+            span: token.span,
+            message,
+        }
+    }
+
+    fn ident(&mut self, kind: TokenKind) -> PResult<Ident> {
+        let token = self.expect(kind)?;
+        Ok(Ident {
+            name: self.text(token.span).to_owned(),
+            span: token.span,
+        })
+    }
+
+    fn text_string(&mut self) -> PResult<TextString> {
+        let token = self.expect(TokenKind::Text)?;
+        Ok(self.text_string_of(&token))
+    }
+
+    fn text_string_of(&self, token: &Token) -> TextString {
+        let text = self.text(token.span);
+        TextString {
+            raw: text[1..text.len() - 1].to_owned(),
+            span: token.span,
+        }
+    }
+
+    fn regex_of(&self, token: &Token) -> Regex {
+        let text = self.text(token.span);
+        let end = text.rfind('/').unwrap_or(text.len());
+        let flags = &text[end + 1..];
+        Regex {
+            pattern: text[1..end].to_owned(),
+            case_insensitive: flags.contains('i'),
+            dot_all: flags.contains('s'),
+            span: token.span,
+        }
+    }
+
+    fn import(&mut self) -> PResult<Import> {
+        let start = self.advance().span.start;
+        let module = self.text_string()?;
+        Ok(Import {
+            module,
+            span: Span::new(start, self.prev_end()),
+        })
+    }
+
+    fn include(&mut self) -> Include {
+        let token = self.advance();
+        let text = self.text(token.span);
+        let start = text.find('"').map_or(0, |i| i + 1);
+        Include {
+            path: text[start..text.len() - 1].to_owned(),
+            span: token.span,
+        }
+    }
+
+    fn rule(&mut self) -> PResult<Rule> {
+        let start = self.token().span.start;
+        let mut is_private = false;
+        let mut is_global = false;
+        loop {
+            match self.peek() {
+                TokenKind::Private => is_private = true,
+                TokenKind::Global => is_global = true,
+                _ => break,
+            }
+            self.advance();
+        }
+        self.expect(TokenKind::Rule)?;
+        let identifier = self.ident(TokenKind::Identifier)?;
+
+        let mut tags = Vec::new();
+        if self.peek_char(b':') {
+            self.advance();
+            tags.push(self.ident(TokenKind::Identifier)?);
+            while *self.peek() == TokenKind::Identifier {
+                tags.push(self.ident(TokenKind::Identifier)?);
+            }
+        }
+        self.expect_char(b'{')?;
+
+        let mut metas = Vec::new();
+        if *self.peek() == TokenKind::Meta {
+            self.advance();
+            self.expect_char(b':')?;
+            metas.push(self.meta()?);
+            while *self.peek() == TokenKind::Identifier {
+                metas.push(self.meta()?);
+            }
+        }
+
+        let mut strings = Vec::new();
+        if *self.peek() == TokenKind::Strings {
+            self.advance();
+            self.expect_char(b':')?;
+            strings.push(self.string_declaration()?);
+            while *self.peek() == TokenKind::StringIdentifier {
+                strings.push(self.string_declaration()?);
+            }
+        }
+
+        self.expect(TokenKind::Condition)?;
+        self.expect_char(b':')?;
+        let condition = self.expression()?;
+        self.expect_char(b'}')?;
+
+        Ok(Rule {
+            is_private,
+            is_global,
+            identifier,
+            tags,
+            metas,
+            strings,
+            condition,
+            span: Span::new(start, self.prev_end()),
+        })
+    }
+
+    fn meta(&mut self) -> PResult<Meta> {
+        let identifier = self.ident(TokenKind::Identifier)?;
+        self.expect_char(b'=')?;
+        let value = match self.peek().clone() {
+            TokenKind::Text => MetaValue::String(self.text_string()?),
+            TokenKind::Number(n) => {
+                self.advance();
+                MetaValue::Integer(n)
+            }
+            TokenKind::Char(b'-') => {
+                self.advance();
+                match self.peek().clone() {
+                    TokenKind::Number(n) => {
+                        self.advance();
+                        MetaValue::Integer(-n)
+                    }
+                    _ => return Err(self.unexpected(Some(&TokenKind::Number(0)))),
+                }
+            }
+            TokenKind::True => {
+                self.advance();
+                MetaValue::Boolean(true)
+            }
+            TokenKind::False => {
+                self.advance();
+                MetaValue::Boolean(false)
+            }
+            _ => return Err(self.unexpected(None)),
+        };
+
+        Ok(Meta {
+            span: Span::new(identifier.span.start, self.prev_end()),
+            identifier,
+            value,
+        })
+    }
+
+    fn string_declaration(&mut self) -> PResult<StringDeclaration> {
+        let identifier = self.ident(TokenKind::StringIdentifier)?;
+        // libyara reports the errors of a declaration at the line of its '='.
+        let equal = self.expect_char(b'=')?;
+        let declaration_error = |parser: &Self, message: String| ParseError {
+            line: parser.line(equal.span.end),
+            span: Span::new(identifier.span.start, parser.prev_end()),
+            message,
+        };
+
+        let token = self.advance();
+        let value = match token.kind {
+            TokenKind::Text => StringValue::Text(self.text_string_of(&token)),
+            TokenKind::Regex => StringValue::Regex(self.regex_of(&token)),
+            TokenKind::Hex => {
+                let hex = parse_hex_string(self.text(token.span), token.span).map_err(|e| {
+                    declaration_error(
+                        self,
+                        format!("invalid hex string \"{}\": {}", identifier.name, e),
+                    )
+                })?;
+                StringValue::Hex(hex)
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected(Some(&TokenKind::Text)));
+            }
+        };
+
+        let mut modifiers = Vec::new();
+        loop {
+            let start = self.token().span.start;
+            let kind = match (self.peek(), &value) {
+                (TokenKind::Private, _) => StringModifierKind::Private,
+                (TokenKind::Wide, StringValue::Text(_) | StringValue::Regex(_)) => {
+                    StringModifierKind::Wide
+                }
+                (TokenKind::Ascii, StringValue::Text(_) | StringValue::Regex(_)) => {
+                    StringModifierKind::Ascii
+                }
+                (TokenKind::Nocase, StringValue::Text(_) | StringValue::Regex(_)) => {
+                    StringModifierKind::Nocase
+                }
+                (TokenKind::Fullword, StringValue::Text(_) | StringValue::Regex(_)) => {
+                    StringModifierKind::Fullword
+                }
+                (TokenKind::Xor, StringValue::Text(_)) => {
+                    self.advance();
+                    let range = self
+                        .xor_range()?
+                        .map(|(min, max)| check_xor_range(min, max))
+                        .transpose()
+                        .map_err(|message| {
+                            declaration_error(
+                                self,
+                                format!("invalid modifier combination \"{}\"", message),
+                            )
+                        })?;
+                    modifiers.push(StringModifier {
+                        kind: StringModifierKind::Xor(range),
+                        span: Span::new(start, self.prev_end()),
+                    });
+                    continue;
+                }
+                _ => break,
+            };
+            self.advance();
+            modifiers.push(StringModifier {
+                kind,
+                span: Span::new(start, self.prev_end()),
+            });
+        }
+
+        Ok(StringDeclaration {
+            span: Span::new(identifier.span.start, self.prev_end()),
+            identifier,
+            value,
+            modifiers,
+        })
+    }
+
+    /// The optional `(n)` or `(min-max)` after `xor`.
+    fn xor_range(&mut self) -> PResult<Option<(i64, i64)>> {
+        if !self.peek_char(b'(') {
+            return Ok(None);
+        }
+        self.advance();
+        let min = self.number()?;
+        let max = if self.peek_char(b'-') {
+            self.advance();
+            self.number()?
+        } else {
+            min
+        };
+        self.expect_char(b')')?;
+        Ok(Some((min, max)))
+    }
+
+    fn number(&mut self) -> PResult<i64> {
+        match *self.peek() {
+            TokenKind::Number(n) => {
+                self.advance();
+                Ok(n)
+            }
+            _ => Err(self.unexpected(Some(&TokenKind::Number(0)))),
+        }
+    }
+
+    fn expression(&mut self) -> PResult<Expr> {
+        let mut lhs = self.and_expression()?;
+        while *self.peek() == TokenKind::Or {
+            self.advance();
+            let rhs = self.and_expression()?;
+            lhs = binary(BinaryOp::Or, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    fn and_expression(&mut self) -> PResult<Expr> {
+        let mut lhs = self.boolean_term()?;
+        while *self.peek() == TokenKind::And {
+            self.advance();
+            let rhs = self.boolean_term()?;
+            lhs = binary(BinaryOp::And, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    /// An expression without `and` or `or` at its top.
+    fn boolean_term(&mut self) -> PResult<Expr> {
+        let start = self.token().span.start;
+        let kind = match self.peek().clone() {
+            TokenKind::Not => {
+                self.advance();
+                let operand = self.boolean_term()?;
+                ExprKind::Unary {
+                    op: UnaryOp::Not,
+                    operand: Box::new(operand),
+                }
+            }
+            TokenKind::True | TokenKind::False => {
+                ExprKind::Boolean(self.advance().kind == TokenKind::True)
+            }
+            TokenKind::StringIdentifier => {
+                let identifier = self.ident(TokenKind::StringIdentifier)?;
+                match self.peek() {
+                    TokenKind::At => {
+                        self.advance();
+                        let offset = self.primary_expression(0)?;
+                        ExprKind::StringAt {
+                            identifier,
+                            offset: Box::new(offset),
+                        }
+                    }
+                    TokenKind::In => {
+                        self.advance();
+                        let (start, end) = self.range()?;
+                        ExprKind::StringIn {
+                            identifier,
+                            start: Box::new(start),
+                            end: Box::new(end),
+                        }
+                    }
+                    _ => ExprKind::String(identifier),
+                }
+            }
+            TokenKind::For => return self.for_expression(),
+            TokenKind::All | TokenKind::Any => {
+                let quantifier = self.quantifier()?;
+                self.expect(TokenKind::Of)?;
+                let strings = self.string_set()?;
+                ExprKind::Of {
+                    quantifier,
+                    strings,
+                }
+            }
+            TokenKind::Char(b'(') => {
+                self.advance();
+                let inner = self.expression()?;
+                self.expect_char(b')')?;
+                let paren = Expr {
+                    kind: ExprKind::Paren(Box::new(inner)),
+                    span: Span::new(start, self.prev_end()),
+                };
+                if !is_primary(&paren) {
+                    return Ok(paren);
+                }
+                let primary = self.binary_operators(paren, 0)?;
+                return self.after_primary(primary);
+            }
+            _ => {
+                let primary = self.primary_expression(0)?;
+                return self.after_primary(primary);
+            }
+        };
+
+        Ok(Expr {
+            kind,
+            span: Span::new(start, self.prev_end()),
+        })
+    }
+
+    /// The boolean expressions starting with a primary expression.
+    fn after_primary(&mut self, lhs: Expr) -> PResult<Expr> {
+        let op = match self.peek() {
+            TokenKind::Eq => BinaryOp::Eq,
+            TokenKind::Ne => BinaryOp::Ne,
+            TokenKind::Lt => BinaryOp::Lt,
+            TokenKind::Le => BinaryOp::Le,
+            TokenKind::Gt => BinaryOp::Gt,
+            TokenKind::Ge => BinaryOp::Ge,
+            TokenKind::Contains => BinaryOp::Contains,
+            TokenKind::Matches => {
+                self.advance();
+                let token = self.expect(TokenKind::Regex)?;
+                let regex = Expr {
+                    kind: ExprKind::Regex(self.regex_of(&token)),
+                    span: token.span,
+                };
+                return Ok(binary(BinaryOp::Matches, lhs, regex));
+            }
+            TokenKind::Of => {
+                self.advance();
+                let strings = self.string_set()?;
+                return Ok(Expr {
+                    span: Span::new(lhs.span.start, self.prev_end()),
+                    kind: ExprKind::Of {
+                        quantifier: Quantifier::Expr(Box::new(lhs)),
+                        strings,
+                    },
+                });
+            }
+            _ => return Ok(lhs),
+        };
+        self.advance();
+        let rhs = self.primary_expression(0)?;
+        Ok(binary(op, lhs, rhs))
+    }
+
+    fn for_expression(&mut self) -> PResult<Expr> {
+        let start = self.advance().span.start;
+        let quantifier = self.quantifier()?;
+        let kind = match self.peek() {
+            TokenKind::Identifier => {
+                let variable = self.ident(TokenKind::Identifier)?;
+                self.expect(TokenKind::In)?;
+                let integers = self.integer_set()?;
+                let body = self.for_body()?;
+                ExprKind::ForIn {
+                    quantifier,
+                    variable,
+                    integers,
+                    body: Box::new(body),
+                }
+            }
+            TokenKind::Of => {
+                self.advance();
+                let strings = self.string_set()?;
+                let body = self.for_body()?;
+                ExprKind::ForOf {
+                    quantifier,
+                    strings,
+                    body: Box::new(body),
+                }
+            }
+            _ => return Err(self.unexpected(None)),
+        };
+
+        Ok(Expr {
+            kind,
+            span: Span::new(start, self.prev_end()),
+        })
+    }
+
+    /// `: ( expression )`
+    fn for_body(&mut self) -> PResult<Expr> {
+        self.expect_char(b':')?;
+        self.expect_char(b'(')?;
+        let body = self.expression()?;
+        self.expect_char(b')')?;
+        Ok(body)
+    }
+
+    fn quantifier(&mut self) -> PResult<Quantifier> {
+        match self.peek() {
+            TokenKind::All => {
+                self.advance();
+                Ok(Quantifier::All)
+            }
+            TokenKind::Any => {
+                self.advance();
+                Ok(Quantifier::Any)
+            }
+            _ => Ok(Quantifier::Expr(Box::new(self.primary_expression(0)?))),
+        }
+    }
+
+    fn string_set(&mut self) -> PResult<StringSet> {
+        if *self.peek() == TokenKind::Them {
+            self.advance();
+            return Ok(StringSet::Them);
+        }
+
+        self.expect_char(b'(')?;
+        let mut strings = Vec::new();
+        loop {
+            let kind = match self.peek() {
+                TokenKind::StringIdentifierWithWildcard => TokenKind::StringIdentifierWithWildcard,
+                _ => TokenKind::StringIdentifier,
+            };
+            strings.push(self.ident(kind)?);
+            if !self.peek_char(b',') {
+                break;
+            }
+            self.advance();
+        }
+        self.expect_char(b')')?;
+        Ok(StringSet::List(strings))
+    }
+
+    /// `(start..end)`
+    fn range(&mut self) -> PResult<(Expr, Expr)> {
+        self.expect_char(b'(')?;
+        let start = self.primary_expression(0)?;
+        self.expect(TokenKind::DotDot)?;
+        let end = self.primary_expression(0)?;
+        self.expect_char(b')')?;
+        Ok((start, end))
+    }
+
+    fn integer_set(&mut self) -> PResult<IntegerSet> {
+        self.expect_char(b'(')?;
+        let first = self.primary_expression(0)?;
+        if *self.peek() == TokenKind::DotDot {
+            self.advance();
+            let end = self.primary_expression(0)?;
+            self.expect_char(b')')?;
+            return Ok(IntegerSet::Range {
+                start: Box::new(first),
+                end: Box::new(end),
+            });
+        }
+
+        let mut integers = vec![first];
+        while self.peek_char(b',') {
+            self.advance();
+            integers.push(self.primary_expression(0)?);
+        }
+        self.expect_char(b')')?;
+        Ok(IntegerSet::Enumeration(integers))
+    }
+
+    /// A primary expression, with the binary operators binding tighter than `min_precedence`.
+    fn primary_expression(&mut self, min_precedence: u8) -> PResult<Expr> {
+        let lhs = self.primary_operand()?;
+        self.binary_operators(lhs, min_precedence)
+    }
+
+    fn binary_operators(&mut self, mut lhs: Expr, min_precedence: u8) -> PResult<Expr> {
+        loop {
+            let (op, precedence) = match self.peek() {
+                TokenKind::Char(b'|') => (BinaryOp::BitOr, 1),
+                TokenKind::Char(b'^') => (BinaryOp::BitXor, 2),
+                TokenKind::Char(b'&') => (BinaryOp::BitAnd, 3),
+                TokenKind::Shl => (BinaryOp::Shl, 4),
+                TokenKind::Shr => (BinaryOp::Shr, 4),
+                TokenKind::Char(b'+') => (BinaryOp::Add, 5),
+                TokenKind::Char(b'-') => (BinaryOp::Sub, 5),
+                TokenKind::Char(b'*') => (BinaryOp::Mul, 6),
+                TokenKind::Char(b'\\') => (BinaryOp::Div, 6),
+                TokenKind::Char(b'%') => (BinaryOp::Mod, 6),
+                _ => return Ok(lhs),
+            };
+            if precedence <= min_precedence {
+                return Ok(lhs);
+            }
+            self.advance();
+            let rhs = self.primary_expression(precedence)?;
+            lhs = binary(op, lhs, rhs);
+        }
+    }
+
+    fn primary_operand(&mut self) -> PResult<Expr> {
+        let start = self.token().span.start;
+        let token = self.advance();
+        let kind = match token.kind {
+            TokenKind::Char(b'(') => {
+                let inner = self.primary_expression(0)?;
+                self.expect_char(b')')?;
+                ExprKind::Paren(Box::new(inner))
+            }
+            TokenKind::Char(c @ (b'-' | b'~')) => {
+                let operand = self.primary_operand()?;
+                ExprKind::Unary {
+                    op: if c == b'-' {
+                        UnaryOp::Neg
+                    } else {
+                        UnaryOp::BitNot
+                    },
+                    operand: Box::new(operand),
+                }
+            }
+            TokenKind::Filesize => ExprKind::Filesize,
+            TokenKind::Entrypoint => ExprKind::Entrypoint,
+            TokenKind::Number(n) => ExprKind::Integer(n),
+            TokenKind::Double(f) => ExprKind::Float(f),
+            TokenKind::Text => ExprKind::Text(self.text_string_of(&token)),
+            TokenKind::Regex => ExprKind::Regex(self.regex_of(&token)),
+            TokenKind::IntegerFunction => {
+                let function = Ident {
+                    name: self.text(token.span).to_owned(),
+                    span: token.span,
+                };
+                self.expect_char(b'(')?;
+                let offset = self.primary_expression(0)?;
+                self.expect_char(b')')?;
+                ExprKind::IntegerFunction {
+                    function,
+                    offset: Box::new(offset),
+                }
+            }
+            TokenKind::StringCount => ExprKind::StringCount(Ident {
+                name: self.text(token.span).to_owned(),
+                span: token.span,
+            }),
+            TokenKind::StringOffset | TokenKind::StringLength => {
+                let identifier = Ident {
+                    name: self.text(token.span).to_owned(),
+                    span: token.span,
+                };
+                let index = if self.peek_char(b'[') {
+                    self.advance();
+                    let index = self.primary_expression(0)?;
+                    self.expect_char(b']')?;
+                    Some(Box::new(index))
+                } else {
+                    None
+                };
+                if token.kind == TokenKind::StringOffset {
+                    ExprKind::StringOffset { identifier, index }
+                } else {
+                    ExprKind::StringLength { identifier, index }
+                }
+            }
+            TokenKind::Identifier => {
+                let identifier = Expr {
+                    kind: ExprKind::Identifier(Ident {
+                        name: self.text(token.span).to_owned(),
+                        span: token.span,
+                    }),
+                    span: token.span,
+                };
+                return self.identifier_suffixes(identifier);
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected(None));
+            }
+        };
+
+        Ok(Expr {
+            kind,
+            span: Span::new(start, self.prev_end()),
+        })
+    }
+
+    /// `.field`, `[index]` and `(arguments)` after an identifier.
+    fn identifier_suffixes(&mut self, mut expr: Expr) -> PResult<Expr> {
+        loop {
+            let kind = match self.peek() {
+                TokenKind::Char(b'.') => {
+                    self.advance();
+                    let field = self.ident(TokenKind::Identifier)?;
+                    ExprKind::Field {
+                        object: Box::new(expr.clone()),
+                        field,
+                    }
+                }
+                TokenKind::Char(b'[') => {
+                    self.advance();
+                    let index = self.primary_expression(0)?;
+                    self.expect_char(b']')?;
+                    ExprKind::Index {
+                        object: Box::new(expr.clone()),
+                        index: Box::new(index),
+                    }
+                }
+                TokenKind::Char(b'(') => {
+                    self.advance();
+                    let mut arguments = Vec::new();
+                    if !self.peek_char(b')') {
+                        arguments.push(self.expression()?);
+                        while self.peek_char(b',') {
+                            self.advance();
+                            arguments.push(self.expression()?);
+                        }
+                    }
+                    self.expect_char(b')')?;
+                    ExprKind::Call {
+                        function: Box::new(expr.clone()),
+                        arguments,
+                    }
+                }
+                _ => return Ok(expr),
+            };
+            expr = Expr {
+                kind,
+                span: Span::new(expr.span.start, self.prev_end()),
+            };
+        }
+    }
+}
+
+fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr {
+        span: Span::new(lhs.span.start, rhs.span.end),
+        kind: ExprKind::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+    }
+}
+
+/// Whether an expression is a primary expression of the grammar, which can be an operand of
+/// arithmetic operators and comparisons.
+fn is_primary(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Boolean(_)
+        | ExprKind::String(_)
+        | ExprKind::StringAt { .. }
+        | ExprKind::StringIn { .. }
+        | ExprKind::Of { .. }
+        | ExprKind::ForOf { .. }
+        | ExprKind::ForIn { .. } => false,
+        ExprKind::Unary { op, .. } => *op != UnaryOp::Not,
+        ExprKind::Binary { op, .. } => !matches!(
+            op,
+            BinaryOp::Or
+                | BinaryOp::And
+                | BinaryOp::Eq
+                | BinaryOp::Ne
+                | BinaryOp::Lt
+                | BinaryOp::Le
+                | BinaryOp::Gt
+                | BinaryOp::Ge
+                | BinaryOp::Matches
+                | BinaryOp::Contains
+        ),
+        ExprKind::Paren(inner) => is_primary(inner),
+        _ => true,
+    }
+}
+
+fn check_xor_range(min: i64, max: i64) -> Result<(u8, u8), &'static str> {
+    if min == max && !(0..=255).contains(&min) {
+        return Err("invalid xor range");
+    }
+    if min < 0 {
+        return Err("lower bound for xor range exceeded (min: 0)");
+    }
+    if max > 255 {
+        return Err("upper bound for xor range exceeded (max: 255)");
+    }
+    if min > max {
+        return Err("xor lower bound exceeds upper bound");
+    }
+    Ok((min as u8, max as u8))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HexTokenKind {
+    Byte(u8, u8),
+    Number(i64),
+    Char(u8),
+    End,
+}
+
+impl HexTokenKind {
+    fn describe(self) -> String {
+        match self {
+            HexTokenKind::Byte(_, 0xFF) => "_BYTE_".to_owned(),
+            HexTokenKind::Byte(..) => "_MASKED_BYTE_".to_owned(),
+            HexTokenKind::Number(_) => "_NUMBER_".to_owned(),
+            HexTokenKind::Char(c) => format!("'{}'", c as char),
+            HexTokenKind::End => "$end".to_owned(),
+        }
+    }
+}
+
+/// Parse a hex string, braces included, the same way as libyara.
+///
+/// Errors are the messages of libyara, without the string identifier.
+fn parse_hex_string(text: &str, span: Span) -> Result<HexString, String> {
+    let tokens = tokenize_hex(text.as_bytes())?;
+    let mut parser = HexParser { tokens, pos: 0 };
+    parser.expect(HexTokenKind::Char(b'{'))?;
+    let tokens = parser.tokens(false)?;
+    parser.expect(HexTokenKind::Char(b'}'))?;
+    parser.expect(HexTokenKind::End)?;
+
+    Ok(HexString { tokens, span })
+}
+
+fn tokenize_hex(text: &[u8]) -> Result<Vec<HexTokenKind>, String> {
+    let nibble = |c: u8| (c as char).to_digit(16).map(|d| d as u8);
+
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut in_jump = false;
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with(b"//") {
+            i += rest.iter().position(|&c| c == b'\n').unwrap_or(rest.len());
+            continue;
+        }
+        if rest.starts_with(b"/*") {
+            i += rest[2..]
+                .windows(2)
+                .position(|w| w == b"*/")
+                .map_or(rest.len(), |end| end + 4);
+            continue;
+        }
+        let c = rest[0];
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if in_jump {
+            match c {
+                b'0'..=b'9' => {
+                    let len = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+                    let value = std::str::from_utf8(&rest[..len])
+                        .unwrap()
+                        .parse()
+                        .unwrap_or(i64::MAX);
+                    tokens.push(HexTokenKind::Number(value));
+                    i += len;
+                    continue;
+                }
+                b'-' => {}
+                b']' => in_jump = false,
+                _ => return Err("invalid character in hex string jump".to_owned()),
+            }
+            tokens.push(HexTokenKind::Char(c));
+            i += 1;
+            continue;
+        }
+
+        let pair = rest.get(1).copied();
+        match (c, pair) {
+            (b'?', Some(b'?')) => tokens.push(HexTokenKind::Byte(0, 0)),
+            (b'?', Some(low)) if nibble(low).is_some() => {
+                tokens.push(HexTokenKind::Byte(nibble(low).unwrap(), 0x0F))
+            }
+            (high, Some(b'?')) if nibble(high).is_some() => {
+                tokens.push(HexTokenKind::Byte(nibble(high).unwrap() << 4, 0xF0))
+            }
+            (high, Some(low)) if nibble(high).is_some() && nibble(low).is_some() => tokens.push(
+                HexTokenKind::Byte(nibble(high).unwrap() << 4 | nibble(low).unwrap(), 0xFF),
+            ),
+            (b'{' | b'}' | b'(' | b')' | b'|' | b'[', _) => {
+                in_jump = c == b'[';
+                tokens.push(HexTokenKind::Char(c));
+                i += 1;
+                continue;
+            }
+            _ => return Err("invalid character in hex string".to_owned()),
+        }
+        i += 2;
+    }
+    tokens.push(HexTokenKind::End);
+
+    Ok(tokens)
+}
+
+struct HexParser {
+    tokens: Vec<HexTokenKind>,
+    pos: usize,
+}
+
+impl HexParser {
+    fn peek(&self) -> HexTokenKind {
+        self.tokens[self.pos.min(self.tokens.len() - 1)]
+    }
+
+    fn advance(&mut self) -> HexTokenKind {
+        let token = self.peek();
+        self.pos += 1;
+        token
+    }
+
+    fn unexpected(&self, expecting: &str) -> String {
+        let mut message = format!("syntax error, unexpected {}", self.peek().describe());
+        if !expecting.is_empty() {
+            message.push_str(", expecting ");
+            message.push_str(expecting);
+        }
+        message
+    }
+
+    fn expect(&mut self, kind: HexTokenKind) -> Result<(), String> {
+        if self.peek() == kind {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.unexpected(&kind.describe()))
+        }
+    }
+
+    /// Bytes, alternatives and jumps, starting and ending with a byte or an alternative.
+    fn tokens(&mut self, in_alternative: bool) -> Result<Vec<HexToken>, String> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = match self.peek() {
+                HexTokenKind::Byte(value, mask) => {
+                    self.advance();
+                    HexToken::Byte { value, mask }
+                }
+                HexTokenKind::Char(b'(') => {
+                    self.advance();
+                    let mut alternatives = vec![self.tokens(true)?];
+                    while self.peek() == HexTokenKind::Char(b'|') {
+                        self.advance();
+                        alternatives.push(self.tokens(true)?);
+                    }
+                    self.expect(HexTokenKind::Char(b')'))?;
+                    HexToken::Alternative(alternatives)
+                }
+                HexTokenKind::Char(b'[') if !tokens.is_empty() => {
+                    self.advance();
+                    self.jump(in_alternative)?
+                }
+                _ if tokens.is_empty() => {
+                    return Err(self.unexpected("_BYTE_ or _MASKED_BYTE_ or '('"))
+                }
+                _ if matches!(
+                    tokens.last(),
+                    Some(HexToken::Skip(_)) | Some(HexToken::Jump { .. })
+                ) =>
+                {
+                    return Err(self.unexpected("_BYTE_ or _MASKED_BYTE_ or '(' or '['"))
+                }
+                _ => return Ok(tokens),
+            };
+            tokens.push(token);
+        }
+    }
+
+    /// `n]`, `n-m]`, `n-]` or `-]`, after the `[`.
+    fn jump(&mut self, in_alternative: bool) -> Result<HexToken, String> {
+        let too_long = || {
+            format!(
+                "jumps over {} not allowed inside alternation (|)",
+                MAX_JUMP_IN_ALTERNATIVE
+            )
+        };
+        let unbounded = "unbounded jumps not allowed inside alternation (|)";
+
+        let start = match self.advance() {
+            HexTokenKind::Number(n) => Some(n),
+            HexTokenKind::Char(b'-') => None,
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected("_NUMBER_ or '-'"));
+            }
+        };
+        let (start, end) = match (start, self.advance()) {
+            (Some(n), HexTokenKind::Char(b']')) => {
+                if n <= 0 {
+                    return Err("invalid jump length".to_owned());
+                }
+                if in_alternative && n > MAX_JUMP_IN_ALTERNATIVE {
+                    return Err(too_long());
+                }
+                return Ok(HexToken::Skip(n.min(u32::MAX as i64) as u32));
+            }
+            (Some(n), HexTokenKind::Char(b'-')) => match self.advance() {
+                HexTokenKind::Number(m) => {
+                    self.expect(HexTokenKind::Char(b']'))?;
+                    if in_alternative
+                        && (n > MAX_JUMP_IN_ALTERNATIVE || m > MAX_JUMP_IN_ALTERNATIVE)
+                    {
+                        return Err(too_long());
+                    }
+                    if n > m {
+                        return Err("invalid jump range".to_owned());
+                    }
+                    (Some(n), Some(m))
+                }
+                HexTokenKind::Char(b']') => {
+                    if in_alternative {
+                        return Err(unbounded.to_owned());
+                    }
+                    (Some(n), None)
+                }
+                _ => {
+                    self.pos -= 1;
+                    return Err(self.unexpected("_NUMBER_ or ']'"));
+                }
+            },
+            (None, HexTokenKind::Char(b']')) => {
+                if in_alternative {
+                    return Err(unbounded.to_owned());
+                }
+                (None, None)
+            }
+            _ => {
+                self.pos -= 1;
+                return Err(self.unexpected(""));
+            }
+        };
+
+        let clamp = |n: i64| n.clamp(0, u32::MAX as i64) as u32;
+        Ok(HexToken::Jump {
+            start: start.map(clamp),
+            end: end.map(clamp),
+        })
+    }
+}
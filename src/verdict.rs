@@ -0,0 +1,322 @@
+//! A cache of scan results, by content and rules.
+//!
+//! Files seen again, on other hosts or shares, are not scanned again: the results of a scan are
+//! stored under the SHA-256 of the scanned data and the [fingerprint](crate::RuleSource::fingerprint)
+//! of the rules. Results are stored as [`OwnedRule`]s, so reports of cached results are the same
+//! as reports of scans, whatever their [`View`](crate::render::View).
+//!
+//! ```no_run
+//! use rs_yara::report::FileReport;
+//! use rs_yara::render::View;
+//! use rs_yara::verdict::VerdictCache;
+//! use rs_yara::{OwnedRule, RuleSet};
+//!
+//! let rule_set = RuleSet::from_directory("rules")?;
+//! let cache = VerdictCache::new("/var/cache/rs_yara/verdicts");
+//! let (rules, fingerprint) = rule_set.rules_with_fingerprint();
+//! let verdict = cache.scan_file(&rules, &fingerprint, "/tmp/sample", 10)?;
+//! let matches: Vec<_> = verdict.rules.iter().map(OwnedRule::as_rule).collect();
+//! println!("{}", FileReport::new("/tmp/sample", &matches, View::Ascii).to_json());
+//! # Ok::<(), rs_yara::errors::Error>(())
+//! ```
+
+use std::cmp::Reverse;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+
+use crate::cache::{to_hex, write_atomically};
+use crate::errors::*;
+use crate::{OwnedRule, Rules};
+
+/// Default size limit of the entries of a cache, in bytes.
+pub const DEFAULT_MAX_SIZE: u64 = 256 * 1024 * 1024;
+
+const ENTRY_EXTENSION: &str = "json";
+
+/// The file which marks a directory as a verdict cache.
+const MARKER: &str = ".rs_yara-verdicts";
+
+/// The results of a scan, and whether they come from the cache.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Verdict {
+    pub rules: Vec<OwnedRule>,
+    pub cached: bool,
+}
+
+/// Scan results stored in a directory, as `FINGERPRINT/KEY.json`.
+///
+/// A directory holds the results of one set of rules: storing results for a new fingerprint
+/// removes the results of the other ones, so all the entries are invalidated when the rules
+/// change. Failed scans, such as timeouts, are not stored. Since subdirectories are removed, the
+/// directory must be empty or already a verdict cache, see [`init`](#method.init).
+///
+/// Readers never see a partial entry, even with concurrent writers. When the entries exceed
+/// `max_size` bytes, the least recently used ones are removed. A corrupted entry is a miss.
+pub struct VerdictCache {
+    dir: PathBuf,
+    max_size: u64,
+    /// Size of the entries, once computed, as this cache stored them.
+    size: Mutex<Option<u64>>,
+}
+
+impl VerdictCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        VerdictCache {
+            dir: dir.as_ref().to_path_buf(),
+            max_size: DEFAULT_MAX_SIZE,
+            size: Mutex::new(None),
+        }
+    }
+
+    /// Set the size limit of the entries, in bytes. Default: [`DEFAULT_MAX_SIZE`].
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Create the directory of the cache, or check that it is one: an empty directory, or one
+    /// marked as a verdict cache by a previous `init`.
+    pub fn init(&self) -> Result<(), Error> {
+        let to_io_error = |e| IoError::new(e, IoErrorKind::WritingVerdictCache);
+
+        fs::create_dir_all(&self.dir).map_err(to_io_error)?;
+        let marker = self.dir.join(MARKER);
+        if marker.is_file() {
+            return Ok(());
+        }
+        if fs::read_dir(&self.dir)
+            .map_err(to_io_error)?
+            .next()
+            .is_some()
+        {
+            let e = io::Error::other(format!(
+                "{} is not empty and not a verdict cache",
+                self.dir.display()
+            ));
+            return Err(to_io_error(e).into());
+        }
+        fs::write(marker, b"").map_err(to_io_error)?;
+        Ok(())
+    }
+
+    /// The key of `data` scanned by `rules`: the SHA-256 of the data, with the flags and the
+    /// context of the scans, which change their results.
+    pub fn key(data: &[u8], rules: &Rules) -> String {
+        digest_key(&Sha256::digest(data), rules)
+    }
+
+    /// The [key](#method.key) of the content of the file at `path`, hashed without reading it
+    /// in memory.
+    pub fn file_key<P: AsRef<Path>>(path: P, rules: &Rules) -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+        Ok(digest_key(&hasher.finalize(), rules))
+    }
+
+    /// The results stored for `key` with the rules of `fingerprint`.
+    pub fn get(&self, fingerprint: &str, key: &str) -> Option<Vec<OwnedRule>> {
+        if !is_fingerprint(fingerprint) {
+            return None;
+        }
+        let entry = self.entry_path(fingerprint, key);
+        let rules = serde_json::from_slice(&fs::read(&entry).ok()?).ok()?;
+        // Keep the entry from being evicted.
+        let _ = fs::OpenOptions::new()
+            .write(true)
+            .open(&entry)
+            .and_then(|f| f.set_modified(SystemTime::now()));
+        Some(rules)
+    }
+
+    /// Store the results of `key` with the rules of `fingerprint`.
+    ///
+    /// The results of other fingerprints are removed, then the least recently used entries if
+    /// the cache is too large. `fingerprint` must be a [fingerprint](crate::RuleSource::fingerprint).
+    pub fn insert(&self, fingerprint: &str, key: &str, rules: &[OwnedRule]) -> Result<(), Error> {
+        let to_io_error = |e| IoError::new(e, IoErrorKind::WritingVerdictCache);
+
+        if !is_fingerprint(fingerprint) {
+            let e = io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid rules fingerprint `{}`", fingerprint),
+            );
+            return Err(to_io_error(e).into());
+        }
+        let dir = self.dir.join(fingerprint);
+        if !dir.is_dir() {
+            self.init()?;
+            self.remove_other_fingerprints(fingerprint)
+                .map_err(to_io_error)?;
+            fs::create_dir_all(&dir).map_err(to_io_error)?;
+        }
+
+        let data = serde_json::to_vec(rules).expect("owned rules should serialize");
+        write_atomically(&self.entry_path(fingerprint, key), &data).map_err(to_io_error)?;
+
+        let mut size = self.size.lock().expect("mutex should not be poisoned");
+        let total = match *size {
+            Some(total) => total + data.len() as u64,
+            None => entries_size(&dir),
+        };
+        *size = Some(total);
+        if total > self.max_size {
+            *size = Some(self.evict(&dir).map_err(to_io_error)?);
+        }
+        Ok(())
+    }
+
+    /// Scan `data`, unless the cache has its results.
+    ///
+    /// The results are returned even if they could not be stored.
+    pub fn scan_mem(
+        &self,
+        rules: &Rules,
+        fingerprint: &str,
+        data: &[u8],
+        timeout: u16,
+    ) -> Result<Verdict, YaraError> {
+        let key = Self::key(data, rules);
+        if let Some(cached) = self.get(fingerprint, &key) {
+            return Ok(Verdict {
+                rules: cached,
+                cached: true,
+            });
+        }
+
+        let matches: Vec<OwnedRule> = rules
+            .scan_mem(data, timeout)?
+            .into_iter()
+            .map(OwnedRule::from)
+            .collect();
+        let _ = self.insert(fingerprint, &key, &matches);
+        Ok(Verdict {
+            rules: matches,
+            cached: false,
+        })
+    }
+
+    /// Scan a file, unless the cache has the results of its content, see
+    /// [`scan_mem`](#method.scan_mem).
+    ///
+    /// The file is hashed in a streaming pass, then scanned by YARA from its path. The results
+    /// are not stored if the file changed in between.
+    pub fn scan_file<P: AsRef<Path>>(
+        &self,
+        rules: &Rules,
+        fingerprint: &str,
+        path: P,
+        timeout: u16,
+    ) -> Result<Verdict, Error> {
+        let path = path.as_ref();
+        let to_io_error = |e| IoError::new(e, IoErrorKind::ReadingScanFile);
+        let before = fs::metadata(path).map_err(to_io_error)?;
+        let key = Self::file_key(path, rules).map_err(to_io_error)?;
+        if let Some(cached) = self.get(fingerprint, &key) {
+            return Ok(Verdict {
+                rules: cached,
+                cached: true,
+            });
+        }
+
+        let matches: Vec<OwnedRule> = rules
+            .scan_file(path, timeout)?
+            .into_iter()
+            .map(OwnedRule::from)
+            .collect();
+        let unchanged = fs::metadata(path).is_ok_and(|after| {
+            after.len() == before.len() && after.modified().ok() == before.modified().ok()
+        });
+        if unchanged {
+            let _ = self.insert(fingerprint, &key, &matches);
+        }
+        Ok(Verdict {
+            rules: matches,
+            cached: false,
+        })
+    }
+
+    fn entry_path(&self, fingerprint: &str, key: &str) -> PathBuf {
+        self.dir
+            .join(fingerprint)
+            .join(key)
+            .with_extension(ENTRY_EXTENSION)
+    }
+
+    /// Remove the directories of the other fingerprints, and only them.
+    fn remove_other_fingerprints(&self, fingerprint: &str) -> io::Result<()> {
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let other = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name != fingerprint && is_fingerprint(name));
+            if other && path.is_dir() {
+                // Another process may have removed it already.
+                let _ = fs::remove_dir_all(&path);
+            }
+        }
+        *self.size.lock().expect("mutex should not be poisoned") = None;
+        Ok(())
+    }
+
+    /// Remove the least recently used entries of `dir`, down to 3/4 of `max_size` so that
+    /// evictions are not done at each insertion. Return the size of the remaining entries.
+    fn evict(&self, dir: &Path) -> io::Result<u64> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == ENTRY_EXTENSION) {
+                if let Ok(metadata) = fs::metadata(&path) {
+                    entries.push((metadata.modified()?, metadata.len(), path));
+                }
+            }
+        }
+        entries.sort_by_key(|(modified, _, _)| Reverse(*modified));
+
+        let target = self.max_size / 4 * 3;
+        let mut total = 0;
+        let mut full = false;
+        for (_, len, path) in entries {
+            full = full || total + len > target;
+            if full {
+                let _ = fs::remove_file(&path);
+            } else {
+                total += len;
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// The key of data with the SHA-256 `digest` scanned by `rules`.
+fn digest_key(digest: &[u8], rules: &Rules) -> String {
+    format!("{}-{}-{}", to_hex(digest), rules.flags(), rules.context())
+}
+
+/// Whether `name` is a rules fingerprint: 64 lowercase hex digits.
+fn is_fingerprint(name: &str) -> bool {
+    name.len() == 64 && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Size of the entries of `dir`.
+fn entries_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.metadata().ok())
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+        .sum()
+}
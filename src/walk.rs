@@ -0,0 +1,49 @@
+//! Walks of the directories to scan.
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::errors::*;
+
+/// The files of `paths`, with the files of directories, recursively, sorted by path, and the
+/// directories which could not be read, with their errors.
+///
+/// The walk goes on after an error, so the files of a directory which could not be read are
+/// missing, or only some of them. Symbolic links to directories are only followed when they are
+/// in `paths`, so that links back to a parent never loop.
+pub fn files(paths: &[PathBuf]) -> (Vec<PathBuf>, Vec<(PathBuf, Error)>) {
+    let to_error = |e| Error::from(IoError::new(e, IoErrorKind::ReadingScanDirectory));
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    let mut pending = paths.to_vec();
+    while let Some(path) = pending.pop() {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        let entries = match fs::read_dir(&path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push((path, to_error(e)));
+                continue;
+            }
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    errors.push((path, to_error(e)));
+                    break;
+                }
+            };
+            let is_symlink = entry.file_type().is_ok_and(|t| t.is_symlink());
+            if is_symlink && entry.path().is_dir() {
+                continue;
+            }
+            pending.push(entry.path());
+        }
+    }
+    files.sort();
+    errors.sort_by(|(a, _), (b, _)| a.cmp(b));
+    (files, errors)
+}
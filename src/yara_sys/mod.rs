@@ -23,6 +23,7 @@ pub use yara_c::ERROR_SCAN_TIMEOUT;
 pub use yara_c::ERROR_SUCCESS;
 pub use yara_c::ERROR_SYNTAX_ERROR;
 pub use yara_c::ERROR_TOO_MANY_MATCHES;
+pub use yara_c::ERROR_TOO_MANY_SCAN_THREADS;
 pub use yara_c::ERROR_UNSUPPORTED_FILE_VERSION;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -49,6 +50,8 @@ pub enum Error {
     SyntaxError,
     /// Too many matches
     TooManyMatches,
+    /// Too many threads scanning with the same rules
+    TooManyScanThreads,
     /// Rule file version is not supported
     UnsupportedFileVersion,
     /// Unknown Yara error
@@ -75,6 +78,7 @@ impl Error {
             ERROR_SCAN_TIMEOUT => ScanTimeout,
             ERROR_SYNTAX_ERROR => SyntaxError,
             ERROR_TOO_MANY_MATCHES => TooManyMatches,
+            ERROR_TOO_MANY_SCAN_THREADS => TooManyScanThreads,
             ERROR_UNSUPPORTED_FILE_VERSION => UnsupportedFileVersion,
             _ => Unknown(code),
         })
@@ -109,6 +113,7 @@ impl From<Error> for &'static str {
             ScanTimeout => "Timeouted during scan",
             SyntaxError => "Syntax error in rule",
             TooManyMatches => "Too many matches",
+            TooManyScanThreads => "Too many threads scanning with the same rules",
             UnsupportedFileVersion => "Rule file version is not supported",
             Unknown(_) => "Unknown Yara error",
         }
@@ -153,6 +158,12 @@ impl YR_MATCHES {
     }
 }
 
+impl YR_EXTERNAL_VARIABLE {
+    pub fn get_identifier(&self) -> *const c_char {
+        unsafe { self.__bindgen_anon_1.identifier }
+    }
+}
+
 impl YR_META {
     pub fn get_identifier(&self) -> *const c_char {
         unsafe { self.__bindgen_anon_1.identifier }
@@ -1,6 +1,30 @@
 /* automatically generated by rust-bindgen */
 
+pub const YR_MAJOR_VERSION: u32 = 3;
+pub const YR_MINOR_VERSION: u32 = 11;
+pub const YR_MICRO_VERSION: u32 = 0;
+pub const YR_MAX_THREADS: u32 = 32;
+pub const STRING_GFLAGS_REFERENCED: u32 = 1;
+pub const STRING_GFLAGS_HEXADECIMAL: u32 = 2;
+pub const STRING_GFLAGS_NO_CASE: u32 = 4;
+pub const STRING_GFLAGS_ASCII: u32 = 8;
+pub const STRING_GFLAGS_WIDE: u32 = 16;
+pub const STRING_GFLAGS_REGEXP: u32 = 32;
+pub const STRING_GFLAGS_FULL_WORD: u32 = 128;
+pub const STRING_GFLAGS_LITERAL: u32 = 1024;
 pub const STRING_GFLAGS_NULL: u32 = 4096;
+pub const STRING_GFLAGS_CHAIN_PART: u32 = 8192;
+pub const STRING_GFLAGS_XOR: u32 = 524288;
+pub const STRING_GFLAGS_PRIVATE: u32 = 1048576;
+pub const RULE_GFLAGS_PRIVATE: u32 = 1;
+pub const RULE_GFLAGS_GLOBAL: u32 = 2;
+pub const RULE_GFLAGS_NULL: u32 = 4096;
+pub const EXTERNAL_VARIABLE_TYPE_NULL: u32 = 0;
+pub const EXTERNAL_VARIABLE_TYPE_FLOAT: u32 = 1;
+pub const EXTERNAL_VARIABLE_TYPE_INTEGER: u32 = 2;
+pub const EXTERNAL_VARIABLE_TYPE_BOOLEAN: u32 = 3;
+pub const EXTERNAL_VARIABLE_TYPE_STRING: u32 = 4;
+pub const EXTERNAL_VARIABLE_TYPE_MALLOC_STRING: u32 = 5;
 pub const META_TYPE_NULL: u32 = 0;
 pub const META_TYPE_INTEGER: u32 = 1;
 pub const META_TYPE_STRING: u32 = 2;
@@ -2397,6 +2421,58 @@ pub type YR_CALLBACK_FUNC = ::std::option::Option<
         user_data: *mut ::std::os::raw::c_void,
     ) -> ::std::os::raw::c_int,
 >;
+pub type YR_MEMORY_BLOCK_FETCH_DATA_FUNC =
+    ::std::option::Option<unsafe extern "C" fn(self_: *mut YR_MEMORY_BLOCK) -> *const u8>;
+pub type YR_MEMORY_BLOCK_ITERATOR_FUNC = ::std::option::Option<
+    unsafe extern "C" fn(self_: *mut YR_MEMORY_BLOCK_ITERATOR) -> *mut YR_MEMORY_BLOCK,
+>;
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct YR_MEMORY_BLOCK {
+    pub size: usize,
+    pub base: u64,
+    pub context: *mut ::std::os::raw::c_void,
+    pub fetch_data: YR_MEMORY_BLOCK_FETCH_DATA_FUNC,
+}
+#[test]
+fn bindgen_test_layout_YR_MEMORY_BLOCK() {
+    assert_eq!(
+        ::std::mem::size_of::<YR_MEMORY_BLOCK>(),
+        32usize,
+        concat!("Size of: ", stringify!(YR_MEMORY_BLOCK))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<YR_MEMORY_BLOCK>(),
+        8usize,
+        concat!("Alignment of ", stringify!(YR_MEMORY_BLOCK))
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct YR_MEMORY_BLOCK_ITERATOR {
+    pub context: *mut ::std::os::raw::c_void,
+    pub first: YR_MEMORY_BLOCK_ITERATOR_FUNC,
+    pub next: YR_MEMORY_BLOCK_ITERATOR_FUNC,
+}
+#[test]
+fn bindgen_test_layout_YR_MEMORY_BLOCK_ITERATOR() {
+    assert_eq!(
+        ::std::mem::size_of::<YR_MEMORY_BLOCK_ITERATOR>(),
+        24usize,
+        concat!("Size of: ", stringify!(YR_MEMORY_BLOCK_ITERATOR))
+    );
+    assert_eq!(
+        ::std::mem::align_of::<YR_MEMORY_BLOCK_ITERATOR>(),
+        8usize,
+        concat!("Alignment of ", stringify!(YR_MEMORY_BLOCK_ITERATOR))
+    );
+}
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct YR_SCAN_CONTEXT {
+    _unused: [u8; 0],
+}
+pub type YR_SCANNER = YR_SCAN_CONTEXT;
 pub type YR_COMPILER_CALLBACK_FUNC = ::std::option::Option<
     unsafe extern "C" fn(
         error_level: ::std::os::raw::c_int,
@@ -2667,3 +2743,72 @@ extern "C" {
 extern "C" {
     pub fn yr_get_tidx() -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn yr_scanner_create(
+        rules: *mut YR_RULES,
+        scanner: *mut *mut YR_SCANNER,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn yr_scanner_destroy(scanner: *mut YR_SCANNER);
+}
+extern "C" {
+    pub fn yr_scanner_set_callback(
+        scanner: *mut YR_SCANNER,
+        callback: YR_CALLBACK_FUNC,
+        user_data: *mut ::std::os::raw::c_void,
+    );
+}
+extern "C" {
+    pub fn yr_scanner_set_timeout(scanner: *mut YR_SCANNER, timeout: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn yr_scanner_set_flags(scanner: *mut YR_SCANNER, flags: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn yr_scanner_define_integer_variable(
+        scanner: *mut YR_SCANNER,
+        identifier: *const ::std::os::raw::c_char,
+        value: i64,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn yr_scanner_define_boolean_variable(
+        scanner: *mut YR_SCANNER,
+        identifier: *const ::std::os::raw::c_char,
+        value: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn yr_scanner_define_float_variable(
+        scanner: *mut YR_SCANNER,
+        identifier: *const ::std::os::raw::c_char,
+        value: f64,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn yr_scanner_define_string_variable(
+        scanner: *mut YR_SCANNER,
+        identifier: *const ::std::os::raw::c_char,
+        value: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn yr_scanner_scan_mem_blocks(
+        scanner: *mut YR_SCANNER,
+        iterator: *mut YR_MEMORY_BLOCK_ITERATOR,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn yr_scanner_scan_mem(
+        scanner: *mut YR_SCANNER,
+        buffer: *const u8,
+        buffer_size: usize,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn yr_scanner_scan_fd(
+        scanner: *mut YR_SCANNER,
+        fd: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
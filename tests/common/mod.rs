@@ -0,0 +1,44 @@
+//! Fixtures shared by the integration tests, each of which uses only some of them.
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use yara::{Compiler, Rules};
+
+/// An empty directory for the test `name`, removed first if a previous run left it.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rs_yara_{}_{}", name, std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).expect("Should have created the directory");
+    dir
+}
+
+/// The rule of the scanner tests, matching `Rust`.
+pub const RUST: &str = r#"rule rust { strings: $a = "Rust" condition: $a }"#;
+
+pub fn compile(source: &str) -> Rules {
+    let mut compiler = Compiler::new().expect("Should have created the compiler");
+    compiler
+        .add_rules_str(source)
+        .expect("Should have parsed the rules");
+    compiler
+        .compile_rules()
+        .expect("Should have compiled the rules")
+}
+
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(data).unwrap();
+    gzip.finish().unwrap()
+}
+
+/// The sources of a corpus file, separated by `-----` lines.
+pub fn corpus(path: &str) -> Vec<String> {
+    fs::read_to_string(path)
+        .expect("Should have read the corpus")
+        .split("\n-----\n")
+        .map(str::to_owned)
+        .collect()
+}
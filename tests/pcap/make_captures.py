@@ -0,0 +1,98 @@
+#!/usr/bin/env python3
+"""Writes the captures of test_pcap.rs: http.pcap and vlan_ipv6.pcapng."""
+
+import os
+import struct
+
+HERE = os.path.dirname(os.path.abspath(__file__))
+
+
+def checksum(data):
+    if len(data) % 2:
+        data += b"\0"
+    total = sum(struct.unpack("!%dH" % (len(data) // 2), data))
+    while total >> 16:
+        total = (total & 0xFFFF) + (total >> 16)
+    return ~total & 0xFFFF
+
+
+def ipv4(src, dst, protocol, payload):
+    header = struct.pack("!BBHHHBBH4s4s", 0x45, 0, 20 + len(payload), 1, 0, 64, protocol, 0,
+                         bytes(src), bytes(dst))
+    header = header[:10] + struct.pack("!H", checksum(header)) + header[12:]
+    return header + payload
+
+
+def ipv6(src, dst, protocol, payload):
+    return struct.pack("!IHBB16s16s", 6 << 28, len(payload), protocol, 64, bytes(src),
+                       bytes(dst)) + payload
+
+
+def tcp(sport, dport, seq, flags, data=b""):
+    return struct.pack("!HHIIBBHHH", sport, dport, seq, 0, 5 << 4, flags, 65535, 0, 0) + data
+
+
+def udp(sport, dport, data):
+    return struct.pack("!HHHH", sport, dport, 8 + len(data), 0) + data
+
+
+def ethernet(packet, ether_type, vlan=None):
+    header = b"\x02\0\0\0\0\x02" + b"\x02\0\0\0\0\x01"
+    if vlan is not None:
+        header += struct.pack("!HH", 0x8100, vlan)
+    return header + struct.pack("!H", ether_type) + packet
+
+
+SYN, ACK, PSH = 0x02, 0x10, 0x08
+CLIENT, SERVER, DNS = [10, 0, 0, 1], [10, 0, 0, 2], [10, 0, 0, 3]
+
+
+def http_pcap():
+    packets = [
+        ipv4(CLIENT, SERVER, 6, tcp(49152, 80, 1000, SYN)),
+        ipv4(SERVER, CLIENT, 6, tcp(80, 49152, 5000, SYN | ACK)),
+        # Out of order, then retransmitted.
+        ipv4(CLIENT, SERVER, 6, tcp(49152, 80, 1008, PSH | ACK, b"st HTTP/1.1\r\n")),
+        ipv4(CLIENT, SERVER, 6, tcp(49152, 80, 1001, PSH | ACK, b"GET /Ru")),
+        ipv4(CLIENT, SERVER, 6, tcp(49152, 80, 1001, PSH | ACK, b"GET /Ru")),
+        ipv4(SERVER, CLIENT, 6, tcp(80, 49152, 5001, PSH | ACK, b"HTTP/1.0 200 OK\r\nRust")),
+        ipv4(CLIENT, DNS, 17, udp(5353, 53, b"Rust dns")),
+    ]
+    out = struct.pack("<IHHiIII", 0xA1B2C3D4, 2, 4, 0, 0, 65535, 1)
+    for i, packet in enumerate(packets):
+        frame = ethernet(packet, 0x0800)
+        out += struct.pack("<IIII", 1700000000 + i, 500000, len(frame), len(frame)) + frame
+    return out
+
+
+def block(block_type, body):
+    body += b"\0" * (-len(body) % 4)
+    length = 12 + len(body)
+    return struct.pack("<II", block_type, length) + body + struct.pack("<I", length)
+
+
+def vlan_ipv6_pcapng():
+    src = bytes.fromhex("20010db8000000000000000000000001")
+    dst = bytes.fromhex("20010db8000000000000000000000002")
+    packets = [
+        # No handshake, and the bytes 105 to 110 are missing.
+        tcp(40000, 443, 100, ACK, b"Ru"),
+        tcp(40000, 443, 102, ACK, b"st!"),
+        tcp(40000, 443, 110, ACK, b"Rust"),
+    ]
+    out = block(0x0A0D0D0A, struct.pack("<IHHq", 0x1A2B3C4D, 1, 0, -1))
+    # if_tsresol of 10^-9.
+    options = struct.pack("<HHB3x", 9, 1, 9) + struct.pack("<HH", 0, 0)
+    out += block(1, struct.pack("<HHI", 1, 0, 65535) + options)
+    for i, packet in enumerate(packets):
+        frame = ethernet(ipv6(src, dst, 6, packet), 0x86DD, vlan=42)
+        timestamp = 1700000000250000000 + i * 1000
+        out += block(6, struct.pack("<IIIII", 0, timestamp >> 32, timestamp & 0xFFFFFFFF,
+                                    len(frame), len(frame)) + frame)
+    return out
+
+
+with open(os.path.join(HERE, "http.pcap"), "wb") as f:
+    f.write(http_pcap())
+with open(os.path.join(HERE, "vlan_ipv6.pcapng"), "wb") as f:
+    f.write(vlan_ipv6_pcapng())
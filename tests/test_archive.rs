@@ -0,0 +1,222 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::io::Write;
+
+use common::{compile, RUST};
+use yara::archive::{ArchiveScanner, Budget, Extractor, Limits, Member};
+use yara::errors::{ArchiveError, Error};
+
+fn zip(members: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (name, data) in members {
+        writer
+            .start_file(*name, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn tar_gz(members: &[(&str, &[u8])]) -> Vec<u8> {
+    let gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(gzip);
+    for (name, data) in members {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *data).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+/// The virtual paths scanned, with the matching rules or the error.
+fn scan(
+    scanner: &ArchiveScanner,
+    name: &str,
+    data: &[u8],
+) -> Vec<(String, Result<Vec<String>, String>)> {
+    let rules = compile(RUST);
+    let mut results = Vec::new();
+    scanner.scan_mem(&rules, name, data, 10, |path, result| {
+        let result = result
+            .map(|rules| rules.iter().map(|r| r.identifier.to_owned()).collect())
+            .map_err(|e| e.to_string());
+        results.push((path.to_owned(), result));
+    });
+    results
+}
+
+fn matched(names: &[&str]) -> Result<Vec<String>, String> {
+    Ok(names.iter().map(|&n| n.to_owned()).collect())
+}
+
+#[test]
+fn test_nested_archives() {
+    let inner = tar_gz(&[("logs/app.log", b"Rust"), ("logs/other.log", b"Go")]);
+    let outer = zip(&[
+        ("inner/tool.exe", b"MZ Rust"),
+        ("inner/logs.tgz", &inner),
+        ("readme.txt", b"nothing"),
+    ]);
+
+    assert_eq!(
+        vec![
+            ("outer.zip".to_owned(), matched(&[])),
+            ("outer.zip!inner/tool.exe".to_owned(), matched(&["rust"])),
+            ("outer.zip!inner/logs.tgz".to_owned(), matched(&[])),
+            // The tar is not compressed.
+            (
+                "outer.zip!inner/logs.tgz!logs.tar".to_owned(),
+                matched(&["rust"])
+            ),
+            (
+                "outer.zip!inner/logs.tgz!logs.tar!logs/app.log".to_owned(),
+                matched(&["rust"])
+            ),
+            (
+                "outer.zip!inner/logs.tgz!logs.tar!logs/other.log".to_owned(),
+                matched(&[])
+            ),
+            ("outer.zip!readme.txt".to_owned(), matched(&[])),
+        ],
+        scan(&ArchiveScanner::new(), "outer.zip", &outer)
+    );
+
+    // Without extractors, only the container is scanned.
+    assert_eq!(1, scan(&ArchiveScanner::empty(), "outer.zip", &outer).len());
+}
+
+#[test]
+fn test_archive_limits() {
+    let inner = zip(&[("a", b"Rust")]);
+    let outer = zip(&[("inner.zip", &inner), ("b", b"Rust"), ("c", b"Rust")]);
+
+    let mut scanner = ArchiveScanner::new();
+    scanner.set_limits(Limits {
+        max_depth: 1,
+        ..Limits::default()
+    });
+    let results = scan(&scanner, "outer.zip", &outer);
+    assert_eq!(
+        (
+            "outer.zip!inner.zip".to_owned(),
+            Err(ArchiveError::TooDeep(1).to_string())
+        ),
+        results[2]
+    );
+    assert_eq!(5, results.len());
+
+    scanner.set_limits(Limits {
+        max_members: 2,
+        ..Limits::default()
+    });
+    let results = scan(&scanner, "outer.zip", &outer);
+    assert_eq!(
+        (
+            "outer.zip".to_owned(),
+            Err(ArchiveError::TooManyMembers(2).to_string())
+        ),
+        *results.last().unwrap()
+    );
+    // The members read before the limit are scanned.
+    assert_eq!(
+        ("outer.zip!b".to_owned(), matched(&["rust"])),
+        results[results.len() - 2]
+    );
+
+    let bomb = tar_gz(&[("zeros", &vec![0; 100_000])]);
+    scanner.set_limits(Limits {
+        max_total_size: 50_000,
+        ..Limits::default()
+    });
+    let results = scan(&scanner, "bomb.tar.gz", &bomb);
+    assert_eq!(
+        vec![
+            ("bomb.tar.gz".to_owned(), matched(&[])),
+            (
+                "bomb.tar.gz".to_owned(),
+                Err(ArchiveError::TooLarge(50_000).to_string())
+            ),
+        ],
+        results
+    );
+
+    let mut truncated = outer.clone();
+    truncated.truncate(40);
+    let results = scan(&ArchiveScanner::new(), "truncated.zip", &truncated);
+    assert!(results[1]
+        .1
+        .as_ref()
+        .unwrap_err()
+        .starts_with("Invalid zip archive"));
+}
+
+/// Members are the lines of `lines:` files.
+struct Lines;
+
+impl Extractor for Lines {
+    fn detect(&self, data: &[u8]) -> bool {
+        data.starts_with(b"lines:")
+    }
+
+    fn extract(
+        &self,
+        _name: &str,
+        data: &[u8],
+        budget: &mut Budget,
+        members: &mut Vec<Member>,
+    ) -> Result<(), ArchiveError> {
+        for (i, line) in data[6..].split(|&b| b == b'\n').enumerate() {
+            members.push(Member {
+                name: format!("line{}", i + 1),
+                data: budget.read("lines", line)?,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_custom_extractor() {
+    let mut scanner = ArchiveScanner::empty();
+    scanner.add_extractor(Lines);
+    let results = scan(&scanner, "file", b"lines:Go\nRust");
+    assert_eq!(
+        vec![
+            ("file".to_owned(), matched(&["rust"])),
+            ("file!line1".to_owned(), matched(&[])),
+            ("file!line2".to_owned(), matched(&["rust"])),
+        ],
+        results
+    );
+
+    let rules = compile(RUST);
+    let mut errors = Vec::new();
+    scanner.scan_file(&rules, "/nonexistent", 10, |path, result| {
+        if let Err(Error::Io(_)) = result {
+            errors.push(path.to_owned());
+        }
+    });
+    assert_eq!(vec!["/nonexistent"], errors);
+}
+
+#[cfg(all(feature = "bzip2", feature = "xz"))]
+#[test]
+fn test_bzip2_and_xz() {
+    let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+    bzip2.write_all(b"Rust").unwrap();
+    let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+    xz.write_all(&bzip2.finish().unwrap()).unwrap();
+
+    assert_eq!(
+        vec![
+            ("a.bz2.xz".to_owned(), matched(&[])),
+            ("a.bz2.xz!a.bz2".to_owned(), matched(&[])),
+            ("a.bz2.xz!a.bz2!a".to_owned(), matched(&["rust"])),
+        ],
+        scan(&ArchiveScanner::new(), "a.bz2.xz", &xz.finish().unwrap())
+    );
+}
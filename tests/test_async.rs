@@ -0,0 +1,150 @@
+#![cfg(feature = "tokio")]
+
+extern crate rs_yara as yara;
+
+use std::time::Duration;
+
+use yara::{AsyncRules, Compiler};
+
+const RULES: &str = r#"
+rule is_awesome {
+  strings:
+    $rust = /[Rr]ust/
+
+  condition:
+    $rust
+}
+
+rule is_ok {
+  strings:
+    $go = "go"
+
+  condition:
+    $go
+}"#;
+
+fn get_default_rules() -> AsyncRules {
+    let mut compiler = Compiler::new().expect("Should create compiler");
+    compiler.add_rules_str(RULES).expect("Should parse rule");
+    AsyncRules::new(compiler.compile_rules().expect("Should compile rules"))
+}
+
+#[tokio::test]
+async fn test_async_scan_mem() {
+    let rules = get_default_rules();
+    let result = rules
+        .scan_mem(b"I love Rust!".to_vec(), 10)
+        .await
+        .expect("Should be Ok");
+
+    assert_eq!(1, result.len());
+    assert_eq!("is_awesome", result[0].identifier);
+    assert_eq!(7, result[0].strings[0].matches[0].offset);
+}
+
+#[tokio::test]
+async fn test_async_scan_file() {
+    let rules = get_default_rules();
+    let result = rules
+        .scan_file("tests/scanfile.txt", 10)
+        .await
+        .expect("Should have scanned file");
+
+    assert_eq!(1, result.len());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_async_many_scans() {
+    let rules = get_default_rules();
+
+    // More scans than libyara scan slots.
+    let tasks: Vec<_> = (0..100)
+        .map(|_| {
+            let rules = rules.clone();
+            tokio::spawn(async move { rules.scan_mem(&b"rust and go"[..], 10).await })
+        })
+        .collect();
+
+    for task in tasks {
+        let result = task.await.unwrap().expect("Should have scanned");
+        assert_eq!(2, result.len());
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_async_cancel() {
+    let rules = get_default_rules();
+    let slots = yara::yara_sys::YR_MAX_THREADS as usize;
+
+    // More aborted scans than scan threads: the waiting ones are cancelled, the running ones
+    // go on until they are finished.
+    let tasks: Vec<_> = (0..slots * 3)
+        .map(|_| {
+            let rules = rules.clone();
+            tokio::spawn(async move { rules.scan_mem(vec![b'x'; 1 << 20], 10).await })
+        })
+        .collect();
+    for task in &tasks {
+        task.abort();
+    }
+    for task in tasks {
+        match task.await {
+            Ok(result) => assert!(result.unwrap().is_empty()),
+            Err(e) => assert!(e.is_cancelled()),
+        }
+    }
+    // Whether this scan is cancelled or runs to its end, its result is dropped.
+    let scan = rules.scan_mem(&b"rust"[..], 10);
+    let _ = tokio::time::timeout(Duration::from_nanos(1), scan).await;
+
+    // Once the running scans are finished, every thread and scan slot is free again: as many
+    // scans as threads complete, then a synchronous scan.
+    let tasks: Vec<_> = (0..slots)
+        .map(|_| {
+            let rules = rules.clone();
+            tokio::spawn(async move { rules.scan_mem(&b"rust"[..], 10).await })
+        })
+        .collect();
+    for task in tasks {
+        let result = tokio::time::timeout(Duration::from_secs(10), task)
+            .await
+            .expect("Should have completed")
+            .unwrap()
+            .expect("Should have scanned");
+        assert_eq!(1, result.len());
+    }
+    assert_eq!(1, rules.rules().scan_mem(b"rust", 10).unwrap().len());
+}
+
+#[tokio::test]
+async fn test_async_scan_panic() {
+    struct PanickingReader;
+
+    impl std::io::Read for PanickingReader {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            panic!("reader failed");
+        }
+    }
+
+    let rules = get_default_rules();
+    let error = tokio::spawn(async move { rules.scan_reader(PanickingReader, 10, 4, 10).await })
+        .await
+        .expect_err("Should have panicked");
+    // The panic of the scan thread, not another one.
+    let payload = error.into_panic();
+    assert_eq!(Some(&"reader failed"), payload.downcast_ref::<&str>());
+}
+
+#[test]
+fn test_async_drop_rules_in_runtime() {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let rules = get_default_rules();
+    runtime.block_on(async move {
+        tokio::spawn(async move {
+            let result = rules.scan_mem(&b"go"[..], 10).await.expect("Should scan");
+            assert_eq!(1, result.len());
+        })
+        .await
+        .unwrap();
+    });
+}
@@ -0,0 +1,178 @@
+extern crate rs_yara as yara;
+
+use std::convert::TryInto;
+
+use sha2::{Digest, Sha256};
+use yara::{
+    errors::{BundleError, Error},
+    Compiler, Rules, SourceHash,
+};
+
+const RULES: &str = r#"
+rule is_awesome {
+  strings:
+    $rust = /[Rr]ust/
+
+  condition:
+    $rust
+}
+
+rule is_ok {
+  strings:
+    $go = "go"
+
+  condition:
+    $go
+}"#;
+
+fn save_bundle() -> Vec<u8> {
+    let mut compiler = Compiler::new().unwrap();
+    compiler.add_rules_str(RULES).unwrap();
+    let mut rules = compiler.compile_rules().unwrap();
+
+    let mut bundle = Vec::new();
+    rules
+        .save_bundle(
+            &mut bundle,
+            vec![SourceHash::new("rules.yar", RULES.as_bytes())],
+        )
+        .expect("Should have saved the bundle");
+    bundle
+}
+
+fn bundle_error(bundle: &[u8]) -> BundleError {
+    match Rules::load_bundle(bundle) {
+        Err(Error::Bundle(e)) => e,
+        Err(e) => panic!("Expected Error::Bundle, found {:?}", e),
+        Ok(_) => panic!("Expected Error::Bundle, found rules"),
+    }
+}
+
+/// Replace the header of an unsigned bundle, with a matching checksum.
+fn reseal(bundle: &[u8], edit: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let header_len = u32::from_le_bytes(bundle[10..14].try_into().unwrap()) as usize;
+    let mut header = bundle[14..14 + header_len].to_vec();
+    edit(&mut header);
+
+    let mut resealed = bundle[..10].to_vec();
+    resealed.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    resealed.extend_from_slice(&header);
+    resealed.extend_from_slice(&bundle[14 + header_len..bundle.len() - 33]);
+    let checksum = Sha256::digest(&resealed);
+    resealed.extend_from_slice(&checksum);
+    resealed.push(0);
+    resealed
+}
+
+#[test]
+fn test_bundle_save_load() {
+    let bundle = save_bundle();
+
+    let (rules, header) = Rules::load_bundle(&bundle[..]).expect("Should have loaded the bundle");
+    assert_eq!(2, header.rule_count);
+    assert_eq!("3.11.0", header.libyara_version);
+    assert_eq!(env!("CARGO_PKG_VERSION"), header.rs_yara_version);
+    assert_eq!(
+        vec![SourceHash::new("rules.yar", RULES.as_bytes())],
+        header.sources
+    );
+    assert_eq!(1, rules.scan_mem(b"I love Rust!", 10).unwrap().len());
+}
+
+#[test]
+fn test_bundle_corrupted() {
+    let bundle = save_bundle();
+
+    let mut tampered = bundle.clone();
+    let middle = tampered.len() / 2;
+    tampered[middle] ^= 0xff;
+    assert_eq!(BundleError::BadChecksum, bundle_error(&tampered));
+
+    assert_eq!(
+        BundleError::Truncated,
+        bundle_error(&bundle[..bundle.len() - 40])
+    );
+
+    // The signature flag is the last byte of an unsigned bundle.
+    let mut flag = bundle.clone();
+    *flag.last_mut().unwrap() = 2;
+    assert_eq!(BundleError::BadTrailer, bundle_error(&flag));
+    let mut trailing = bundle.clone();
+    trailing.push(0);
+    assert_eq!(BundleError::BadTrailer, bundle_error(&trailing));
+
+    let mut rules = Vec::new();
+    let mut compiler = Compiler::new().unwrap();
+    compiler.add_rules_str(RULES).unwrap();
+    compiler
+        .compile_rules()
+        .unwrap()
+        .save_to_stream(&mut rules)
+        .unwrap();
+    assert_eq!(BundleError::BadMagic, bundle_error(&rules));
+
+    let trailing_header = reseal(&bundle, |header| header.push(0));
+    assert_eq!(BundleError::BadHeader, bundle_error(&trailing_header));
+}
+
+#[test]
+fn test_bundle_libyara_mismatch() {
+    let bundle = save_bundle();
+    assert!(Rules::load_bundle(&bundle[..]).is_ok());
+
+    // The libyara version follows the rs_yara version in the header.
+    let other = reseal(&bundle, |header| {
+        let start = 2 + usize::from(u16::from_le_bytes([header[0], header[1]])) + 2;
+        header[start..start + 6].copy_from_slice(b"3.10.0");
+    });
+    assert_eq!(
+        BundleError::LibyaraMismatch {
+            bundle: "3.10.0".to_owned(),
+            running: "3.11.0".to_owned(),
+        },
+        bundle_error(&other)
+    );
+}
+
+#[cfg(feature = "signatures")]
+#[test]
+fn test_bundle_signature() {
+    use ed25519_dalek::SigningKey;
+
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let other_key = SigningKey::from_bytes(&[8; 32]);
+
+    let mut compiler = Compiler::new().unwrap();
+    compiler.add_rules_str(RULES).unwrap();
+    let mut rules = compiler.compile_rules().unwrap();
+    let mut bundle = Vec::new();
+    rules
+        .save_signed_bundle(&mut bundle, Vec::new(), &key)
+        .expect("Should have saved the bundle");
+
+    assert!(Rules::load_signed_bundle(&bundle[..], &key.verifying_key()).is_ok());
+    match Rules::load_signed_bundle(&bundle[..], &other_key.verifying_key()) {
+        Err(Error::Bundle(BundleError::BadSignature)) => {}
+        _ => panic!("Expected a bad signature"),
+    }
+
+    let unsigned = save_bundle();
+    match Rules::load_signed_bundle(&unsigned[..], &key.verifying_key()) {
+        Err(Error::Bundle(BundleError::MissingSignature)) => {}
+        _ => panic!("Expected a missing signature"),
+    }
+}
+
+#[test]
+fn test_bundle_long_source_name() {
+    let mut compiler = Compiler::new().unwrap();
+    compiler.add_rules_str(RULES).unwrap();
+    let mut rules = compiler.compile_rules().unwrap();
+
+    let name = "a".repeat(usize::from(u16::MAX) + 1);
+    let result = rules.save_bundle(Vec::new(), vec![SourceHash::new(&name, b"")]);
+    match result {
+        Err(Error::Bundle(e)) => assert_eq!(BundleError::FieldTooLong, e),
+        other => panic!("Expected Error::Bundle, found {:?}", other),
+    }
+}
@@ -0,0 +1,132 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use common::temp_dir;
+use yara::CachedCompiler;
+
+const RULES: &str = "rule is_ok {
+  strings:
+    $a = \"ok\"
+  condition:
+    $a
+}
+";
+
+fn count_entries(dir: &PathBuf) -> usize {
+    fs::read_dir(dir)
+        .unwrap()
+        .filter(|e| {
+            e.as_ref()
+                .unwrap()
+                .path()
+                .extension()
+                .is_some_and(|ext| ext == "yarc")
+        })
+        .count()
+}
+
+#[test]
+fn test_cache_hit() {
+    let dir = temp_dir("cache_hit");
+
+    let mut compiler = CachedCompiler::new(&dir);
+    compiler.add_rules_str(RULES);
+    assert!(!compiler.is_cached());
+    let rules = compiler.compile_rules().expect("Should have compiled");
+    assert_eq!(1, rules.scan_mem(b"ok", 10).unwrap().len());
+
+    let mut compiler = CachedCompiler::new(&dir);
+    compiler.add_rules_str(RULES);
+    assert!(compiler.is_cached());
+    let rules = compiler.compile_rules().expect("Should have loaded");
+    assert_eq!(1, rules.scan_mem(b"ok", 10).unwrap().len());
+    assert_eq!(1, count_entries(&dir));
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_cache_key() {
+    let key = |namespace: Option<&str>, variable: Option<i64>| {
+        let mut compiler = CachedCompiler::new("unused");
+        match namespace {
+            Some(namespace) => compiler.add_rules_str_with_namespace(RULES, namespace),
+            None => compiler.add_rules_str(RULES),
+        }
+        if let Some(value) = variable {
+            compiler.define_variable("var", value);
+        }
+        compiler.key()
+    };
+
+    assert_eq!(key(None, None), key(None, None));
+    assert_ne!(key(None, None), key(Some("ns"), None));
+    assert_ne!(key(None, None), key(Some(""), None));
+    assert_ne!(key(None, None), key(None, Some(1)));
+    assert_ne!(key(None, Some(1)), key(None, Some(2)));
+}
+
+#[test]
+fn test_cache_file_change() {
+    let dir = temp_dir("cache_file_change");
+    let main = dir.join("main.yar");
+    fs::write(&main, RULES).unwrap();
+
+    let mut compiler = CachedCompiler::new(&dir);
+    compiler.add_rules_file(&main).unwrap();
+    let key = compiler.key();
+    // The content added is the one compiled, and cached.
+    fs::write(&main, RULES.replace("ok", "ko")).unwrap();
+    let rules = compiler.compile_rules().expect("Should have compiled");
+    assert_eq!(1, rules.scan_mem(b"ok", 10).unwrap().len());
+    assert!(dir.join(key).with_extension("yarc").is_file());
+
+    let mut compiler = CachedCompiler::new(&dir);
+    compiler.add_rules_file(&main).unwrap();
+    assert!(!compiler.is_cached());
+    let rules = compiler.compile_rules().expect("Should have compiled");
+    assert_eq!(1, rules.scan_mem(b"ko", 10).unwrap().len());
+    assert_eq!(2, count_entries(&dir));
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_cache_include_change() {
+    let dir = temp_dir("cache_include");
+    let main = dir.join("main.yar");
+    let included = dir.join("included.yar");
+    fs::write(&main, "include \"included.yar\"\n").unwrap();
+    fs::write(&included, RULES).unwrap();
+
+    let key = || {
+        let mut compiler = CachedCompiler::new(dir.join("cache"));
+        compiler.add_rules_file(&main).unwrap();
+        compiler.key()
+    };
+
+    let before = key();
+    assert_eq!(before, key());
+    fs::write(&included, RULES.replace("ok", "ko")).unwrap();
+    assert_ne!(before, key());
+
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_cache_eviction() {
+    let dir = temp_dir("cache_eviction");
+
+    for i in 0..4 {
+        let mut compiler = CachedCompiler::new(&dir).max_entries(2);
+        compiler.add_rules_str(&RULES.replace("is_ok", &format!("is_ok_{}", i)));
+        compiler.compile_rules().expect("Should have compiled");
+    }
+    assert_eq!(2, count_entries(&dir));
+
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,156 @@
+extern crate rs_yara as yara;
+
+use std::fs;
+
+use yara::render::{self, View};
+use yara::report::FileReport;
+use yara::{Compiler, Match, MatchContext, Rules};
+
+const RULES: &str = r#"
+rule rust : lang {
+  meta:
+    score = 10
+  strings:
+    $rust = "Rust"
+    $long = /BA{600}C/
+  condition:
+    any of them
+}
+"#;
+
+fn rules(context: usize) -> Rules {
+    let mut compiler = Compiler::new().unwrap();
+    compiler.add_rules_str(RULES).unwrap();
+    let mut rules = compiler.compile_rules().unwrap();
+    rules.set_context(context);
+    rules
+}
+
+fn context(m: &Match) -> (&[u8], &[u8], &[u8]) {
+    let context = m
+        .context
+        .as_ref()
+        .expect("Should have captured the context");
+    (&context.before, &context.data, &context.after)
+}
+
+#[test]
+fn test_scan_mem_context() {
+    let without = rules(0);
+    let matches = without.scan_mem(b"I love Rust!", 10).unwrap();
+    assert_eq!(None, matches[0].strings[0].matches[0].context);
+
+    let rules = rules(7);
+    let matches = rules.scan_mem(b"I love Rust! Rust", 10).unwrap();
+    let rust = &matches[0].strings[0].matches;
+    assert_eq!(
+        (&b"I love "[..], &b"Rust"[..], &b"! Rust"[..]),
+        context(&rust[0])
+    );
+    assert_eq!(0, rust[0].context.as_ref().unwrap().offset);
+    // Cut by the end of the data.
+    assert_eq!((&b" Rust! "[..], &b"Rust"[..], &b""[..]), context(&rust[1]));
+    assert_eq!(
+        b" Rust! Rust".to_vec(),
+        rust[1].context.as_ref().unwrap().bytes()
+    );
+}
+
+#[test]
+fn test_scan_file_context() {
+    let path = std::env::temp_dir().join(format!("rs_yara_context_{}", std::process::id()));
+    let long = format!("xyzB{}Cxyz", "A".repeat(600));
+    fs::write(&path, format!("I love Rust!\n{}", long)).unwrap();
+
+    let rules = rules(3);
+    let matches = rules.scan_file(&path, 10).unwrap();
+    let strings = &matches[0].strings;
+    assert_eq!(
+        (&b"ve "[..], &b"Rust"[..], &b"!\nx"[..]),
+        context(&strings[0].matches[0])
+    );
+    // libyara truncates the matched data, not the context.
+    let m = &strings[1].matches[0];
+    assert_eq!(602, m.length);
+    assert!(m.data.len() < m.length);
+    assert_eq!(
+        (&b"xyz"[..], &long.as_bytes()[3..605], &b"xyz"[..]),
+        context(m)
+    );
+
+    let matches = rules.scan_file_range(&path, 4, 10, 10).unwrap();
+    let context = matches[0].strings[0].matches[0].context.as_ref().unwrap();
+    assert_eq!(
+        MatchContext {
+            offset: 4,
+            before: b"ve ".to_vec(),
+            data: b"Rust".to_vec(),
+            after: b"!\nx".to_vec(),
+        },
+        *context
+    );
+    fs::remove_file(&path).ok();
+}
+
+#[test]
+fn test_render() {
+    assert_eq!(
+        r#"a \"b\" \\ \t\r\n\x00\xff"#,
+        render::escape_ascii(b"a \"b\" \\ \t\r\n\x00\xff")
+    );
+    assert_eq!(
+        r"wide\n\u{00e9}\u{d800}\x01",
+        render::escape_wide(b"w\0i\0d\0e\0\n\0\xe9\0\x00\xd8\x01")
+    );
+    let data: Vec<u8> = (0x40..0x52).collect();
+    assert_eq!(
+        "0000fff0  40 41 42 43 44 45 46 47  48 49 4a 4b 4c 4d 4e 4f  |@ABCDEFGHIJKLMNO|
+00010000  50 51                                             |PQ|",
+        render::hexdump(&data, 0xfff0)
+    );
+    assert_eq!("", render::hexdump(b"", 0));
+    assert_eq!(Ok(View::Wide), "wide".parse());
+    assert!("utf8".parse::<View>().is_err());
+}
+
+#[test]
+fn test_report() {
+    let rules = rules(2);
+    let matches = rules.scan_mem(b"I love Rust!", 10).unwrap();
+    let report = FileReport::new("sample", &matches, View::Ascii);
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(
+        serde_json::json!({
+            "path": "sample",
+            "rules": [{
+                "identifier": "rust",
+                "namespace": "default",
+                "tags": ["lang"],
+                "metadata": [{ "identifier": "score", "value": 10 }],
+                "strings": [{
+                    "identifier": "$rust",
+                    "kind": "text",
+                    "modifiers": ["ascii"],
+                    "matches": [{
+                        "offset": 7,
+                        "length": 4,
+                        "data": "Rust",
+                        "context": { "before": "e ", "after": "!" },
+                    }],
+                }],
+            }],
+        }),
+        json
+    );
+
+    let report = FileReport::new("sample", &matches, View::Hex);
+    let m = &report.rules[0].strings[0].matches[0];
+    assert_eq!(
+        "00000007  52 75 73 74                                       |Rust|",
+        m.data
+    );
+    assert_eq!(
+        "00000005  65 20                                             |e |",
+        m.context.as_ref().unwrap().before
+    );
+}
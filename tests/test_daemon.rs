@@ -0,0 +1,203 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::fs;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use common::temp_dir;
+use serde_json::Value;
+use yara::daemon::{self, Daemon, Request, Response};
+use yara::{yara_sys, RuleSet};
+
+/// Serve `rules` on a socket of `dir`, from a background thread.
+fn start(dir: &Path, rules: &str, max_size: usize) -> PathBuf {
+    fs::write(dir.join("rules.yar"), rules).unwrap();
+    let mut daemon = Daemon::new(RuleSet::from_directory(dir).unwrap());
+    daemon.set_max_size(max_size);
+    let socket = dir.join("daemon.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    thread::spawn(move || daemon.serve(listener));
+    socket
+}
+
+fn request(stream: &mut UnixStream, request: &Request, data: Option<&[u8]>) -> Value {
+    daemon::write_frame(stream, &serde_json::to_vec(request).unwrap()).unwrap();
+    if let Some(data) = data {
+        daemon::write_frame(stream, data).unwrap();
+    }
+    let response = daemon::read_frame(stream, usize::MAX).unwrap().unwrap();
+    serde_json::from_slice(&response).unwrap()
+}
+
+#[test]
+fn test_daemon_requests() {
+    let dir = temp_dir("daemon_requests");
+    let socket = start(
+        &dir,
+        r#"rule rust { strings: $a = "Rust" condition: $a }"#,
+        1024,
+    );
+    let mut stream = UnixStream::connect(&socket).unwrap();
+
+    let scan = Request::ScanBytes {
+        name: Some("upload".to_owned()),
+        timeout: None,
+    };
+    let response = request(&mut stream, &scan, Some(b"I love Rust"));
+    assert_eq!("scanned", response["status"]);
+    assert_eq!("upload", response["path"]);
+    assert_eq!("rust", response["rules"][0]["identifier"]);
+    assert_eq!(
+        7,
+        response["rules"][0]["strings"][0]["matches"][0]["offset"]
+    );
+
+    let sample = dir.join("sample");
+    fs::write(&sample, "no match").unwrap();
+    let response = request(
+        &mut stream,
+        &Request::ScanPath {
+            path: sample,
+            timeout: None,
+        },
+        None,
+    );
+    assert_eq!("scanned", response["status"]);
+    assert_eq!(Value::Array(vec![]), response["rules"]);
+
+    let missing = dir.join("missing");
+    let response = request(
+        &mut stream,
+        &Request::ScanPath {
+            path: missing,
+            timeout: None,
+        },
+        None,
+    );
+    assert_eq!("error", response["status"]);
+
+    fs::write(
+        dir.join("more.yar"),
+        r#"private rule hidden { condition: true }"#,
+    )
+    .unwrap();
+    let response = request(&mut stream, &Request::Reload, None);
+    assert_eq!(
+        serde_json::json!({ "status": "reloaded", "rules": 2 }),
+        response
+    );
+
+    let response = request(&mut stream, &Request::Stats, None);
+    assert_eq!("stats", response["status"]);
+    assert_eq!(2, response["rules"]);
+    assert_eq!(3, response["scans"]);
+    assert_eq!(1, response["matched"]);
+    assert_eq!(1, response["errors"]);
+    assert_eq!(11, response["bytes"]);
+    assert_eq!(1, response["reloads"]);
+
+    daemon::write_frame(&mut stream, b"{\"command\": \"format\"}").unwrap();
+    let response = daemon::read_frame(&mut stream, usize::MAX)
+        .unwrap()
+        .unwrap();
+    let response: Value = serde_json::from_slice(&response).unwrap();
+    assert_eq!("error", response["status"]);
+
+    // Too large: answered, then closed.
+    let scan = Request::ScanBytes {
+        name: None,
+        timeout: Some(5),
+    };
+    let response = request(&mut stream, &scan, Some(&[0; 2048]));
+    assert_eq!("error", response["status"]);
+    assert_eq!(
+        None,
+        daemon::read_frame(&mut stream, usize::MAX).unwrap_or(None)
+    );
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_daemon_concurrent_scans() {
+    let dir = temp_dir("daemon_concurrent");
+    let socket = start(
+        &dir,
+        r#"rule rust { strings: $a = "Rust" condition: $a }"#,
+        daemon::DEFAULT_MAX_SIZE,
+    );
+
+    let clients: Vec<_> = (0..40)
+        .map(|i| {
+            let socket = socket.clone();
+            thread::spawn(move || {
+                let mut stream = UnixStream::connect(&socket).unwrap();
+                let data = if i % 2 == 0 { "Rust" } else { "Go" };
+                let scan = Request::ScanBytes {
+                    name: None,
+                    timeout: Some(5),
+                };
+                let response = request(&mut stream, &scan, Some(data.as_bytes()));
+                assert_eq!("scanned", response["status"]);
+                response["rules"].as_array().unwrap().len()
+            })
+        })
+        .collect();
+    let matched: usize = clients.into_iter().map(|c| c.join().unwrap()).sum();
+    assert_eq!(20, matched);
+
+    let mut stream = UnixStream::connect(&socket).unwrap();
+    let response = request(&mut stream, &Request::Stats, None);
+    assert_eq!(40, response["scans"]);
+    assert_eq!(0, response["active"]);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_daemon_timeouts() {
+    let dir = temp_dir("daemon_timeouts");
+    // Minutes of scanning for 64 KiB of data.
+    fs::write(
+        dir.join("rules.yar"),
+        "rule slow { condition: for any i in (0..filesize) : \
+         (for any j in (0..filesize) : (uint32(i) == uint32(j) + 1)) }",
+    )
+    .unwrap();
+    let mut daemon = Daemon::new(RuleSet::from_directory(&dir).unwrap());
+    let data = vec![0; 64 * 1024];
+    let timed_out = |response: Response| match response {
+        Response::Error { message } => message == yara_sys::Error::ScanTimeout.to_string(),
+        other => panic!("unexpected {:?}", other),
+    };
+
+    // A request cannot lift the timeout of the daemon.
+    daemon.set_timeout(1);
+    assert!(timed_out(daemon.scan_bytes("slow", &data, Some(0))));
+    // A daemon without timeout uses the one of the request.
+    daemon.set_timeout(0);
+    assert!(timed_out(daemon.scan_bytes("slow", &data, Some(1))));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_daemon_bind() {
+    let dir = temp_dir("daemon_bind");
+
+    // A socket nobody listens on is replaced.
+    let socket = dir.join("stale.sock");
+    drop(UnixListener::bind(&socket).unwrap());
+    let listener = daemon::bind(&socket).unwrap();
+    UnixStream::connect(&socket).unwrap();
+    // The socket of a running daemon is not.
+    assert!(daemon::bind(&socket).is_err());
+    drop(listener);
+
+    // Nor is any other file.
+    let file = dir.join("important.txt");
+    fs::write(&file, "keep me").unwrap();
+    assert!(daemon::bind(&file).is_err());
+    assert_eq!(fs::read(&file).unwrap(), b"keep me");
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,177 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use common::{compile, gzip};
+use yara::decode::{DecodeScanner, Decoded, Decoder, Gzip, Layer, LayerReport, Limits, Zlib};
+use yara::render::View;
+
+const RULE: &str = r#"rule rust { strings: $a = "I love Rust" condition: $a }"#;
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The provenance of each layer, with the offsets of the `rust` matches in it.
+fn scan(scanner: &DecodeScanner, data: &[u8]) -> Vec<(String, Vec<usize>)> {
+    let rules = compile(RULE);
+    let mut results = Vec::new();
+    scanner.scan_mem(&rules, data, 10, |layer, result| {
+        let offsets = result
+            .unwrap()
+            .iter()
+            .flat_map(|r| r.strings.iter())
+            .flat_map(|s| s.matches.iter().map(|m| m.offset))
+            .collect();
+        results.push((layer.to_string(), offsets));
+    });
+    results
+}
+
+#[test]
+fn test_decoders() {
+    let script = format!(
+        "var a = '{}';\nvar b = '{}';\nvar c = 'SSBsb3ZlIFJ1c3Q_IFlvdSB0b28-Pz8_';\n",
+        base64(b"...I love Rust, and you?"),
+        hex(b"I love Rust more than Go"),
+    );
+    assert_eq!(
+        vec![
+            ("".to_owned(), vec![]),
+            ("0x9:base64".to_owned(), vec![3]),
+            ("0x35:hex".to_owned(), vec![0]),
+            ("0x71:base64url".to_owned(), vec![0]),
+        ],
+        scan(&DecodeScanner::new(), script.as_bytes())
+    );
+
+    // Shorter runs are not decoded.
+    let mut scanner = DecodeScanner::new();
+    scanner.set_limits(Limits {
+        min_length: 64,
+        ..Limits::default()
+    });
+    assert_eq!(1, scan(&scanner, script.as_bytes()).len());
+
+    // Runs without digits, without upper case letters, of hex digits, or not made of whole
+    // groups of 4 characters are not base64.
+    let code = b"let veryLongIdentifierWithoutDigits = decodeAlphanumericRuns2024(\
+        'internationalizationsinternationalizations', 0xDEADBEEF00112233445566778899aabb);";
+    let mut scanner = DecodeScanner::empty();
+    scanner.add_decoder(yara::decode::Base64);
+    scanner.add_decoder(yara::decode::Base64Url);
+    assert_eq!(vec![("".to_owned(), vec![])], scan(&scanner, code));
+}
+
+#[test]
+fn test_nested_layers() {
+    let mut inner = b"\x00\x01".to_vec();
+    inner.extend(gzip(b"Some say I love Rust"));
+    let data = format!("payload=\"{}\"", base64(&inner));
+
+    let mut scanner = DecodeScanner::empty();
+    scanner.add_decoder(yara::decode::Base64);
+    scanner.add_decoder(Gzip);
+    scanner.add_decoder(Zlib);
+    assert_eq!(
+        vec![
+            ("".to_owned(), vec![]),
+            ("0x9:base64".to_owned(), vec![]),
+            ("0x9:base64!0x2:gzip".to_owned(), vec![9]),
+        ],
+        scan(&scanner, data.as_bytes())
+    );
+
+    let layers = scanner.layers(data.as_bytes());
+    let step = layers[2].provenance[0];
+    assert_eq!((9, base64(&inner).len()), (step.offset, step.length));
+    assert_eq!(b"Some say I love Rust".to_vec(), layers[2].data);
+
+    // Decoded data is not decoded deeper than the depth limit.
+    scanner.set_limits(Limits {
+        max_depth: 1,
+        ..Limits::default()
+    });
+    assert_eq!(2, scanner.layers(data.as_bytes()).len());
+
+    // Nor beyond the total size limit.
+    scanner.set_limits(Limits {
+        max_total_size: inner.len(),
+        ..Limits::default()
+    });
+    assert_eq!(2, scanner.layers(data.as_bytes()).len());
+}
+
+/// Decodes runs of `%XX` escapes.
+struct Percent;
+
+impl Decoder for Percent {
+    fn name(&self) -> &'static str {
+        "percent"
+    }
+
+    fn decode(&self, data: &[u8], min_length: usize, _max_size: usize) -> Vec<Decoded> {
+        let start = match data.iter().position(|&b| b == b'%') {
+            Some(start) => start,
+            None => return Vec::new(),
+        };
+        let run: Vec<u8> = data[start..]
+            .chunks(3)
+            .take_while(|c| c.len() == 3 && c[0] == b'%')
+            .map(|c| u8::from_str_radix(std::str::from_utf8(&c[1..]).unwrap(), 16).unwrap())
+            .collect();
+        if run.len() * 3 < min_length {
+            return Vec::new();
+        }
+        vec![Decoded {
+            offset: start,
+            length: run.len() * 3,
+            data: run,
+        }]
+    }
+}
+
+#[test]
+fn test_custom_decoder_and_report() {
+    let mut scanner = DecodeScanner::empty();
+    scanner.add_decoder(Percent);
+    let data = b"GET /?q=%49%20%6c%6f%76%65%20%52%75%73%74%21";
+    let layers = scanner.layers(data);
+    assert_eq!(b"I love Rust!".to_vec(), layers[1].data);
+
+    let rules = compile(RULE);
+    let layer: &Layer = &layers[1];
+    let report = LayerReport::new(
+        "request.log",
+        layer,
+        &rules.scan_mem(&layer.data, 10).unwrap(),
+        View::Ascii,
+    );
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(
+        serde_json::json!([{ "offset": 8, "length": 36, "decoder": "percent" }]),
+        json["provenance"]
+    );
+    assert_eq!(0, json["rules"][0]["strings"][0]["matches"][0]["offset"]);
+}
@@ -0,0 +1,178 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::fs::{self, Permissions};
+use std::os::unix::fs::{symlink, PermissionsExt};
+
+use common::compile;
+use common::temp_dir;
+use yara::diff::{self, HitCount, RuleChange};
+use yara::walk;
+
+const OLD: &str = r#"
+rule rust : lang {
+  meta:
+    author = "me"
+  strings:
+    $a = "Rust"
+  condition:
+    $a
+}
+
+rule go {
+  strings:
+    $a = "Go"
+  condition:
+    $a
+}
+
+rule removed {
+  condition:
+    false
+}
+"#;
+
+const NEW: &str = r#"
+rule rust : lang {
+  meta:
+    author = "someone else"
+  strings:
+    $a = "rust" nocase
+  condition:
+    $a
+}
+
+rule go {
+  strings:
+    $a = "Go"
+  condition:
+    $a
+}
+
+rule added {
+  strings:
+    $a = "love"
+  condition:
+    $a
+}
+"#;
+
+#[test]
+fn test_get_rules() {
+    let rules = compile(OLD).get_rules();
+    let names: Vec<_> = rules.iter().map(|r| r.identifier.as_str()).collect();
+    assert_eq!(vec!["rust", "go", "removed"], names);
+    assert_eq!("default", rules[0].namespace);
+    assert_eq!(vec!["lang"], rules[0].tags);
+    assert_eq!("$a", rules[0].strings[0].identifier);
+    assert_eq!(b"Rust".to_vec(), rules[0].strings[0].data);
+}
+
+#[test]
+fn test_diff_rules() {
+    let structure = diff::diff_rules(&compile(OLD), &compile(NEW));
+    assert_eq!(vec!["default:added"], structure.added);
+    assert_eq!(vec!["default:removed"], structure.removed);
+    assert_eq!(
+        vec![RuleChange {
+            rule: "default:rust".to_owned(),
+            strings: true,
+            metadata: true,
+            tags: false,
+        }],
+        structure.changed
+    );
+}
+
+#[test]
+fn test_diff_corpus() {
+    let dir = temp_dir("diff");
+    fs::create_dir_all(dir.join("nested")).unwrap();
+    fs::write(dir.join("lower.txt"), "I love rust").unwrap();
+    fs::write(dir.join("upper.txt"), "Rust and Go").unwrap();
+    fs::write(dir.join("nested/none.txt"), "nothing here").unwrap();
+    // Links to directories of the corpus are not followed.
+    symlink(&dir, dir.join("nested/loop")).unwrap();
+    symlink("..", dir.join("nested/parent")).unwrap();
+
+    let report = diff::diff(&compile(OLD), &compile(NEW), std::slice::from_ref(&dir));
+    assert_eq!(3, report.samples);
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    assert_eq!(34, report.throughput.bytes);
+
+    assert_eq!(1, report.new_matches.len());
+    assert_eq!(dir.join("lower.txt"), report.new_matches[0].path);
+    assert_eq!(
+        vec!["default:added", "default:rust"],
+        report.new_matches[0].rules
+    );
+    assert!(report.lost_matches.is_empty());
+    assert_eq!(
+        vec![
+            HitCount {
+                rule: "default:added".to_owned(),
+                old: 0,
+                new: 1,
+            },
+            HitCount {
+                rule: "default:rust".to_owned(),
+                old: 1,
+                new: 2,
+            },
+        ],
+        report.hit_counts
+    );
+
+    let text = report.to_string();
+    assert!(
+        text.contains("  ~ default:rust (strings, metadata)"),
+        "{}",
+        text
+    );
+    assert!(text.contains("  default:rust: 1 -> 2"), "{}", text);
+
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!("default:added", json["structure"]["added"][0]);
+    assert_eq!(2, json["hit_counts"][1]["new"]);
+
+    // Swapped, the matches are lost.
+    let report = diff::diff(&compile(NEW), &compile(OLD), &[dir.join("lower.txt")]);
+    assert!(report.new_matches.is_empty());
+    assert_eq!(
+        vec!["default:added", "default:rust"],
+        report.lost_matches[0].rules
+    );
+
+    let report = diff::diff(&compile(OLD), &compile(OLD), &[dir.join("missing")]);
+    assert_eq!(1, report.errors.len());
+    assert!(report.same_matches());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_diff_unreadable_directory() {
+    let dir = temp_dir("diff_unreadable");
+    let locked = dir.join("locked");
+    fs::create_dir_all(&locked).unwrap();
+    fs::create_dir_all(dir.join("open")).unwrap();
+    fs::write(locked.join("hidden.txt"), "Rust").unwrap();
+    fs::write(dir.join("open/seen.txt"), "Rust").unwrap();
+    fs::set_permissions(&locked, Permissions::from_mode(0o000)).unwrap();
+
+    // Permissions do not stop root.
+    if fs::read_dir(&locked).is_err() {
+        // The walk goes on after the directory which could not be read.
+        let (files, errors) = walk::files(std::slice::from_ref(&dir));
+        assert_eq!(vec![dir.join("open/seen.txt")], files);
+        assert_eq!(1, errors.len());
+        assert_eq!(locked, errors[0].0);
+
+        let report = diff::diff(&compile(OLD), &compile(NEW), std::slice::from_ref(&dir));
+        assert_eq!(1, report.samples);
+        assert_eq!(1, report.errors.len());
+        assert_eq!(locked, report.errors[0].path);
+    }
+    fs::set_permissions(&locked, Permissions::from_mode(0o755)).unwrap();
+    fs::remove_dir_all(&dir).ok();
+}
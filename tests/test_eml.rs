@@ -0,0 +1,187 @@
+extern crate rs_yara as yara;
+
+use yara::eml::{self, Part, PartKind};
+use yara::errors::Error;
+use yara::Compiler;
+
+const MESSAGE: &[u8] = b"From: Alice <alice@example.com>\r\n\
+Subject: =?utf-8?Q?Caf=C3=A9?= report\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed;\r\n\
+\tboundary=\"outer\"\r\n\
+\r\n\
+This is a multi-part message in MIME format.\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=inner\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+I love R=\r\n\
+ust =E2=9D=A4\r\n\
+--inner\r\n\
+Content-Type: text/html\r\n\
+\r\n\
+<p>Go</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/octet-stream; name=\"=?iso-8859-1?B?cukgc3Vt6S5leGU=?=\"\r\n\
+Content-Disposition: attachment\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+TVpSdXN0\r\n\
+--outer\r\n\
+Content-Type: text/plain\r\n\
+Content-Disposition: attachment; filename*=utf-8''notes%20%E2%9C%93.txt\r\n\
+\r\n\
+notes\r\n\
+--outer--\r\n\
+epilogue\r\n";
+
+fn part(id: &str, kind: PartKind, content_type: &str, filename: Option<&str>, data: &[u8]) -> Part {
+    Part {
+        id: id.to_owned(),
+        kind,
+        content_type: content_type.to_owned(),
+        filename: filename.map(str::to_owned),
+        data: data.to_vec(),
+    }
+}
+
+#[test]
+fn test_parse() {
+    let parts = eml::parse(MESSAGE);
+    assert_eq!(PartKind::Headers, parts[0].kind);
+    assert!(parts[0].data.starts_with(b"From: Alice"));
+    assert!(parts[0].data.ends_with(b"boundary=\"outer\""));
+    assert_eq!(
+        vec![
+            part(
+                "1.1",
+                PartKind::Body,
+                "text/plain",
+                None,
+                "I love Rust ❤".as_bytes()
+            ),
+            part("1.2", PartKind::Body, "text/html", None, b"<p>Go</p>"),
+            part(
+                "2",
+                PartKind::Attachment,
+                "application/octet-stream",
+                Some("ré sumé.exe"),
+                b"MZRust"
+            ),
+            part(
+                "3",
+                PartKind::Attachment,
+                "text/plain",
+                Some("notes ✓.txt"),
+                b"notes"
+            ),
+        ],
+        parts[1..].to_vec()
+    );
+
+    // A message without MIME structure is a single body.
+    let parts = eml::parse(b"Subject: plain\n\nRust\n");
+    assert_eq!(
+        vec![
+            part(
+                "headers",
+                PartKind::Headers,
+                "text/rfc822-headers",
+                None,
+                b"Subject: plain"
+            ),
+            part("1", PartKind::Body, "text/plain", None, b"Rust\n"),
+        ],
+        parts
+    );
+}
+
+#[test]
+fn test_parse_signs() {
+    // Signs are not hex digits: the escapes stay literal.
+    let message = b"Content-Type: text/plain\r\n\
+Content-Disposition: attachment; filename*=utf-8''a%+1.txt\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+R=+1ust=-1\r\n";
+    let parts = eml::parse(message);
+    assert_eq!(Some("a%+1.txt"), parts[1].filename.as_deref());
+    assert_eq!(b"R=+1ust=-1\r\n", &parts[1].data[..]);
+}
+
+#[test]
+fn test_scan_parts() {
+    let mut compiler = Compiler::new().unwrap();
+    compiler
+        .define_variable(eml::FILENAME_VARIABLE, "")
+        .unwrap();
+    compiler
+        .add_rules_str(
+            r#"
+rule rust { strings: $a = "Rust" condition: $a }
+rule exe { condition: attachment_filename matches /\.exe$/ and uint16(0) == 0x5a4d }
+rule alice { strings: $a = "alice@" condition: $a }
+"#,
+        )
+        .unwrap();
+    let rules = compiler.compile_rules().unwrap();
+
+    let mut results = Vec::new();
+    eml::scan_mem(&rules, MESSAGE, 10, |part, result| {
+        let identifiers: Vec<_> = result.unwrap().iter().map(|r| r.identifier).collect();
+        results.push((part.id.clone(), identifiers));
+    });
+    assert_eq!(
+        vec![
+            ("headers".to_owned(), vec!["alice"]),
+            ("1.1".to_owned(), vec!["rust"]),
+            ("1.2".to_owned(), vec![]),
+            ("2".to_owned(), vec!["rust", "exe"]),
+            ("3".to_owned(), vec![]),
+        ],
+        results
+    );
+
+    // Rules without the variable are scanned too.
+    let mut compiler = Compiler::new().unwrap();
+    compiler
+        .add_rules_str(r#"rule rust { strings: $a = "Rust" condition: $a }"#)
+        .unwrap();
+    let rules = compiler.compile_rules().unwrap();
+    let mut matched = 0;
+    eml::scan_mem(&rules, MESSAGE, 10, |_, result| {
+        matched += result.unwrap().len();
+    });
+    assert_eq!(2, matched);
+
+    match eml::scan_file(&rules, "/nonexistent.eml", 10, |_, _| panic!()) {
+        Err(Error::Io(_)) => {}
+        other => panic!("unexpected {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn test_parse_deep_nesting() {
+    let mut message = String::new();
+    for i in (0..10_000).rev() {
+        message.push_str(&format!(
+            "Content-Type: multipart/mixed; boundary=\"b{0}\"\r\n\r\n--b{0}\r\n",
+            i
+        ));
+    }
+    message.push_str("Content-Type: text/plain\r\n\r\nRust");
+    for i in 0..10_000 {
+        message.push_str(&format!("\r\n--b{}--\r\n", i));
+    }
+
+    // Entities nested deeper than the limit are leaf parts.
+    let parts = eml::parse(message.as_bytes());
+    assert_eq!(2, parts.len());
+    assert_eq!(vec!["1"; eml::MAX_NESTING].join("."), parts[1].id);
+    assert_eq!("multipart/mixed", parts[1].content_type);
+    assert!(parts[1].data.starts_with(b"--b9967\r\n"));
+}
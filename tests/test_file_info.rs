@@ -0,0 +1,77 @@
+extern crate rs_yara as yara;
+
+use yara::errors::Error;
+use yara::render::View;
+use yara::report::FileReport;
+use yara::{Compiler, FileInfo};
+
+#[test]
+fn test_file_info() {
+    let info = FileInfo::new(b"abc");
+    assert_eq!(
+        FileInfo {
+            size: 3,
+            md5: "900150983cd24fb0d6963f7d28e17f72".to_owned(),
+            sha1: "a9993e364706816aba3e25717850c26c9cd0d89d".to_owned(),
+            sha256: "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_owned(),
+            entropy: 1.585,
+            file_type: "text",
+        },
+        info
+    );
+
+    let empty = FileInfo::new(b"");
+    assert_eq!(
+        (0, 0.0, "data"),
+        (empty.size, empty.entropy, empty.file_type)
+    );
+    let all_bytes: Vec<u8> = (0..=255).collect();
+    assert_eq!(8.0, FileInfo::new(&all_bytes).entropy);
+
+    let types: Vec<_> = [
+        &b"\x7fELF\x02\x01\x01"[..],
+        b"MZ\x90\x00",
+        b"PK\x03\x04\x14\x00",
+        b"%PDF-1.7",
+        b"#!/bin/sh\necho",
+        b"caf\xc3\xa9\r\n\tdone",
+        b"\x00\x01\x02\x03",
+    ]
+    .iter()
+    .map(|data| FileInfo::new(data).file_type)
+    .collect();
+    assert_eq!(
+        vec!["elf", "pe", "zip", "pdf", "script", "text", "data"],
+        types
+    );
+}
+
+#[test]
+fn test_scan_file_with_info() {
+    let mut compiler = Compiler::new().unwrap();
+    compiler
+        .add_rules_str(r#"rule rust { strings: $a = "Rust" condition: $a }"#)
+        .unwrap();
+    let rules = compiler.compile_rules().unwrap();
+
+    let (matches, info) = rules.scan_file_with_info("tests/scanfile.txt", 10).unwrap();
+    assert_eq!("rust", matches[0].identifier);
+    assert_eq!(
+        "c23bf7a8c017adf4ddd854049f12cd587100019ca62a0a2028d15624c7daefac",
+        info.sha256
+    );
+
+    let report = FileReport::new("tests/scanfile.txt", &matches, View::Ascii).with_info(info);
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(13, json["size"]);
+    assert_eq!("text", json["file_type"]);
+    assert_eq!("rust", json["rules"][0]["identifier"]);
+    // Reports without info are unchanged.
+    let report = FileReport::new("tests/scanfile.txt", &matches, View::Ascii);
+    assert!(!report.to_json().contains("sha256"));
+
+    match rules.scan_file_with_info("tests/missing.txt", 10) {
+        Err(Error::Io(_)) => {}
+        other => panic!("unexpected {:?}", other.map(|(_, info)| info)),
+    }
+}
@@ -0,0 +1,137 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use common::compile;
+use yara::{OwnedRule, RuleFlags, StringKind, StringModifiers};
+
+const RULES: &str = r#"
+private rule is_rust {
+  strings:
+    $a = "Rust"
+  condition:
+    $a
+}
+
+global rule small {
+  condition:
+    filesize < 1KB
+}
+
+rule flags {
+  strings:
+    $text = "love" wide ascii nocase
+    $word = "Rust" fullword
+    $xor = "key" xor
+    $hidden = "I" private
+    $hex = { 49 20 6C }
+    $jump = { 49 [2] 6F }
+    $regex = /R[a-z]+t/
+  condition:
+    is_rust and any of them
+}
+"#;
+
+#[test]
+fn test_string_flags() {
+    let rules = compile(RULES);
+    let matches = rules.scan_mem(b"I love Rust", 10).unwrap();
+    // The private rule is not reported, the global one is.
+    assert_eq!(
+        vec![
+            (
+                "small",
+                RuleFlags {
+                    private: false,
+                    global: true
+                }
+            ),
+            ("flags", RuleFlags::default()),
+        ],
+        matches
+            .iter()
+            .map(|r| (r.identifier, r.flags))
+            .collect::<Vec<_>>()
+    );
+
+    let strings = &matches[1].strings;
+    let kinds: Vec<_> = strings.iter().map(|s| s.kind).collect();
+    assert_eq!(
+        vec![
+            StringKind::Text,
+            StringKind::Text,
+            StringKind::Text,
+            StringKind::Text,
+            StringKind::Hex,
+            StringKind::Hex,
+            StringKind::Regex,
+        ],
+        kinds
+    );
+    let modifiers: Vec<_> = strings.iter().map(|s| s.modifiers.to_string()).collect();
+    assert_eq!(
+        vec![
+            "ascii wide nocase",
+            "ascii fullword",
+            "xor",
+            "ascii private",
+            "ascii",
+            "ascii",
+            "ascii",
+        ],
+        modifiers
+    );
+    assert_eq!(
+        StringModifiers {
+            ascii: true,
+            wide: true,
+            nocase: true,
+            ..Default::default()
+        },
+        strings[0].modifiers
+    );
+
+    let literals: Vec<_> = strings.iter().map(|s| s.literal).collect();
+    assert_eq!(
+        vec![
+            Some(&b"love"[..]),
+            Some(&b"Rust"[..]),
+            Some(&b"key"[..]),
+            Some(&b"I"[..]),
+            Some(&b"I l"[..]),
+            None,
+            None,
+        ],
+        literals
+    );
+
+    // Private strings never have matches.
+    assert!(strings[3].modifiers.private);
+    assert!(strings[3].matches.is_empty());
+    assert_eq!(1, strings[4].matches.len());
+
+    let owned = OwnedRule::from(matches.into_iter().nth(1).unwrap());
+    assert_eq!(Some(b"love".to_vec()), owned.strings[0].literal);
+    assert_eq!(StringKind::Regex, owned.strings[6].kind);
+}
+
+#[test]
+fn test_rule_info_flags() {
+    let infos = compile(RULES).get_rules();
+    assert!(infos[0].flags.private);
+    assert!(infos[1].flags.global);
+    let public: Vec<_> = infos
+        .iter()
+        .filter(|r| !r.flags.private)
+        .map(|r| r.identifier.as_str())
+        .collect();
+    assert_eq!(vec!["small", "flags"], public);
+
+    let strings = &infos[2].strings;
+    assert_eq!(StringKind::Hex, strings[5].kind);
+    assert!(strings[2].modifiers.xor);
+    assert_eq!(
+        r#"["ascii","private"]"#,
+        serde_json::to_string(&strings[3].modifiers).unwrap()
+    );
+}
@@ -0,0 +1,175 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use common::corpus;
+use yara::syntax;
+use yara::Compiler;
+
+/// A summary of the compiled rules.
+///
+/// Saved rules are compared by size only, libyara leaves some padding bytes uninitialized.
+fn compile(source: &str) -> (usize, u32, u32, u32, u32) {
+    let mut compiler = Compiler::new().unwrap();
+    compiler
+        .add_rules_str(source)
+        .unwrap_or_else(|e| panic!("Should have compiled:\n{}\n{}", source, e));
+    let mut rules = compiler.compile_rules().unwrap();
+    let stats = rules.stats().unwrap();
+    let mut saved = Vec::new();
+    rules.save_to_stream(&mut saved).unwrap();
+    (
+        saved.len(),
+        stats.rules,
+        stats.strings,
+        stats.ac_matches,
+        stats.ac_tables_size,
+    )
+}
+
+#[test]
+fn test_format_corpus() {
+    for source in corpus("tests/syntax/valid.txt") {
+        let formatted = syntax::format(&source).expect("Should have formatted");
+        assert_eq!(
+            formatted,
+            syntax::format(&formatted).unwrap(),
+            "Should be idempotent:\n{}",
+            source
+        );
+        assert_eq!(
+            compile(&source),
+            compile(&formatted),
+            "Should compile to the same rules:\n{}\n{}",
+            source,
+            formatted
+        );
+    }
+}
+
+#[test]
+fn test_format_rule() {
+    let source = r#"import "pe"
+private  rule a:t1 t2{meta: author="me" version=0x10
+strings: $text="x" xor(1-1)  wide
+$long_name={4d 5a??[2-2]4?(01|02 03)[0-0]05[1-1]06[1]07}
+condition: $text and   #long_name > 1}"#;
+    let expected = r#"import "pe"
+
+private rule a : t1 t2 {
+  meta:
+    author  = "me"
+    version = 0x10
+
+  strings:
+    $text      = "x" wide xor(1)
+    $long_name = { 4D 5A ?? [2] 4? ( 01 | 02 03 ) [0-0] 05 [1-1] 06 [1] 07 }
+
+  condition:
+    $text and #long_name > 1
+}
+"#;
+    let formatted = syntax::format(source).unwrap();
+    assert_eq!(expected, formatted);
+    assert_eq!(compile(source), compile(&formatted));
+}
+
+#[test]
+fn test_format_comments() {
+    let source = r#"// License.
+
+// About a.
+rule a { // The first rule.
+  strings:
+    /* Text. */
+    $a = "a" // Trailing.
+    $b = "b" /* inside */ wide
+  condition:
+    // Both.
+    $a and // First.
+    $b
+  // Done.
+}
+// End.
+"#;
+    let expected = r#"// License.
+
+// About a.
+rule a { // The first rule.
+  strings:
+    /* Text. */
+    $a = "a" // Trailing.
+    $b = "b" /* inside */ wide
+
+  condition:
+    // Both.
+    $a and // First.
+    $b
+    // Done.
+}
+// End.
+"#;
+    let formatted = syntax::format(source).unwrap();
+    assert_eq!(expected, formatted);
+    assert_eq!(formatted, syntax::format(&formatted).unwrap());
+}
+
+#[test]
+fn test_format_comments_in_header() {
+    let source = "rule a // c1\n{ // c2\n condition: true }\n";
+    let expected = "// c1\nrule a { // c2\n  condition:\n    true\n}\n";
+    let formatted = syntax::format(source).unwrap();
+    assert_eq!(expected, formatted);
+    assert_eq!(formatted, syntax::format(&formatted).unwrap());
+}
+
+#[test]
+fn test_format_comment_after_paren() {
+    for source in [
+        "rule a { condition: for any i in (1..3) : ( /* c */ i == 1 ) }",
+        "rule a { condition: for /* c */ any i in (1..3) : ( i == 1 ) }",
+        "rule a { condition: true and ( // c\n false ) }",
+    ] {
+        let formatted = syntax::format(source).unwrap();
+        assert_eq!(
+            formatted,
+            syntax::format(&formatted).unwrap(),
+            "Should be idempotent:\n{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_format_long_condition() {
+    let strings: Vec<_> = (0..8).map(|i| format!("$long_string_name_{}", i)).collect();
+    let source = format!(
+        "rule a {{ strings: {} condition: ({}) and not ({}) }}",
+        strings
+            .iter()
+            .map(|s| format!("{} = \"{}\"", s, s))
+            .collect::<Vec<_>>()
+            .join(" "),
+        strings[..4].join(" or "),
+        strings[4..].join(" and ")
+    );
+    let formatted = syntax::format(&source).unwrap();
+    let condition = &formatted[formatted.find("condition:").unwrap()..];
+    assert_eq!(
+        "condition:
+    ($long_string_name_0 or $long_string_name_1 or $long_string_name_2 or $long_string_name_3) and
+    not (
+      $long_string_name_4 and $long_string_name_5 and $long_string_name_6 and $long_string_name_7
+    )
+}
+",
+        condition
+    );
+    assert_eq!(compile(&source), compile(&formatted));
+}
+
+#[test]
+fn test_format_error() {
+    let error = syntax::format("rule a {\n  condition\n    true\n}").unwrap_err();
+    assert_eq!(3, error.line);
+}
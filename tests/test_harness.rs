@@ -0,0 +1,73 @@
+extern crate rs_yara as yara;
+
+use yara::harness::{self, Fixture, Mismatch, TestCase, TestManifest};
+use yara::Compiler;
+
+#[test]
+fn test_manifest() {
+    let manifest = TestManifest::from_file("tests/harness/manifest.toml").unwrap();
+    assert_eq!(
+        vec![std::path::PathBuf::from("tests/harness/../rules.txt")],
+        manifest.rules
+    );
+    assert_eq!(Fixture::Data(b"RUST".to_vec()), manifest.tests[2].fixture);
+
+    let report = manifest.run().unwrap();
+    report.assert_passed();
+    assert_eq!(3, report.results.len());
+}
+
+#[test]
+fn test_mismatches() {
+    let mut compiler = Compiler::new().unwrap();
+    compiler.add_rules_file("tests/rules.txt").unwrap();
+    let rules = compiler.compile_rules().unwrap();
+
+    let tests = vec![
+        TestCase::data("go", "go rust")
+            .matches(&["is_ok", "is_awesome"])
+            .not_matches(&["is_awesome"])
+            .string_at("is_awesome", "$rust", 0)
+            .string_at("is_ok", "$go", 1),
+        TestCase::file("missing", "tests/missing.txt"),
+    ];
+    let report = harness::run_tests(&rules, &tests);
+    assert!(!report.passed());
+    assert_eq!(
+        vec![
+            Mismatch::UnexpectedMatch {
+                rule: "is_awesome".to_owned()
+            },
+            Mismatch::MissingOffset {
+                rule: "is_awesome".to_owned(),
+                string: "$rust".to_owned(),
+                offset: 0,
+                actual: vec![3],
+            },
+            Mismatch::MissingOffset {
+                rule: "is_ok".to_owned(),
+                string: "$go".to_owned(),
+                offset: 1,
+                actual: vec![0],
+            },
+        ],
+        report.results[0].mismatches
+    );
+    assert!(matches!(
+        report.results[1].mismatches[..],
+        [Mismatch::Scan(_)]
+    ));
+
+    let report = report.to_string();
+    assert!(report.contains("test go ... FAILED\n    rule is_awesome matched\n"));
+    assert!(report.contains("    $rust of rule is_awesome did not match at 0 (matched at 3)\n"));
+    assert!(report.ends_with("0 passed, 2 failed"));
+}
+
+#[test]
+fn test_manifest_errors() {
+    let error = TestManifest::from_toml("rules = []\n[[test]]\nname = \"a\"\n").unwrap_err();
+    assert_eq!("test a: expected one of file, data or hex", error.message);
+    assert!(TestManifest::from_toml("rules = []\n[[test]]\nname = \"a\"\nhex = \"4\"").is_err());
+    assert!(TestManifest::from_toml("rule = []").is_err());
+}
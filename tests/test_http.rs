@@ -0,0 +1,168 @@
+#![cfg(feature = "http")]
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::thread;
+
+use common::temp_dir;
+use serde_json::Value;
+use yara::daemon::Daemon;
+use yara::RuleSet;
+
+const RULES: &str = r#"
+rule rust : lang {
+  meta:
+    score = 10
+  strings:
+    $a = "Rust" wide ascii
+  condition:
+    $a
+}
+"#;
+
+/// Serve `RULES` of `dir` on a loopback port, from a background thread.
+fn start(dir: &PathBuf) -> SocketAddr {
+    fs::write(dir.join("rules.yar"), RULES).unwrap();
+    let mut daemon = Daemon::new(RuleSet::from_directory(dir).unwrap());
+    daemon.set_max_size(1024);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    thread::spawn(move || yara::http::serve(&daemon, listener));
+    address
+}
+
+/// Send a request, return the status and the body.
+fn request(
+    address: SocketAddr,
+    method: &str,
+    path: &str,
+    headers: &[&str],
+    body: &[u8],
+) -> (u16, String) {
+    let mut stream = TcpStream::connect(address).unwrap();
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        body.len()
+    );
+    for header in headers {
+        head.push_str(header);
+        head.push_str("\r\n");
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).unwrap();
+    stream.write_all(body).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let status = response[9..12].parse().unwrap();
+    let body = response.split("\r\n\r\n").nth(1).unwrap_or_default();
+    (status, body.to_owned())
+}
+
+fn json(body: &str) -> Value {
+    serde_json::from_str(body).unwrap()
+}
+
+#[test]
+fn test_http_scan() {
+    let dir = temp_dir("http_scan");
+    let address = start(&dir);
+
+    let (status, body) = request(address, "POST", "/scan?name=up%20load", &[], b"I love Rust");
+    assert_eq!(200, status);
+    let response = json(&body);
+    assert_eq!("scanned", response["status"]);
+    assert_eq!("up load", response["path"]);
+    assert_eq!("rust", response["rules"][0]["identifier"]);
+
+    let multipart = b"--XyZ\r\n\
+Content-Disposition: form-data; name=\"first\"; filename=\"a.txt\"\r\n\
+Content-Type: text/plain\r\n\r\n\
+R\0u\0s\0t\0\r\n\
+--XyZ\r\n\
+Content-Disposition: form-data; name=\"second\"\r\n\r\n\
+Go\r\n\
+--XyZ--\r\n";
+    let (status, body) = request(
+        address,
+        "POST",
+        "/scan?timeout=1",
+        &["Content-Type: multipart/form-data; boundary=XyZ"],
+        multipart,
+    );
+    assert_eq!(200, status);
+    let responses = json(&body);
+    assert_eq!("a.txt", responses[0]["path"]);
+    assert_eq!("rust", responses[0]["rules"][0]["identifier"]);
+    assert_eq!("second", responses[1]["path"]);
+    assert_eq!(Value::Array(vec![]), responses[1]["rules"]);
+
+    let sample = dir.join("sample");
+    fs::write(&sample, "Rust").unwrap();
+    let path = serde_json::json!({ "path": sample }).to_string();
+    let (status, body) = request(address, "POST", "/scan/path", &[], path.as_bytes());
+    assert_eq!(200, status);
+    assert_eq!("rust", json(&body)["rules"][0]["identifier"]);
+
+    let path = serde_json::json!({ "path": dir.join("missing") }).to_string();
+    let (status, body) = request(address, "POST", "/scan/path", &[], path.as_bytes());
+    assert_eq!(
+        (422, "error".into()),
+        (status, json(&body)["status"].clone())
+    );
+
+    assert_eq!(400, request(address, "POST", "/scan?timeout=x", &[], b"").0);
+    assert_eq!(400, request(address, "POST", "/scan?timeout=0", &[], b"").0);
+    assert_eq!(413, request(address, "POST", "/scan", &[], &[0; 2048]).0);
+    assert_eq!(405, request(address, "GET", "/scan", &[], b"").0);
+    assert_eq!(404, request(address, "GET", "/", &[], b"").0);
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_http_rules_and_metrics() {
+    let dir = temp_dir("http_rules");
+    let address = start(&dir);
+
+    let (status, body) = request(address, "GET", "/rules", &[], b"");
+    assert_eq!(200, status);
+    assert_eq!(
+        serde_json::json!([{
+            "identifier": "rust",
+            "namespace": "default",
+            "tags": ["lang"],
+            "metadata": [{ "identifier": "score", "value": 10 }],
+            "flags": { "private": false, "global": false },
+            "strings": [{ "identifier": "$a", "kind": "text", "modifiers": ["ascii", "wide"] }],
+        }]),
+        json(&body)
+    );
+
+    fs::write(dir.join("more.yar"), "rule go { condition: false }").unwrap();
+    let (status, body) = request(address, "POST", "/reload", &[], b"");
+    assert_eq!(200, status);
+    assert_eq!(
+        serde_json::json!({ "status": "reloaded", "rules": 2 }),
+        json(&body)
+    );
+
+    request(address, "POST", "/scan", &[], b"Rust");
+    let (status, metrics) = request(address, "GET", "/metrics", &[], b"");
+    assert_eq!(200, status);
+    let lines: Vec<_> = metrics.lines().filter(|l| !l.starts_with('#')).collect();
+    assert!(lines.contains(&"rs_yara_rules 2"));
+    assert!(lines.contains(&"rs_yara_scans_total 1"));
+    assert!(lines.contains(&"rs_yara_scans_matched_total 1"));
+    assert!(lines.contains(&"rs_yara_reloads_total 1"));
+    assert!(lines.contains(&"rs_yara_cached_scans_total 0"));
+    assert!(lines.contains(&"rs_yara_http_requests_total{code=\"200\"} 3"));
+    assert!(metrics.contains("# TYPE rs_yara_scans_total counter"));
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,271 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use common::{compile, gzip, RUST};
+use yara::archive::Limits;
+use yara::errors::ArchiveError;
+use yara::image::{ImageFileReport, ImageScanner};
+use yara::render::View;
+
+enum Entry<'a> {
+    File(&'a str, &'a [u8]),
+    HardLink(&'a str, &'a str),
+    Symlink(&'a str, &'a str),
+    Dir(&'a str),
+}
+
+fn tar(entries: &[Entry]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for entry in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        match entry {
+            Entry::File(path, data) => {
+                header.set_size(data.len() as u64);
+                header.set_cksum();
+                builder.append_data(&mut header, path, *data).unwrap();
+            }
+            Entry::HardLink(path, target) => {
+                header.set_entry_type(tar::EntryType::Link);
+                header.set_size(0);
+                builder.append_link(&mut header, path, target).unwrap();
+            }
+            Entry::Symlink(path, target) => {
+                header.set_entry_type(tar::EntryType::Symlink);
+                header.set_size(0);
+                builder.append_link(&mut header, path, target).unwrap();
+            }
+            Entry::Dir(path) => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_size(0);
+                header.set_cksum();
+                builder.append_data(&mut header, path, &b""[..]).unwrap();
+            }
+        }
+    }
+    builder.into_inner().unwrap()
+}
+
+fn layers() -> Vec<Vec<u8>> {
+    use Entry::*;
+    vec![
+        tar(&[
+            File("etc/motd", b"Rust inside"),
+            File("app/old.bin", b"Rust"),
+            File("app/lib/a.so", b"Rust"),
+            File("tmp/cache", b"Rust"),
+        ]),
+        tar(&[
+            // Whiteouts hide the files of the lower layers only.
+            File("app/.wh..wh..opq", b""),
+            File("app/new.bin", b"Go"),
+            File("./tmp/.wh.cache", b""),
+            File("etc/motd", b"Welcome"),
+            HardLink("etc/motd.link", "etc/motd"),
+        ]),
+    ]
+}
+
+/// The path, layer and matches of each file of an image.
+fn scan(scanner: &ImageScanner, tarball: &[u8]) -> Vec<(String, String, bool)> {
+    let rules = compile(RUST);
+    let mut results = Vec::new();
+    scanner
+        .scan_mem(&rules, tarball, 10, |file, result| {
+            results.push((
+                file.path.clone(),
+                file.layer.clone(),
+                !result.unwrap().is_empty(),
+            ))
+        })
+        .unwrap();
+    results
+}
+
+fn result(path: &str, layer: &str, matched: bool) -> (String, String, bool) {
+    (path.to_owned(), layer.to_owned(), matched)
+}
+
+/// A `docker save` tarball of two layers.
+fn docker_save(config: &serde_json::Value, layers: &[Vec<u8>]) -> Vec<u8> {
+    let manifest = serde_json::json!([{
+        "Config": "cfg.json",
+        "RepoTags": ["app:latest"],
+        "Layers": ["1111/layer.tar", "2222/layer.tar"],
+    }]);
+    tar(&[
+        Entry::File("manifest.json", manifest.to_string().as_bytes()),
+        Entry::File("cfg.json", config.to_string().as_bytes()),
+        Entry::File("1111/layer.tar", &layers[0]),
+        Entry::File("2222/layer.tar", &layers[1]),
+    ])
+}
+
+#[test]
+fn test_docker_save() {
+    let layers = layers();
+    let config = serde_json::json!({
+        "rootfs": { "type": "layers", "diff_ids": ["sha256:aaaa", "sha256:bbbb"] }
+    });
+    let tarball = docker_save(&config, &layers);
+
+    let expected = vec![
+        result("/app/new.bin", "sha256:bbbb", false),
+        result("/etc/motd", "sha256:bbbb", false),
+        result("/etc/motd.link", "sha256:bbbb", false),
+    ];
+    assert_eq!(expected, scan(&ImageScanner::new(), &tarball));
+    // docker save | gzip
+    assert_eq!(expected, scan(&ImageScanner::new(), &gzip(&tarball)));
+}
+
+#[test]
+fn test_replaced_files() {
+    use Entry::*;
+    let layers = vec![
+        tar(&[
+            File("bin/sh", b"Rust"),
+            File("lib/a.so", b"Rust"),
+            File("opt", b"Rust"),
+            File("var/log/old", b"Rust"),
+        ]),
+        tar(&[
+            // Entries which are not files replace the lower files at their path.
+            Symlink("bin/sh", "busybox"),
+            Symlink("lib", "usr/lib"),
+            Dir("opt"),
+            // A directory keeps the files of a lower directory.
+            Dir("var/log"),
+        ]),
+    ];
+    let config = serde_json::json!({
+        "rootfs": { "type": "layers", "diff_ids": ["sha256:aaaa", "sha256:bbbb"] }
+    });
+
+    assert_eq!(
+        vec![result("/var/log/old", "sha256:aaaa", true)],
+        scan(&ImageScanner::new(), &docker_save(&config, &layers))
+    );
+}
+
+#[test]
+fn test_oci_layout() {
+    let layers = layers();
+    let digests = ["sha256:1111", "sha256:2222"];
+    let manifest = serde_json::json!({
+        "schemaVersion": 2,
+        "layers": [
+            { "mediaType": "application/vnd.oci.image.layer.v1.tar+gzip", "digest": digests[0] },
+            { "mediaType": "application/vnd.oci.image.layer.v1.tar", "digest": digests[1] },
+        ],
+    });
+    let platforms = serde_json::json!({
+        "manifests": [{ "digest": "sha256:3333" }, { "digest": "sha256:4444" }],
+    });
+    let index = serde_json::json!({ "manifests": [{ "digest": "sha256:5555" }] });
+    let mut tarball = tar(&[
+        Entry::File("oci-layout", br#"{"imageLayoutVersion": "1.0.0"}"#),
+        Entry::File("index.json", index.to_string().as_bytes()),
+        Entry::File("blobs/sha256/5555", platforms.to_string().as_bytes()),
+        Entry::File("blobs/sha256/3333", manifest.to_string().as_bytes()),
+        Entry::File("blobs/sha256/1111", &gzip(&layers[0])),
+        // The second layer is missing.
+    ]);
+    let (files, read) = ImageScanner::new().files(&tarball);
+    assert_eq!(
+        Err(ArchiveError::Invalid {
+            format: "image",
+            message: "missing layer blobs/sha256/2222".to_owned()
+        }),
+        read
+    );
+    // The files of the first layer are still there.
+    assert_eq!(4, files.len());
+
+    tarball = tar(&[
+        Entry::File("index.json", index.to_string().as_bytes()),
+        Entry::File("blobs/sha256/5555", platforms.to_string().as_bytes()),
+        Entry::File("blobs/sha256/3333", manifest.to_string().as_bytes()),
+        Entry::File("blobs/sha256/1111", &gzip(&layers[0])),
+        Entry::File(
+            "blobs/sha256/2222",
+            &tar(&[Entry::File("bin/tool", b"Rust")]),
+        ),
+    ]);
+    assert_eq!(
+        vec![
+            result("/app/lib/a.so", "sha256:1111", true),
+            result("/app/old.bin", "sha256:1111", true),
+            result("/bin/tool", "sha256:2222", true),
+            result("/etc/motd", "sha256:1111", true),
+            result("/tmp/cache", "sha256:1111", true),
+        ],
+        scan(&ImageScanner::new(), &tarball)
+    );
+
+    let (files, read) = ImageScanner::new().files(&tarball);
+    assert_eq!(Ok(()), read);
+    let rules = compile(RUST);
+    let report = ImageFileReport::new(
+        "image.tar",
+        &files[2],
+        &rules.scan_mem(&files[2].data, 10).unwrap(),
+        View::Ascii,
+    );
+    let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(
+        ("image.tar", "sha256:2222", "/bin/tool", "rust"),
+        (
+            json["image"].as_str().unwrap(),
+            json["layer"].as_str().unwrap(),
+            json["path"].as_str().unwrap(),
+            json["rules"][0]["identifier"].as_str().unwrap()
+        )
+    );
+
+    let mut scanner = ImageScanner::new();
+    scanner.set_limits(Limits {
+        max_members: 3,
+        ..Limits::default()
+    });
+    let (files, read) = scanner.files(&tarball);
+    assert_eq!(Err(ArchiveError::TooManyMembers(3)), read);
+    // The decompressed layer, then 2 files.
+    let paths: Vec<_> = files.iter().map(|f| f.path.as_str()).collect();
+    assert_eq!(vec!["/app/old.bin", "/etc/motd"], paths);
+}
+
+#[test]
+fn test_large_image() {
+    let names: Vec<_> = (0..10_001).map(|i| format!("usr/share/{}", i)).collect();
+    let entries: Vec<_> = names.iter().map(|n| Entry::File(n, b"Go")).collect();
+    let manifest = serde_json::json!([{ "Layers": ["1111/layer.tar"] }]);
+    let tarball = tar(&[
+        Entry::File("manifest.json", manifest.to_string().as_bytes()),
+        Entry::File("1111/layer.tar", &tar(&entries)),
+    ]);
+
+    // More files than archives have by default.
+    let (files, read) = ImageScanner::new().files(&tarball);
+    assert_eq!(Ok(()), read);
+    assert_eq!(10_001, files.len());
+}
+
+#[test]
+fn test_oci_index_cycle() {
+    let index = serde_json::json!({ "manifests": [{ "digest": "sha256:5555" }] });
+    let nested = serde_json::json!({ "manifests": [{ "digest": "sha256:6666" }] });
+    let tarball = tar(&[
+        Entry::File("index.json", index.to_string().as_bytes()),
+        Entry::File("blobs/sha256/5555", nested.to_string().as_bytes()),
+        Entry::File("blobs/sha256/6666", index.to_string().as_bytes()),
+    ]);
+    assert_eq!(
+        ArchiveError::Invalid {
+            format: "image",
+            message: "index cycle through sha256:5555".to_owned()
+        },
+        ImageScanner::new().files(&tarball).1.unwrap_err()
+    );
+}
@@ -0,0 +1,140 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::ffi::OsStr;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use common::temp_dir;
+use yara::errors::{Error, IoErrorKind};
+use yara::incremental::{Change, FileState, ScanState, SkipReport};
+
+#[test]
+fn test_scan_state_changes() {
+    let dir = temp_dir("incremental");
+    let file = dir.join("sample");
+    fs::write(&file, "version 1").unwrap();
+
+    let mut state = ScanState::new();
+    let metadata = fs::metadata(&file).unwrap();
+    assert_eq!(Change::New, state.change(&file, &metadata, "f1"));
+    state.record(
+        &file,
+        FileState::new(&metadata, "f1", vec!["is_v1".to_owned()]),
+    );
+    assert_eq!(Change::Unchanged, state.change(&file, &metadata, "f1"));
+    assert_eq!(Change::RulesChanged, state.change(&file, &metadata, "f2"));
+
+    // Same size, other modification time.
+    fs::write(&file, "version 2").unwrap();
+    let handle = fs::OpenOptions::new().write(true).open(&file).unwrap();
+    handle
+        .set_modified(SystemTime::now() - Duration::from_secs(60))
+        .unwrap();
+    assert_eq!(
+        Change::Modified,
+        state.change(&file, &fs::metadata(&file).unwrap(), "f1")
+    );
+
+    // Replaced by another file with the same size and modification time.
+    let other = dir.join("other");
+    fs::write(&other, "version 1").unwrap();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&other)
+        .unwrap()
+        .set_modified(metadata.modified().unwrap())
+        .unwrap();
+    fs::rename(&other, &file).unwrap();
+    assert_eq!(
+        Change::Modified,
+        state.change(&file, &fs::metadata(&file).unwrap(), "f1")
+    );
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_scan_state_file() {
+    let dir = temp_dir("incremental_file");
+    let state_file = dir.join("sweep.state");
+    let file = dir.join("sample");
+    fs::write(&file, "version 1").unwrap();
+
+    // A missing state file is an empty state.
+    let mut state = ScanState::load(&state_file).unwrap();
+    assert!(state.is_empty());
+    let metadata = fs::metadata(&file).unwrap();
+    state.record(
+        &file,
+        FileState::new(&metadata, "f1", vec!["is_v1".to_owned()]),
+    );
+    state.record(dir.join("removed"), FileState::new(&metadata, "f1", vec![]));
+    state.retain(|path, _| path != dir.join("removed"));
+    state.save(&state_file).unwrap();
+
+    let loaded = ScanState::load(&state_file).unwrap();
+    assert_eq!(state, loaded);
+    assert_eq!(1, loaded.len());
+    assert_eq!(Change::Unchanged, loaded.change(&file, &metadata, "f1"));
+
+    let report = SkipReport::new("sample", loaded.get(&file).unwrap(), Change::Unchanged);
+    assert_eq!(
+        r#"{"path":"sample","skipped":"unchanged","rules":["is_v1"]}"#,
+        report.to_json()
+    );
+
+    // The state of another version is dropped, an invalid one is an error.
+    fs::write(&state_file, r#"{"version": 1, "files": {"sample": {}}}"#).unwrap();
+    assert!(ScanState::load(&state_file).unwrap().is_empty());
+    fs::write(&state_file, "{").unwrap();
+    match ScanState::load(&state_file) {
+        Err(Error::Io(e)) => assert_eq!(&IoErrorKind::ReadingStateFile, e.kind()),
+        other => panic!("unexpected {:?}", other),
+    }
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_scan_state_any_file() {
+    let dir = temp_dir("incremental_any");
+    let state_file = dir.join("sweep.state");
+    let mut state = ScanState::new();
+
+    // Names which are not UTF-8, and modification times before 1970.
+    let latin1 = dir.join(OsStr::from_bytes(b"caf\xe9"));
+    fs::write(&latin1, "version 1").unwrap();
+    for (i, mtime) in [
+        UNIX_EPOCH - Duration::new(86_400, 500_000_000),
+        UNIX_EPOCH - Duration::from_secs(1),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let file = dir.join(format!("old{}", i));
+        fs::write(&file, "version 1").unwrap();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(*mtime)
+            .unwrap();
+        let metadata = fs::metadata(&file).unwrap();
+        assert_eq!(Some(*mtime), metadata.modified().ok());
+        state.record(&file, FileState::new(&metadata, "f1", vec![]));
+    }
+    let metadata = fs::metadata(&latin1).unwrap();
+    state.record(&latin1, FileState::new(&metadata, "f1", vec![]));
+    state.save(&state_file).unwrap();
+
+    let loaded = ScanState::load(&state_file).unwrap();
+    assert_eq!(state, loaded);
+    assert_eq!(Change::Unchanged, loaded.change(&latin1, &metadata, "f1"));
+    for i in 0..2 {
+        let file = dir.join(format!("old{}", i));
+        let metadata = fs::metadata(&file).unwrap();
+        assert_eq!(Change::Unchanged, loaded.change(&file, &metadata, "f1"));
+    }
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,122 @@
+extern crate rs_yara as yara;
+
+use yara::lint::{self, Check, Finding, LintConfig, MetaType, Severity};
+
+const RULES: &str = r#"rule a : malware bad {
+  meta:
+    author = 1
+  strings:
+    $short = "ab"
+    $nocase = "abcde" nocase wide
+    $re = /foo[^\n]+bar/
+    $hex = { 4D ?? ?? ( 01 | 02 03 ) }
+  condition:
+    all of them
+}
+
+rule b {
+  strings:
+    $same = "abcde" wide nocase
+    $unused = "unused string"
+  condition:
+    $same
+}
+"#;
+
+fn checks(findings: &[Finding]) -> Vec<(usize, Check)> {
+    findings.iter().map(|f| (f.line, f.check)).collect()
+}
+
+#[test]
+fn test_lint_default_config() {
+    let findings = lint::lint(RULES, &LintConfig::default()).unwrap();
+    assert_eq!(
+        vec![
+            (5, Check::ShortString),
+            (6, Check::NocaseWideShort),
+            (7, Check::UnboundedRegex),
+            (8, Check::ShortString),
+            (8, Check::CompilerWarning),
+            (15, Check::NocaseWideShort),
+            (15, Check::DuplicateString),
+            (16, Check::UnreferencedString),
+        ],
+        checks(&findings)
+    );
+
+    let duplicate = &findings[6];
+    assert_eq!(Some("b"), duplicate.rule.as_deref());
+    assert_eq!(Severity::Warning, duplicate.severity);
+    assert_eq!(
+        "line 15: warning[duplicate-string]: string $same is the same as $nocase of rule a (rule b)",
+        duplicate.to_string()
+    );
+    // Reported by the compiler too, but once.
+    assert_eq!(Severity::Error, findings[7].severity);
+}
+
+#[test]
+fn test_lint_config() {
+    let config = LintConfig::from_toml(
+        r#"
+[checks]
+short-string = "off"
+nocase-wide-short = "info"
+unreferenced-string = "warning"
+
+[meta]
+required = ["author", "date"]
+types = { author = "string" }
+
+[tags]
+allowed = ["malware"]
+
+[strings]
+nocase-wide-min-length = 4
+"#,
+    )
+    .unwrap();
+    assert_eq!(Some(&MetaType::String), config.meta.types.get("author"));
+    assert_eq!(4, config.strings.min_length);
+
+    let findings = lint::lint(RULES, &config).unwrap();
+    assert_eq!(
+        vec![
+            (1, Check::RequiredMeta),
+            (1, Check::DisallowedTag),
+            (3, Check::MetaType),
+            (7, Check::UnboundedRegex),
+            (8, Check::CompilerWarning),
+            (13, Check::RequiredMeta),
+            (13, Check::RequiredMeta),
+            (15, Check::DuplicateString),
+            (16, Check::UnreferencedString),
+        ],
+        checks(&findings)
+    );
+    assert_eq!(Severity::Warning, findings[8].severity);
+}
+
+#[test]
+fn test_lint_compiler_messages() {
+    let source =
+        "rule a {\n  strings:\n    $a = /abc.*/\n  condition:\n    $a and entrypoint == 0\n}";
+    let findings = lint::lint(source, &LintConfig::default()).unwrap();
+    assert_eq!(
+        vec![(3, Check::UnboundedRegex), (5, Check::CompilerWarning)],
+        checks(&findings)
+    );
+
+    let findings = lint::lint("rule a { condition: b }", &LintConfig::default()).unwrap();
+    assert_eq!(vec![(1, Check::CompilerError)], checks(&findings));
+    assert_eq!(Some("a"), findings[0].rule.as_deref());
+
+    assert!(lint::lint("rule a {", &LintConfig::default()).is_err());
+}
+
+#[test]
+fn test_lint_config_error() {
+    let error = LintConfig::from_toml("[strings]\nmin-lenght = 4").unwrap_err();
+    assert!(error.message.contains("min-lenght"), "{}", error);
+    assert!(LintConfig::from_toml("[checks]\nshort-string = \"fatal\"").is_err());
+}
@@ -0,0 +1,202 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::net::IpAddr;
+
+use common::{compile, RUST};
+use yara::errors::PcapError;
+use yara::pcap::{self, Flow, Mode, PayloadReport, Protocol};
+use yara::render::View;
+
+fn capture(name: &str) -> Vec<u8> {
+    std::fs::read(format!("tests/pcap/{}", name)).unwrap()
+}
+
+fn flow(protocol: Protocol, src: &str, src_port: u16, dst: &str, dst_port: u16) -> Flow {
+    Flow {
+        protocol,
+        src: src.parse::<IpAddr>().unwrap(),
+        src_port,
+        dst: dst.parse::<IpAddr>().unwrap(),
+        dst_port,
+    }
+}
+
+/// The flow, offset and data of each payload, with the offsets of the `rust` matches in the
+/// stream.
+fn scan(capture: &[u8], mode: Mode) -> Vec<(String, u64, String, Vec<usize>)> {
+    let rules = compile(RUST);
+    let mut results = Vec::new();
+    pcap::scan_mem(&rules, capture, mode, 10, |payload, result| {
+        let report = PayloadReport::new("http.pcap", payload, &result.unwrap(), View::Ascii);
+        let offsets = report
+            .rules
+            .iter()
+            .flat_map(|r| &r.strings)
+            .flat_map(|s| &s.matches)
+            .map(|m| m.offset)
+            .collect();
+        results.push((
+            payload.flow.to_string(),
+            payload.offset,
+            String::from_utf8_lossy(&payload.data).into_owned(),
+            offsets,
+        ));
+    })
+    .unwrap();
+    results
+}
+
+fn result(
+    flow: &str,
+    offset: u64,
+    data: &str,
+    offsets: &[usize],
+) -> (String, u64, String, Vec<usize>) {
+    (flow.to_owned(), offset, data.to_owned(), offsets.to_vec())
+}
+
+const REQUEST: &str = "tcp 10.0.0.1:49152 > 10.0.0.2:80";
+const RESPONSE: &str = "tcp 10.0.0.2:80 > 10.0.0.1:49152";
+const DNS: &str = "udp 10.0.0.1:5353 > 10.0.0.3:53";
+
+#[test]
+fn test_packets() {
+    let capture = capture("http.pcap");
+    assert_eq!(
+        vec![
+            result(REQUEST, 7, "st HTTP/1.1\r\n", &[]),
+            result(REQUEST, 0, "GET /Ru", &[]),
+            result(REQUEST, 0, "GET /Ru", &[]),
+            result(RESPONSE, 0, "HTTP/1.0 200 OK\r\nRust", &[17]),
+            result(DNS, 0, "Rust dns", &[0]),
+        ],
+        scan(&capture, Mode::Packets)
+    );
+
+    let (payloads, result) = pcap::payloads(&capture, Mode::Packets);
+    assert_eq!(Ok(()), result);
+    assert_eq!(
+        flow(Protocol::Tcp, "10.0.0.1", 49152, "10.0.0.2", 80),
+        payloads[0].flow
+    );
+    assert_eq!(1_700_000_002.5, payloads[0].timestamp);
+}
+
+#[test]
+fn test_streams() {
+    assert_eq!(
+        vec![
+            result(REQUEST, 0, "GET /Rust HTTP/1.1\r\n", &[5]),
+            result(RESPONSE, 0, "HTTP/1.0 200 OK\r\nRust", &[17]),
+            result(DNS, 0, "Rust dns", &[0]),
+        ],
+        scan(&capture("http.pcap"), Mode::Streams)
+    );
+
+    // A gap in the stream splits it.
+    let capture = capture("vlan_ipv6.pcapng");
+    let ipv6 = "tcp [2001:db8::1]:40000 > [2001:db8::2]:443";
+    assert_eq!(
+        vec![
+            result(ipv6, 0, "Rust!", &[0]),
+            result(ipv6, 10, "Rust", &[10]),
+        ],
+        scan(&capture, Mode::Streams)
+    );
+    let payloads = pcap::payloads(&capture, Mode::Streams).0;
+    assert_eq!(1_700_000_000.25, payloads[0].timestamp);
+    assert_eq!(1_700_000_000.250_002, payloads[1].timestamp);
+}
+
+#[test]
+fn test_invalid_captures() {
+    assert_eq!(
+        (vec![], Err(PcapError::UnknownFormat)),
+        pcap::payloads(b"GIF89a", Mode::Packets)
+    );
+
+    // The payloads before the truncation are read, the last datagram is cut.
+    let mut capture = capture("http.pcap");
+    let (payloads, _) = pcap::payloads(&capture, Mode::Packets);
+    capture.truncate(capture.len() - 10);
+    assert_eq!(
+        (
+            payloads[..payloads.len() - 1].to_vec(),
+            Err(PcapError::Truncated(508))
+        ),
+        pcap::payloads(&capture, Mode::Packets)
+    );
+    let mut scanned = 0;
+    let result = pcap::scan_mem(&compile(RUST), &capture, Mode::Streams, 10, |_, _| {
+        scanned += 1
+    });
+    assert_eq!(Err(PcapError::Truncated(508)), result);
+    assert_eq!(2, scanned);
+}
+
+#[test]
+fn test_invalid_tcp_headers() {
+    // A raw IP capture of one IPv4 packet.
+    let capture = |transport: &[u8]| {
+        let mut packet = vec![
+            0x45, 0, 0, 0, 0, 0, 0, 0, 64, 6, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2,
+        ];
+        packet.extend_from_slice(transport);
+        let length = packet.len() as u16;
+        packet[2..4].copy_from_slice(&length.to_be_bytes());
+        let mut capture = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        capture.extend_from_slice(&[0; 12]);
+        capture.extend_from_slice(&101u32.to_le_bytes());
+        capture.extend_from_slice(&[0; 8]);
+        capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        capture.extend_from_slice(&packet);
+        capture
+    };
+    let mut tcp = vec![
+        0xc0, 0, 0, 80, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x18, 0xff, 0xff,
+    ];
+    tcp.extend_from_slice(&[0, 0, 0, 0]);
+    tcp.extend_from_slice(b"Rust");
+
+    assert_eq!(1, pcap::payloads(&capture(&tcp), Mode::Packets).0.len());
+    // Cut before the flags.
+    assert!(pcap::payloads(&capture(&tcp[..13]), Mode::Packets)
+        .0
+        .is_empty());
+    // A data offset shorter than the header.
+    tcp[12] = 0x40;
+    assert!(pcap::payloads(&capture(&tcp), Mode::Packets).0.is_empty());
+}
+
+#[test]
+fn test_report() {
+    let capture = capture("http.pcap");
+    let payload = &pcap::payloads(&capture, Mode::Streams).0[0];
+    let rules = compile(RUST);
+    let report = PayloadReport::new(
+        "http.pcap",
+        payload,
+        &rules.scan_mem(&payload.data, 10).unwrap(),
+        View::Ascii,
+    );
+    let mut json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+    assert_eq!(5, json["rules"][0]["strings"][0]["matches"][0]["offset"]);
+    json.as_object_mut().unwrap().remove("rules");
+    assert_eq!(
+        serde_json::json!({
+            "path": "http.pcap",
+            "protocol": "tcp",
+            "src": "10.0.0.1",
+            "src_port": 49152,
+            "dst": "10.0.0.2",
+            "dst_port": 80,
+            "timestamp": 1_700_000_002.5,
+            "offset": 0,
+            "length": 20,
+        }),
+        json
+    );
+}
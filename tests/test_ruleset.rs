@@ -0,0 +1,195 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use common::temp_dir;
+use yara::{Compiler, RuleSet, RuleSource};
+
+const RULE_V1: &str = "rule is_v1 {
+  strings:
+    $a = \"version 1\"
+  condition:
+    $a
+}
+";
+
+const RULE_V2: &str = "rule is_v2 {
+  strings:
+    $a = \"version 2\"
+  condition:
+    $a
+}
+";
+
+/// Write a file with a modification time different from the previous one.
+fn write_file(path: &Path, content: &[u8], age: u64) {
+    fs::write(path, content).expect("Should have written the file");
+    let file = fs::OpenOptions::new().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() - Duration::from_secs(age))
+        .expect("Should have set the modification time");
+}
+
+fn matching(rule_set: &RuleSet, data: &[u8]) -> Vec<String> {
+    let rules = rule_set.rules();
+    rules
+        .scan_mem(data, 10)
+        .expect("Should have scanned")
+        .iter()
+        .map(|r| r.identifier.to_owned())
+        .collect()
+}
+
+#[test]
+fn test_ruleset_directory_reload() {
+    let dir = temp_dir("directory");
+    let rule_file = dir.join("rules.yar");
+    write_file(&rule_file, RULE_V1.as_bytes(), 100);
+    fs::write(dir.join("notes.txt"), "not a rule").unwrap();
+
+    let rule_set = RuleSet::from_directory(&dir).expect("Should have loaded the rules");
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    {
+        let outcomes = Arc::clone(&outcomes);
+        rule_set.on_reload(move |result| outcomes.lock().unwrap().push(result.is_ok()));
+    }
+    assert_eq!(vec!["is_v1"], matching(&rule_set, b"version 1"));
+    assert!(!rule_set.reload().expect("Should not have reloaded"));
+
+    // In-flight scans keep the rules they started with.
+    let old_rules = rule_set.rules();
+
+    write_file(&rule_file, b"rule broken {", 50);
+    assert!(rule_set.reload().is_err());
+    assert_eq!(vec!["is_v1"], matching(&rule_set, b"version 1"));
+    // A broken source is not reloaded until it changes.
+    assert!(!rule_set.reload().expect("Should not have reloaded"));
+
+    write_file(&rule_file, RULE_V2.as_bytes(), 10);
+    assert!(rule_set.reload().expect("Should have reloaded"));
+    assert_eq!(vec!["is_v2"], matching(&rule_set, b"version 2"));
+    assert_eq!(1, old_rules.scan_mem(b"version 1", 10).unwrap().len());
+
+    assert_eq!(vec![false, true], *outcomes.lock().unwrap());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_ruleset_compiled_watch() {
+    let dir = temp_dir("compiled");
+    let compiled = dir.join("rules.yarc");
+    let save = |source: &str, age: u64| {
+        let mut compiler = Compiler::new().unwrap();
+        compiler.add_rules_str(source).unwrap();
+        let mut buffer = Vec::new();
+        compiler
+            .compile_rules()
+            .unwrap()
+            .save_to_stream(&mut buffer)
+            .unwrap();
+        write_file(&compiled, &buffer, age);
+    };
+    save(RULE_V1, 100);
+
+    let rule_set = RuleSet::from_compiled_file(&compiled).expect("Should have loaded the rules");
+    let _watcher = rule_set.watch(Duration::from_millis(10));
+    save(RULE_V2, 10);
+
+    for _ in 0..500 {
+        if matching(&rule_set, b"version 2") == vec!["is_v2"] {
+            fs::remove_dir_all(&dir).ok();
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("The rules should have been reloaded");
+}
+
+#[test]
+fn test_ruleset_file_includes() {
+    let dir = temp_dir("file_includes");
+    let rule_file = dir.join("main.yar");
+    let include = dir.join("inc/v.yar");
+    fs::create_dir(dir.join("inc")).unwrap();
+    write_file(&rule_file, b"include \"inc/v.yar\"\n", 100);
+    write_file(&include, RULE_V1.as_bytes(), 100);
+
+    let rule_set = RuleSet::new(RuleSource::File(rule_file.clone())).unwrap();
+    let outcomes = Arc::new(Mutex::new(Vec::new()));
+    {
+        let outcomes = Arc::clone(&outcomes);
+        rule_set.on_reload(move |result| outcomes.lock().unwrap().push(result.is_ok()));
+    }
+    assert_eq!(vec!["is_v1"], matching(&rule_set, b"version 1"));
+
+    // A change of an included file is a change of the source.
+    write_file(&include, RULE_V2.as_bytes(), 50);
+    assert!(rule_set.reload().expect("Should have reloaded"));
+    assert_eq!(vec!["is_v2"], matching(&rule_set, b"version 2"));
+
+    // A persistent error is reported once.
+    fs::remove_file(&rule_file).unwrap();
+    for _ in 0..3 {
+        assert!(rule_set.reload().is_err());
+    }
+    write_file(&rule_file, RULE_V1.as_bytes(), 10);
+    assert!(rule_set.reload().expect("Should have reloaded"));
+    assert_eq!(vec![true, false, true], *outcomes.lock().unwrap());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_ruleset_reload_from_callback() {
+    let dir = temp_dir("reload_from_callback");
+    let rule_file = dir.join("rules.yar");
+    write_file(&rule_file, RULE_V1.as_bytes(), 100);
+
+    let rule_set = RuleSet::from_directory(&dir).expect("Should have loaded the rules");
+    let reloads = Arc::new(Mutex::new(Vec::new()));
+    {
+        let rule_set_ref = rule_set.clone();
+        let reloads = Arc::clone(&reloads);
+        rule_set.on_reload(move |_| {
+            // Reloading again does not deadlock, and finds nothing new.
+            let reloaded = rule_set_ref
+                .reload()
+                .expect("Should have checked the rules");
+            reloads.lock().unwrap().push(reloaded);
+        });
+    }
+    write_file(&rule_file, RULE_V2.as_bytes(), 50);
+    assert!(rule_set.reload().expect("Should have reloaded"));
+    assert_eq!(vec![false], *reloads.lock().unwrap());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_ruleset_non_utf8_path() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let dir = temp_dir("non_utf8");
+    let rule_file = dir.join(OsStr::from_bytes(b"rules\xff.yar"));
+    write_file(&rule_file, RULE_V1.as_bytes(), 100);
+    assert!(RuleSet::new(RuleSource::File(rule_file)).is_err());
+    assert!(RuleSet::from_directory(&dir).is_err());
+
+    let compiled = dir.join(OsStr::from_bytes(b"rules\xff.yarc"));
+    let mut compiler = Compiler::new().unwrap();
+    compiler.add_rules_str(RULE_V1).unwrap();
+    let mut buffer = Vec::new();
+    compiler
+        .compile_rules()
+        .unwrap()
+        .save_to_stream(&mut buffer)
+        .unwrap();
+    fs::write(&compiled, buffer).unwrap();
+    let rule_set = RuleSet::from_compiled_file(&compiled).expect("Should have loaded the rules");
+    assert_eq!(vec!["is_v1"], matching(&rule_set, b"version 1"));
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,256 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use common::corpus;
+use yara::errors::{CompileErrorLevel, Error};
+use yara::syntax::{
+    self, BinaryOp, ExprKind, HexToken, Item, MetaValue, Quantifier, StringModifierKind, StringSet,
+    StringValue, UnaryOp,
+};
+use yara::Compiler;
+
+/// The line of the first error of the compiler, if any.
+fn compile(source: &str) -> Option<usize> {
+    let mut compiler = Compiler::new().unwrap();
+    match compiler.add_rules_str(source) {
+        Ok(()) => None,
+        Err(Error::Compile(errors)) => errors
+            .iter()
+            .find(|e| e.level == CompileErrorLevel::Error)
+            .map(|e| e.line),
+        Err(e) => panic!("Unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_corpus_valid() {
+    for source in corpus("tests/syntax/valid.txt") {
+        assert_eq!(None, compile(&source), "Should have compiled:\n{}", source);
+        if let Err(e) = syntax::parse(&source) {
+            panic!("Should have parsed:\n{}\n{}", source, e);
+        }
+    }
+}
+
+#[test]
+fn test_corpus_invalid() {
+    for source in corpus("tests/syntax/invalid.txt") {
+        let compile_line =
+            compile(&source).unwrap_or_else(|| panic!("Should not compile:\n{}", source));
+        let error = syntax::parse(&source).expect_err(&format!("Should not parse:\n{}", source));
+        // libyara reports errors at the end of the source at line 0.
+        if compile_line != 0 {
+            assert_eq!(compile_line, error.line, "{}\n{}", source, error);
+        }
+    }
+}
+
+#[test]
+fn test_corpus_rules_file() {
+    let source = std::fs::read_to_string("tests/rules.txt").unwrap();
+    let file = syntax::parse(&source).expect("Should have parsed");
+    let names: Vec<_> = file.rules().map(|r| r.identifier.name.as_str()).collect();
+    assert_eq!(vec!["is_awesome", "is_ok"], names);
+}
+
+#[test]
+fn test_parse_rule() {
+    let source = r#"import "pe"
+// A comment.
+private rule a : t1 t2 {
+  meta:
+    author = "me"
+    version = -2
+    enabled = true
+  strings:
+    $text = "x\x41" wide xor(1-2)
+    $hex = { 4D ?? 5? [2-] ( 01 | 02 ) }
+    $re = /a.c/s
+  condition:
+    $text at 0 and not #hex > 1
+}"#;
+    let file = syntax::parse(source).expect("Should have parsed");
+
+    assert_eq!(2, file.items.len());
+    match &file.items[0] {
+        Item::Import(import) => assert_eq!("pe", import.module.raw),
+        item => panic!("Unexpected item {:?}", item),
+    }
+    assert_eq!(1, file.comments.len());
+    assert_eq!("// A comment.", file.comments[0].text);
+
+    let rule = file.rules().next().unwrap();
+    assert!(rule.is_private);
+    assert!(!rule.is_global);
+    assert_eq!(
+        "a",
+        &source[rule.identifier.span.start..rule.identifier.span.end]
+    );
+    assert_eq!(
+        vec!["t1", "t2"],
+        rule.tags.iter().map(|t| &t.name).collect::<Vec<_>>()
+    );
+    assert_eq!(source.find("private").unwrap(), rule.span.start);
+    assert_eq!(source.len(), rule.span.end);
+
+    assert_eq!(MetaValue::Integer(-2), rule.metas[1].value);
+    assert_eq!(MetaValue::Boolean(true), rule.metas[2].value);
+
+    let text = &rule.strings[0];
+    assert_eq!("$text", text.identifier.name);
+    match &text.value {
+        StringValue::Text(s) => {
+            assert_eq!("x\\x41", s.raw);
+            assert_eq!(b"xA".to_vec(), s.value());
+        }
+        value => panic!("Unexpected value {:?}", value),
+    }
+    let modifiers: Vec<_> = text.modifiers.iter().map(|m| m.kind).collect();
+    assert_eq!(
+        vec![
+            StringModifierKind::Wide,
+            StringModifierKind::Xor(Some((1, 2)))
+        ],
+        modifiers
+    );
+
+    match &rule.strings[1].value {
+        StringValue::Hex(hex) => assert_eq!(
+            vec![
+                HexToken::Byte {
+                    value: 0x4D,
+                    mask: 0xFF
+                },
+                HexToken::Byte { value: 0, mask: 0 },
+                HexToken::Byte {
+                    value: 0x50,
+                    mask: 0xF0
+                },
+                HexToken::Jump {
+                    start: Some(2),
+                    end: None
+                },
+                HexToken::Alternative(vec![
+                    vec![HexToken::Byte {
+                        value: 1,
+                        mask: 0xFF
+                    }],
+                    vec![HexToken::Byte {
+                        value: 2,
+                        mask: 0xFF
+                    }],
+                ]),
+            ],
+            hex.tokens
+        ),
+        value => panic!("Unexpected value {:?}", value),
+    }
+    match &rule.strings[2].value {
+        StringValue::Regex(re) => {
+            assert_eq!("a.c", re.pattern);
+            assert!(re.dot_all);
+            assert!(!re.case_insensitive);
+        }
+        value => panic!("Unexpected value {:?}", value),
+    }
+
+    match &rule.condition.kind {
+        ExprKind::Binary {
+            op: BinaryOp::And,
+            lhs,
+            rhs,
+        } => {
+            assert!(matches!(lhs.kind, ExprKind::StringAt { .. }));
+            match &rhs.kind {
+                ExprKind::Unary {
+                    op: UnaryOp::Not,
+                    operand,
+                } => assert!(matches!(
+                    operand.kind,
+                    ExprKind::Binary {
+                        op: BinaryOp::Gt,
+                        ..
+                    }
+                )),
+                kind => panic!("Unexpected condition {:?}", kind),
+            }
+        }
+        kind => panic!("Unexpected condition {:?}", kind),
+    }
+}
+
+#[test]
+fn test_parse_precedence() {
+    let condition = |source: &str| {
+        let file = syntax::parse(&format!("rule a {{ condition: {} }}", source)).unwrap();
+        let condition = file.rules().next().unwrap().condition.clone();
+        condition
+    };
+
+    // `|` binds looser than `==`, but comparisons take whole arithmetic operands.
+    match condition("1 | 2 == 3 + 4 * 5").kind {
+        ExprKind::Binary {
+            op: BinaryOp::Eq,
+            lhs,
+            rhs,
+        } => {
+            assert!(matches!(
+                lhs.kind,
+                ExprKind::Binary {
+                    op: BinaryOp::BitOr,
+                    ..
+                }
+            ));
+            match rhs.kind {
+                ExprKind::Binary {
+                    op: BinaryOp::Add,
+                    rhs,
+                    ..
+                } => assert!(matches!(
+                    rhs.kind,
+                    ExprKind::Binary {
+                        op: BinaryOp::Mul,
+                        ..
+                    }
+                )),
+                kind => panic!("Unexpected expression {:?}", kind),
+            }
+        }
+        kind => panic!("Unexpected expression {:?}", kind),
+    }
+
+    // `and` binds tighter than `or`.
+    assert!(matches!(
+        condition("true or true and false").kind,
+        ExprKind::Binary {
+            op: BinaryOp::Or,
+            ..
+        }
+    ));
+
+    match condition("2 of ($a*, $b)").kind {
+        ExprKind::Of {
+            quantifier: Quantifier::Expr(n),
+            strings: StringSet::List(strings),
+        } => {
+            assert_eq!(ExprKind::Integer(2), n.kind);
+            assert_eq!("$a*", strings[0].name);
+        }
+        kind => panic!("Unexpected expression {:?}", kind),
+    }
+}
+
+#[test]
+fn test_parse_error() {
+    let error = syntax::parse("rule a {\n  condition\n    true\n}").unwrap_err();
+    assert_eq!(3, error.line);
+    assert_eq!(
+        "syntax error, unexpected <true>, expecting ':'",
+        error.message
+    );
+    assert_eq!(
+        "Syntax error at line 3: syntax error, unexpected <true>, expecting ':'",
+        error.to_string()
+    );
+}
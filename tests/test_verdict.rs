@@ -0,0 +1,257 @@
+extern crate rs_yara as yara;
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use common::compile;
+use common::temp_dir;
+use yara::daemon::Daemon;
+use yara::render::View;
+use yara::report::FileReport;
+use yara::verdict::VerdictCache;
+use yara::{OwnedRule, RuleSet, RuleSource};
+
+const RULE_V1: &str = "rule is_v1 : tag {
+  meta:
+    author = \"me\"
+    score = 7
+  strings:
+    $a = \"version\" wide ascii nocase
+  condition:
+    $a
+}
+";
+
+const RULE_V2: &str = "rule is_v2 {
+  strings:
+    $a = \"version 2\"
+  condition:
+    $a
+}
+";
+
+const F1: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+const F2: &str = "2222222222222222222222222222222222222222222222222222222222222222";
+
+/// The fingerprints with entries in the cache directory.
+fn fingerprints(dir: &Path) -> Vec<String> {
+    let mut fingerprints: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|path| path.is_dir())
+        .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    fingerprints.sort();
+    fingerprints
+}
+
+#[test]
+fn test_owned_rules_round_trip() {
+    let mut rules = compile(RULE_V1);
+    rules.set_context(4);
+    let matches = rules.scan_mem(b"I am VERSION 1", 10).unwrap();
+    let report = FileReport::new("data", &matches, View::Hex);
+
+    let owned: Vec<OwnedRule> = matches.into_iter().map(OwnedRule::from).collect();
+    let json = serde_json::to_string(&owned).unwrap();
+    let owned: Vec<OwnedRule> = serde_json::from_str(&json).unwrap();
+    let modifiers = owned[0].strings[0].modifiers;
+    assert!(modifiers.ascii && modifiers.wide && modifiers.nocase && !modifiers.xor);
+
+    let matches: Vec<_> = owned.iter().map(OwnedRule::as_rule).collect();
+    assert_eq!(report, FileReport::new("data", &matches, View::Hex));
+}
+
+#[test]
+fn test_verdict_cache() {
+    let dir = temp_dir("verdict");
+    let cache = VerdictCache::new(&dir);
+    let mut rules = compile(RULE_V1);
+
+    let first = cache.scan_mem(&rules, F1, b"version", 10).unwrap();
+    assert!(!first.cached);
+    assert_eq!("is_v1", first.rules[0].identifier);
+    let second = cache.scan_mem(&rules, F1, b"version", 10).unwrap();
+    assert!(second.cached);
+    assert_eq!(first.rules, second.rules);
+    // Results without matches are cached too.
+    assert!(cache
+        .scan_mem(&rules, F1, b"other", 10)
+        .unwrap()
+        .rules
+        .is_empty());
+    assert!(cache.scan_mem(&rules, F1, b"other", 10).unwrap().cached);
+
+    // Scans capturing context have their own results.
+    rules.set_context(2);
+    let with_context = cache.scan_mem(&rules, F1, b"version", 10).unwrap();
+    assert!(!with_context.cached);
+    assert!(with_context.rules[0].strings[0].matches[0]
+        .context
+        .is_some());
+
+    // A corrupted entry is a miss, then replaced.
+    let key = VerdictCache::key(b"version", &rules);
+    fs::write(dir.join(F1).join(format!("{}.json", key)), "{").unwrap();
+    assert!(cache.get(F1, &key).is_none());
+    assert!(!cache.scan_mem(&rules, F1, b"version", 10).unwrap().cached);
+    assert!(cache.get(F1, &key).is_some());
+
+    // Results of other rules are removed.
+    assert!(!cache.scan_mem(&rules, F2, b"version", 10).unwrap().cached);
+    assert_eq!(vec![F2.to_owned()], fingerprints(&dir));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_verdict_cache_files() {
+    let dir = temp_dir("verdict_files");
+    let cache = VerdictCache::new(dir.join("cache"));
+    let rules = compile(RULE_V1);
+    let sample = dir.join("sample");
+    fs::write(&sample, "a version").unwrap();
+
+    // Files have the keys of their content.
+    let key = VerdictCache::file_key(&sample, &rules).unwrap();
+    assert_eq!(VerdictCache::key(b"a version", &rules), key);
+    let first = cache.scan_file(&rules, F1, &sample, 10).unwrap();
+    assert!(!first.cached);
+    assert_eq!("is_v1", first.rules[0].identifier);
+    assert!(cache.scan_mem(&rules, F1, b"a version", 10).unwrap().cached);
+    assert!(!cache.scan_mem(&rules, F1, b"other", 10).unwrap().cached);
+    fs::write(&sample, "other").unwrap();
+    assert!(cache.scan_file(&rules, F1, &sample, 10).unwrap().cached);
+
+    assert!(cache
+        .scan_file(&rules, F1, dir.join("missing"), 10)
+        .is_err());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_verdict_cache_directory() {
+    let dir = temp_dir("verdict_directory");
+    let rules = compile(RULE_V1);
+
+    // A directory which is not empty is not taken over.
+    fs::create_dir_all(dir.join("home/documents")).unwrap();
+    let cache = VerdictCache::new(dir.join("home"));
+    assert!(cache.init().is_err());
+    assert!(cache.insert(F1, "key", &[]).is_err());
+    assert_eq!(
+        vec!["documents".to_owned()],
+        fingerprints(&dir.join("home"))
+    );
+
+    // Only the directories of other fingerprints are removed.
+    let cache = VerdictCache::new(dir.join("cache"));
+    cache.init().unwrap();
+    fs::create_dir(dir.join("cache/other")).unwrap();
+    cache.scan_mem(&rules, F1, b"version", 10).unwrap();
+    cache.scan_mem(&rules, F2, b"version", 10).unwrap();
+    assert_eq!(
+        vec![F2.to_owned(), "other".to_owned()],
+        fingerprints(&dir.join("cache"))
+    );
+    cache.init().unwrap();
+
+    // Fingerprints are not paths.
+    assert!(cache.insert("../home", "key", &[]).is_err());
+    assert!(cache.get("..", "home").is_none());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_verdict_cache_eviction() {
+    let dir = temp_dir("verdict_eviction");
+    let mut cache = VerdictCache::new(&dir);
+    let rules = compile(RULE_V1);
+
+    let data: Vec<Vec<u8>> = (0..8)
+        .map(|i| format!("version {}", i).into_bytes())
+        .collect();
+    for (i, data) in data.iter().enumerate() {
+        cache.scan_mem(&rules, F1, data, 10).unwrap();
+        // The first entries are the least recently used.
+        let entry = dir
+            .join(F1)
+            .join(format!("{}.json", VerdictCache::key(data, &rules)));
+        let file = fs::OpenOptions::new().write(true).open(entry).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(100 - i as u64))
+            .unwrap();
+    }
+    let entry_size = fs::metadata(
+        dir.join(F1)
+            .join(format!("{}.json", VerdictCache::key(&data[0], &rules))),
+    )
+    .unwrap()
+    .len();
+
+    cache.set_max_size(entry_size * 8);
+    cache.scan_mem(&rules, F1, b"version 8", 10).unwrap();
+    let cached: Vec<bool> = data
+        .iter()
+        .map(|data| cache.get(F1, &VerdictCache::key(data, &rules)).is_some())
+        .collect();
+    // Down to 3/4 of the limit: the entry of "version 8" and the 5 most recent ones.
+    assert_eq!(
+        vec![false, false, false, true, true, true, true, true],
+        cached
+    );
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_rule_set_fingerprint() {
+    let dir = temp_dir("verdict_fingerprint");
+    let rules_dir = dir.join("rules");
+    fs::create_dir(&rules_dir).unwrap();
+    fs::write(rules_dir.join("v1.yar"), RULE_V1).unwrap();
+    fs::write(rules_dir.join("common.yar"), "include \"inc/common.inc\"\n").unwrap();
+    fs::create_dir(rules_dir.join("inc")).unwrap();
+    fs::write(rules_dir.join("inc/common.inc"), RULE_V2).unwrap();
+
+    let source = RuleSource::Directory(rules_dir.clone());
+    let fingerprint = source.fingerprint().unwrap();
+    assert_eq!(64, fingerprint.len());
+    assert_eq!(fingerprint, source.fingerprint().unwrap());
+
+    let rule_set = RuleSet::new(source.clone()).unwrap();
+    assert_eq!(fingerprint, rule_set.rules_with_fingerprint().1);
+
+    // Included files are part of the fingerprint.
+    fs::write(
+        rules_dir.join("inc/common.inc"),
+        RULE_V2.replace("is_v2", "is_v3"),
+    )
+    .unwrap();
+    let changed = source.fingerprint().unwrap();
+    assert_ne!(fingerprint, changed);
+    rule_set.force_reload().unwrap();
+    assert_eq!(changed, rule_set.rules_with_fingerprint().1);
+
+    // The daemon answers from the cache until the rules change.
+    let mut daemon = Daemon::new(rule_set.clone());
+    daemon.set_verdict_cache(VerdictCache::new(dir.join("cache")));
+    let sample = dir.join("sample");
+    fs::write(&sample, "version 2").unwrap();
+    for _ in 0..2 {
+        daemon.scan_path(&sample, None);
+    }
+    daemon.scan_bytes("sample", b"version 2", None);
+    assert_eq!(2, daemon.stats().cached);
+
+    fs::write(rules_dir.join("v1.yar"), RULE_V2).unwrap();
+    rule_set.force_reload().unwrap();
+    let response = daemon.scan_path(&sample, None).to_json();
+    assert!(response.contains("\"identifier\":\"is_v2\""));
+    assert_eq!(2, daemon.stats().cached);
+    assert_eq!(
+        vec![rule_set.rules_with_fingerprint().1],
+        fingerprints(&dir.join("cache"))
+    );
+    fs::remove_dir_all(&dir).ok();
+}
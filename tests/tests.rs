@@ -1,6 +1,6 @@
 extern crate rs_yara as yara;
 
-use yara::{yara_sys::CompileErrorLevel, Compiler, errors::Error, Metadata, MetadataValue, Rules, Yara};
+use yara::{yara_sys::CompileErrorLevel, Compiler, errors::Error, MemoryBlocks, Metadata, MetadataValue, Rules, Yara};
 
 const RULES: &str = r#"
 rule is_awesome {
@@ -100,6 +100,78 @@ fn test_scan_file() {
     assert_eq!(1, result.len());
 }
 
+#[test]
+fn test_scan_file_range() {
+    let rules = get_default_rules();
+
+    let result = rules
+        .scan_file_range("tests/scanfile.txt", 2, 10, 10)
+        .expect("Should have scanned file range");
+    assert_eq!(1, result.len());
+    assert_eq!(7, result[0].strings[0].matches[0].offset);
+
+    let result = rules
+        .scan_file_range_relative("tests/scanfile.txt", 2, 10, 10)
+        .expect("Should have scanned file range");
+    assert_eq!(1, result.len());
+    assert_eq!(5, result[0].strings[0].matches[0].offset);
+
+    let result = rules
+        .scan_file_range("tests/scanfile.txt", 0, 7, 10)
+        .expect("Should have scanned file range");
+    assert!(result.is_empty());
+}
+
+#[test]
+fn test_scan_reader() {
+    let rules = compile(
+        r#"
+rule has_rust {
+  strings:
+    $rust = "Rust"
+  condition:
+    $rust
+}
+"#,
+    );
+    // The first "Rust" crosses the boundary of the first window, the second one is in the
+    // overlap of the second and third windows.
+    let data: &[u8] = b"I love Rust, more Rust!";
+
+    let result = rules
+        .scan_reader(data, 10, 6, 10)
+        .expect("Should have scanned reader");
+    assert_eq!(1, result.len());
+    let offsets: Vec<usize> = result[0].strings[0].matches.iter().map(|m| m.offset).collect();
+    assert_eq!(vec![7, 18], offsets);
+}
+
+#[test]
+fn test_scan_reader_empty() {
+    let rules = compile(
+        "
+rule is_empty {
+  condition:
+    filesize == 0
+}",
+    );
+    let result = rules
+        .scan_reader(&b""[..], 10, 4, 10)
+        .expect("Should have scanned reader");
+    assert_eq!(1, result.len());
+}
+
+#[test]
+fn test_scan_reader_invalid_window() {
+    let rules = compile(RULES);
+    for (window, overlap) in [(0, 0), (10, 10), (10, 11)] {
+        match rules.scan_reader(&b"Rust"[..], window, overlap, 10) {
+            Err(Error::InvalidArgument(_)) => {}
+            other => panic!("unexpected {:?}", other.map(|r| r.len())),
+        }
+    }
+}
+
 #[test]
 fn test_scan_fast_mode() {
     let test_mem = b"
@@ -312,6 +384,40 @@ rule is_slow
     compile(rule);
 }
 
+#[test]
+fn test_scan_blocks() {
+    let rules = compile(
+        r#"
+rule in_second_block {
+  strings:
+    $rust = "Rust"
+  condition:
+    $rust at 0x5007 and uint32be(0x1000) == 0x41424344
+}
+"#,
+    );
+    let first: &[u8] = b"ABCD is not matching";
+    let second: &[u8] = b"I love Rust!";
+    let blocks = MemoryBlocks::new(vec![(0x1000, first), (0x5000, second)]);
+
+    let result = rules.scan_blocks(blocks, 10).expect("Should have scanned blocks");
+    assert_eq!(1, result.len());
+    let string = &result[0].strings[0];
+    assert_eq!(1, string.matches.len());
+    assert_eq!(0x5000, string.matches[0].base);
+    assert_eq!(0x5007, string.matches[0].offset);
+    assert_eq!(b"Rust", string.matches[0].data.as_slice());
+}
+
+#[test]
+fn test_scan_blocks_empty() {
+    let rules = get_default_rules();
+    let result = rules
+        .scan_blocks(MemoryBlocks::new(Vec::new()), 10)
+        .expect("Should have scanned blocks");
+    assert!(result.is_empty());
+}
+
 fn test_default_rules(rules: &Rules) {
     let scan_mem_result = rules.scan_mem("I love Rust!".as_bytes(), 10);
     let scan_result = scan_mem_result.expect("Should be Ok");